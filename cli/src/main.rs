@@ -1,10 +1,24 @@
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+mod report;
+mod serve;
+
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use demonax_mapper_core::*;
+use demonax_mapper_render::*;
 use indicatif::{ProgressBar, ProgressStyle};
-use rayon;
+use report::{format_bytes, BuildReport, PendingWarnings, WarningCollector};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::fs;
+use std::process::ExitCode;
+use std::time::Instant;
+use tracing_chrome::ChromeLayerBuilder;
+use tracing_subscriber::filter::{filter_fn, LevelFilter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
 
 #[derive(Parser)]
 #[command(name = "demonax-mapper")]
@@ -15,9 +29,82 @@ struct Cli {
 
     #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
+
+    #[arg(long, global = true, help = "Write a Chrome trace (open in Perfetto or chrome://tracing)")]
+    trace_out: Option<PathBuf>,
+}
+
+/// Build flags that a `--profile` takes from the project file instead, and
+/// that override a `--config` file when given.
+const BUILD_CONFIG_ARGS: [&str; 52] = [
+    "objects_path",
+    "objects_extra",
+    "map_path",
+    "sprite_path",
+    "sprite_overrides",
+    "substitute_sprites",
+    "strict",
+    "sprite_cache_mb",
+    "sprite_frames",
+    "layer_rules",
+    "mode",
+    "color_map",
+    "tile_format",
+    "pyramid",
+    "pmtiles",
+    "output_archive",
+    "composite_brightness",
+    "output",
+    "floors",
+    "min_zoom",
+    "max_zoom",
+    "monster_db",
+    "mon_path",
+    "monster_sprites",
+    "client_spr",
+    "client_dat",
+    "npc_csv",
+    "npc_sprites",
+    "quest_csv",
+    "raids_path",
+    "data_path",
+    "encoding",
+    "prices_csv",
+    "heatmap_csv",
+    "heatmap_radius",
+    "houses_status",
+    "house_names",
+    "live_players",
+    "overlay_refresh",
+    "admin",
+    "admin_command",
+    "archive",
+    "marker_style",
+    "overlays",
+    "sectors",
+    "region",
+    "incremental",
+    "cache_dir",
+    "force",
+    "extra_assets",
+    "offline",
+    "threads",
+];
+
+/// The [`BuildConfig`] key set by a build flag, where their names differ.
+fn config_key(arg_id: &str) -> &str {
+    match arg_id {
+        "mode" => "render_mode",
+        "data_path" => "data_paths",
+        "overlay_refresh" => "overlay_refresh_minutes",
+        "admin" => "admin_command",
+        "marker_style" => "marker_styles",
+        other => other,
+    }
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     ParseObjects {
         #[arg(help = "Path to objects.srv file")]
@@ -28,20 +115,76 @@ enum Commands {
     },
 
     Build {
-        #[arg(long, help = "Path to objects.srv file")]
-        objects_path: PathBuf,
+        #[arg(
+            long,
+            value_name = "NAME",
+            conflicts_with_all = BUILD_CONFIG_ARGS,
+            help = "Build a profile from the project file instead of from flags"
+        )]
+        profile: Option<String>,
 
-        #[arg(long, help = "Path to map directory with .sec files")]
-        map_path: PathBuf,
+        #[arg(long, value_name = "FILE", default_value = PROJECT_FILE, requires = "profile", help = "Project file defining the profiles")]
+        project: PathBuf,
 
-        #[arg(long, help = "Path to sprite PNG directory")]
-        sprite_path: PathBuf,
+        #[arg(long, value_name = "FILE", conflicts_with = "profile", help = "TOML file of build settings (see init-config); flags given on the command line override it")]
+        config: Option<PathBuf>,
+
+        #[arg(long, required_unless_present_any = ["profile", "config"], help = "Path to objects.srv file")]
+        objects_path: Option<PathBuf>,
+
+        #[arg(long, help = "Additional .srv file merged over objects.srv (repeatable)")]
+        objects_extra: Vec<PathBuf>,
+
+        #[arg(long, required_unless_present_any = ["profile", "config"], help = "Path to map directory with .sec files")]
+        map_path: Option<PathBuf>,
+
+        #[arg(long, help = "Path to sprite PNG directory, a zip of sprite PNGs, or a sprite sheet index JSON (required unless --client-spr/--client-dat or --mode color with --color-map)")]
+        sprite_path: Option<PathBuf>,
+
+        #[arg(long, help = "Directory or JSON mapping of hand-fixed sprites that replace those in --sprite-path")]
+        sprite_overrides: Option<PathBuf>,
+
+        #[arg(long, help = "Draw the nearest lower/upper object ID's sprite instead of a placeholder for missing or corrupt sprites")]
+        substitute_sprites: bool,
+
+        #[arg(long, help = "Fail the build with the full list of sprite IDs that would be drawn as placeholders")]
+        strict: bool,
+
+        #[arg(long, value_name = "MB", help = "Cap decoded sprite memory at MB MiB, dropping the least recently used sprites (default: unlimited)")]
+        sprite_cache_mb: Option<usize>,
+
+        #[arg(long, default_value = "first", help = "Sprites stored as <id>_<frame>.png frames: first, average (blend them) or animate (animated WebP tiles, needs --tile-format webp)")]
+        sprite_frames: SpriteFrames,
+
+        #[arg(long, help = "TOML or JSON file of rules that put objects on a drawing layer by ID, flags or name pattern")]
+        layer_rules: Option<PathBuf>,
+
+        #[arg(long, default_value = "sprites", help = "Draw tiles from sprites, or as flat minimap colors: sprites or color")]
+        mode: RenderMode,
+
+        #[arg(long, alias = "colors", value_name = "FILE", help = "Object colors TOML or JSON from export-colors for --mode color (default: computed from sprites)")]
+        color_map: Option<PathBuf>,
+
+        #[arg(long, default_value = "png", help = "Map tile image format: png, webp (lossless, smaller) or avif (lossy, smallest)")]
+        tile_format: TileFormat,
+
+        #[arg(long, conflicts_with_all = ["region", "incremental"], help = "Draw only --max-zoom and build lower zoom levels by shrinking it (faster, same look at every zoom)")]
+        pyramid: bool,
+
+        #[arg(long, value_name = "LAYOUT", num_args = 0..=1, default_missing_value = "per-floor", conflicts_with_all = ["region", "incremental", "archive"], help = "Write tiles into PMTiles archives instead of tile directories: per-floor (default) or combined (one archive for all floors)")]
+        pmtiles: Option<PmtilesLayout>,
+
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["region", "incremental", "archive", "pmtiles"], help = "Write tiles into one .zip or .tar.gz file instead of the output directory, unpacked into it on the server")]
+        output_archive: Option<PathBuf>,
+
+        #[arg(long, value_name = "LIST", value_delimiter = ',', help = "Also render an 'All floors' layer showing the floors below each floor darkened to these brightness factors, nearest first (e.g. 0.6,0.35)")]
+        composite_brightness: Vec<f32>,
 
         #[arg(short, long, default_value = "output")]
         output: PathBuf,
 
-        #[arg(short, long, help = "Floors to generate (e.g. 0-15 or 7)")]
-        floors: String,
+        #[arg(short, long, required_unless_present_any = ["profile", "config"], help = "Floors to generate (e.g. 7, 0-15 or 0,7,9-11)")]
+        floors: Option<String>,
 
         #[arg(long, default_value = "0")]
         min_zoom: u8,
@@ -58,6 +201,12 @@ enum Commands {
         #[arg(long, help = "Path to monster sprite PNG directory")]
         monster_sprites: Option<PathBuf>,
 
+        #[arg(long, requires = "client_dat", help = "Client .spr file to draw monster icons from each race's Outfit, and map sprites without --sprite-path")]
+        client_spr: Option<PathBuf>,
+
+        #[arg(long, requires = "client_spr", help = "Client .dat file matching --client-spr")]
+        client_dat: Option<PathBuf>,
+
         #[arg(long, help = "Path to NPC CSV file")]
         npc_csv: Option<PathBuf>,
 
@@ -67,13 +216,332 @@ enum Commands {
         #[arg(long, help = "Path to quest_overview.csv file")]
         quest_csv: Option<PathBuf>,
 
+        #[arg(long, value_name = "DIR", help = "Path to a directory of .raid files")]
+        raids_path: Option<PathBuf>,
+
+        #[arg(long, value_name = "DIR", help = "Game data directory (dat/monster.db, mon/, quest_overview.csv, raids/) merged over earlier ones (repeatable)")]
+        data_path: Vec<PathBuf>,
+
+        #[arg(long, default_value = "auto", help = "Encoding of the game data files: auto, utf8 or latin1")]
+        encoding: InputEncoding,
+
+        #[arg(long, help = "Path to prices.csv (item id, avg price) for loot values")]
+        prices_csv: Option<PathBuf>,
+
+        #[arg(long, help = "Path to CSV of x,y,z[,weight] points to render as a heatmap")]
+        heatmap_csv: Option<PathBuf>,
+
+        #[arg(long, default_value = "8", help = "Heatmap point radius in tiles")]
+        heatmap_radius: u32,
+
+        #[arg(long, help = "Path to houses-status.json (house id -> owner, rent due, auction)")]
+        houses_status: Option<PathBuf>,
+
+        #[arg(long, value_name = "FILE", help = "house_id,name CSV used to label houses on the map")]
+        house_names: Option<PathBuf>,
+
+        #[arg(long, help = "Show online players streamed by serve mode (staff-only maps)")]
+        live_players: bool,
+
+        #[arg(long, value_name = "MINUTES", help = "Make the viewer re-fetch spawns and quest chests every N minutes")]
+        overlay_refresh: Option<u32>,
+
+        #[arg(long, help = "Add a right-click menu item copying a GM command for the clicked tile")]
+        admin: bool,
+
+        #[arg(long, default_value = "/goto {x} {y} {z}", requires = "admin", help = "Command template for --admin")]
+        admin_command: String,
+
+        #[arg(long, value_name = "DATE", help = "Archive this build's tiles under history/DATE (YYYY-MM-DD) for the time slider")]
+        archive: Option<String>,
+
+        #[arg(long = "marker-style", value_name = "OVERLAY.KEY=VALUE", help = "Override a marker style, e.g. spawns.min_zoom=2 or quests.color=#ff8800 (repeatable)")]
+        marker_style: Vec<String>,
+
+        #[arg(long, value_name = "LIST", default_value = "all", help = "Overlays to generate: comma list of spawns, quests, npcs, water, zones, heatmap, houses, transitions, teleports, signs, poi, raids, or all/none")]
+        overlays: String,
+
+        #[arg(long, value_name = "X-Y,...", help = "Only parse and render these sectors (e.g. 1004-1002,1005-1002); bypasses the floor cache")]
+        sectors: Option<String>,
+
+        #[arg(long, value_name = "X1,Y1,X2,Y2", conflicts_with_all = ["sectors", "incremental"], help = "Only re-render tiles covering this rectangle of world tiles, in place over a full build")]
+        region: Option<Region>,
+
+        #[arg(long, conflicts_with = "sectors", help = "Only re-render tiles covering sector files changed since the last build")]
+        incremental: bool,
+
+        #[arg(long, value_name = "DIR", default_value = DEFAULT_CACHE_DIR, help = "Directory for parsed objects and floors kept between builds")]
+        cache_dir: PathBuf,
+
+        #[arg(long, conflicts_with = "incremental", help = "Clear the cache directory first and parse everything again")]
+        force: bool,
+
+        #[arg(long, value_name = "DIR", help = "Copy CSS/JS files from DIR into the output and link them from index.html")]
+        extra_assets: Option<PathBuf>,
+
+        #[arg(long, value_name = "LEAFLET_DIR", help = "Copy Leaflet from LEAFLET_DIR (its dist directory) into the output instead of loading it from unpkg")]
+        offline: Option<PathBuf>,
+
+        #[arg(long, help = "Open the generated index.html in the default browser when done")]
+        open: bool,
+
         #[arg(short = 'j', long, help = "Number of worker threads (default: all cores)")]
         threads: Option<usize>,
     },
+
+    #[command(about = "Write a commented template of build settings for build --config")]
+    InitConfig {
+        #[arg(short, long, default_value = PROJECT_FILE, help = "File to write")]
+        output: PathBuf,
+
+        #[arg(long, help = "Overwrite the file if it exists")]
+        force: bool,
+    },
+
+    #[command(about = "Export every sign, book and other readable text on the map")]
+    ExportTexts {
+        #[arg(long, help = "Path to objects.srv file")]
+        objects_path: PathBuf,
+
+        #[arg(long, help = "Additional .srv file merged over objects.srv (repeatable)")]
+        objects_extra: Vec<PathBuf>,
+
+        #[arg(long, help = "Path to map directory with .sec files")]
+        map_path: PathBuf,
+
+        #[arg(short, long, default_value = "0-15", help = "Floors to scan (e.g. 7, 0-15 or 0,7,9-11)")]
+        floors: String,
+
+        #[arg(short, long, default_value = "texts.json", help = "Output file (.json or .csv)")]
+        output: PathBuf,
+    },
+
+    #[command(about = "Flag sector Content entries that reference object IDs missing from objects.srv")]
+    Lint {
+        #[arg(long, help = "Path to objects.srv file")]
+        objects_path: PathBuf,
+
+        #[arg(long, help = "Additional .srv file merged over objects.srv (repeatable)")]
+        objects_extra: Vec<PathBuf>,
+
+        #[arg(long, help = "Path to map directory with .sec files")]
+        map_path: PathBuf,
+
+        #[arg(short, long, default_value = "0-15", help = "Floors to scan (e.g. 7, 0-15 or 0,7,9-11)")]
+        floors: String,
+
+        #[arg(short, long, help = "Also write the findings as JSON to this file")]
+        output: Option<PathBuf>,
+    },
+
+    #[command(about = "Check objects.srv, sector files, monster.db and quest names for problems; exits 2 on warnings, 3 on errors")]
+    Validate {
+        #[arg(long, help = "Path to objects.srv file")]
+        objects_path: PathBuf,
+
+        #[arg(long, help = "Additional .srv file merged over objects.srv (repeatable)")]
+        objects_extra: Vec<PathBuf>,
+
+        #[arg(long, help = "Path to map directory with .sec files")]
+        map_path: PathBuf,
+
+        #[arg(short, long, default_value = "0-15", help = "Floors to scan (e.g. 7, 0-15 or 0,7,9-11)")]
+        floors: String,
+
+        #[arg(long, help = "Path to monster.db file")]
+        monster_db: Option<PathBuf>,
+
+        #[arg(long, help = "Path to directory with .mon files for monster names")]
+        mon_path: Option<PathBuf>,
+
+        #[arg(long, help = "Path to quest_overview.csv file")]
+        quest_csv: Option<PathBuf>,
+
+        #[arg(long, value_name = "DIR", help = "Game data directory (dat/monster.db, mon/, quest_overview.csv) merged over earlier ones (repeatable)")]
+        data_path: Vec<PathBuf>,
+
+        #[arg(long, default_value = "auto", help = "Encoding of the game data files: auto, utf8 or latin1")]
+        encoding: InputEncoding,
+
+        #[arg(short, long, help = "Also write the diagnostics as JSON to this file")]
+        output: Option<PathBuf>,
+    },
+
+    #[command(about = "Compare the maps of two game data versions and list the sectors and tiles that changed")]
+    Diff {
+        #[arg(help = "Old game data directory (containing map/) or map directory")]
+        old_path: PathBuf,
+
+        #[arg(help = "New game data directory (containing map/) or map directory")]
+        new_path: PathBuf,
+
+        #[arg(short, long, default_value = "0-15", help = "Floors to compare (e.g. 7, 0-15 or 0,7,9-11)")]
+        floors: String,
+
+        #[arg(short, long, help = "Also write the changed sectors and tiles as JSON to this file")]
+        output: Option<PathBuf>,
+
+        #[arg(long, value_name = "DIR", help = "Write diff.json into this build output directory to show the changes as a viewer overlay")]
+        overlay: Option<PathBuf>,
+    },
+
+    #[command(about = "Write the per-object colors computed from sprites to an editable TOML or JSON file")]
+    ExportColors {
+        #[arg(long, help = "Path to objects.srv file")]
+        objects_path: PathBuf,
+
+        #[arg(long, help = "Additional .srv file merged over objects.srv (repeatable)")]
+        objects_extra: Vec<PathBuf>,
+
+        #[arg(long, help = "Path to sprite PNG directory, or a sprite sheet index JSON")]
+        sprite_path: PathBuf,
+
+        #[arg(short, long, default_value = "colors.json", help = "Output JSON file, or TOML if it ends in .toml (object id -> #rrggbb)")]
+        output: PathBuf,
+    },
+
+    #[command(about = "Render a whole floor as one stitched PNG, for wikis or printing")]
+    RenderFloor {
+        #[arg(long, help = "Path to objects.srv file")]
+        objects_path: PathBuf,
+
+        #[arg(long, help = "Additional .srv file merged over objects.srv (repeatable)")]
+        objects_extra: Vec<PathBuf>,
+
+        #[arg(long, help = "Path to map directory with .sec files")]
+        map_path: PathBuf,
+
+        #[arg(long, help = "Path to sprite PNG directory, or a sprite sheet index JSON")]
+        sprite_path: PathBuf,
+
+        #[arg(long, help = "TOML or JSON file of rules that put objects on a drawing layer by ID, flags or name pattern")]
+        layer_rules: Option<PathBuf>,
+
+        #[arg(short, long, help = "Floor to render (0-15)")]
+        floor: u8,
+
+        #[arg(long, default_value = "2", help = "Zoom level to render at: 2^zoom pixels per game tile, 5 for full-size sprites")]
+        zoom: u8,
+
+        #[arg(long, value_name = "PIXELS", help = "Downscale so the longer side is at most this many pixels")]
+        max_size: Option<u32>,
+
+        #[arg(short, long, help = "Output PNG file (default: floor-<floor>.png)")]
+        output: Option<PathBuf>,
+    },
+
+    #[command(about = "Check a build output against its tile manifest and overlay schemas")]
+    Verify {
+        #[arg(help = "Output directory of a build")]
+        output_dir: PathBuf,
+    },
+
+    #[command(about = "Render a built-in test map and compare it against golden tiles")]
+    SelfTest {
+        #[arg(long, default_value = "2", help = "Largest per-channel difference (0-255) a pixel may have")]
+        tolerance: u8,
+
+        #[arg(long, value_name = "DIR", help = "Write the current rendering as golden images instead of comparing")]
+        write_golden: Option<PathBuf>,
+    },
+
+    #[command(about = "Print the build metadata embedded in a generated tile PNG")]
+    TileInfo {
+        #[arg(help = "Path to a tile PNG")]
+        tile: PathBuf,
+    },
+
+    #[command(about = "Report duplicate and heavily overlapping spawns in monster.db")]
+    CheckSpawns {
+        #[arg(long, help = "Path to monster.db file")]
+        monster_db: PathBuf,
+
+        #[arg(long, help = "Path to directory with .mon files for monster names")]
+        mon_path: Option<PathBuf>,
+
+        #[arg(long, default_value = "0.8", help = "Minimum shared area (0-1 of the smaller spawn) to report")]
+        min_overlap: f64,
+
+        #[arg(short, long, help = "Also write the report to this file (.json or .csv)")]
+        output: Option<PathBuf>,
+    },
+
+    #[command(about = "Write a per-cell monster density CSV for comparing hunting grounds")]
+    SpawnDensity {
+        #[arg(long, help = "Path to monster.db file")]
+        monster_db: PathBuf,
+
+        #[arg(long, help = "Path to directory with .mon files for monster names")]
+        mon_path: Option<PathBuf>,
+
+        #[arg(long, default_value = "100", help = "Cell width and height in tiles")]
+        cell_size: u32,
+
+        #[arg(short, long, default_value = "spawn-density.csv", help = "Output CSV file")]
+        output: PathBuf,
+    },
+
+    #[command(about = "Convert a server death log into a heatmap CSV for --heatmap-csv")]
+    ImportDeaths {
+        #[arg(long, help = "Path to the death log file")]
+        log_path: PathBuf,
+
+        #[arg(long, help = "Only include deaths on or after this date (YYYY-MM-DD)")]
+        since: Option<String>,
+
+        #[arg(long, help = "Only include deaths on or before this date (YYYY-MM-DD)")]
+        until: Option<String>,
+
+        #[arg(long, help = "Only include deaths caused by this monster (repeatable)")]
+        monster: Vec<String>,
+
+        #[arg(short, long, default_value = "deaths.csv", help = "Output heatmap CSV file")]
+        output: PathBuf,
+    },
+
+    #[command(about = "Serve a build output over HTTP to view the map locally")]
+    Serve {
+        #[arg(default_value = "output", help = "Output directory of a build")]
+        output_dir: PathBuf,
+
+        #[arg(long, default_value = "127.0.0.1", help = "Address to listen on (0.0.0.0 for other machines)")]
+        host: String,
+
+        #[arg(short, long, default_value = "8000", help = "Port to listen on")]
+        port: u16,
+
+        #[arg(long, help = "Open the map in the default browser once the server is up")]
+        open: bool,
+
+        #[arg(long, value_name = "FILE", help = "Render missing map tiles on demand from the inputs of this build config (see init-config)")]
+        render_config: Option<PathBuf>,
+
+        #[arg(long, default_value = "2", requires = "render_config", help = "Rings of neighbouring tiles pre-rendered around each requested tile")]
+        prefetch_radius: u32,
+
+        #[arg(long, value_name = "TOKEN", help = "Accept online player positions POSTed to /players with this bearer token and stream them to maps built with --live-players")]
+        players_token: Option<String>,
+    },
+
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    #[command(about = "Show cached objects and floor data with size and memory estimates")]
+    Status {
+        #[arg(long, value_name = "DIR", default_value = DEFAULT_CACHE_DIR)]
+        cache_dir: PathBuf,
+    },
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+fn main() -> Result<ExitCode> {
+    // Kept to tell flags given on the command line from defaults for --config
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
     let filter = match cli.verbose {
         0 => "warn",
@@ -82,19 +550,60 @@ fn main() -> Result<()> {
         _ => "trace",
     };
 
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_writer(std::io::stderr)
+    let warnings = WarningCollector::default();
+
+    // The trace file must record spans even when the console only shows warnings,
+    // so each layer gets its own filter instead of a global one.
+    let (chrome_layer, _chrome_guard) = match &cli.trace_out {
+        Some(path) => {
+            let (layer, guard) = ChromeLayerBuilder::new()
+                .file(path)
+                .include_args(true)
+                .build();
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(EnvFilter::new(filter))
+                .with_filter(filter_fn(|metadata| !WarningCollector::is_grouped(metadata))),
+        )
+        .with(warnings.clone().with_filter(LevelFilter::WARN))
+        .with(chrome_layer)
         .init();
+    let _pending_warnings = PendingWarnings(warnings.clone());
 
+    // Returned rather than exited with, so the guards above still flush
+    let mut exit_code = ExitCode::SUCCESS;
     match cli.command {
         Commands::ParseObjects { input, output } => {
             cmd_parse_objects(input, output)?;
         }
         Commands::Build {
+            profile,
+            project,
+            config: config_file,
             objects_path,
+            objects_extra,
             map_path,
             sprite_path,
+            sprite_overrides,
+            substitute_sprites,
+            strict,
+            sprite_cache_mb,
+            sprite_frames,
+            layer_rules,
+            mode,
+            color_map,
+            tile_format,
+            pyramid,
+            pmtiles,
+            output_archive,
+            composite_brightness,
             output,
             floors,
             min_zoom,
@@ -102,369 +611,1846 @@ fn main() -> Result<()> {
             monster_db,
             mon_path,
             monster_sprites,
+            client_spr,
+            client_dat,
             npc_csv,
             npc_sprites,
             quest_csv,
+            raids_path,
+            data_path,
+            encoding,
+            prices_csv,
+            heatmap_csv,
+            heatmap_radius,
+            houses_status,
+            house_names,
+            live_players,
+            overlay_refresh,
+            admin,
+            admin_command,
+            archive,
+            marker_style,
+            overlays,
+            sectors,
+            region,
+            incremental,
+            cache_dir,
+            force,
+            extra_assets,
+            offline,
+            open,
             threads,
         } => {
-            cmd_build(
-                objects_path,
-                map_path,
-                sprite_path,
-                output,
-                floors,
-                min_zoom,
-                max_zoom,
-                monster_db,
-                mon_path,
-                monster_sprites,
-                npc_csv,
-                npc_sprites,
-                quest_csv,
-                threads,
+            let config = if let Some(profile) = profile {
+                Project::load(&project)?.profile(&profile)?
+            } else {
+                let mut marker_styles = MarkerStyles::default();
+                for spec in &marker_style {
+                    marker_styles.set(spec)?;
+                }
+
+                // Clap requires the inputs unless a config file provides them
+                let flags = BuildConfig {
+                    objects_path: objects_path.unwrap_or_default(),
+                    objects_extra,
+                    map_path: map_path.unwrap_or_default(),
+                    sprite_path,
+                    sprite_overrides,
+                    substitute_sprites,
+                    strict,
+                    sprite_cache_mb,
+                    sprite_frames,
+                    layer_rules,
+                    render_mode: mode,
+                    color_map,
+                    tile_format,
+                    pyramid,
+                    pmtiles,
+                    output_archive,
+                    composite_brightness,
+                    output,
+                    floors: floors.as_deref().map(parse_floor_range).transpose()?.unwrap_or_default(),
+                    min_zoom,
+                    max_zoom,
+                    monster_db,
+                    mon_path,
+                    monster_sprites,
+                    client_spr,
+                    client_dat,
+                    npc_csv,
+                    npc_sprites,
+                    quest_csv,
+                    raids_path,
+                    data_paths: data_path,
+                    encoding,
+                    prices_csv,
+                    heatmap_csv,
+                    heatmap_radius,
+                    houses_status,
+                    house_names,
+                    live_players,
+                    overlay_refresh_minutes: overlay_refresh,
+                    admin_command: admin.then_some(admin_command),
+                    marker_styles,
+                    overlays: parse_overlay_list(&overlays)?,
+                    archive,
+                    sectors: sectors.as_deref().map(parse_sector_list).transpose()?.unwrap_or_default(),
+                    region,
+                    incremental,
+                    cache_dir,
+                    force,
+                    extra_assets,
+                    offline,
+                    threads,
+                };
+
+                match config_file {
+                    Some(path) => {
+                        let build_matches = matches.subcommand_matches("build").context("build arguments missing")?;
+                        let given: Vec<&str> = BUILD_CONFIG_ARGS
+                            .iter()
+                            .filter(|id| build_matches.value_source(id) == Some(ValueSource::CommandLine))
+                            .map(|id| config_key(id))
+                            .collect();
+                        load_build_config(&path, &flags, &given)?
+                    }
+                    None => flags,
+                }
+            };
+            config.validate()?;
+            cmd_build(&config, &warnings)?;
+
+            if open {
+                open_viewer(&config.output.join("index.html"));
+            }
+        }
+        Commands::InitConfig { output, force } => {
+            cmd_init_config(&output, force)?;
+        }
+        Commands::ExportTexts {
+            objects_path,
+            objects_extra,
+            map_path,
+            floors,
+            output,
+        } => {
+            cmd_export_texts(
+                &objects_path,
+                &objects_extra,
+                &map_path,
+                &parse_floor_range(&floors)?,
+                &output,
+            )?;
+        }
+        Commands::Lint {
+            objects_path,
+            objects_extra,
+            map_path,
+            floors,
+            output,
+        } => {
+            cmd_lint(
+                &objects_path,
+                &objects_extra,
+                &map_path,
+                &parse_floor_range(&floors)?,
+                output.as_deref(),
+            )?;
+        }
+        Commands::Validate {
+            objects_path,
+            objects_extra,
+            map_path,
+            floors,
+            monster_db,
+            mon_path,
+            quest_csv,
+            data_path,
+            encoding,
+            output,
+        } => {
+            let data_sources =
+                DataSources::collect(monster_db.as_deref(), mon_path.as_deref(), quest_csv.as_deref(), None, &data_path, encoding);
+            let diagnostics = cmd_validate(
+                &objects_path,
+                &objects_extra,
+                &map_path,
+                &parse_floor_range(&floors)?,
+                &data_sources,
+                output.as_deref(),
+            )?;
+            match diagnostics.iter().map(|d| d.severity).max() {
+                Some(Severity::Error) => exit_code = ExitCode::from(3),
+                Some(Severity::Warning) => exit_code = ExitCode::from(2),
+                None => {}
+            }
+        }
+        Commands::Diff {
+            old_path,
+            new_path,
+            floors,
+            output,
+            overlay,
+        } => {
+            cmd_diff(&old_path, &new_path, &parse_floor_range(&floors)?, output.as_deref(), overlay.as_deref())?;
+        }
+        Commands::ExportColors {
+            objects_path,
+            objects_extra,
+            sprite_path,
+            output,
+        } => {
+            cmd_export_colors(&objects_path, &objects_extra, &sprite_path, &output)?;
+        }
+        Commands::RenderFloor {
+            objects_path,
+            objects_extra,
+            map_path,
+            sprite_path,
+            layer_rules,
+            floor,
+            zoom,
+            max_size,
+            output,
+        } => {
+            let output = output.unwrap_or_else(|| PathBuf::from(format!("floor-{}.png", floor)));
+            cmd_render_floor(
+                &objects_path,
+                &objects_extra,
+                &map_path,
+                &sprite_path,
+                layer_rules.as_deref(),
+                floor,
+                zoom,
+                max_size,
+                &output,
             )?;
         }
+        Commands::Verify { output_dir } => {
+            cmd_verify(&output_dir)?;
+        }
+        Commands::SelfTest { tolerance, write_golden } => {
+            cmd_self_test(tolerance, write_golden.as_deref())?;
+        }
+        Commands::TileInfo { tile } => {
+            cmd_tile_info(&tile)?;
+        }
+        Commands::CheckSpawns {
+            monster_db,
+            mon_path,
+            min_overlap,
+            output,
+        } => {
+            cmd_check_spawns(&monster_db, mon_path.as_deref(), min_overlap, output.as_deref())?;
+        }
+        Commands::SpawnDensity {
+            monster_db,
+            mon_path,
+            cell_size,
+            output,
+        } => {
+            cmd_spawn_density(&monster_db, mon_path.as_deref(), cell_size, &output)?;
+        }
+        Commands::ImportDeaths {
+            log_path,
+            since,
+            until,
+            monster,
+            output,
+        } => {
+            let filter = DeathFilter {
+                since,
+                until,
+                killers: monster,
+            };
+            cmd_import_deaths(&log_path, &filter, &output)?;
+        }
+        Commands::Serve {
+            output_dir,
+            host,
+            port,
+            open,
+            render_config,
+            prefetch_radius,
+            players_token,
+        } => {
+            let prefetcher = render_config
+                .map(|path| open_prefetcher(&path, &output_dir, prefetch_radius))
+                .transpose()?;
+            let players = players_token.map(|token| serve::LivePlayers {
+                feed: Arc::new(PlayerFeed::new()),
+                token,
+            });
+            serve::serve(&output_dir, &host, port, prefetcher.as_ref(), players.as_ref(), |url| {
+                println!("Serving {:?} at {} (Ctrl+C to stop)", output_dir, url);
+                if open && let Err(e) = open::that_detached(url) {
+                    tracing::warn!("Failed to open {} in a browser: {}", url, e);
+                }
+            })?;
+        }
+        Commands::Cache { action } => match action {
+            CacheCommands::Status { cache_dir } => cmd_cache_status(&cache_dir)?,
+        },
+    }
+
+    Ok(exit_code)
+}
+
+fn cmd_parse_objects(input: PathBuf, output: PathBuf) -> Result<()> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Parsing objects.srv...");
+
+    let objects = parse_objects(&input, InputEncoding::Auto)?;
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&output, serde_json::to_string_pretty(&objects)?)?;
+
+    pb.finish_with_message(format!("Parsed {} objects → {:?}", objects.len(), output));
+    Ok(())
+}
+
+fn cmd_init_config(output: &std::path::Path, force: bool) -> Result<()> {
+    if output.exists() && !force {
+        anyhow::bail!("{:?} already exists; pass --force to overwrite it", output);
+    }
+
+    fs::write(output, CONFIG_TEMPLATE).with_context(|| format!("Failed to write {:?}", output))?;
+    println!("Wrote {:?}; edit the paths, then run: demonax-mapper build --config {}", output, output.display());
+    Ok(())
+}
+
+fn cmd_export_texts(
+    objects_path: &std::path::Path,
+    objects_extra: &[PathBuf],
+    map_path: &std::path::Path,
+    floors: &[u8],
+    output: &std::path::Path,
+) -> Result<()> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Parsing objects.srv...");
+    let mut objects = parse_objects(objects_path, InputEncoding::Auto)?;
+    merge_extra_objects(&mut objects, objects_extra, InputEncoding::Auto)?;
+
+    pb.set_message("Scanning sectors for readables...");
+    let readables = parse_readables_from_sectors(map_path, floors, &objects, InputEncoding::Auto)?;
+
+    let is_csv = output.extension().and_then(|e| e.to_str()) == Some("csv");
+    let contents = if is_csv {
+        generate_readables_csv(&readables)
+    } else {
+        generate_readables_json(&readables)?
+    };
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output, contents)?;
+
+    pb.finish_with_message(format!("Exported {} readables → {:?}", readables.len(), output));
+    Ok(())
+}
+
+fn cmd_lint(
+    objects_path: &std::path::Path,
+    objects_extra: &[PathBuf],
+    map_path: &std::path::Path,
+    floors: &[u8],
+    output: Option<&std::path::Path>,
+) -> Result<()> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Parsing objects.srv...");
+    let mut objects = parse_objects(objects_path, InputEncoding::Auto)?;
+    merge_extra_objects(&mut objects, objects_extra, InputEncoding::Auto)?;
+
+    pb.set_message("Scanning sectors for unknown object IDs...");
+    let unknown = find_unknown_object_ids(map_path, floors, &objects, InputEncoding::Auto)?;
+    pb.finish_and_clear();
+
+    if let Some(output) = output {
+        fs::write(output, serde_json::to_string_pretty(&unknown)?)?;
+    }
+
+    if unknown.is_empty() {
+        println!("✓ No unknown object IDs");
+        return Ok(());
+    }
+
+    println!("{:<20} {:>8} {:>6}  First occurrence", "Sector", "ID", "Count");
+    for u in &unknown {
+        println!(
+            "{:<20} {:>8} {:>6}  {},{},{} (line {})",
+            u.sector, u.object_id, u.count, u.first_x, u.first_y, u.z, u.first_line
+        );
+    }
+
+    let sectors = unknown.iter().map(|u| u.sector.as_str()).collect::<HashSet<_>>();
+    anyhow::bail!(
+        "{} unknown object IDs in {} sectors ({} uses)",
+        unknown.len(),
+        sectors.len(),
+        unknown.iter().map(|u| u.count).sum::<usize>()
+    )
+}
+
+fn cmd_validate(
+    objects_path: &std::path::Path,
+    objects_extra: &[PathBuf],
+    map_path: &std::path::Path,
+    floors: &[u8],
+    data_sources: &DataSources,
+    output: Option<&std::path::Path>,
+) -> Result<Vec<Diagnostic>> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Parsing objects.srv...");
+    let mut objects = parse_objects(objects_path, data_sources.encoding)?;
+    merge_extra_objects(&mut objects, objects_extra, data_sources.encoding)?;
+    let mut diagnostics = validate_objects(&objects);
+
+    pb.set_message("Checking sector files...");
+    diagnostics.extend(validate_sectors(map_path, floors, &objects, data_sources.encoding)?);
+
+    if data_sources.has_spawns() {
+        pb.set_message("Checking monster spawns...");
+        let spawns = data_sources.spawns()?;
+        let monster_names = data_sources.monster_names()?;
+        let monster_db = data_sources.monster_dbs.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ");
+        diagnostics.extend(validate_spawn_races(&spawns, &monster_names, &monster_db));
+    }
+
+    if !data_sources.quest_csvs.is_empty() {
+        pb.set_message("Checking quest names...");
+        let chests = parse_questchests_from_sectors(map_path, floors, &data_sources.quest_names()?, data_sources.encoding)?;
+        diagnostics.extend(validate_quest_names(&chests));
+    }
+    pb.finish_and_clear();
+
+    if let Some(output) = output {
+        fs::write(output, serde_json::to_string_pretty(&diagnostics)?)?;
+    }
+
+    for d in &diagnostics {
+        let location = match (&d.file, d.line) {
+            (Some(file), Some(line)) => format!("{}:{}", file, line),
+            (Some(file), None) => file.clone(),
+            (None, _) => String::new(),
+        };
+        let severity = match d.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        println!("{:<8} {:<28} {}", severity, location, d.message);
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+    let warnings = diagnostics.len() - errors;
+    if diagnostics.is_empty() {
+        println!("✓ No problems found");
+    } else {
+        println!("{} errors, {} warnings", errors, warnings);
+    }
+    Ok(diagnostics)
+}
+
+/// The map directory of a game data directory, or `path` itself when it
+/// has no `map` folder and so is taken to be one.
+fn game_map_dir(path: &std::path::Path) -> PathBuf {
+    let map_dir = path.join("map");
+    if map_dir.is_dir() { map_dir } else { path.to_path_buf() }
+}
+
+fn cmd_diff(
+    old_path: &std::path::Path,
+    new_path: &std::path::Path,
+    floors: &[u8],
+    output: Option<&std::path::Path>,
+    overlay: Option<&std::path::Path>,
+) -> Result<()> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Comparing sector files...");
+    let diff = diff_maps(game_map_dir(old_path), game_map_dir(new_path), floors, InputEncoding::Auto)?;
+    pb.finish_and_clear();
+
+    if let Some(output) = output {
+        fs::write(output, serde_json::to_string_pretty(&diff)?)?;
+    }
+    if let Some(overlay) = overlay {
+        if !overlay.join("index.html").is_file() {
+            anyhow::bail!("{:?} is not a build output directory (no index.html)", overlay);
+        }
+        fs::write(overlay.join("diff.json"), generate_diff_json(&diff)?)?;
+    }
+
+    for sector in &diff.sectors {
+        println!("{:<8} {:<20} {} tiles", sector.kind.as_str(), sector.file, sector.tiles);
+    }
+
+    if diff.sectors.is_empty() {
+        println!("✓ No changes");
+    } else {
+        let count = |kind: ChangeKind| diff.tiles.iter().filter(|t| t.kind == kind).count();
+        println!(
+            "{} sectors changed: {} tiles added, {} removed, {} changed",
+            diff.sectors.len(),
+            count(ChangeKind::Added),
+            count(ChangeKind::Removed),
+            count(ChangeKind::Changed)
+        );
+    }
+    Ok(())
+}
+
+fn cmd_export_colors(
+    objects_path: &std::path::Path,
+    objects_extra: &[PathBuf],
+    sprite_path: &std::path::Path,
+    output: &std::path::Path,
+) -> Result<()> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Parsing objects.srv...");
+    let mut objects = parse_objects(objects_path, InputEncoding::Auto)?;
+    merge_extra_objects(&mut objects, objects_extra, InputEncoding::Auto)?;
+
+    pb.set_message("Averaging sprite colors...");
+    let sprite_cache = SpriteCache::new(sprite_path)?;
+    let colors = create_color_map(&objects, &sprite_cache)?;
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    save_color_map(&colors, output)?;
+    pb.finish_with_message(format!("Wrote colors for {} objects to {:?}", colors.len(), output));
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cmd_render_floor(
+    objects_path: &std::path::Path,
+    objects_extra: &[PathBuf],
+    map_path: &std::path::Path,
+    sprite_path: &std::path::Path,
+    layer_rules: Option<&std::path::Path>,
+    floor: u8,
+    zoom: u8,
+    max_size: Option<u32>,
+    output: &std::path::Path,
+) -> Result<()> {
+    if floor > MAX_FLOOR {
+        anyhow::bail!("Floor must be 0-{}, got {}", MAX_FLOOR, floor);
+    }
+
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Parsing objects.srv...");
+    let mut objects = parse_objects(objects_path, InputEncoding::Auto)?;
+    merge_extra_objects(&mut objects, objects_extra, InputEncoding::Auto)?;
+    apply_layer_rules(&mut objects, &load_layer_rules(layer_rules)?);
+
+    pb.set_message(format!("Parsing floor {}...", floor));
+    let bounds = WorldBounds::scan(map_path, &[floor], &[])?;
+    let map_data = parse_sprite_map(map_path, floor, &bounds, &[], InputEncoding::Auto)?;
+    let sprite_cache = SpriteCache::new(sprite_path)?;
+    pb.finish_and_clear();
+
+    let (num_tiles_x, num_tiles_y) = tile_grid_size(&map_data, zoom);
+    let pb = ProgressBar::new(num_tiles_x as u64 * num_tiles_y as u64);
+    pb.set_style(ProgressStyle::default_bar().template("{spinner} {msg} [{bar:30}] {pos}/{len} tiles, ETA {eta}")?);
+    pb.set_message(format!("Rendering floor {} at zoom {}", floor, zoom));
+    let progress = |_: RenderProgress| pb.inc(1);
+    let image = render_floor_image(&map_data, &sprite_cache, &objects, zoom, Some(&progress))?;
+    pb.finish_and_clear();
+
+    let image = match max_size {
+        Some(max_size) => downscale_to_fit(image, max_size),
+        None => image,
+    };
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    image.save(output).with_context(|| format!("Failed to write {:?}", output))?;
+
+    println!(
+        "✓ Floor {}: {}x{} pixels ({}) written to {:?}",
+        floor,
+        image.width(),
+        image.height(),
+        format_bytes(fs::metadata(output)?.len()),
+        output
+    );
+    Ok(())
+}
+
+fn cmd_verify(output_dir: &std::path::Path) -> Result<()> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Checking tiles against the manifest...");
+    let manifest = TileManifest::load(output_dir)?;
+    let tile_issues = verify_tile_manifest(output_dir, &manifest)?;
+
+    pb.set_message("Validating overlay JSON...");
+    let schema_issues = validate_overlays(output_dir);
+    pb.finish_and_clear();
+
+    for issue in &tile_issues {
+        println!("{:<16} {}", issue.problem.as_str(), issue.tile);
+    }
+    for issue in &schema_issues {
+        println!("{:<16} {}{}: {}", "schema", issue.file, issue.path, issue.problem);
+    }
+
+    if tile_issues.is_empty() && schema_issues.is_empty() {
+        println!(
+            "✓ {} tiles and overlay JSON verified ({})",
+            manifest.tiles.len(),
+            manifest.generator
+        );
+        return Ok(());
+    }
+
+    anyhow::bail!(
+        "{} of {} tiles and {} overlay fields failed verification",
+        tile_issues.len(),
+        manifest.tiles.len(),
+        schema_issues.len()
+    )
+}
+
+fn cmd_self_test(tolerance: u8, write_golden: Option<&std::path::Path>) -> Result<()> {
+    if let Some(dir) = write_golden {
+        for path in write_self_test_golden(dir)? {
+            println!("Wrote {}", path.display());
+        }
+        return Ok(());
+    }
+
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Rendering the self-test map...");
+    let report = run_self_test(tolerance)?;
+    pb.finish_and_clear();
+
+    for mismatch in &report.mismatches {
+        println!(
+            "{:<16} {} pixels differ, by up to {}",
+            mismatch.key, mismatch.differing_pixels, mismatch.max_difference
+        );
+    }
+
+    if report.passed() {
+        println!("✓ {} tiles match the golden images", report.tiles);
+        return Ok(());
+    }
+
+    anyhow::bail!(
+        "{} of {} tiles differ from the golden images",
+        report.mismatches.len(),
+        report.tiles
+    )
+}
+
+fn cmd_tile_info(tile: &std::path::Path) -> Result<()> {
+    let chunks = read_tile_metadata(tile)?;
+    if !chunks.iter().any(|(keyword, _)| keyword.starts_with("Demonax-")) {
+        anyhow::bail!("{:?} carries no demonax-mapper metadata", tile);
+    }
+
+    for (keyword, text) in &chunks {
+        println!("{:<16} {}", keyword, text);
+    }
+    Ok(())
+}
+
+fn cmd_check_spawns(
+    monster_db: &std::path::Path,
+    mon_path: Option<&std::path::Path>,
+    min_overlap: f64,
+    output: Option<&std::path::Path>,
+) -> Result<()> {
+    if !(0.0..=1.0).contains(&min_overlap) {
+        anyhow::bail!("--min-overlap must be between 0 and 1, got {}", min_overlap);
+    }
+
+    let spawns = parse_monster_db(monster_db, InputEncoding::Auto)?;
+    let monster_names = match mon_path {
+        Some(mon_dir) => parse_monster_names(mon_dir, InputEncoding::Auto)?,
+        None => Default::default(),
+    };
+
+    let issues = find_spawn_issues(&spawns, min_overlap);
+
+    if let Some(output) = output {
+        let is_csv = output.extension().and_then(|e| e.to_str()) == Some("csv");
+        let contents = if is_csv {
+            generate_spawn_issues_csv(&issues, &monster_names)
+        } else {
+            serde_json::to_string_pretty(&issues)?
+        };
+        fs::write(output, contents)?;
+    }
+
+    if issues.is_empty() {
+        println!("✓ No duplicate or overlapping spawns in {} entries", spawns.len());
+        return Ok(());
+    }
+
+    println!("{:<10} {:>7}  {:<24} {:<28} {:<28}", "Kind", "Overlap", "Monster", "First", "Second");
+    for issue in &issues {
+        let (a, b) = (&issue.first, &issue.second);
+        let monster = monster_names
+            .get(&a.race)
+            .cloned()
+            .unwrap_or_else(|| format!("race {}", a.race));
+        let describe = |s: &MonsterSpawn| format!("{},{},{} r{} (line {})", s.x, s.y, s.z, s.radius, s.line);
+
+        println!(
+            "{:<10} {:>6.0}%  {:<24} {:<28} {:<28}",
+            issue.kind.as_str(),
+            issue.overlap * 100.0,
+            monster,
+            describe(a),
+            describe(b)
+        );
+    }
+
+    let duplicates = issues.iter().filter(|i| i.kind == SpawnIssueKind::Duplicate).count();
+    println!();
+    println!(
+        "{} duplicates, {} overlaps in {} spawns",
+        duplicates,
+        issues.len() - duplicates,
+        spawns.len()
+    );
+    Ok(())
+}
+
+/// Opening the viewer is a convenience, so failures only warn.
+fn open_viewer(index: &std::path::Path) {
+    let path = index.canonicalize().unwrap_or_else(|_| index.to_path_buf());
+    match open::that_detached(&path) {
+        Ok(()) => println!("Opened {:?} (overlays need `demonax-mapper serve`)", path),
+        Err(e) => tracing::warn!("Failed to open {:?} in a browser: {}", path, e),
+    }
+}
+
+fn cmd_spawn_density(
+    monster_db: &std::path::Path,
+    mon_path: Option<&std::path::Path>,
+    cell_size: u32,
+    output: &std::path::Path,
+) -> Result<()> {
+    if cell_size == 0 {
+        anyhow::bail!("--cell-size must be at least 1");
+    }
+
+    let spawns = parse_monster_db(monster_db, InputEncoding::Auto)?;
+    let monster_names = match mon_path {
+        Some(mon_dir) => parse_monster_names(mon_dir, InputEncoding::Auto)?,
+        None => Default::default(),
+    };
+
+    let cells = spawn_density(&spawns, cell_size);
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output, generate_spawn_density_csv(&cells, cell_size, &monster_names))?;
+
+    let floors: HashSet<u8> = cells.iter().map(|c| c.z).collect();
+    println!(
+        "✓ Wrote {} cells on {} floors from {} spawns to {:?}",
+        cells.len(),
+        floors.len(),
+        spawns.len(),
+        output
+    );
+    Ok(())
+}
+
+fn cmd_import_deaths(log_path: &std::path::Path, filter: &DeathFilter, output: &std::path::Path) -> Result<()> {
+    for date in filter.since.iter().chain(&filter.until) {
+        if !is_iso_date(date) {
+            anyhow::bail!("Invalid date {:?}, expected YYYY-MM-DD", date);
+        }
+    }
+
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Parsing death log...");
+    let deaths = parse_death_log(log_path, InputEncoding::Auto)?;
+
+    let kept: Vec<DeathRecord> = deaths.iter().filter(|d| filter.matches(d)).cloned().collect();
+    let points = deaths_to_heat_points(&kept);
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output, generate_heatmap_csv(&points))?;
+
+    pb.finish_with_message(format!(
+        "Imported {} of {} deaths on {} tiles → {:?}",
+        kept.len(),
+        deaths.len(),
+        points.len(),
+        output
+    ));
+    Ok(())
+}
+
+/// The sprite cache a build with `config` draws from, or `None` for color
+/// builds given a color map, which never touch sprites.
+fn open_sprite_cache(config: &BuildConfig) -> Result<Option<SpriteCache>> {
+    let sprite_source = match (&config.sprite_path, &config.client_spr, &config.client_dat) {
+        (Some(sprite_path), _, _) => open_sprite_source(sprite_path)?,
+        (None, Some(spr), Some(dat)) => Box::new(ClientSprites::open(spr, dat)?) as Box<dyn SpriteSource>,
+        _ => return Ok(None),
+    };
+
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Initializing sprite cache...");
+    let mut sprite_cache = SpriteCache::from_source(sprite_source)
+        .with_substitutes(config.substitute_sprites)
+        .with_frames(config.sprite_frames);
+    if let Some(mb) = config.sprite_cache_mb {
+        sprite_cache = sprite_cache.with_memory_limit(mb * 1024 * 1024);
+    }
+    match &config.sprite_overrides {
+        Some(path) => {
+            let overrides = SpriteOverrides::open(path)?;
+            pb.finish_with_message(format!("Sprite cache initialized ({} overrides)", overrides.len()));
+            sprite_cache = sprite_cache.with_overrides(overrides);
+        }
+        None => pb.finish_with_message("Sprite cache initialized"),
     }
-
-    Ok(())
+    Ok(Some(sprite_cache))
 }
 
-fn cmd_parse_objects(input: PathBuf, output: PathBuf) -> Result<()> {
+/// Loads the inputs of the build config at `config_path` for `serve` to
+/// render the sprite tiles missing from `output_dir` as they are requested.
+fn open_prefetcher(config_path: &std::path::Path, output_dir: &std::path::Path, radius: u32) -> Result<ViewportPrefetcher> {
+    let config = read_build_config(config_path)?;
+    config.validate()?;
+    if config.render_mode != RenderMode::Sprites || config.pmtiles.is_some() || config.output_archive.is_some() {
+        anyhow::bail!("On-demand rendering needs a sprite build written as plain tile files");
+    }
+
     let pb = ProgressBar::new_spinner();
     pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
     pb.set_message("Parsing objects.srv...");
-
-    let objects = parse_objects(&input)?;
-
-    if let Some(parent) = output.parent() {
-        fs::create_dir_all(parent)?;
+    let mut objects = parse_objects(&config.objects_path, config.encoding)?;
+    merge_extra_objects(&mut objects, &config.objects_extra, config.encoding)?;
+    apply_layer_rules(&mut objects, &load_layer_rules(config.layer_rules.as_deref())?);
+
+    // The same bounds as the build, so rendered tiles line up with its viewer
+    let bounds = WorldBounds::scan(&config.map_path, &config.floors, &config.sectors)?;
+    let mut floors = Vec::with_capacity(config.floors.len());
+    for &floor in &config.floors {
+        pb.set_message(format!("Parsing floor {}...", floor));
+        floors.push(parse_sprite_map(&config.map_path, floor, &bounds, &config.sectors, config.encoding)?);
     }
-    fs::write(&output, serde_json::to_string_pretty(&objects)?)?;
+    pb.finish_with_message(format!("Loaded {} floors for on-demand rendering", floors.len()));
+
+    let sprite_cache = open_sprite_cache(&config)?.context("On-demand rendering needs sprite_path or client_spr/client_dat")?;
+    let workers = config.threads.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, usize::from));
+    Ok(ViewportPrefetcher::new(
+        floors,
+        sprite_cache,
+        objects,
+        output_dir,
+        config.tile_format,
+        config.min_zoom,
+        config.max_zoom,
+        radius,
+        workers,
+    ))
+}
 
-    pb.finish_with_message(format!("Parsed {} objects → {:?}", objects.len(), output));
+fn merge_extra_objects(objects: &mut ObjectDatabase, extra_paths: &[PathBuf], encoding: InputEncoding) -> Result<()> {
+    for path in extra_paths {
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message(format!("Merging {:?}...", path));
+        let extra = parse_objects(path, encoding)?;
+        let (added, overridden) = merge_objects(objects, extra);
+        pb.finish_with_message(format!(
+            "Merged {:?}: {} added, {} overridden",
+            path, added, overridden
+        ));
+    }
     Ok(())
 }
 
-fn parse_sector_coords_from_filename(filename: &str) -> Option<(u32, u32, u8)> {
-    let name = filename.strip_suffix(".sec")?;
-    let parts: Vec<&str> = name.split('-').collect();
-    if parts.len() != 3 {
-        return None;
+fn cmd_cache_status(cache_dir: &std::path::Path) -> Result<()> {
+    if !cache_dir.exists() {
+        println!("No cache at {:?}", cache_dir);
+        return Ok(());
     }
 
-    let x = parts[0].parse().ok()?;
-    let y = parts[1].parse().ok()?;
-    let z = parts[2].parse().ok()?;
+    let cache_store = CacheStore::open(cache_dir)?;
+    if let Ok(meta) = fs::metadata(cache_store.objects_path()) {
+        println!("objects.json: {}", report::format_bytes(meta.len()));
+    }
 
-    Some((x, y, z))
-}
+    let floor_files = cache_store.floor_files();
 
-fn calculate_global_bounds(
-    map_dir: &std::path::Path,
-    floors: &[u8],
-) -> Result<(u32, u32, u32, u32)> {
-    let mut global_min_x = u32::MAX;
-    let mut global_max_x = 0;
-    let mut global_min_y = u32::MAX;
-    let mut global_max_y = 0;
-
-    for entry in fs::read_dir(map_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
-            if let Some((x, y, z)) = parse_sector_coords_from_filename(filename) {
-                if floors.contains(&z) {
-                    global_min_x = global_min_x.min(x);
-                    global_max_x = global_max_x.max(x);
-                    global_min_y = global_min_y.min(y);
-                    global_max_y = global_max_y.max(y);
-                }
+    println!();
+    println!("{:<28} {:>10} {:>12} {:>12}", "Floor cache", "Tiles", "On disk", "In memory");
+
+    let mut total_disk = 0;
+    let mut peak_memory = 0;
+    for path in &floor_files {
+        let disk = fs::metadata(path)?.len();
+        let map_data = match decode_floor_cache(&fs::read(path)?) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::warn!("Unreadable cache file {:?}: {}", path, e);
+                continue;
             }
-        }
+        };
+        let memory = map_data.estimated_memory_bytes();
+        total_disk += disk;
+        peak_memory = peak_memory.max(memory);
+
+        println!(
+            "{:<28} {:>10} {:>12} {:>12}",
+            path.file_name().and_then(|n| n.to_str()).unwrap_or("?"),
+            map_data.tiles.len(),
+            report::format_bytes(disk),
+            report::format_bytes(memory as u64)
+        );
     }
 
-    if global_min_x == u32::MAX {
-        anyhow::bail!("No map sectors found for specified floors");
-    }
+    println!();
+    println!("Floor caches on disk: {}", report::format_bytes(total_disk));
+    println!("Peak map data in memory (largest floor): {}", report::format_bytes(peak_memory as u64));
 
-    Ok((global_min_x, global_max_x, global_min_y, global_max_y))
+    Ok(())
 }
 
-fn cmd_build(
-    objects_path: PathBuf,
-    map_path: PathBuf,
-    sprite_path: PathBuf,
-    output: PathBuf,
-    floors_str: String,
-    min_zoom: u8,
-    max_zoom: u8,
-    monster_db: Option<PathBuf>,
-    mon_path: Option<PathBuf>,
-    monster_sprites: Option<PathBuf>,
-    npc_csv: Option<PathBuf>,
-    npc_sprites: Option<PathBuf>,
-    quest_csv: Option<PathBuf>,
-    threads: Option<usize>,
-) -> Result<()> {
+fn cmd_build(config: &BuildConfig, warnings: &WarningCollector) -> Result<()> {
+    let mut report = BuildReport::new(warnings.clone());
+    let _build_span = tracing::info_span!("build").entered();
+
     // Configure thread pool if --threads is specified
-    if let Some(num_threads) = threads {
+    if let Some(num_threads) = config.threads {
         rayon::ThreadPoolBuilder::new()
             .num_threads(num_threads)
             .build_global()
             .ok(); // Ignore error if pool already initialized
     }
 
-    // Validate required paths
-    if !objects_path.exists() {
-        anyhow::bail!("Objects file not found: {:?}", objects_path);
+    let BuildConfig {
+        objects_path,
+        objects_extra,
+        map_path,
+        strict,
+        layer_rules,
+        render_mode,
+        color_map,
+        tile_format,
+        pyramid,
+        pmtiles,
+        output_archive,
+        composite_brightness,
+        output,
+        floors,
+        min_zoom,
+        max_zoom,
+        monster_db,
+        mon_path,
+        monster_sprites,
+        client_spr,
+        client_dat,
+        npc_csv,
+        npc_sprites,
+        quest_csv,
+        raids_path,
+        data_paths,
+        encoding,
+        prices_csv,
+        heatmap_csv,
+        heatmap_radius,
+        houses_status,
+        house_names,
+        live_players,
+        overlay_refresh_minutes,
+        admin_command,
+        marker_styles,
+        overlays,
+        archive,
+        sectors,
+        region,
+        incremental,
+        cache_dir,
+        force,
+        extra_assets,
+        offline,
+        ..
+    } = config;
+    let (min_zoom, max_zoom) = (*min_zoom, *max_zoom);
+
+    let cache_store = CacheStore::open(cache_dir)?;
+    if *force {
+        cache_store.clear()?;
+        println!("Cleared cache at {:?}", cache_dir);
     }
-    if !map_path.exists() || !map_path.is_dir() {
-        anyhow::bail!("Map directory not found: {:?}", map_path);
-    }
-    if !sprite_path.exists() || !sprite_path.is_dir() {
-        anyhow::bail!("Sprite directory not found: {:?}", sprite_path);
-    }
-
-    let floors = parse_floor_range(&floors_str)?;
-
-    let cache_dir = PathBuf::from(".demonax-cache");
-    fs::create_dir_all(&cache_dir.join("maps"))?;
-    fs::create_dir_all(&output)?;
+    fs::create_dir_all(output)?;
 
-    let objects_cache_path = cache_dir.join("objects.json");
+    let objects_cache_path = cache_store.objects_path();
 
+    let stage_start = Instant::now();
     if !objects_cache_path.exists() {
         let pb = ProgressBar::new_spinner();
         pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
         pb.set_message("Parsing objects.srv...");
-        let objects = parse_objects(&objects_path)?;
+        let objects = tracing::info_span!("parse_objects").in_scope(|| parse_objects(objects_path, *encoding))?;
         fs::write(&objects_cache_path, serde_json::to_string(&objects)?)?;
         pb.finish_with_message(format!("Cached {} objects", objects.len()));
     }
 
-    let objects: ObjectDatabase = serde_json::from_str(&fs::read_to_string(&objects_cache_path)?)?;
-
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
-    pb.set_message("Initializing sprite cache...");
-    let sprite_cache = SpriteCache::new(&sprite_path)?;
-    pb.finish_with_message("Sprite cache initialized");
-
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
-    pb.set_message("Preloading sprites...");
-    let mut all_sprite_ids: Vec<u32> = objects.keys().copied().collect();
-
-    // Also preload DisguiseTarget sprites
-    let disguise_targets: Vec<u32> = objects
-        .values()
-        .filter_map(|obj| obj.disguise_target)
-        .collect();
-    all_sprite_ids.extend(disguise_targets);
-    all_sprite_ids.sort_unstable();
-    all_sprite_ids.dedup();
-
-    sprite_cache.preload_sprites(&all_sprite_ids)?;
-    pb.finish_with_message(format!("Loaded {} sprites", sprite_cache.cache_size()));
+    // Extras are merged after the cache so editing them never needs a cache reset
+    let mut objects: ObjectDatabase = serde_json::from_str(&fs::read_to_string(&objects_cache_path)?)?;
+    merge_extra_objects(&mut objects, objects_extra, *encoding)?;
+    apply_layer_rules(&mut objects, &load_layer_rules(layer_rules.as_deref())?);
+    report.record_stage("Load objects", stage_start.elapsed());
 
     let pb = ProgressBar::new_spinner();
     pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
     pb.set_message("Calculating map bounds...");
 
-    let (global_min_sector_x, global_max_sector_x, global_min_sector_y, global_max_sector_y) =
-        calculate_global_bounds(&map_path, &floors)?;
+    let bounds = WorldBounds::scan(map_path, floors, sectors)?;
 
     pb.finish_with_message(format!(
         "Map bounds: sectors ({}-{}, {}-{})",
-        global_min_sector_x, global_max_sector_x,
-        global_min_sector_y, global_max_sector_y
+        bounds.min_sector_x, bounds.max_sector_x,
+        bounds.min_sector_y, bounds.max_sector_y
     ));
+    if let Some(region) = region
+        && region.to_map(&bounds).is_none()
+    {
+        anyhow::bail!(
+            "Region {} lies outside the map (world tiles {},{} to {},{})",
+            region,
+            bounds.min_tile().0,
+            bounds.min_tile().1,
+            bounds.max_tile().0,
+            bounds.max_tile().1
+        );
+    }
+
+    // Sector stamps from the last whole-map build tell which floor caches and
+    // tiles are stale. A sector subset renders with its own bounds, so it
+    // neither uses nor keeps them.
+    let render_state_path = cache_store.render_state_path();
+    let output_key = output.canonicalize()?.to_string_lossy().into_owned();
+    let (previous_state, sector_index) = if sectors.is_empty() {
+        let previous_state = RenderState::load(&render_state_path);
+        let sector_index = tracing::info_span!("scan_sectors")
+            .in_scope(|| SectorIndex::scan(map_path, floors, previous_state.as_ref().map(|state| &state.sectors)))?;
+        (previous_state, Some(sector_index))
+    } else {
+        (None, None)
+    };
+    let changed_sectors: Option<Vec<(u32, u32, u8)>> = previous_state
+        .as_ref()
+        .zip(sector_index.as_ref())
+        .map(|(state, index)| index.changed_since(&state.sectors));
+    if let Some(changed) = &changed_sectors {
+        tracing::info!("{} sector files changed since the last build", changed.len());
+    }
+
+    let mut floor_maps: Vec<SpriteMapData> = Vec::with_capacity(floors.len());
 
-    for floor in &floors {
-        let map_cache_path = cache_dir.join(format!("maps/floor_{:02}_sprite.json", floor));
+    for floor in floors {
+        let _floor_span = tracing::info_span!("floor", floor = *floor).entered();
+        let stage_start = Instant::now();
+        let parse_floor = || {
+            parse_sprite_map(map_path, *floor, &bounds, sectors, *encoding)
+        };
 
-        if !map_cache_path.exists() {
+        // A sector subset is cheap to parse and must not replace the full floor cache
+        if !sectors.is_empty() {
             let pb = ProgressBar::new_spinner();
             pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
-            pb.set_message(format!("Parsing floor {}...", floor));
-            let map_data = parse_sprite_map(
-                &map_path,
-                *floor,
-                global_min_sector_x,
-                global_min_sector_y,
-                global_max_sector_x,
-                global_max_sector_y,
-            )?;
-            fs::write(&map_cache_path, serde_json::to_string(&map_data)?)?;
-            pb.finish_with_message(format!("Cached floor {} ({} tiles)", floor, map_data.tiles.len()));
+            pb.set_message(format!("Parsing {} sectors of floor {}...", sectors.len(), floor));
+            let map_data = parse_floor()?;
+            pb.finish_with_message(format!("Parsed floor {} ({} tiles, uncached)", floor, map_data.tiles.len()));
+            report.record_stage(format!("Parse floor {}", floor), stage_start.elapsed());
+            floor_maps.push(map_data);
+            continue;
+        }
+
+        let floor_changed = changed_sectors
+            .as_ref()
+            .is_some_and(|changed| changed.iter().any(|&(_, _, z)| z == *floor));
+        let cached = if floor_changed { None } else { cache_store.load_floor(*floor) };
+        let map_data = match cached {
+            Some(map_data) => map_data,
+            None => {
+                let pb = ProgressBar::new_spinner();
+                pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+                pb.set_message(format!("Parsing floor {}...", floor));
+                let map_data = parse_floor()?;
+                let bytes = cache_store.save_floor(&map_data)?;
+                pb.finish_with_message(format!(
+                    "Cached floor {} ({} tiles, {})",
+                    floor,
+                    map_data.tiles.len(),
+                    format_bytes(bytes)
+                ));
+                map_data
+            }
+        };
+
+        report.record_stage(format!("Parse floor {}", floor), stage_start.elapsed());
+        floor_maps.push(map_data);
+    }
+
+    report.record_map_data_memory(
+        floor_maps.iter().map(|m| m.estimated_memory_bytes()).sum(),
+    );
+
+    let parse_issues: Vec<ParseIssue> = floor_maps
+        .iter()
+        .flat_map(|m| m.parse_issues.iter().cloned())
+        .collect();
+    fs::write(output.join("parse-report.json"), generate_parse_report_json(&parse_issues)?)?;
+    report.record_parse_issues(&parse_issues);
+
+    let sprite_cache = open_sprite_cache(config)?;
+
+    if let Some(sprite_cache) = &sprite_cache {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Validating sprites...");
+
+        // Only sprites that will actually be drawn on the selected floors
+        let mut used_sprite_ids: Vec<u32> = floor_maps
+            .iter()
+            .flat_map(|map_data| referenced_sprite_ids(map_data, &objects))
+            .collect();
+        used_sprite_ids.sort_unstable();
+        used_sprite_ids.dedup();
+
+        let sprite_issues =
+            tracing::info_span!("validate_sprites").in_scope(|| sprite_cache.validate_sprites(&used_sprite_ids));
+        fs::write(output.join("sprite-report.json"), generate_sprite_report_json(&sprite_issues)?)?;
+        report.record_sprite_issues(&sprite_issues);
+        let unreadable = sprite_issues
+            .iter()
+            .filter(|issue| !matches!(issue.problem, SpriteProblem::UnsupportedSize { .. }))
+            .count();
+        pb.finish_with_message(format!(
+            "Loaded {} of {} referenced sprites, {} problems",
+            used_sprite_ids.len() - unreadable,
+            used_sprite_ids.len(),
+            sprite_issues.len()
+        ));
+        for issue in sprite_issues.iter().take(10) {
+            println!("  {}", issue);
+        }
+        if sprite_issues.len() > 10 {
+            println!("  ... and {} more (see sprite-report.json)", sprite_issues.len() - 10);
+        }
+
+        if *strict {
+            let placeholders: Vec<String> = sprite_issues
+                .iter()
+                .filter(|issue| issue.substitute.is_none() && !matches!(issue.problem, SpriteProblem::UnsupportedSize { .. }))
+                .map(|issue| issue.id.to_string())
+                .collect();
+            if !placeholders.is_empty() {
+                anyhow::bail!(
+                    "--strict: {} sprites would be drawn as placeholders: {}",
+                    placeholders.len(),
+                    placeholders.join(", ")
+                );
+            }
         }
+        report.record_stage("Validate sprites", stage_start.elapsed());
+    }
 
-        let mut map_data: SpriteMapData = serde_json::from_str(&fs::read_to_string(&map_cache_path)?)?;
-        if map_data.version < 2 {
-            tracing::info!("Regenerating outdated cache for floor {}", floor);
+    let colors: Option<ColorMap> = match render_mode {
+        RenderMode::Sprites => None,
+        RenderMode::Color => {
+            let stage_start = Instant::now();
             let pb = ProgressBar::new_spinner();
             pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
-            pb.set_message(format!("Parsing floor {} (outdated cache)...", floor));
-            map_data = parse_sprite_map(
-                &map_path,
-                *floor,
-                global_min_sector_x,
-                global_min_sector_y,
-                global_max_sector_x,
-                global_max_sector_y,
-            )?;
-            fs::write(&map_cache_path, serde_json::to_string(&map_data)?)?;
-            pb.finish_with_message(format!("Cached floor {} ({} tiles)", floor, map_data.tiles.len()));
+            pb.set_message("Loading object colors...");
+            let colors = match (color_map, &sprite_cache) {
+                (Some(path), _) => load_color_map(path)?,
+                (None, Some(sprite_cache)) => create_color_map(&objects, sprite_cache)?,
+                (None, None) => anyhow::bail!("--mode color needs --color-map or --sprite-path"),
+            };
+            pb.finish_with_message(format!("Colors for {} objects", colors.len()));
+            report.record_stage("Object colors", stage_start.elapsed());
+            Some(colors)
+        }
+    };
+
+    let stage_start = Instant::now();
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Detecting floor backgrounds...");
+    let color_of = |id: u32| match (&colors, &sprite_cache) {
+        (Some(colors), _) => colors.get(&id).copied(),
+        (None, Some(sprite_cache)) => object_sprite_color(&objects, sprite_cache, id),
+        (None, None) => None,
+    };
+    let floor_backgrounds: BTreeMap<u8, String> = floor_maps
+        .iter()
+        .filter_map(|map_data| Some((map_data.floor, hex_color(floor_background(map_data, &objects, color_of)?))))
+        .collect();
+    let floors_to_scan: Vec<&SpriteMapData> = floor_maps.iter().collect();
+    let void = void_color(&floors_to_scan, &objects, color_of).map(hex_color);
+    let water = water_color(&floors_to_scan, &objects, color_of).map(hex_color);
+    pb.finish_with_message(format!(
+        "Detected backgrounds for {} of {} floors (void {}, water {})",
+        floor_backgrounds.len(),
+        floor_maps.len(),
+        void.as_deref().unwrap_or("default"),
+        water.as_deref().unwrap_or("default")
+    ));
+    report.record_stage("Floor backgrounds", stage_start.elapsed());
+
+    // Region builds only redraw tiles over the region, and incremental
+    // builds those over changed sectors, as long as the last render of
+    // each floor used the same bounds and zoom levels
+    let mut updated_floors = HashSet::new();
+    let jobs: Vec<FloorJob> = floor_maps
+        .iter()
+        .map(|map_data| {
+            let floor = map_data.floor;
+            let tiles = match (region, &previous_state, &changed_sectors) {
+                (Some(region), _, _) => tiles_covering_region(map_data, region, min_zoom, max_zoom),
+                (None, Some(state), Some(changed))
+                    if *incremental
+                        && state.can_update(&output_key, &bounds, min_zoom, max_zoom, *render_mode, *tile_format, floor)
+                        && output.join(floor.to_string()).is_dir() =>
+                {
+                    let floor_sectors: Vec<(u32, u32)> =
+                        changed.iter().filter(|&&(_, _, z)| z == floor).map(|&(x, y, _)| (x, y)).collect();
+                    tiles_covering_sectors(map_data, &floor_sectors, min_zoom, max_zoom)
+                }
+                _ => {
+                    if *incremental {
+                        tracing::warn!("No matching previous render of floor {}, rendering all tiles", floor);
+                    }
+                    return FloorJob::all(map_data, min_zoom, max_zoom);
+                }
+            };
+            updated_floors.insert(floor);
+            FloorJob { map_data, tiles }
+        })
+        .collect();
+
+    // All floors and zoom levels share the thread pool, reporting into one bar
+    let stage_start = Instant::now();
+    let total_tiles: usize = jobs.iter().map(|job| job.tiles.len()).sum();
+    let pb = ProgressBar::new(total_tiles as u64);
+    pb.set_style(ProgressStyle::default_bar().template("{spinner} {msg} [{bar:30}] {pos}/{len} tiles, ETA {eta}")?);
+    pb.set_message(format!("Rendering {} floors", jobs.len()));
+    let progress = |p: RenderProgress| {
+        pb.inc(1);
+        if p.rendered == p.total {
+            pb.println(format!("  Floor {} zoom {}: {} tiles", p.floor, p.zoom, p.total));
+        }
+    };
+    let renderer = match (&colors, &sprite_cache) {
+        (Some(colors), _) => TileRenderer::Colors(colors),
+        (None, Some(sprite_cache)) => TileRenderer::Sprites(sprite_cache),
+        (None, None) => anyhow::bail!("--sprite-path is required for sprite rendering"),
+    };
+    // PMTiles and tile archives are finished once every tile is in, after the heatmap
+    let pmtiles_sink = pmtiles.map(|layout| PmtilesSink::new(output, layout));
+    let archive_sink = output_archive.as_ref().map(ArchiveSink::create).transpose()?;
+    let file_sink = FileSink::new(output);
+    let tile_sink: &dyn OutputSink = match (&pmtiles_sink, &archive_sink) {
+        (Some(sink), _) => sink,
+        (None, Some(sink)) => sink,
+        (None, None) => &file_sink,
+    };
+    let floor_stats = tracing::info_span!("render_floors").in_scope(|| {
+        if *pyramid {
+            let floors: Vec<&SpriteMapData> = jobs.iter().map(|job| job.map_data).collect();
+            render_floor_pyramids(&floors, renderer, &objects, tile_sink, min_zoom, max_zoom, *tile_format, Some(&progress))
+        } else {
+            render_floors(&jobs, renderer, &objects, tile_sink, *tile_format, Some(&progress))
         }
+    })?;
+    pb.finish_with_message(format!("Rendered {} tiles on {} floors", total_tiles, jobs.len()));
+    for (result, job) in floor_stats.iter().zip(&jobs) {
+        let updated = if updated_floors.contains(&result.floor) { " updated" } else { "" };
+        println!("  Floor {}: {} tiles{}", result.floor, result.stats.tiles, updated);
+        report.record_floor(result.floor, result.stats, &job.tiles, result.elapsed);
+    }
+    report.record_stage("Render tiles", stage_start.elapsed());
 
+    if let (false, Some(sprite_cache)) = (composite_brightness.is_empty(), &sprite_cache) {
+        let stage_start = Instant::now();
         let pb = ProgressBar::new_spinner();
         pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
-        pb.set_message(format!("Generating tiles for floor {}...", floor));
-        let n_tiles = generate_sprite_tiles(
-            &map_data,
-            &sprite_cache,
-            &objects,
-            &output,
-            *floor,
+        pb.set_message("Rendering all floors composite...");
+
+        // A composite tile changes with the same tile of any floor it shows
+        let depth = composite_brightness.len() as u8;
+        let composite_tiles: BTreeSet<(u8, u8, u32, u32)> = jobs
+            .iter()
+            .flat_map(|job| {
+                let floor = job.map_data.floor;
+                jobs.iter()
+                    .filter(move |upper| upper.map_data.floor <= floor && floor - upper.map_data.floor <= depth)
+                    .flat_map(move |upper| job.tiles.iter().map(move |&(zoom, x, y)| (upper.map_data.floor, zoom, x, y)))
+            })
+            .collect();
+        let composite_tiles: Vec<(u8, u8, u32, u32)> = composite_tiles.into_iter().collect();
+        let floors: Vec<&SpriteMapData> = jobs.iter().map(|job| job.map_data).collect();
+        let stats = tracing::info_span!("composite").in_scope(|| {
+            render_composite_tiles(&floors, composite_brightness, sprite_cache, &objects, tile_sink, &composite_tiles, *tile_format)
+        })?;
+
+        pb.finish_with_message(format!("All floors composite: {} tiles ({})", stats.tiles, format_bytes(stats.bytes)));
+        report.record_stage("All floors composite", stage_start.elapsed());
+    }
+
+    let stage_start = Instant::now();
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+    pb.set_message("Generating floor thumbnails...");
+    if *tile_format == TileFormat::Avif {
+        // Thumbnails are shrunk from the rendered tiles, which this build cannot decode
+        pb.finish_with_message("Thumbnails: skipped for AVIF tiles");
+    } else if pmtiles.is_some() || output_archive.is_some() {
+        // ...and read them from the tile directories
+        pb.finish_with_message("Thumbnails: skipped for archived tiles");
+    } else {
+        let mut thumbnail_bytes = 0;
+        for map_data in &floor_maps {
+            thumbnail_bytes += generate_floor_thumbnail(map_data, output, min_zoom, *tile_format)?;
+        }
+        pb.finish_with_message(format!("Thumbnails: {} floors ({})", floor_maps.len(), format_bytes(thumbnail_bytes)));
+    }
+    report.record_stage("Thumbnails", stage_start.elapsed());
+
+    // A region build leaves the rest of the output as the last full build
+    // drew it, so that build's state stays in place
+    match sector_index {
+        Some(_) if region.is_some() => {}
+        Some(sectors) => RenderState {
+            output: output_key,
+            bounds,
             min_zoom,
             max_zoom,
-        )?;
-        pb.finish_with_message(format!("Floor {}: {} tiles", floor, n_tiles));
+            floors: floors.to_vec(),
+            render_mode: *render_mode,
+            tile_format: *tile_format,
+            sectors,
+        }
+        .save(&render_state_path)?,
+        None if render_state_path.exists() => fs::remove_file(&render_state_path)?,
+        None => {}
+    }
+
+    let extra_asset_urls = match extra_assets {
+        Some(dir) => copy_extra_assets(dir, output)?,
+        None => Vec::new(),
+    };
+    if let Some(leaflet_dir) = offline {
+        copy_leaflet(leaflet_dir, output)?;
+    }
+    let has_extension = |url: &String, ext: &str| url.rsplit('.').next().is_some_and(|e| e.eq_ignore_ascii_case(ext));
+
+    let viewer_options = ViewerOptions {
+        live_players: *live_players,
+        overlay_refresh_minutes: *overlay_refresh_minutes,
+        admin_command: admin_command.clone(),
+        extra_stylesheets: extra_asset_urls.iter().filter(|u| has_extension(u, "css")).cloned().collect(),
+        extra_scripts: extra_asset_urls.iter().filter(|u| has_extension(u, "js")).cloned().collect(),
+        marker_styles: marker_styles.clone(),
+        floor_backgrounds,
+        void_color: void,
+        water_color: water,
+        tile_format: *tile_format,
+        render_mode: *render_mode,
+        composite: !composite_brightness.is_empty(),
+        local_leaflet: offline.is_some(),
+        pmtiles: *pmtiles,
+    };
+    generate_html(
+        output,
+        floors,
+        min_zoom,
+        max_zoom,
+        &bounds,
+        &viewer_options,
+    )?;
+
+    if let Some(date) = archive {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message(format!("Archiving tiles as {}...", date));
+        let files = archive_build(output, date, floors, *tile_format)?;
+        pb.finish_with_message(format!("Archived {} tiles → history/{}", files, date));
+        report.record_stage("Archive", stage_start.elapsed());
     }
 
-    let min_tile_x = global_min_sector_x * 32;
-    let max_tile_x = (global_max_sector_x + 1) * 32 - 1;
-    let min_tile_y = global_min_sector_y * 32;
-    let max_tile_y = (global_max_sector_y + 1) * 32 - 1;
+    // Drop data left by earlier builds so the viewer hides skipped overlays
+    for overlay in Overlay::ALL.iter().filter(|overlay| !overlays.contains(overlay)) {
+        for name in overlay.outputs() {
+            let path = output.join(name);
+            if path.is_dir() {
+                fs::remove_dir_all(&path)?;
+            } else if path.exists() {
+                fs::remove_file(&path)?;
+            }
+        }
+    }
+
+    let prices = match prices_csv {
+        Some(path) => parse_price_csv(path)?,
+        None => PriceTable::new(),
+    };
 
-    generate_html(&output, &floors, min_zoom, max_zoom, min_tile_x, max_tile_x, min_tile_y, max_tile_y)?;
+    let stage_start = Instant::now();
 
-    // Process monster data if both monster_db and monster_sprites are provided
-    if let (Some(monster_db_path), Some(monster_sprites_dir)) = (&monster_db, &monster_sprites) {
+    // Explicit files first, then each --data-path layered over them
+    let mon_path = mon_path.as_deref().filter(|dir| {
+        dir.exists() || {
+            tracing::warn!("Monster names directory not found: {:?}", dir);
+            false
+        }
+    });
+    let quest_csv = quest_csv.as_deref().filter(|path| {
+        path.exists() || {
+            tracing::warn!("Quest CSV not found: {:?}", path);
+            false
+        }
+    });
+    let raids_path = raids_path.as_deref().filter(|dir| {
+        dir.is_dir() || {
+            tracing::warn!("Raid directory not found: {:?}", dir);
+            false
+        }
+    });
+    let data_sources = DataSources::collect(monster_db.as_deref(), mon_path, quest_csv, raids_path, data_paths, *encoding);
+
+    // Process monster data if a monster.db and an icon source (sprite directory or client archives) are provided
+    let spawns_enabled = overlays.contains(&Overlay::Spawns) && data_sources.has_spawns();
+    let client_archives = match (client_spr, client_dat) {
+        (Some(spr), Some(dat)) if spawns_enabled => Some((SpriteArchive::open(spr)?, DatFile::open(dat)?)),
+        _ => None,
+    };
+    if spawns_enabled && (monster_sprites.is_some() || client_archives.is_some()) {
         let pb = ProgressBar::new_spinner();
         pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
         pb.set_message("Parsing monster data...");
 
-        let spawns = parse_monster_db(monster_db_path)?;
+        let spawns = tracing::info_span!("parse_monster_db").in_scope(|| data_sources.spawns())?;
 
-        pb.set_message("Copying monster sprites...");
+        pb.set_message("Writing monster icons...");
         let monsters_dir = output.join("monsters");
         fs::create_dir_all(&monsters_dir)?;
 
-        // Copy PNG files (named by race ID)
+        // Outfit colors let grey template sprites be tinted like in game
+        let monster_looks = data_sources.monster_looks().unwrap_or_else(|e| {
+            tracing::warn!("Failed to load monster outfits: {}", e);
+            Default::default()
+        });
+
+        // Draw icons from the client archives where the race has an Outfit, otherwise
+        // copy PNG files (named by race ID), colorizing those with a `{race}_template.png`
         let mut copied_count = 0;
-        for spawn in &spawns {
-            let race_id = spawn.race;
-            let src = monster_sprites_dir.join(format!("{}.png", race_id));
+        let races: std::collections::BTreeSet<u32> = spawns.iter().map(|spawn| spawn.race).collect();
+        for race_id in races {
             let dst = monsters_dir.join(format!("{}.png", race_id));
 
+            if let (Some((sprites, dat)), Some(look)) = (&client_archives, monster_looks.get(&race_id)) {
+                match render_monster_look(sprites, dat, look) {
+                    Ok(icon) => {
+                        icon.save(&dst)?;
+                        copied_count += 1;
+                        continue;
+                    }
+                    Err(e) => tracing::warn!("Failed to draw race ID {} from client archives: {:#}", race_id, e),
+                }
+            }
+
+            let Some(monster_sprites_dir) = monster_sprites else {
+                tracing::warn!("No icon for race ID {}: no Outfit in its .mon file", race_id);
+                continue;
+            };
+            let src = monster_sprites_dir.join(format!("{}.png", race_id));
+            let template = monster_sprites_dir.join(format!("{}_template.png", race_id));
+
             if src.exists() {
-                fs::copy(&src, &dst)?;
+                match monster_looks.get(&race_id) {
+                    Some(&MonsterLook::Outfit { colors, .. }) if template.exists() => {
+                        load_colorized_outfit(&src, &template, colors)?.save(&dst)?;
+                    }
+                    _ => {
+                        fs::copy(&src, &dst)?;
+                    }
+                }
                 copied_count += 1;
             } else {
-                tracing::warn!("Missing PNG for race ID {}: {:?}", race_id, src);
+                tracing::warn!(kind = "monster_icon", id = race_id, "Missing PNG for race ID {}: {:?}", race_id, src);
             }
         }
 
-        pb.set_message("Loading monster names...");
-        let monster_names = if let Some(ref mon_dir) = mon_path {
-            if mon_dir.exists() {
-                match parse_monster_names(mon_dir) {
-                    Ok(names) => names,
-                    Err(e) => {
-                        tracing::warn!("Failed to load monster names: {}", e);
-                        Default::default()
-                    }
-                }
-            } else {
-                tracing::warn!("Monster names directory not found: {:?}", mon_dir);
+        pb.set_message("Loading monster stats...");
+        let monster_stats = match data_sources.monster_stats() {
+            Ok(stats) => stats,
+            Err(e) => {
+                tracing::warn!("Failed to load monster stats: {}", e);
                 Default::default()
             }
+        };
+        let monster_names: HashMap<u32, String> =
+            monster_stats.iter().map(|(&race, stats)| (race, stats.name.clone())).collect();
+
+        let loot_values = if !prices.is_empty() {
+            pb.set_message("Pricing monster loot...");
+            let loot: HashMap<u32, Vec<LootEntry>> =
+                monster_stats.iter().map(|(&race, stats)| (race, stats.loot.clone())).collect();
+            expected_loot_values(&loot, &prices)
         } else {
             Default::default()
         };
 
         pb.set_message("Generating spawn data...");
-        let spawn_json = generate_spawn_json(&spawns, &floors, &monster_names)?;
+        let spawn_json =
+            generate_spawn_json(&spawns, floors, &monster_names, &loot_values, &monster_stats, &objects)?;
         fs::write(output.join("spawns.json"), spawn_json)?;
 
+        pb.set_message("Rating hunting grounds...");
+        let experience: HashMap<u32, u32> =
+            monster_stats.iter().filter_map(|(&race, stats)| Some((race, stats.experience?))).collect();
+        let hunting = hunting_values(&spawns, &experience);
+        fs::write(output.join("hunting.json"), generate_hunting_json(&hunting, floors, &monster_names)?)?;
+
         pb.finish_with_message(format!(
-            "Monster spawns: {} spawns, {} sprites copied",
+            "Monster spawns: {} spawns, {} icons",
             spawns.len(),
             copied_count
         ));
     }
 
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
-    pb.set_message("Parsing quest chests...");
-
-    let quest_names = if let Some(ref quest_csv_path) = quest_csv {
-        if quest_csv_path.exists() {
-            pb.set_message("Loading quest names from CSV...");
-            match parse_quest_csv(quest_csv_path) {
-                Ok(names) => names,
-                Err(e) => {
-                    tracing::warn!("Failed to load quest names: {}", e);
-                    Default::default()
-                }
+    report.record_stage("Monster spawns", stage_start.elapsed());
+
+    // Places viewers go first, used to order prewarm.txt
+    let mut landmarks: Vec<Landmark> = Vec::new();
+
+    if overlays.contains(&Overlay::Quests) {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Parsing quest chests...");
+
+        pb.set_message("Loading quest names from CSV...");
+        let quest_names = match data_sources.quest_names() {
+            Ok(names) => names,
+            Err(e) => {
+                tracing::warn!("Failed to load quest names: {}", e);
+                Default::default()
             }
-        } else {
-            tracing::warn!("Quest CSV not found: {:?}", quest_csv_path);
-            Default::default()
-        }
-    } else {
-        Default::default()
-    };
+        };
+
+        let quest_chests = tracing::info_span!("parse_questchests")
+            .in_scope(|| parse_questchests_from_sectors(map_path, floors, &quest_names, *encoding))?;
 
-    let quest_chests = parse_questchests_from_sectors(&map_path, &floors, &quest_names)?;
+        pb.set_message("Generating quest chest data...");
+        let questchests_json = generate_questchests_json(&quest_chests, floors, &prices, &objects)?;
+        fs::write(output.join("questchests.json"), questchests_json)?;
 
-    pb.set_message("Generating quest chest data...");
-    let questchests_json = generate_questchests_json(&quest_chests, &floors)?;
-    fs::write(output.join("questchests.json"), questchests_json)?;
+        pb.finish_with_message(format!("Quest chests: {} found", quest_chests.len()));
+        report.record_stage("Quest chests", stage_start.elapsed());
 
-    pb.finish_with_message(format!("Quest chests: {} found", quest_chests.len()));
+        landmarks.extend(quest_chests.iter().map(|chest| Landmark { x: chest.x, y: chest.y, z: chest.z, weight: 0.5 }));
+    }
 
-    // Process NPC data if both npc_csv and npc_sprites are provided
-    if let (Some(npc_csv_path), Some(npc_sprites_dir)) = (&npc_csv, &npc_sprites) {
+    if overlays.contains(&Overlay::Water) {
+        let stage_start = Instant::now();
         let pb = ProgressBar::new_spinner();
         pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
-        pb.set_message("Parsing NPC CSV...");
+        pb.set_message("Finding water regions...");
 
-        let npcs = parse_npc_csv(npc_csv_path)?;
+        let water_regions: Vec<WaterRegion> = tracing::info_span!("find_water_regions").in_scope(|| {
+            floor_maps
+                .iter()
+                .flat_map(|map_data| find_water_regions(map_data, &objects))
+                .collect()
+        });
+        fs::write(output.join("water.json"), generate_water_json(&water_regions)?)?;
 
-        pb.set_message("Copying NPC sprites...");
-        let npcs_dir = output.join("npcs");
-        fs::create_dir_all(&npcs_dir)?;
+        pb.finish_with_message(format!(
+            "Water: {} regions, {} tiles",
+            water_regions.len(),
+            water_regions.iter().map(|r| r.area).sum::<usize>()
+        ));
+        report.record_stage("Water regions", stage_start.elapsed());
+    }
 
-        // Copy PNG files (named by file_name)
-        let mut copied_count = 0;
-        let mut missing_sprites = Vec::new();
-        for npc in &npcs {
-            let src = npc_sprites_dir.join(format!("{}.png", npc.file_name));
-            let dst = npcs_dir.join(format!("{}.png", npc.file_name));
+    if overlays.contains(&Overlay::Transitions) {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Finding floor transitions...");
 
-            if src.exists() {
-                fs::copy(&src, &dst)?;
-                copied_count += 1;
-            } else {
-                missing_sprites.push(npc.file_name.clone());
+        let transitions: Vec<Transition> = floor_maps
+            .iter()
+            .flat_map(|map_data| find_transitions(map_data, &objects))
+            .collect();
+        fs::write(output.join("transitions.json"), generate_transitions_json(&transitions)?)?;
+
+        let up = transitions.iter().filter(|t| t.to_floor < t.floor).count();
+        pb.finish_with_message(format!(
+            "Transitions: {} up, {} down",
+            up,
+            transitions.len() - up
+        ));
+        report.record_stage("Transitions", stage_start.elapsed());
+    }
+
+    if overlays.contains(&Overlay::Teleports) {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Parsing teleports...");
+
+        let teleports = tracing::info_span!("parse_teleports")
+            .in_scope(|| parse_teleports_from_sectors(map_path, floors, &objects, *encoding))?;
+        fs::write(output.join("teleports.json"), generate_teleports_json(&teleports)?)?;
+
+        let cross_floor = teleports.iter().filter(|t| t.to_floor != t.floor).count();
+        pb.finish_with_message(format!(
+            "Teleports: {} found, {} to another floor",
+            teleports.len(),
+            cross_floor
+        ));
+        report.record_stage("Teleports", stage_start.elapsed());
+    }
+
+    if overlays.contains(&Overlay::Signs) {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Scanning sectors for signs...");
+
+        let readables = tracing::info_span!("parse_readables")
+            .in_scope(|| parse_readables_from_sectors(map_path, floors, &objects, *encoding))?;
+        fs::write(output.join("signs.json"), generate_signs_json(&readables)?)?;
+
+        pb.finish_with_message(format!("Signs: {} texts", readables.len()));
+        report.record_stage("Signs", stage_start.elapsed());
+    }
+
+    if overlays.contains(&Overlay::Poi) {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Finding depots, temples, mailboxes and banks...");
+
+        let points: Vec<PointOfInterest> = floor_maps
+            .iter()
+            .flat_map(|map_data| find_points_of_interest(map_data, &objects))
+            .collect();
+        fs::write(output.join("poi.json"), generate_poi_json(&points)?)?;
+
+        let depots = points.iter().filter(|p| p.kind == PoiKind::Depot).count();
+        pb.finish_with_message(format!("Points of interest: {} found, {} depots", points.len(), depots));
+        report.record_stage("Points of interest", stage_start.elapsed());
+
+        landmarks.extend(points.iter().map(|p| Landmark { x: p.x, y: p.y, z: p.floor, weight: 0.5 }));
+    }
+
+    if overlays.contains(&Overlay::Raids) && !data_sources.raid_dirs.is_empty() {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Parsing raids...");
+
+        let raids = data_sources.raids()?;
+        let monster_names = match data_sources.monster_names() {
+            Ok(names) => names,
+            Err(e) => {
+                tracing::warn!("Failed to load monster names: {}", e);
+                Default::default()
             }
+        };
+        fs::write(output.join("raids.json"), generate_raids_json(&raids, floors, &monster_names)?)?;
+
+        let spawns: usize = raids.iter().map(|raid| raid.spawns.len()).sum();
+        pb.finish_with_message(format!("Raids: {} raids, {} spawns", raids.len(), spawns));
+        report.record_stage("Raids", stage_start.elapsed());
+    }
+
+    if overlays.contains(&Overlay::Zones) {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Parsing zone flags...");
+
+        let zones = tracing::info_span!("parse_zones").in_scope(|| parse_zones_from_sectors(map_path, floors, *encoding))?;
+        fs::write(output.join("zones.json"), generate_zones_json(&zones)?)?;
+
+        let mut zone_kinds: Vec<&str> = zones.iter().map(|z| z.kind.as_str()).collect();
+        zone_kinds.sort_unstable();
+        zone_kinds.dedup();
+        pb.finish_with_message(format!(
+            "Zones: {} tiles ({})",
+            zones.iter().map(|z| z.tile_count).sum::<usize>(),
+            if zone_kinds.is_empty() { "none".to_string() } else { zone_kinds.join(", ") }
+        ));
+        report.record_stage("Zones", stage_start.elapsed());
+    }
+
+    if let Some(heatmap_csv_path) = heatmap_csv.as_ref().filter(|_| overlays.contains(&Overlay::Heatmap)) {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Loading heatmap points...");
+
+        let points = parse_heatmap_csv(heatmap_csv_path)?;
+        let mut stats = TileRenderStats::default();
+        for map_data in &floor_maps {
+            pb.set_message(format!("Rendering heatmap for floor {}...", map_data.floor));
+            stats += tracing::info_span!("heatmap", floor = map_data.floor).in_scope(|| {
+                generate_heatmap_tiles(&points, map_data, tile_sink, min_zoom, max_zoom, *heatmap_radius)
+            })?;
         }
+        fs::write(output.join("heatmap.json"), generate_heatmap_json(&points, floors)?)?;
+
+        pb.finish_with_message(format!(
+            "Heatmap: {} points, {} tiles ({})",
+            points.len(),
+            stats.tiles,
+            format_bytes(stats.bytes)
+        ));
+        report.record_stage("Heatmap", stage_start.elapsed());
+    }
+
+    if let Some(sink) = &pmtiles_sink {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Writing PMTiles archives...");
+        sink.finish()?;
+        pb.finish_with_message(format!("PMTiles: {}", sink.archive_paths().join(", ")));
+        report.record_stage("PMTiles", stage_start.elapsed());
+    }
+    if let Some(sink) = &archive_sink {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Finishing tile archive...");
+        sink.finish()?;
+        let bytes = fs::metadata(sink.path())?.len();
+        pb.finish_with_message(format!(
+            "Tile archive: {} tiles → {} ({})",
+            sink.manifest_entries().len(),
+            sink.path().display(),
+            format_bytes(bytes)
+        ));
+        report.record_stage("Tile archive", stage_start.elapsed());
+    }
+
+    if overlays.contains(&Overlay::Houses) {
+        let stage_start = Instant::now();
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Parsing house areas...");
+
+        let names = match house_names {
+            Some(path) => parse_house_names(path, *encoding)?,
+            None => Default::default(),
+        };
+        let houses =
+            tracing::info_span!("parse_houses").in_scope(|| parse_houses_from_sectors(map_path, floors, &names, *encoding))?;
+        fs::write(output.join("houses.json"), generate_houses_json(&houses)?)?;
+
+        let mut house_ids: Vec<u32> = houses.iter().map(|h| h.id).collect();
+        house_ids.sort_unstable();
+        house_ids.dedup();
+        pb.finish_with_message(format!(
+            "Houses: {} houses, {} tiles",
+            house_ids.len(),
+            houses.iter().map(|h| h.tile_count).sum::<usize>()
+        ));
+        report.record_stage("Houses", stage_start.elapsed());
+    }
+
+    if let Some(houses_status_path) = houses_status.as_ref().filter(|_| overlays.contains(&Overlay::Houses)) {
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Loading house status...");
+
+        let statuses = parse_house_status(houses_status_path)?;
+        fs::write(output.join("houses-status.json"), generate_house_status_json(&statuses)?)?;
+
+        let auctioned = statuses.values().filter(|s| s.state() == HouseState::Auctioned).count();
+        let owned = statuses.values().filter(|s| s.state() == HouseState::Owned).count();
+        pb.finish_with_message(format!(
+            "House status: {} houses ({} owned, {} auctioned)",
+            statuses.len(),
+            owned,
+            auctioned
+        ));
+    }
+
+    let stage_start = Instant::now();
 
-        if !missing_sprites.is_empty() {
-            tracing::warn!("Missing {} NPC sprites", missing_sprites.len());
-            for sprite in missing_sprites.iter().take(5) {
-                tracing::warn!("  Missing sprite: {}.png", sprite);
+    // Process NPC data if npc_csv is provided; npc_sprites adds their images
+    if let (Some(npc_csv_path), true) = (&npc_csv, overlays.contains(&Overlay::Npcs)) {
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}")?);
+        pb.set_message("Parsing NPC CSV...");
+
+        let npcs = tracing::info_span!("parse_npc_csv").in_scope(|| parse_npc_csv(npc_csv_path, *encoding))?;
+        landmarks.extend(npcs.iter().map(|npc| Landmark { x: npc.x, y: npc.y, z: npc.z, weight: 1.0 }));
+
+        // Without sprites the viewer draws NPCs as plain dots
+        let mut copied_count = 0;
+        if let Some(npc_sprites_dir) = &npc_sprites {
+            pb.set_message("Copying NPC sprites...");
+            let npcs_dir = output.join("npcs");
+            fs::create_dir_all(&npcs_dir)?;
+
+            // Copy PNG files (named by file_name)
+            let mut missing_sprites = Vec::new();
+            for npc in &npcs {
+                let src = npc_sprites_dir.join(format!("{}.png", npc.file_name));
+                let dst = npcs_dir.join(format!("{}.png", npc.file_name));
+
+                if src.exists() {
+                    fs::copy(&src, &dst)?;
+                    copied_count += 1;
+                } else {
+                    missing_sprites.push(npc.file_name.clone());
+                }
             }
-            if missing_sprites.len() > 5 {
-                tracing::warn!("  ... and {} more", missing_sprites.len() - 5);
+
+            if !missing_sprites.is_empty() {
+                tracing::warn!("Missing {} NPC sprites", missing_sprites.len());
+                for sprite in missing_sprites.iter().take(5) {
+                    tracing::warn!("  Missing sprite: {}.png", sprite);
+                }
+                if missing_sprites.len() > 5 {
+                    tracing::warn!("  ... and {} more", missing_sprites.len() - 5);
+                }
             }
         }
 
         pb.set_message("Generating NPC data...");
-        let npc_json = generate_npc_json(&npcs, &floors)?;
+        let npc_json = generate_npc_json(&npcs, floors)?;
         fs::write(output.join("npcs.json"), npc_json)?;
 
         pb.finish_with_message(format!(
@@ -474,19 +2460,30 @@ fn cmd_build(
         ));
     }
 
-    println!("✓ Build complete → {:?}/index.html", output);
+    report.record_stage("NPCs", stage_start.elapsed());
+
+    let stage_start = Instant::now();
+    let mut tile_dirs: Vec<String> = floors.iter().map(|f| f.to_string()).collect();
+    tile_dirs.push("heatmap".to_string());
+    tile_dirs.push("composite".to_string());
+    tile_dirs.extend(pmtiles_sink.iter().flat_map(PmtilesSink::archive_paths));
+    let mut manifest = build_tile_manifest(output, &tile_dirs)?;
+    if let Some(sink) = &archive_sink {
+        manifest.tiles.extend(sink.manifest_entries());
+        // Found from the output directory by verify, wherever that is run from
+        let archive = fs::canonicalize(sink.path())?;
+        let output = fs::canonicalize(output)?;
+        manifest.archive = Some(archive.strip_prefix(&output).map(std::path::Path::to_path_buf).unwrap_or(archive));
+    }
+    manifest.save(output)?;
+    write_prewarm_list(output, &prewarm_order(&manifest, &bounds, &landmarks))?;
+    report.record_stage("Tile manifest", stage_start.elapsed());
 
-    Ok(())
-}
+    report.finish(sprite_cache.map(|cache| cache.stats()).unwrap_or_default());
+    report.record_artifacts(output)?;
+    report.write_json(output)?;
+    report.print_summary();
+    report.print_artifacts(output);
 
-fn parse_floor_range(s: &str) -> Result<Vec<u8>> {
-    if s.contains('-') {
-        let parts: Vec<&str> = s.split('-').collect();
-        if parts.len() == 2 {
-            let start: u8 = parts[0].parse()?;
-            let end: u8 = parts[1].parse()?;
-            return Ok((start..=end).collect());
-        }
-    }
-    Ok(vec![s.parse()?])
+    Ok(())
 }
@@ -0,0 +1,463 @@
+use anyhow::Result;
+use console::style;
+use demonax_mapper_core::ParseIssue;
+use demonax_mapper_render::{SpriteCacheStats, SpriteIssue, SpriteProblem, TileRenderStats};
+use serde::Serialize;
+use std::fs;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Distinct ids listed per warning kind in a stage summary.
+const SUMMARY_IDS_PER_KIND: usize = 5;
+
+/// Tracing layer that counts every WARN-level event, and groups the ones
+/// tagged with `kind` and `id` fields (sprite load failures, malformed
+/// Content lines...) so each stage ends with one compact summary instead of
+/// thousands of interleaved lines from rayon workers.
+#[derive(Debug, Clone, Default)]
+pub struct WarningCollector {
+    count: Arc<AtomicUsize>,
+    groups: Arc<Mutex<BTreeMap<(String, String), WarningGroup>>>,
+}
+
+/// Occurrences of one `(kind, id)` warning within a stage.
+#[derive(Debug, Clone, Serialize)]
+pub struct WarningGroup {
+    pub stage: String,
+    pub kind: String,
+    pub id: String,
+    pub count: usize,
+    /// The first message logged for this group.
+    pub message: String,
+}
+
+impl WarningCollector {
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Whether the console should skip this event because it is grouped instead.
+    pub fn is_grouped(metadata: &Metadata<'_>) -> bool {
+        *metadata.level() == Level::WARN && metadata.fields().field("kind").is_some()
+    }
+
+    /// Takes the groups collected since the last call, labelled with `stage`.
+    pub fn drain(&self, stage: &str) -> Vec<WarningGroup> {
+        let groups = std::mem::take(&mut *self.groups.lock().unwrap());
+        groups
+            .into_values()
+            .map(|group| WarningGroup {
+                stage: stage.to_string(),
+                ..group
+            })
+            .collect()
+    }
+}
+
+/// Prints the grouped warnings no build stage claimed when dropped, so they
+/// still show up for subcommands without stages or when a command fails.
+pub struct PendingWarnings(pub WarningCollector);
+
+impl Drop for PendingWarnings {
+    fn drop(&mut self) {
+        print_warning_groups(None, &self.0.drain(""));
+    }
+}
+
+#[derive(Default)]
+struct WarningFields {
+    kind: Option<String>,
+    id: Option<String>,
+    message: String,
+}
+
+impl Visit for WarningFields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "kind" => self.kind = Some(format!("{:?}", value).trim_matches('"').to_string()),
+            "id" => self.id = Some(format!("{:?}", value).trim_matches('"').to_string()),
+            "message" => self.message = format!("{:?}", value),
+            _ => {}
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "kind" => self.kind = Some(value.to_string()),
+            "id" => self.id = Some(value.to_string()),
+            _ => self.record_debug(field, &value),
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for WarningCollector {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::WARN {
+            return;
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+
+        let mut fields = WarningFields::default();
+        event.record(&mut fields);
+        let Some(kind) = fields.kind else {
+            return;
+        };
+
+        let id = fields.id.unwrap_or_default();
+        let mut groups = self.groups.lock().unwrap();
+        groups
+            .entry((kind.clone(), id.clone()))
+            .or_insert_with(|| WarningGroup {
+                stage: String::new(),
+                kind,
+                id,
+                count: 0,
+                message: fields.message,
+            })
+            .count += 1;
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StageTiming {
+    pub name: String,
+    pub seconds: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FloorReport {
+    pub floor: u8,
+    pub tiles: usize,
+    pub tiles_by_zoom: BTreeMap<u8, usize>,
+    pub bytes: u64,
+    pub seconds: f64,
+}
+
+/// A file or directory the build left in the output directory.
+#[derive(Debug, Serialize)]
+pub struct ArtifactReport {
+    pub name: String,
+    pub path: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Optional outputs listed in the artifact table when present, in display order.
+const KNOWN_ARTIFACTS: [(&str, &str); 27] = [
+    ("index.html", "Viewer"),
+    ("tiles.pmtiles", "All floors tiles"),
+    ("thumbnails", "Floor thumbnails"),
+    ("spawns.json", "Spawns"),
+    ("hunting.json", "Hunting values"),
+    ("monsters", "Monster sprites"),
+    ("questchests.json", "Quest chests"),
+    ("npcs.json", "NPCs"),
+    ("npcs", "NPC sprites"),
+    ("water.json", "Water regions"),
+    ("zones.json", "Zones"),
+    ("transitions.json", "Floor transitions"),
+    ("teleports.json", "Teleports"),
+    ("signs.json", "Signs"),
+    ("poi.json", "Points of interest"),
+    ("raids.json", "Raids"),
+    ("heatmap.json", "Heatmap data"),
+    ("heatmap", "Heatmap tiles"),
+    ("diff.json", "Map diff"),
+    ("houses.json", "Houses"),
+    ("houses-status.json", "House status"),
+    ("history", "Archived builds"),
+    ("extra", "Extra assets"),
+    ("parse-report.json", "Parse report"),
+    ("sprite-report.json", "Sprite report"),
+    ("tiles-manifest.json", "Tile manifest"),
+    ("prewarm.txt", "Prewarm list"),
+];
+
+#[derive(Debug, Serialize)]
+pub struct BuildReport {
+    pub floors: Vec<FloorReport>,
+    pub stages: Vec<StageTiming>,
+    pub total_tiles: usize,
+    pub total_bytes: u64,
+    pub total_seconds: f64,
+    pub sprite_cache: SpriteCacheStats,
+    pub sprite_cache_hit_rate: f64,
+    pub peak_map_data_bytes: usize,
+    pub warnings: usize,
+    pub parse_issues: usize,
+    /// Sector files left off the map entirely because they failed to parse.
+    pub skipped_sectors: Vec<String>,
+    /// Referenced sprite IDs with no sprite, drawn as a substitute or placeholder.
+    pub missing_sprites: Vec<u32>,
+    pub artifacts: Vec<ArtifactReport>,
+    pub warning_groups: Vec<WarningGroup>,
+    #[serde(skip)]
+    started: Instant,
+    #[serde(skip)]
+    collector: WarningCollector,
+}
+
+impl BuildReport {
+    pub fn new(collector: WarningCollector) -> Self {
+        Self {
+            floors: Vec::new(),
+            stages: Vec::new(),
+            total_tiles: 0,
+            total_bytes: 0,
+            total_seconds: 0.0,
+            sprite_cache: SpriteCacheStats::default(),
+            sprite_cache_hit_rate: 0.0,
+            peak_map_data_bytes: 0,
+            warnings: 0,
+            parse_issues: 0,
+            skipped_sectors: Vec::new(),
+            missing_sprites: Vec::new(),
+            artifacts: Vec::new(),
+            warning_groups: Vec::new(),
+            started: Instant::now(),
+            collector,
+        }
+    }
+
+    /// Records the stage timing and prints the warnings grouped during it.
+    pub fn record_stage(&mut self, name: impl Into<String>, elapsed: Duration) {
+        let name = name.into();
+        let groups = self.collector.drain(&name);
+        print_warning_groups(Some(&name), &groups);
+        self.warning_groups.extend(groups);
+
+        self.stages.push(StageTiming {
+            name,
+            seconds: elapsed.as_secs_f64(),
+        });
+    }
+
+    /// `tiles` lists the `(zoom, x, y)` tiles the floor's render covered.
+    pub fn record_floor(&mut self, floor: u8, stats: TileRenderStats, tiles: &[(u8, u32, u32)], elapsed: Duration) {
+        self.total_tiles += stats.tiles;
+        self.total_bytes += stats.bytes;
+        let mut tiles_by_zoom = BTreeMap::new();
+        for &(zoom, _, _) in tiles {
+            *tiles_by_zoom.entry(zoom).or_insert(0) += 1;
+        }
+        self.floors.push(FloorReport {
+            floor,
+            tiles: stats.tiles,
+            tiles_by_zoom,
+            bytes: stats.bytes,
+            seconds: elapsed.as_secs_f64(),
+        });
+    }
+
+    pub fn record_parse_issues(&mut self, issues: &[ParseIssue]) {
+        self.parse_issues = issues.len();
+        self.skipped_sectors = issues
+            .iter()
+            .filter(|issue| issue.line.is_none())
+            .map(|issue| issue.file.clone())
+            .collect();
+    }
+
+    pub fn record_sprite_issues(&mut self, issues: &[SpriteIssue]) {
+        self.missing_sprites = issues
+            .iter()
+            .filter(|issue| matches!(issue.problem, SpriteProblem::Missing))
+            .map(|issue| issue.id)
+            .collect();
+    }
+
+    pub fn record_map_data_memory(&mut self, bytes: usize) {
+        self.peak_map_data_bytes = self.peak_map_data_bytes.max(bytes);
+    }
+
+    /// Lists the rendered floors and whichever known outputs exist in `output_dir`.
+    pub fn record_artifacts(&mut self, output_dir: &Path) -> Result<()> {
+        // Measured on disk, since an incremental build only rewrites some tiles
+        self.artifacts = Vec::with_capacity(self.floors.len());
+        for floor in &self.floors {
+            // PMTiles builds keep a floor in one archive, or all floors in tiles.pmtiles
+            let (path, name) = (format!("{}/", floor.floor), format!("{}.pmtiles", floor.floor));
+            let Some(path) = [path, name].into_iter().find(|path| output_dir.join(path).exists()) else {
+                continue;
+            };
+            let (files, bytes) = measure(&output_dir.join(&path))?;
+            self.artifacts.push(ArtifactReport {
+                name: format!("Floor {} tiles", floor.floor),
+                path,
+                files,
+                bytes,
+            });
+        }
+
+        for (path, name) in KNOWN_ARTIFACTS {
+            let full_path = output_dir.join(path);
+            if !full_path.exists() {
+                continue;
+            }
+
+            let (files, bytes) = measure(&full_path)?;
+            self.artifacts.push(ArtifactReport {
+                name: name.to_string(),
+                path: if full_path.is_dir() { format!("{}/", path) } else { path.to_string() },
+                files,
+                bytes,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(&mut self, sprite_cache: SpriteCacheStats) {
+        self.sprite_cache = sprite_cache;
+        self.sprite_cache_hit_rate = sprite_cache.hit_rate();
+        self.warnings = self.collector.count();
+        self.total_seconds = self.started.elapsed().as_secs_f64();
+    }
+
+    pub fn write_json(&self, output_dir: &Path) -> Result<()> {
+        fs::write(
+            output_dir.join("build-report.json"),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn print_summary(&self) {
+        println!();
+        println!("{:<8} {:>10} {:>12} {:>10}", "Floor", "Tiles", "Size", "Time");
+        for floor in &self.floors {
+            println!(
+                "{:<8} {:>10} {:>12} {:>9.1}s",
+                floor.floor,
+                floor.tiles,
+                format_bytes(floor.bytes),
+                floor.seconds
+            );
+        }
+        println!(
+            "{:<8} {:>10} {:>12} {:>9.1}s",
+            "Total",
+            self.total_tiles,
+            format_bytes(self.total_bytes),
+            self.total_seconds
+        );
+
+        println!();
+        println!("{:<24} {:>10}", "Stage", "Time");
+        for stage in &self.stages {
+            println!("{:<24} {:>9.1}s", stage.name, stage.seconds);
+        }
+
+        println!();
+        println!(
+            "Sprite cache: {} entries, {}, {:.1}% hit rate, {} placeholder fallbacks, {} evictions",
+            self.sprite_cache.entries,
+            format_bytes(self.sprite_cache.bytes as u64),
+            self.sprite_cache_hit_rate * 100.0,
+            self.sprite_cache.placeholders,
+            self.sprite_cache.evictions
+        );
+        println!("Peak map data: {}", format_bytes(self.peak_map_data_bytes as u64));
+        println!("Warnings: {}", self.warnings);
+        if self.parse_issues > 0 {
+            println!(
+                "Parse issues: {}, {} sectors skipped (see parse-report.json)",
+                self.parse_issues,
+                self.skipped_sectors.len()
+            );
+        }
+        if !self.missing_sprites.is_empty() {
+            println!("Missing sprites: {} (see sprite-report.json)", self.missing_sprites.len());
+        }
+    }
+
+    pub fn print_artifacts(&self, output_dir: &Path) {
+        println!();
+        println!("{} {}", style("✓ Build complete →").green().bold(), output_dir.display());
+        println!();
+        println!(
+            "{}",
+            style(format!("{:<20} {:>8} {:>12}  {}", "Artifact", "Files", "Size", "Path")).bold()
+        );
+        for artifact in &self.artifacts {
+            println!(
+                "{} {:>8} {}  {}",
+                style(format!("{:<20}", artifact.name)).cyan(),
+                artifact.files,
+                style(format!("{:>12}", format_bytes(artifact.bytes))).green(),
+                style(output_dir.join(&artifact.path).display()).dim()
+            );
+        }
+    }
+}
+
+/// Number of files and total bytes under `path` (a file counts as one).
+fn measure(path: &Path) -> Result<(usize, u64)> {
+    if path.is_file() {
+        return Ok((1, fs::metadata(path)?.len()));
+    }
+
+    let mut totals = (0, 0);
+    for entry in fs::read_dir(path)? {
+        let (files, bytes) = measure(&entry?.path())?;
+        totals.0 += files;
+        totals.1 += bytes;
+    }
+    Ok(totals)
+}
+
+/// One line per warning kind: total count, the most frequent ids and an
+/// example message.
+fn print_warning_groups(stage: Option<&str>, groups: &[WarningGroup]) {
+    if groups.is_empty() {
+        return;
+    }
+
+    let mut by_kind: BTreeMap<&str, Vec<&WarningGroup>> = BTreeMap::new();
+    for group in groups {
+        by_kind.entry(&group.kind).or_default().push(group);
+    }
+
+    let total: usize = groups.iter().map(|g| g.count).sum();
+    match stage {
+        Some(stage) => eprintln!("{} {}: {} warnings", style("⚠").yellow(), stage, total),
+        None => eprintln!("{} {} warnings", style("⚠").yellow(), total),
+    }
+    for (kind, mut kind_groups) in by_kind {
+        kind_groups.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.id.cmp(&b.id)));
+        let count: usize = kind_groups.iter().map(|g| g.count).sum();
+        let mut ids: Vec<String> = kind_groups
+            .iter()
+            .take(SUMMARY_IDS_PER_KIND)
+            .map(|g| format!("{} ×{}", g.id, g.count))
+            .collect();
+        if kind_groups.len() > SUMMARY_IDS_PER_KIND {
+            ids.push(format!("{} more", kind_groups.len() - SUMMARY_IDS_PER_KIND));
+        }
+
+        eprintln!("    {} ×{}: {}", style(kind).yellow(), count, ids.join(", "));
+        eprintln!("      {}", style(&kind_groups[0].message).dim());
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
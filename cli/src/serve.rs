@@ -0,0 +1,275 @@
+use anyhow::{Context, Result};
+use demonax_mapper_core::{parse_player_positions, players_sse_event, PlayerFeed};
+use demonax_mapper_render::{TileKey, ViewportPrefetcher};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+/// Requests handled in parallel; the viewer fetches many tiles at once.
+const WORKER_THREADS: usize = 8;
+
+/// Time between SSE comments that keep idle player streams open through proxies.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Largest player list accepted on `/players`.
+const MAX_PLAYERS_BODY: u64 = 4 * 1024 * 1024;
+
+/// Online player positions POSTed to `/players` by the game server, pushed to
+/// maps built with `--live-players` over `/players/stream`.
+pub struct LivePlayers {
+    pub feed: Arc<PlayerFeed>,
+    /// Bearer token a POST must carry in its `Authorization` header.
+    pub token: String,
+}
+
+/// Serves a build output directory until the process is stopped. With a
+/// `prefetcher`, map tiles missing from `dir` are rendered as they are asked
+/// for, and their neighbours in the background.
+pub fn serve(
+    dir: &Path,
+    host: &str,
+    port: u16,
+    prefetcher: Option<&ViewportPrefetcher>,
+    players: Option<&LivePlayers>,
+    on_ready: impl FnOnce(&str),
+) -> Result<()> {
+    if !dir.join("index.html").is_file() {
+        anyhow::bail!("No index.html in {:?}; run `build` first", dir);
+    }
+
+    let server = Server::http((host, port)).map_err(|e| anyhow::anyhow!("Failed to listen on {}:{}: {}", host, port, e))?;
+    on_ready(&format!("http://{}:{}/", host, port));
+
+    std::thread::scope(|scope| {
+        for _ in 0..WORKER_THREADS {
+            scope.spawn(|| {
+                for request in server.incoming_requests() {
+                    if let Err(e) = handle(dir, prefetcher, players, request) {
+                        tracing::warn!("Failed to answer request: {}", e);
+                    }
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn handle(
+    dir: &Path,
+    prefetcher: Option<&ViewportPrefetcher>,
+    players: Option<&LivePlayers>,
+    request: Request,
+) -> Result<()> {
+    if let Some(players) = players {
+        match (request.method(), request.url().split(['?', '#']).next().unwrap_or_default()) {
+            (Method::Post, "/players") => return ingest_players(players, request),
+            (Method::Get, "/players/stream") => {
+                // Held open for as long as the viewer stays, so off the worker pool
+                let feed = Arc::clone(&players.feed);
+                std::thread::spawn(move || {
+                    if let Err(e) = stream_players(&feed, request) {
+                        tracing::debug!("Player stream closed: {}", e);
+                    }
+                });
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+
+    if !matches!(request.method(), Method::Get | Method::Head) {
+        return Ok(request.respond(Response::empty(StatusCode(405)))?);
+    }
+
+    if let Some(prefetcher) = prefetcher {
+        let path = request.url().split(['?', '#']).next().unwrap_or_default();
+        if let Some(key) = TileKey::parse(path.trim_start_matches('/'), prefetcher.format())
+            && let Err(e) = prefetcher.request(key)
+        {
+            tracing::warn!("Failed to render tile {:?}: {}", key, e);
+        }
+    }
+
+    let Some(path) = resolve(dir, request.url()) else {
+        return Ok(request.respond(Response::from_string("Not found").with_status_code(404))?);
+    };
+
+    let metadata = fs::metadata(&path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let etag = format!("\"{:x}-{:x}\"", metadata.len(), modified.as_nanos());
+
+    let not_modified = request
+        .headers()
+        .iter()
+        .any(|h| h.field.equiv("If-None-Match") && h.value.as_str() == etag);
+    let headers = [
+        header("ETag", &etag),
+        // Revalidate every time so a rebuild shows up on reload; unchanged
+        // files are answered with an empty 304
+        header("Cache-Control", "no-cache"),
+        header("Content-Type", content_type(&path)),
+    ];
+
+    if not_modified {
+        let mut response = Response::empty(StatusCode(304));
+        for h in headers {
+            response.add_header(h);
+        }
+        return Ok(request.respond(response)?);
+    }
+
+    let mut file = fs::File::open(&path).with_context(|| format!("Failed to open {:?}", path))?;
+    let range = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Range"))
+        .map(|h| byte_range(h.value.as_str(), metadata.len()));
+    let mut headers = Vec::from(headers);
+    headers.push(header("Accept-Ranges", "bytes"));
+
+    // PMTiles archives are read a directory or tile at a time
+    let response = match range {
+        Some(Some((start, end))) => {
+            file.seek(SeekFrom::Start(start))?;
+            headers.push(header("Content-Range", &format!("bytes {}-{}/{}", start, end, metadata.len())));
+            let length = end - start + 1;
+            Response::new(StatusCode(206), headers, file.take(length), Some(length as usize), None).boxed()
+        }
+        Some(None) => {
+            headers.push(header("Content-Range", &format!("bytes */{}", metadata.len())));
+            Response::new(StatusCode(416), headers, std::io::empty(), Some(0), None).boxed()
+        }
+        None => {
+            let mut response = Response::from_file(file);
+            for h in headers {
+                response.add_header(h);
+            }
+            response.boxed()
+        }
+    };
+    Ok(request.respond(response)?)
+}
+
+/// Replaces the online players with the JSON list in the request body.
+fn ingest_players(players: &LivePlayers, mut request: Request) -> Result<()> {
+    let authorized = request
+        .headers()
+        .iter()
+        .any(|h| h.field.equiv("Authorization") && h.value.as_str().strip_prefix("Bearer ") == Some(players.token.as_str()));
+    if !authorized {
+        return Ok(request.respond(Response::empty(StatusCode(401)))?);
+    }
+
+    let mut body = String::new();
+    request.as_reader().take(MAX_PLAYERS_BODY).read_to_string(&mut body)?;
+    match parse_player_positions(&body) {
+        Ok(positions) => {
+            players.feed.update(positions);
+            Ok(request.respond(Response::empty(StatusCode(204)))?)
+        }
+        Err(e) => Ok(request.respond(Response::from_string(format!("{:#}", e)).with_status_code(400))?),
+    }
+}
+
+/// Sends the current players, then every update, as `players` events until
+/// the viewer disconnects.
+fn stream_players(feed: &PlayerFeed, request: Request) -> Result<()> {
+    let mut writer = request.into_writer();
+    write!(
+        writer,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n"
+    )?;
+
+    let (mut version, players) = feed.snapshot();
+    writer.write_all(players_sse_event(&players)?.as_bytes())?;
+    writer.flush()?;
+
+    loop {
+        match feed.wait_for_update(version, KEEP_ALIVE) {
+            Some((latest, players)) => {
+                version = latest;
+                writer.write_all(players_sse_event(&players)?.as_bytes())?;
+            }
+            None => writer.write_all(b": keep-alive\n\n")?,
+        }
+        writer.flush()?;
+    }
+}
+
+/// The first range of a `Range: bytes=...` header as inclusive offsets
+/// into a file of `length` bytes; `None` when it does not fit.
+fn byte_range(value: &str, length: u64) -> Option<(u64, u64)> {
+    let spec = value.trim().strip_prefix("bytes=")?.split(',').next()?.trim();
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        // The last `end` bytes
+        ("", end) => (length.checked_sub(end.parse::<u64>().ok()?.min(length))?, length.checked_sub(1)?),
+        (start, "") => (start.parse().ok()?, length.checked_sub(1)?),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(length.checked_sub(1)?)),
+    };
+    (start <= end).then_some((start, end))
+}
+
+/// Maps a request URL to a file inside `dir`. Directories serve their
+/// `index.html`; anything reaching outside `dir` is not found.
+fn resolve(dir: &Path, url: &str) -> Option<PathBuf> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let decoded = percent_decode(path)?;
+
+    let mut resolved = dir.to_path_buf();
+    for component in Path::new(decoded.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    if resolved.is_dir() {
+        resolved.push("index.html");
+    }
+    resolved.is_file().then_some(resolved)
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    match extension.to_ascii_lowercase().as_str() {
+        "html" => "text/html; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("static header names and ASCII values")
+}
@@ -0,0 +1,258 @@
+use crate::{parse_sector_coords, MapperError};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Width and height of a sector in tiles.
+pub const SECTOR_SIZE: u32 = 32;
+
+/// The rectangle of sectors a build covers, inclusive on both ends.
+///
+/// World coordinates are the server's absolute tile positions. Map
+/// coordinates are relative to the top-left tile of the rectangle; they are
+/// what [`SpriteMapData`](crate::SpriteMapData) stores and what the viewer
+/// uses as Leaflet `[lat, lng]` = `[y, x]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorldBounds {
+    pub min_sector_x: u32,
+    pub max_sector_x: u32,
+    pub min_sector_y: u32,
+    pub max_sector_y: u32,
+}
+
+impl WorldBounds {
+    /// Scans `map_dir` for the sectors present on `floors`, limited to
+    /// `sectors` when it is not empty.
+    pub fn scan<P: AsRef<Path>>(map_dir: P, floors: &[u8], sectors: &[(u32, u32)]) -> Result<Self> {
+        let map_dir = map_dir.as_ref();
+        let mut bounds: Option<Self> = None;
+        let mut found_floors = Vec::new();
+
+        for entry in fs::read_dir(map_dir).with_context(|| format!("Failed to read map directory: {:?}", map_dir))? {
+            let path = entry?.path();
+            if let Some(filename) = path.file_name().and_then(|n| n.to_str())
+                && let Some((x, y, z)) = parse_sector_coords(filename)
+                && floors.contains(&z)
+                && (sectors.is_empty() || sectors.contains(&(x, y)))
+            {
+                found_floors.push(z);
+                bounds = Some(match bounds {
+                    Some(b) => b.including(x, y),
+                    None => Self { min_sector_x: x, max_sector_x: x, min_sector_y: y, max_sector_y: y },
+                });
+            }
+        }
+
+        let missing: Vec<u8> = floors.iter().copied().filter(|z| !found_floors.contains(z)).collect();
+        if bounds.is_some() && !missing.is_empty() {
+            let label = if missing.len() == 1 { "floor" } else { "floors" };
+            tracing::warn!("No sector files for {} {}, left empty", label, format_floor_list(&missing));
+        }
+
+        match bounds {
+            Some(bounds) => Ok(bounds),
+            None if !sectors.is_empty() => Err(MapperError::InvalidSector(
+                "None of the requested sectors exist on the specified floors".to_string(),
+            )),
+            None => Err(MapperError::InvalidConfig(format!(
+                "No sector files for floors {} in {:?}; {}",
+                format_floor_list(floors),
+                map_dir,
+                match available_floors(map_dir)? {
+                    available if available.is_empty() => "the directory has no sector files".to_string(),
+                    available => format!("floors with sector files: {}", format_floor_list(&available)),
+                }
+            ))),
+        }
+    }
+
+    fn including(self, sector_x: u32, sector_y: u32) -> Self {
+        Self {
+            min_sector_x: self.min_sector_x.min(sector_x),
+            max_sector_x: self.max_sector_x.max(sector_x),
+            min_sector_y: self.min_sector_y.min(sector_y),
+            max_sector_y: self.max_sector_y.max(sector_y),
+        }
+    }
+
+    /// World coordinates of the top-left tile.
+    pub fn min_tile(&self) -> (u32, u32) {
+        (self.min_sector_x * SECTOR_SIZE, self.min_sector_y * SECTOR_SIZE)
+    }
+
+    /// World coordinates of the bottom-right tile.
+    pub fn max_tile(&self) -> (u32, u32) {
+        (
+            (self.max_sector_x + 1) * SECTOR_SIZE - 1,
+            (self.max_sector_y + 1) * SECTOR_SIZE - 1,
+        )
+    }
+
+    /// Width in tiles.
+    pub fn width(&self) -> u32 {
+        (self.max_sector_x - self.min_sector_x + 1) * SECTOR_SIZE
+    }
+
+    /// Height in tiles.
+    pub fn height(&self) -> u32 {
+        (self.max_sector_y - self.min_sector_y + 1) * SECTOR_SIZE
+    }
+
+    pub fn contains_world(&self, x: u32, y: u32) -> bool {
+        let (min_x, min_y) = self.min_tile();
+        let (max_x, max_y) = self.max_tile();
+        (min_x..=max_x).contains(&x) && (min_y..=max_y).contains(&y)
+    }
+
+    /// Converts world coordinates to map coordinates, or `None` when the
+    /// position lies outside the bounds.
+    pub fn world_to_map(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        let (min_x, min_y) = self.min_tile();
+        self.contains_world(x, y).then(|| (x - min_x, y - min_y))
+    }
+
+    pub fn map_to_world(&self, x: u32, y: u32) -> (u32, u32) {
+        let (min_x, min_y) = self.min_tile();
+        (min_x + x, min_y + y)
+    }
+}
+
+/// A rectangle of world tiles, inclusive on both ends, such as one city a
+/// mapper is iterating on. Written `x1,y1,x2,y2` in either corner order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Region {
+    pub min_x: u32,
+    pub min_y: u32,
+    pub max_x: u32,
+    pub max_y: u32,
+}
+
+impl Region {
+    /// The part of the region inside `bounds` as map coordinates
+    /// `(min_x, min_y, max_x, max_y)`, or `None` when they do not overlap.
+    pub fn to_map(&self, bounds: &WorldBounds) -> Option<(u32, u32, u32, u32)> {
+        let (min_tile_x, min_tile_y) = bounds.min_tile();
+        let (max_tile_x, max_tile_y) = bounds.max_tile();
+        if self.max_x < min_tile_x || self.min_x > max_tile_x || self.max_y < min_tile_y || self.min_y > max_tile_y {
+            return None;
+        }
+
+        Some((
+            self.min_x.max(min_tile_x) - min_tile_x,
+            self.min_y.max(min_tile_y) - min_tile_y,
+            self.max_x.min(max_tile_x) - min_tile_x,
+            self.max_y.min(max_tile_y) - min_tile_y,
+        ))
+    }
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{},{}", self.min_x, self.min_y, self.max_x, self.max_y)
+    }
+}
+
+impl TryFrom<String> for Region {
+    type Error = MapperError;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Region> for String {
+    fn from(region: Region) -> Self {
+        region.to_string()
+    }
+}
+
+impl std::str::FromStr for Region {
+    type Err = MapperError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || MapperError::InvalidConfig(format!("Invalid region {:?}, expected x1,y1,x2,y2 in world tiles", s));
+        let coords: Vec<u32> = s
+            .split(',')
+            .map(|c| c.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_>>()?;
+        let [x1, y1, x2, y2] = coords[..] else {
+            return Err(invalid());
+        };
+
+        Ok(Self { min_x: x1.min(x2), min_y: y1.min(y2), max_x: x1.max(x2), max_y: y1.max(y2) })
+    }
+}
+
+/// World coordinates of the tile at `(local_x, local_y)` inside a sector.
+pub fn sector_to_world(sector_x: u32, sector_y: u32, local_x: u32, local_y: u32) -> (u32, u32) {
+    (sector_x * SECTOR_SIZE + local_x, sector_y * SECTOR_SIZE + local_y)
+}
+
+/// Floors that have at least one sector file in `map_dir`, sorted.
+pub fn available_floors<P: AsRef<Path>>(map_dir: P) -> Result<Vec<u8>> {
+    let map_dir = map_dir.as_ref();
+    let mut floors: Vec<u8> = fs::read_dir(map_dir)
+        .with_context(|| format!("Failed to read map directory: {:?}", map_dir))?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| parse_sector_coords(entry.file_name().to_str()?).map(|(_, _, z)| z))
+        .collect();
+    floors.sort_unstable();
+    floors.dedup();
+    Ok(floors)
+}
+
+/// Writes floors compactly, e.g. `0, 7, 9-11`.
+pub fn format_floor_list(floors: &[u8]) -> String {
+    let mut sorted = floors.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut parts: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < sorted.len() {
+        let start = sorted[i];
+        while i + 1 < sorted.len() && sorted[i + 1] == sorted[i] + 1 {
+            i += 1;
+        }
+        parts.push(if sorted[i] == start { start.to_string() } else { format!("{}-{}", start, sorted[i]) });
+        i += 1;
+    }
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_world_bounds_conversions() {
+        let bounds = WorldBounds { min_sector_x: 1000, max_sector_x: 1001, min_sector_y: 1000, max_sector_y: 1000 };
+        assert_eq!(bounds.min_tile(), (32000, 32000));
+        assert_eq!(bounds.max_tile(), (32063, 32031));
+        assert_eq!((bounds.width(), bounds.height()), (64, 32));
+
+        assert_eq!(bounds.world_to_map(32010, 32005), Some((10, 5)));
+        assert_eq!(bounds.world_to_map(32064, 32005), None);
+        assert_eq!(bounds.map_to_world(10, 5), (32010, 32005));
+        assert_eq!(sector_to_world(1001, 1000, 3, 4), (32035, 32004));
+    }
+
+    #[test]
+    fn test_region_clips_to_bounds() {
+        let region: Region = "32100, 32050, 31990,32010".parse().unwrap();
+        assert_eq!(region, Region { min_x: 31990, min_y: 32010, max_x: 32100, max_y: 32050 });
+        assert!("1,2,3".parse::<Region>().is_err());
+
+        let bounds = WorldBounds { min_sector_x: 1000, max_sector_x: 1003, min_sector_y: 1000, max_sector_y: 1000 };
+        assert_eq!(region.to_map(&bounds), Some((0, 10, 100, 31)));
+        assert_eq!(Region { min_x: 0, min_y: 0, max_x: 10, max_y: 10 }.to_map(&bounds), None);
+    }
+
+    #[test]
+    fn test_format_floor_list() {
+        assert_eq!(format_floor_list(&[11, 0, 7, 9, 10, 7]), "0, 7, 9-11");
+        assert_eq!(format_floor_list(&[]), "");
+    }
+}
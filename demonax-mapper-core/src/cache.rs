@@ -0,0 +1,288 @@
+use crate::{MapperError, ParseIssue, SpriteMapData, TileStack, RENDER_STATE_FILE, SPRITE_MAP_VERSION};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Cache location used when none is configured, relative to the working
+/// directory.
+pub const DEFAULT_CACHE_DIR: &str = ".demonax-cache";
+
+/// Bump when the binary layout of cached floors changes. Files written with
+/// another version are discarded and the floor is parsed again.
+pub const CACHE_FORMAT_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 4] = b"DMXF";
+const FLAG_ZSTD: u8 = 1;
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 3;
+
+/// [`SpriteMapData`] as stored in the cache. bincode is not self-describing,
+/// so the `skip_serializing_if` attributes that keep the JSON reports tidy
+/// would corrupt it; parse issues are stored as plain tuples instead.
+#[derive(Serialize, Deserialize)]
+struct CachedFloor<'a> {
+    floor: u8,
+    min_sector_x: u32,
+    max_sector_x: u32,
+    min_sector_y: u32,
+    max_sector_y: u32,
+    version: u32,
+    tiles: Cow<'a, [TileStack]>,
+    parse_issues: Vec<(String, Option<usize>, String)>,
+}
+
+/// Encodes a floor as `DMXF`, the format version, a flags byte and the
+/// bincode payload, zstd-compressed when the `zstd` feature is enabled.
+pub fn encode_floor_cache(map_data: &SpriteMapData) -> Result<Vec<u8>> {
+    let cached = CachedFloor {
+        floor: map_data.floor,
+        min_sector_x: map_data.min_sector_x,
+        max_sector_x: map_data.max_sector_x,
+        min_sector_y: map_data.min_sector_y,
+        max_sector_y: map_data.max_sector_y,
+        version: map_data.version,
+        tiles: Cow::Borrowed(&map_data.tiles),
+        parse_issues: map_data
+            .parse_issues
+            .iter()
+            .map(|issue| (issue.file.clone(), issue.line, issue.reason.clone()))
+            .collect(),
+    };
+    let payload = bincode::serialize(&cached)?;
+
+    let mut data = Vec::with_capacity(payload.len() / 2 + 9);
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&CACHE_FORMAT_VERSION.to_le_bytes());
+    #[cfg(feature = "zstd")]
+    {
+        data.push(FLAG_ZSTD);
+        data.extend(zstd::encode_all(payload.as_slice(), ZSTD_LEVEL)?);
+    }
+    #[cfg(not(feature = "zstd"))]
+    {
+        data.push(0);
+        data.extend(payload);
+    }
+    Ok(data)
+}
+
+/// Fails on anything [`encode_floor_cache`] of this build would not have
+/// written, including other format versions.
+pub fn decode_floor_cache(data: &[u8]) -> Result<SpriteMapData> {
+    if data.len() < 9 || &data[..4] != MAGIC {
+        return Err(MapperError::Cache("not a floor cache file".to_string()));
+    }
+    let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    if version != CACHE_FORMAT_VERSION {
+        return Err(MapperError::Cache(format!(
+            "cache format version {} (expected {})",
+            version, CACHE_FORMAT_VERSION
+        )));
+    }
+
+    let (flags, payload) = (data[8], &data[9..]);
+    let payload = if flags & FLAG_ZSTD != 0 {
+        decompress(payload)?
+    } else {
+        Cow::Borrowed(payload)
+    };
+
+    let cached: CachedFloor = bincode::deserialize(&payload)?;
+    Ok(SpriteMapData {
+        floor: cached.floor,
+        tiles: cached.tiles.into_owned(),
+        min_sector_x: cached.min_sector_x,
+        max_sector_x: cached.max_sector_x,
+        min_sector_y: cached.min_sector_y,
+        max_sector_y: cached.max_sector_y,
+        version: cached.version,
+        parse_issues: cached
+            .parse_issues
+            .into_iter()
+            .map(|(file, line, reason)| ParseIssue { file, line, reason })
+            .collect(),
+    })
+}
+
+#[cfg(feature = "zstd")]
+fn decompress(payload: &[u8]) -> Result<Cow<'_, [u8]>> {
+    Ok(Cow::Owned(zstd::decode_all(payload)?))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress(_payload: &[u8]) -> Result<Cow<'_, [u8]>> {
+    Err(MapperError::Cache("compressed with zstd, which this build does not support".to_string()))
+}
+
+/// Parsed objects and floors kept between builds: `<dir>/objects.json`,
+/// `<dir>/maps/` and the incremental render state.
+#[derive(Debug, Clone)]
+pub struct CacheStore {
+    dir: PathBuf,
+}
+
+impl CacheStore {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(dir.join("maps")).with_context(|| format!("Failed to create cache directory {:?}", dir))?;
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn objects_path(&self) -> PathBuf {
+        self.dir.join("objects.json")
+    }
+
+    pub fn render_state_path(&self) -> PathBuf {
+        self.dir.join(RENDER_STATE_FILE)
+    }
+
+    pub fn floor_path(&self, floor: u8) -> PathBuf {
+        self.dir.join("maps").join(format!("floor_{:02}.bin", floor))
+    }
+
+    /// JSON cache written by releases before the binary format.
+    fn legacy_floor_path(&self, floor: u8) -> PathBuf {
+        self.dir.join("maps").join(format!("floor_{:02}_sprite.json", floor))
+    }
+
+    /// The cached floor, or `None` when there is none or it was written by
+    /// another cache format or an older parser, so the caller re-parses it.
+    /// A JSON cache from an older release is converted on first load.
+    pub fn load_floor(&self, floor: u8) -> Option<SpriteMapData> {
+        let path = self.floor_path(floor);
+        let map_data = match fs::read(&path) {
+            Ok(data) => match decode_floor_cache(&data) {
+                Ok(map_data) => map_data,
+                Err(e) => {
+                    tracing::info!("Discarding cache for floor {}: {:#}", floor, e);
+                    return None;
+                }
+            },
+            Err(_) => self.migrate_legacy_floor(floor)?,
+        };
+
+        if map_data.version < SPRITE_MAP_VERSION {
+            tracing::info!("Regenerating outdated cache for floor {}", floor);
+            return None;
+        }
+        Some(map_data)
+    }
+
+    /// Writes `map_data` to its floor's cache file, returning the size.
+    pub fn save_floor(&self, map_data: &SpriteMapData) -> Result<u64> {
+        let path = self.floor_path(map_data.floor);
+        let data = encode_floor_cache(map_data)?;
+        fs::write(&path, &data).with_context(|| format!("Failed to write {:?}", path))?;
+        Ok(data.len() as u64)
+    }
+
+    /// Deletes everything in the cache so the next build parses from scratch.
+    /// Only removes what builds write, in case the directory was shared.
+    pub fn clear(&self) -> Result<()> {
+        for path in [self.objects_path(), self.render_state_path()] {
+            if path.exists() {
+                fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+            }
+        }
+        let maps_dir = self.dir.join("maps");
+        fs::remove_dir_all(&maps_dir).with_context(|| format!("Failed to remove {:?}", maps_dir))?;
+        fs::create_dir_all(&maps_dir)?;
+        Ok(())
+    }
+
+    /// Every cached floor file, sorted by name.
+    pub fn floor_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = match fs::read_dir(self.dir.join("maps")) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("bin"))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        files.sort();
+        files
+    }
+
+    fn migrate_legacy_floor(&self, floor: u8) -> Option<SpriteMapData> {
+        let legacy_path = self.legacy_floor_path(floor);
+        let content = fs::read_to_string(&legacy_path).ok()?;
+        fs::remove_file(&legacy_path).ok();
+
+        let map_data: SpriteMapData = serde_json::from_str(&content).ok()?;
+        if map_data.version < SPRITE_MAP_VERSION {
+            return None;
+        }
+        match self.save_floor(&map_data) {
+            Ok(_) => tracing::info!("Converted JSON cache for floor {} to the binary format", floor),
+            Err(e) => tracing::warn!("Failed to convert JSON cache for floor {}: {:#}", floor, e),
+        }
+        Some(map_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_floor() -> SpriteMapData {
+        SpriteMapData {
+            floor: 7,
+            tiles: vec![
+                TileStack { x: 0, y: 0, object_ids: vec![100, 200] },
+                TileStack { x: 5, y: 3, object_ids: vec![101] },
+            ],
+            min_sector_x: 1000,
+            max_sector_x: 1001,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            version: SPRITE_MAP_VERSION,
+            parse_issues: vec![
+                ParseIssue { file: "1000-1000-07.sec".into(), line: Some(4), reason: "bad tile".into() },
+                ParseIssue { file: "1001-1000-07.sec".into(), line: None, reason: "unreadable".into() },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_floor_cache_round_trip_and_version_check() {
+        let map_data = sample_floor();
+        let data = encode_floor_cache(&map_data).unwrap();
+        let decoded = decode_floor_cache(&data).unwrap();
+        assert_eq!(decoded.tiles.len(), 2);
+        assert_eq!(decoded.tiles[0].object_ids, vec![100, 200]);
+        assert_eq!(decoded.parse_issues, map_data.parse_issues);
+        assert_eq!(decoded.bounds(), map_data.bounds());
+
+        let mut other_version = data.clone();
+        other_version[4..8].copy_from_slice(&(CACHE_FORMAT_VERSION + 1).to_le_bytes());
+        assert!(decode_floor_cache(&other_version).is_err());
+        assert!(decode_floor_cache(b"{\"floor\":7}").is_err());
+    }
+
+    #[test]
+    fn test_legacy_json_cache_is_migrated() {
+        let dir = std::env::temp_dir().join(format!("demonax-cache-store-{}", std::process::id()));
+        let store = CacheStore::open(&dir).unwrap();
+        let legacy_path = dir.join("maps/floor_07_sprite.json");
+        fs::write(&legacy_path, serde_json::to_string(&sample_floor()).unwrap()).unwrap();
+
+        let migrated = store.load_floor(7).unwrap();
+        assert_eq!(migrated.tiles.len(), 2);
+        assert!(!legacy_path.exists());
+        assert_eq!(store.floor_files(), vec![store.floor_path(7)]);
+        assert_eq!(store.load_floor(7).unwrap().parse_issues.len(), 2);
+        assert!(store.load_floor(8).is_none());
+
+        store.clear().unwrap();
+        assert!(store.floor_files().is_empty());
+        assert!(store.load_floor(7).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,856 @@
+use crate::html::MarkerStyles;
+use crate::{InputEncoding, MapperError, Region, DEFAULT_CACHE_DIR};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const MAX_FLOOR: u8 = 15;
+pub const MAX_ZOOM: u8 = 8;
+
+/// Everything a `build` run needs. Filled from CLI flags or deserialized from a
+/// config file; call [`BuildConfig::validate`] before using it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildConfig {
+    pub objects_path: PathBuf,
+    /// Extra `.srv` files merged over `objects_path`, later files winning.
+    #[serde(default)]
+    pub objects_extra: Vec<PathBuf>,
+    pub map_path: PathBuf,
+    /// Sprite PNG directory, zip of PNGs or sheet index. Only optional when
+    /// `client_spr`/`client_dat` are given, or for color builds given a
+    /// `color_map` file.
+    #[serde(default)]
+    pub sprite_path: Option<PathBuf>,
+    /// Directory of `<id>.png` files, or a JSON `{"<id>": "file.png"}` mapping,
+    /// used instead of `sprite_path` for the objects it lists.
+    #[serde(default)]
+    pub sprite_overrides: Option<PathBuf>,
+    /// Draw the nearest lower or upper object ID's sprite instead of the
+    /// placeholder when a sprite is missing or corrupt.
+    #[serde(default)]
+    pub substitute_sprites: bool,
+    /// Abort the build, listing every ID, instead of drawing placeholders
+    /// for missing or corrupt sprites.
+    #[serde(default)]
+    pub strict: bool,
+    /// Most decoded sprite memory to keep, in MiB; the least recently drawn
+    /// sprites are dropped and decoded again when needed. Unlimited if unset.
+    #[serde(default)]
+    pub sprite_cache_mb: Option<usize>,
+    /// Which frames of animated sprites are drawn.
+    #[serde(default)]
+    pub sprite_frames: SpriteFrames,
+    /// TOML or JSON rules placing objects on drawing layers by ID, flags or
+    /// name, tried before the built-in [`DEFAULT_LAYER_RULES`].
+    #[serde(default)]
+    pub layer_rules: Option<PathBuf>,
+    /// Draw tiles from sprites, or as flat minimap-style colors.
+    #[serde(default)]
+    pub render_mode: RenderMode,
+    /// Object colors for color builds, as written by `export-colors`, in
+    /// TOML or JSON. Computed from the sprites when not given.
+    #[serde(default, alias = "colors")]
+    pub color_map: Option<PathBuf>,
+    /// Image format of the map tiles. Overlay tiles such as the heatmap
+    /// are always PNG.
+    #[serde(default)]
+    pub tile_format: TileFormat,
+    /// Draw only the max zoom level and make each lower level by shrinking
+    /// the four tiles below it. Whole floors only.
+    #[serde(default)]
+    pub pyramid: bool,
+    /// Pack the map, composite and heatmap tiles into PMTiles archives
+    /// instead of one file per tile, for static hosts and object storage.
+    #[serde(default)]
+    pub pmtiles: Option<PmtilesLayout>,
+    /// Stream the map, composite and heatmap tiles into one `.zip` or
+    /// `.tar.gz` file instead of the output directory, to be unpacked
+    /// there on the server.
+    #[serde(default)]
+    pub output_archive: Option<PathBuf>,
+    /// Also render an "All floors" layer: each floor drawn over the floors
+    /// beneath it, the nearest at `composite_brightness[0]` of its
+    /// brightness, the next at `[1]` and so on. Empty to skip it.
+    #[serde(default)]
+    pub composite_brightness: Vec<f32>,
+    #[serde(default = "default_output")]
+    pub output: PathBuf,
+    /// Either a list (`[7, 8]`) or a range spec (`"0-15"`).
+    #[serde(deserialize_with = "deserialize_floors")]
+    pub floors: Vec<u8>,
+    #[serde(default)]
+    pub min_zoom: u8,
+    #[serde(default = "default_max_zoom")]
+    pub max_zoom: u8,
+    #[serde(default)]
+    pub monster_db: Option<PathBuf>,
+    #[serde(default)]
+    pub mon_path: Option<PathBuf>,
+    #[serde(default)]
+    pub monster_sprites: Option<PathBuf>,
+    /// Client `.spr`/`.dat` pair used to draw monster icons from each race's
+    /// `Outfit` instead of `monster_sprites`, and map sprites when there is
+    /// no `sprite_path`.
+    #[serde(default)]
+    pub client_spr: Option<PathBuf>,
+    #[serde(default)]
+    pub client_dat: Option<PathBuf>,
+    #[serde(default)]
+    pub npc_csv: Option<PathBuf>,
+    #[serde(default)]
+    pub npc_sprites: Option<PathBuf>,
+    #[serde(default)]
+    pub quest_csv: Option<PathBuf>,
+    /// Directory of `.raid` files for the raids overlay.
+    #[serde(default)]
+    pub raids_path: Option<PathBuf>,
+    /// Game data directories layered over `monster_db`, `mon_path`,
+    /// `quest_csv` and `raids_path`, later directories overriding earlier ones.
+    #[serde(default)]
+    pub data_paths: Vec<PathBuf>,
+    /// Encoding of objects.srv, sector, `.mon`, NPC and quest files.
+    #[serde(default)]
+    pub encoding: InputEncoding,
+    /// `item_id,avg_price` CSV used to show market values in popups.
+    #[serde(default)]
+    pub prices_csv: Option<PathBuf>,
+    /// `x,y,z[,weight]` CSV rendered as a heat overlay.
+    #[serde(default)]
+    pub heatmap_csv: Option<PathBuf>,
+    /// House id → owner / rent / auction JSON, used to color houses.
+    #[serde(default)]
+    pub houses_status: Option<PathBuf>,
+    /// `house_id,name` CSV naming the houses found in the sector files.
+    #[serde(default)]
+    pub house_names: Option<PathBuf>,
+    /// Spread of each heatmap point, in tiles.
+    #[serde(default = "default_heatmap_radius")]
+    pub heatmap_radius: u32,
+    /// Adds the staff-only online player overlay, fed by `serve`.
+    #[serde(default)]
+    pub live_players: bool,
+    /// Minutes between viewer re-fetches of spawn and quest chest data.
+    #[serde(default)]
+    pub overlay_refresh_minutes: Option<u32>,
+    /// Enables the viewer's right-click admin command with this template.
+    #[serde(default)]
+    pub admin_command: Option<String>,
+    /// Minimum zoom, size and color of the spawn, NPC, quest chest and grid overlays.
+    #[serde(default)]
+    pub marker_styles: MarkerStyles,
+    /// Overlays to generate. Defaults to all of them.
+    #[serde(default = "default_overlays")]
+    pub overlays: Vec<Overlay>,
+    /// Also archive the rendered tiles under `history/<date>/`.
+    #[serde(default)]
+    pub archive: Option<String>,
+    /// Only parse and render these `(x, y)` sectors, on every floor. Empty
+    /// means the whole map.
+    #[serde(default)]
+    pub sectors: Vec<(u32, u32)>,
+    /// Only re-render the tiles covering this rectangle of world tiles,
+    /// keeping the full map bounds so they replace tiles of a full build.
+    #[serde(default)]
+    pub region: Option<Region>,
+    /// Re-render only the tiles covering sector files that changed since the
+    /// last build into the same output directory.
+    #[serde(default)]
+    pub incremental: bool,
+    /// Where parsed objects and floors are kept between builds.
+    #[serde(default = "default_cache_dir")]
+    pub cache_dir: PathBuf,
+    /// Discard the cache and parse everything again.
+    #[serde(default)]
+    pub force: bool,
+    /// Directory of CSS/JS files copied to `extra/` and linked from the viewer.
+    #[serde(default)]
+    pub extra_assets: Option<PathBuf>,
+    /// Leaflet's `dist` directory, copied to `leaflet/` so the viewer loads
+    /// nothing from the internet.
+    #[serde(default)]
+    pub offline: Option<PathBuf>,
+    #[serde(default)]
+    pub threads: Option<usize>,
+}
+
+fn default_output() -> PathBuf {
+    PathBuf::from("output")
+}
+
+fn default_cache_dir() -> PathBuf {
+    PathBuf::from(DEFAULT_CACHE_DIR)
+}
+
+fn default_max_zoom() -> u8 {
+    5
+}
+
+fn default_overlays() -> Vec<Overlay> {
+    Overlay::ALL.to_vec()
+}
+
+fn default_heatmap_radius() -> u32 {
+    8
+}
+
+impl BuildConfig {
+    /// Checks ranges and input paths, reporting every problem at once.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        if self.floors.is_empty() {
+            problems.push("no floors selected".to_string());
+        }
+        let bad_floors: Vec<String> = self
+            .floors
+            .iter()
+            .filter(|&&f| f > MAX_FLOOR)
+            .map(|f| f.to_string())
+            .collect();
+        if !bad_floors.is_empty() {
+            problems.push(format!(
+                "floors must be between 0 and {}, got {}",
+                MAX_FLOOR,
+                bad_floors.join(", ")
+            ));
+        }
+
+        if self.min_zoom > self.max_zoom {
+            problems.push(format!(
+                "min_zoom ({}) must not be greater than max_zoom ({})",
+                self.min_zoom, self.max_zoom
+            ));
+        }
+        if self.max_zoom > MAX_ZOOM {
+            problems.push(format!(
+                "max_zoom ({}) must not exceed {}",
+                self.max_zoom, MAX_ZOOM
+            ));
+        }
+
+        if self.threads == Some(0) {
+            problems.push("threads must be at least 1".to_string());
+        }
+        if let Some(date) = &self.archive
+            && !crate::is_iso_date(date)
+        {
+            problems.push(format!("archive date must be YYYY-MM-DD, got {:?}", date));
+        }
+        if self.overlay_refresh_minutes == Some(0) {
+            problems.push("overlay_refresh_minutes must be at least 1".to_string());
+        }
+
+        if !self.objects_path.is_file() {
+            problems.push(format!("objects file not found: {:?}", self.objects_path));
+        }
+        for extra in &self.objects_extra {
+            if !extra.is_file() {
+                problems.push(format!("extra objects file not found: {:?}", extra));
+            }
+        }
+        if !self.map_path.is_dir() {
+            problems.push(format!("map directory not found: {:?}", self.map_path));
+        }
+        let has_client_sprites = self.client_spr.is_some() && self.client_dat.is_some();
+        match (&self.sprite_path, self.render_mode, &self.color_map) {
+            (Some(sprite_path), _, _) if !sprite_path.exists() => {
+                problems.push(format!("sprite directory, archive or sheet index not found: {:?}", sprite_path));
+            }
+            (None, _, _) if has_client_sprites => {}
+            (None, RenderMode::Sprites, _) => {
+                problems.push("sprite_path or client_spr/client_dat is required for sprite rendering".to_string())
+            }
+            (None, RenderMode::Color, None) => {
+                problems.push("color rendering needs either sprite_path or a color_map file".to_string())
+            }
+            _ => {}
+        }
+        if let Some(color_map) = &self.color_map
+            && !color_map.is_file()
+        {
+            problems.push(format!("color map not found: {:?}", color_map));
+        }
+        if let Some(overrides) = &self.sprite_overrides
+            && !overrides.exists()
+        {
+            problems.push(format!("sprite overrides not found: {:?}", overrides));
+        }
+        if let Some(rules) = &self.layer_rules
+            && !rules.is_file()
+        {
+            problems.push(format!("layer rules not found: {:?}", rules));
+        }
+        for data_path in &self.data_paths {
+            if !data_path.is_dir() {
+                problems.push(format!("data path not found: {:?}", data_path));
+            }
+        }
+        if let Some(prices) = &self.prices_csv
+            && !prices.is_file()
+        {
+            problems.push(format!("prices CSV not found: {:?}", prices));
+        }
+        if let Some(heatmap) = &self.heatmap_csv
+            && !heatmap.is_file()
+        {
+            problems.push(format!("heatmap CSV not found: {:?}", heatmap));
+        }
+        if let Some(houses_status) = &self.houses_status
+            && !houses_status.is_file()
+        {
+            problems.push(format!("house status file not found: {:?}", houses_status));
+        }
+        if let Some(house_names) = &self.house_names
+            && !house_names.is_file()
+        {
+            problems.push(format!("house names CSV not found: {:?}", house_names));
+        }
+        self.marker_styles.validate(&mut problems);
+
+        match (&self.client_spr, &self.client_dat) {
+            (Some(spr), Some(dat)) => {
+                if !spr.is_file() {
+                    problems.push(format!("client sprite archive not found: {:?}", spr));
+                }
+                if !dat.is_file() {
+                    problems.push(format!("client dat file not found: {:?}", dat));
+                }
+            }
+            (None, None) => {}
+            _ => problems.push("client_spr and client_dat must be given together".to_string()),
+        }
+        if let Some(extra_assets) = &self.extra_assets
+            && !extra_assets.is_dir()
+        {
+            problems.push(format!("extra assets directory not found: {:?}", extra_assets));
+        }
+        if self.sprite_cache_mb == Some(0) {
+            problems.push("sprite_cache_mb must be at least 1".to_string());
+        }
+        if let Some(leaflet_dir) = &self.offline {
+            let pmtiles_js = self.pmtiles.map(|_| "pmtiles.js");
+            for file in ["leaflet.js", "leaflet.css"].into_iter().chain(pmtiles_js) {
+                if !leaflet_dir.join(file).is_file() {
+                    problems.push(format!("{} not found in Leaflet directory {:?}", file, leaflet_dir));
+                }
+            }
+        }
+        if self.incremental && !self.sectors.is_empty() {
+            problems.push("incremental builds render the whole map and cannot be limited to sectors".to_string());
+        }
+        if self.region.is_some() && !self.sectors.is_empty() {
+            problems.push("region keeps the full map bounds and cannot be combined with sectors".to_string());
+        }
+        if self.incremental && self.region.is_some() {
+            problems.push("incremental builds pick their own tiles and cannot be limited to a region".to_string());
+        }
+        if self.pyramid && (self.incremental || self.region.is_some()) {
+            problems.push("pyramid builds render whole floors and cannot be combined with region or incremental".to_string());
+        }
+        if self.pmtiles.is_some() && (self.incremental || self.region.is_some() || self.archive.is_some()) {
+            problems.push("PMTiles archives are written whole and cannot be combined with region, incremental or archive".to_string());
+        }
+        if let Some(path) = &self.output_archive {
+            if TileArchiveFormat::of(path).is_none() {
+                problems.push(format!("output_archive must end in .zip, .tar.gz or .tgz, got {:?}", path));
+            }
+            if self.pmtiles.is_some() || self.incremental || self.region.is_some() || self.archive.is_some() {
+                problems.push("output_archive replaces the tile directories and cannot be combined with pmtiles, region, incremental or archive".to_string());
+            }
+        }
+        if self.pmtiles == Some(PmtilesLayout::Combined) && self.max_zoom + PMTILES_FLOOR_ZOOM_SHIFT > 31 {
+            problems.push(format!("combined PMTiles archives allow max_zoom up to {}", 31 - PMTILES_FLOOR_ZOOM_SHIFT));
+        }
+        if self.sprite_frames == SpriteFrames::Animate && (self.tile_format != TileFormat::Webp || self.pyramid) {
+            problems.push("animated sprites need tile_format webp and cannot be combined with pyramid".to_string());
+        }
+        if !self.composite_brightness.is_empty() {
+            if self.render_mode != RenderMode::Sprites {
+                problems.push("the all floors composite is drawn from sprites and needs render_mode sprites".to_string());
+            }
+            if self.composite_brightness.len() > MAX_FLOOR as usize {
+                problems.push(format!("composite_brightness can list at most {} floors", MAX_FLOOR));
+            }
+            if self.composite_brightness.iter().any(|b| !(*b > 0.0 && *b <= 1.0)) {
+                problems.push(format!("composite_brightness values must be above 0 and at most 1, got {:?}", self.composite_brightness));
+            }
+        }
+        if self.incremental && self.force {
+            problems.push("force clears the state incremental builds compare against".to_string());
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        Err(MapperError::InvalidConfig(format!(
+            "Invalid build configuration:\n  - {}",
+            problems.join("\n  - ")
+        )))
+    }
+}
+
+/// Parses a floor spec: a comma-separated list of floors and ranges, such as
+/// `7`, `0-15` or `0,7,9-11`. Returns the floors sorted, without duplicates.
+pub fn parse_floor_range(s: &str) -> Result<Vec<u8>> {
+    let parse_floor = |part: &str| -> Result<u8> {
+        let floor = part.trim().parse::<u8>().with_context(|| format!("Invalid floor {:?}", part.trim()))?;
+        if floor > MAX_FLOOR {
+            return Err(MapperError::InvalidConfig(format!(
+                "Floor {} is out of range, floors are 0-{}",
+                floor, MAX_FLOOR
+            )));
+        }
+        Ok(floor)
+    };
+
+    let mut floors = Vec::new();
+    for part in s.split(',').filter(|part| !part.trim().is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse_floor(start)?, parse_floor(end)?);
+                if start > end {
+                    return Err(MapperError::InvalidConfig(format!(
+                        "Invalid floor range {:?}, start is above end",
+                        part.trim()
+                    )));
+                }
+                floors.extend(start..=end);
+            }
+            None => floors.push(parse_floor(part)?),
+        }
+    }
+    if floors.is_empty() {
+        return Err(MapperError::InvalidConfig(format!("No floors in {:?}", s)));
+    }
+
+    floors.sort_unstable();
+    floors.dedup();
+    Ok(floors)
+}
+
+/// A viewer overlay the build can generate. Skipped overlays write no data
+/// file, so the viewer hides their toggles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overlay {
+    Spawns,
+    Quests,
+    Npcs,
+    Water,
+    Zones,
+    Heatmap,
+    Houses,
+    Transitions,
+    Teleports,
+    Signs,
+    Poi,
+    Raids,
+}
+
+impl Overlay {
+    pub const ALL: [Overlay; 12] = [
+        Overlay::Spawns,
+        Overlay::Quests,
+        Overlay::Npcs,
+        Overlay::Water,
+        Overlay::Zones,
+        Overlay::Heatmap,
+        Overlay::Houses,
+        Overlay::Transitions,
+        Overlay::Teleports,
+        Overlay::Signs,
+        Overlay::Poi,
+        Overlay::Raids,
+    ];
+
+    /// Files and directories this overlay writes to the output directory.
+    pub fn outputs(self) -> &'static [&'static str] {
+        match self {
+            Overlay::Spawns => &["spawns.json", "hunting.json", "monsters"],
+            Overlay::Quests => &["questchests.json"],
+            Overlay::Npcs => &["npcs.json", "npcs"],
+            Overlay::Water => &["water.json"],
+            Overlay::Zones => &["zones.json"],
+            Overlay::Heatmap => &["heatmap.json", "heatmap"],
+            Overlay::Houses => &["houses.json", "houses-status.json"],
+            Overlay::Transitions => &["transitions.json"],
+            Overlay::Teleports => &["teleports.json"],
+            Overlay::Signs => &["signs.json"],
+            Overlay::Poi => &["poi.json"],
+            Overlay::Raids => &["raids.json"],
+        }
+    }
+}
+
+impl std::str::FromStr for Overlay {
+    type Err = MapperError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "spawns" => Ok(Overlay::Spawns),
+            "quests" => Ok(Overlay::Quests),
+            "npcs" => Ok(Overlay::Npcs),
+            "water" => Ok(Overlay::Water),
+            "zones" => Ok(Overlay::Zones),
+            "heatmap" => Ok(Overlay::Heatmap),
+            "houses" => Ok(Overlay::Houses),
+            "transitions" => Ok(Overlay::Transitions),
+            "teleports" => Ok(Overlay::Teleports),
+            "signs" => Ok(Overlay::Signs),
+            "poi" => Ok(Overlay::Poi),
+            "raids" => Ok(Overlay::Raids),
+            _ => Err(MapperError::InvalidConfig(format!(
+                "Unknown overlay {:?}, expected spawns, quests, npcs, water, zones, heatmap, houses, transitions, teleports, signs, poi, raids, all or none",
+                s
+            ))),
+        }
+    }
+}
+
+/// Parses `spawns,quests`, `all` or `none` into a list of overlays.
+pub fn parse_overlay_list(s: &str) -> Result<Vec<Overlay>> {
+    match s.trim() {
+        "all" => Ok(Overlay::ALL.to_vec()),
+        "none" | "" => Ok(Vec::new()),
+        list => list.split(',').map(str::parse).collect(),
+    }
+}
+
+/// How map tiles are drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderMode {
+    /// Object sprites, layered like the game client.
+    #[default]
+    Sprites,
+    /// One flat color per game tile, from its topmost colored object.
+    Color,
+}
+
+impl std::str::FromStr for RenderMode {
+    type Err = MapperError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "sprites" => Ok(RenderMode::Sprites),
+            "color" | "colour" => Ok(RenderMode::Color),
+            _ => Err(MapperError::InvalidConfig(format!("Unknown render mode {:?}, expected sprites or color", s))),
+        }
+    }
+}
+
+/// Image format of the map tiles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TileFormat {
+    /// PNG carrying provenance text chunks.
+    #[default]
+    Png,
+    /// Lossless WebP, usually far smaller than PNG for sprite tiles.
+    Webp,
+    /// Lossy AVIF, the smallest but the slowest to encode.
+    Avif,
+}
+
+impl TileFormat {
+    /// File extension of tiles in this format, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            TileFormat::Png => "png",
+            TileFormat::Webp => "webp",
+            TileFormat::Avif => "avif",
+        }
+    }
+}
+
+impl std::str::FromStr for TileFormat {
+    type Err = MapperError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "png" => Ok(TileFormat::Png),
+            "webp" => Ok(TileFormat::Webp),
+            "avif" => Ok(TileFormat::Avif),
+            _ => Err(MapperError::InvalidConfig(format!("Unknown tile format {:?}, expected png, webp or avif", s))),
+        }
+    }
+}
+
+/// How a PMTiles build packs its tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PmtilesLayout {
+    /// One archive per floor and tile directory, e.g. `7.pmtiles` and
+    /// `composite/7.pmtiles`.
+    PerFloor,
+    /// One `tiles.pmtiles` per tile directory holding every floor, floor
+    /// `f`'s tile `z/x/y` stored at [`PMTILES_FLOOR_ZOOM_SHIFT`] zoom levels
+    /// deeper, as `(z + 12)/(x + f * 2^(z + 8))/y`.
+    Combined,
+}
+
+/// Zoom levels a combined PMTiles archive adds to each tile: 4 bits of
+/// floor and 8 bits of room for the map width.
+pub const PMTILES_FLOOR_ZOOM_SHIFT: u8 = 12;
+
+impl std::str::FromStr for PmtilesLayout {
+    type Err = MapperError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "per-floor" | "floor" => Ok(PmtilesLayout::PerFloor),
+            "combined" => Ok(PmtilesLayout::Combined),
+            _ => Err(MapperError::InvalidConfig(format!("Unknown PMTiles layout {:?}, expected per-floor or combined", s))),
+        }
+    }
+}
+
+/// Container of an `output_archive`, chosen by its file name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileArchiveFormat {
+    /// Tiles stored uncompressed, as images already are.
+    Zip,
+    /// A gzip-compressed tar stream.
+    TarGz,
+}
+
+impl TileArchiveFormat {
+    /// `.zip`, `.tar.gz` or `.tgz`, ignoring case.
+    pub fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// What is drawn for objects whose sprite comes as animation frames
+/// (`<id>_<frame>.png`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpriteFrames {
+    /// `<id>.png` when there is one, else the lowest numbered frame.
+    #[default]
+    First,
+    /// All frames blended into one still image.
+    Average,
+    /// Every frame, written as animated WebP tiles.
+    Animate,
+}
+
+impl std::str::FromStr for SpriteFrames {
+    type Err = MapperError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "first" => Ok(SpriteFrames::First),
+            "average" => Ok(SpriteFrames::Average),
+            "animate" => Ok(SpriteFrames::Animate),
+            _ => Err(MapperError::InvalidConfig(format!("Unknown sprite frame mode {:?}, expected first, average or animate", s))),
+        }
+    }
+}
+
+/// Parses `1004-1002,1005-1002` into `(x, y)` sector pairs.
+pub fn parse_sector_list(s: &str) -> Result<Vec<(u32, u32)>> {
+    s.split(',')
+        .map(|sector| {
+            let invalid = || MapperError::InvalidSector(format!("Invalid sector {:?}, expected X-Y (e.g. 1004-1002)", sector));
+            let (x, y) = sector.trim().split_once('-').ok_or_else(invalid)?;
+            Ok((x.trim().parse().map_err(|_| invalid())?, y.trim().parse().map_err(|_| invalid())?))
+        })
+        .collect()
+}
+
+fn deserialize_floors<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum FloorSpec {
+        List(Vec<u8>),
+        Single(u8),
+        Spec(String),
+    }
+
+    match FloorSpec::deserialize(deserializer)? {
+        FloorSpec::List(floors) => Ok(floors),
+        FloorSpec::Single(floor) => Ok(vec![floor]),
+        FloorSpec::Spec(spec) => parse_floor_range(&spec).map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BuildConfig {
+        let dir = std::env::temp_dir();
+        BuildConfig {
+            objects_path: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")),
+            objects_extra: Vec::new(),
+            map_path: dir.clone(),
+            sprite_path: Some(dir),
+            sprite_overrides: None,
+            substitute_sprites: false,
+            strict: false,
+            sprite_cache_mb: None,
+            sprite_frames: SpriteFrames::First,
+            layer_rules: None,
+            render_mode: RenderMode::Sprites,
+            color_map: None,
+            tile_format: TileFormat::Png,
+            pyramid: false,
+            pmtiles: None,
+            output_archive: None,
+            composite_brightness: Vec::new(),
+            output: default_output(),
+            floors: vec![7],
+            min_zoom: 0,
+            max_zoom: 5,
+            monster_db: None,
+            mon_path: None,
+            monster_sprites: None,
+            client_spr: None,
+            client_dat: None,
+            marker_styles: MarkerStyles::default(),
+            overlays: default_overlays(),
+            npc_csv: None,
+            npc_sprites: None,
+            quest_csv: None,
+            raids_path: None,
+            data_paths: Vec::new(),
+            encoding: InputEncoding::Auto,
+            prices_csv: None,
+            heatmap_csv: None,
+            houses_status: None,
+            house_names: None,
+            heatmap_radius: default_heatmap_radius(),
+            live_players: false,
+            overlay_refresh_minutes: None,
+            admin_command: None,
+            archive: None,
+            sectors: Vec::new(),
+            region: None,
+            incremental: false,
+            cache_dir: default_cache_dir(),
+            force: false,
+            extra_assets: None,
+            offline: None,
+            threads: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_accepts_defaults() {
+        assert!(config().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_reports_all_range_problems() {
+        let mut cfg = config();
+        cfg.floors = vec![7, 16];
+        cfg.min_zoom = 6;
+        cfg.max_zoom = 5;
+
+        let err = cfg.validate().unwrap_err().to_string();
+        assert!(err.contains("got 16"), "{}", err);
+        assert!(err.contains("min_zoom (6)"), "{}", err);
+    }
+
+    #[test]
+    fn test_floors_deserialize_from_spec_or_list() {
+        let from_spec: BuildConfig = serde_json::from_str(
+            r#"{"objects_path":"o","map_path":"m","sprite_path":"s","floors":"6-8"}"#,
+        )
+        .unwrap();
+        assert_eq!(from_spec.floors, vec![6, 7, 8]);
+        assert_eq!(from_spec.max_zoom, 5);
+
+        let from_list: BuildConfig = serde_json::from_str(
+            r#"{"objects_path":"o","map_path":"m","sprite_path":"s","floors":[7,9]}"#,
+        )
+        .unwrap();
+        assert_eq!(from_list.floors, vec![7, 9]);
+    }
+
+    #[test]
+    fn test_marker_styles_from_spec_and_json() {
+        let mut cfg = config();
+        cfg.marker_styles.set("spawns.min_zoom=1").unwrap();
+        cfg.marker_styles.set("quests.color=#ff8800").unwrap();
+        assert_eq!(cfg.marker_styles.spawns.min_zoom, Some(1));
+        assert!(cfg.marker_styles.set("houses.size=4").is_err());
+        assert!(cfg.validate().is_ok());
+
+        cfg.marker_styles.grid.color = Some("red; x".to_string());
+        assert!(cfg.validate().unwrap_err().to_string().contains("marker_styles.grid.color"));
+
+        let cfg: BuildConfig = serde_json::from_str(
+            r#"{"objects_path":"o","map_path":"m","sprite_path":"s","floors":7,"marker_styles":{"npcs":{"size":48}}}"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.marker_styles.npcs.size, Some(48));
+        assert_eq!(cfg.marker_styles.spawns, Default::default());
+    }
+
+    #[test]
+    fn test_parse_floor_range() {
+        assert_eq!(parse_floor_range("7").unwrap(), vec![7]);
+        assert_eq!(parse_floor_range("6-8").unwrap(), vec![6, 7, 8]);
+        assert_eq!(parse_floor_range("9-11, 0,7,10").unwrap(), vec![0, 7, 9, 10, 11]);
+        assert!(parse_floor_range("7,16").unwrap_err().to_string().contains("Floor 16"));
+        assert!(parse_floor_range("8-6").is_err());
+        assert!(parse_floor_range("7,x").is_err());
+        assert!(parse_floor_range(" , ").is_err());
+    }
+
+    #[test]
+    fn test_parse_sector_list() {
+        assert_eq!(parse_sector_list("1004-1002, 1005-1002").unwrap(), vec![(1004, 1002), (1005, 1002)]);
+        assert!(parse_sector_list("1004").is_err());
+        assert!(parse_sector_list("1004-x").is_err());
+    }
+
+    #[test]
+    fn test_parse_overlay_list() {
+        assert_eq!(parse_overlay_list("spawns, NPCs").unwrap(), vec![Overlay::Spawns, Overlay::Npcs]);
+        assert_eq!(parse_overlay_list("all").unwrap(), Overlay::ALL.to_vec());
+        assert!(parse_overlay_list("none").unwrap().is_empty());
+        assert!(parse_overlay_list("spawns,towns").is_err());
+
+        let cfg: BuildConfig = serde_json::from_str(
+            r#"{"objects_path":"o","map_path":"m","sprite_path":"s","floors":7,"overlays":["quests"]}"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.overlays, vec![Overlay::Quests]);
+        assert_eq!(config().overlays, Overlay::ALL.to_vec());
+    }
+
+    #[test]
+    fn test_color_mode_needs_sprites_or_colors() {
+        let mut cfg = config();
+        cfg.sprite_path = None;
+        assert!(cfg.validate().is_err());
+
+        cfg.render_mode = RenderMode::Color;
+        let message = cfg.validate().unwrap_err().to_string();
+        assert!(message.contains("sprite_path or a color_map file"), "{}", message);
+
+        cfg.color_map = Some(cfg.objects_path.clone());
+        assert!(cfg.validate().is_ok());
+        assert_eq!("Color".parse::<RenderMode>().unwrap(), RenderMode::Color);
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        let result: std::result::Result<BuildConfig, _> = serde_json::from_str(
+            r#"{"objects_path":"o","map_path":"m","sprite_path":"s","floors":7,"max_zom":4}"#,
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{
+    parse_monster_db, parse_monster_loot, parse_monster_looks, parse_monster_names, parse_monster_stats, parse_quest_csv,
+    parse_raids, InputEncoding, LootEntry, MonsterLook, MonsterSpawn, MonsterStats, Raid,
+};
+use crate::error::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Spawn, monster, quest and raid inputs layered from several sources. Later
+/// layers override earlier ones: spawns by position, monsters by race, quest
+/// names by quest value and raids by name.
+#[derive(Debug, Clone, Default)]
+pub struct DataSources {
+    pub monster_dbs: Vec<PathBuf>,
+    pub mon_dirs: Vec<PathBuf>,
+    pub quest_csvs: Vec<PathBuf>,
+    pub raid_dirs: Vec<PathBuf>,
+    /// Text encoding all of them are read in.
+    pub encoding: InputEncoding,
+}
+
+impl DataSources {
+    /// Starts from the explicitly given files, then adds `dat/monster.db`,
+    /// `mon/`, `quest_overview.csv` and `raids/` from each data path that
+    /// has them.
+    pub fn collect(
+        monster_db: Option<&Path>,
+        mon_path: Option<&Path>,
+        quest_csv: Option<&Path>,
+        raids_path: Option<&Path>,
+        data_paths: &[PathBuf],
+        encoding: InputEncoding,
+    ) -> Self {
+        let mut sources = Self {
+            monster_dbs: monster_db.map(Path::to_path_buf).into_iter().collect(),
+            mon_dirs: mon_path.map(Path::to_path_buf).into_iter().collect(),
+            quest_csvs: quest_csv.map(Path::to_path_buf).into_iter().collect(),
+            raid_dirs: raids_path.map(Path::to_path_buf).into_iter().collect(),
+            encoding,
+        };
+
+        for dir in data_paths {
+            let monster_db = dir.join("dat").join("monster.db");
+            if monster_db.is_file() {
+                sources.monster_dbs.push(monster_db);
+            }
+            let mon_dir = dir.join("mon");
+            if mon_dir.is_dir() {
+                sources.mon_dirs.push(mon_dir);
+            }
+            let quest_csv = dir.join("quest_overview.csv");
+            if quest_csv.is_file() {
+                sources.quest_csvs.push(quest_csv);
+            }
+            let raid_dir = dir.join("raids");
+            if raid_dir.is_dir() {
+                sources.raid_dirs.push(raid_dir);
+            }
+        }
+
+        sources
+    }
+
+    pub fn has_spawns(&self) -> bool {
+        !self.monster_dbs.is_empty()
+    }
+
+    pub fn has_monsters(&self) -> bool {
+        !self.mon_dirs.is_empty()
+    }
+
+    /// Spawns from a later `monster.db` replace every earlier spawn at the
+    /// same position, so an event file can swap out individual base spawns.
+    pub fn spawns(&self) -> Result<Vec<MonsterSpawn>> {
+        let mut spawns: Vec<MonsterSpawn> = Vec::new();
+
+        for path in &self.monster_dbs {
+            let layer = parse_monster_db(path, self.encoding)?;
+            let replaced: HashSet<(u32, u32, u8)> = layer.iter().map(|s| (s.x, s.y, s.z)).collect();
+            spawns.retain(|s| !replaced.contains(&(s.x, s.y, s.z)));
+            spawns.extend(layer);
+        }
+
+        Ok(spawns)
+    }
+
+    pub fn monster_names(&self) -> Result<HashMap<u32, String>> {
+        merge_layers(&self.mon_dirs, |dir| parse_monster_names(dir, self.encoding))
+    }
+
+    pub fn monster_stats(&self) -> Result<HashMap<u32, MonsterStats>> {
+        merge_layers(&self.mon_dirs, |dir| parse_monster_stats(dir, self.encoding))
+    }
+
+    pub fn monster_looks(&self) -> Result<HashMap<u32, MonsterLook>> {
+        merge_layers(&self.mon_dirs, |dir| parse_monster_looks(dir, self.encoding))
+    }
+
+    pub fn monster_loot(&self) -> Result<HashMap<u32, Vec<LootEntry>>> {
+        merge_layers(&self.mon_dirs, |dir| parse_monster_loot(dir, self.encoding))
+    }
+
+    /// Raids from a later directory replace earlier raids of the same name.
+    pub fn raids(&self) -> Result<Vec<Raid>> {
+        let mut raids: Vec<Raid> = Vec::new();
+        for dir in &self.raid_dirs {
+            let layer = parse_raids(dir, self.encoding)?;
+            raids.retain(|raid| !layer.iter().any(|r| r.name == raid.name));
+            raids.extend(layer);
+        }
+        raids.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(raids)
+    }
+
+    pub fn quest_names(&self) -> Result<HashMap<u32, String>> {
+        merge_layers(&self.quest_csvs, |csv| parse_quest_csv(csv, self.encoding))
+    }
+}
+
+fn merge_layers<V>(paths: &[PathBuf], parse: impl Fn(&Path) -> Result<HashMap<u32, V>>) -> Result<HashMap<u32, V>> {
+    let mut merged = HashMap::new();
+    for path in paths {
+        merged.extend(parse(path)?);
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_later_data_paths_override_earlier_ones() {
+        let root = std::env::temp_dir().join(format!("demonax-datapaths-{}", std::process::id()));
+        let (base, event) = (root.join("base"), root.join("event"));
+        for dir in [&base, &event] {
+            fs::create_dir_all(dir.join("dat")).unwrap();
+        }
+        fs::write(base.join("dat/monster.db"), "11 32000 32000 7 2 1 60\n12 32010 32010 7 2 1 60\n12 32010 32010 7 3 1 60\n").unwrap();
+        fs::write(event.join("dat/monster.db"), "99 32010 32010 7 2 1 60\n").unwrap();
+        fs::write(base.join("quest_overview.csv"), "quest_value,quest_name\n110,Plate Armor Quest\n111,Old Name\n").unwrap();
+        fs::write(event.join("quest_overview.csv"), "quest_value,quest_name\n111,Event Quest\n").unwrap();
+
+        let sources = DataSources::collect(None, None, None, None, &[base, event], InputEncoding::Auto);
+        assert_eq!(sources.monster_dbs.len(), 2);
+        assert!(!sources.has_monsters());
+
+        let races: Vec<u32> = sources.spawns().unwrap().iter().map(|s| s.race).collect();
+        assert_eq!(races, vec![11, 99]);
+        let quests = sources.quest_names().unwrap();
+        assert_eq!(quests[&110], "Plate Armor Quest");
+        assert_eq!(quests[&111], "Event Quest");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
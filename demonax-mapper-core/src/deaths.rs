@@ -0,0 +1,170 @@
+use crate::{read_text, HeatPoint, InputEncoding};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// One line of the server death log, e.g.
+/// `2024-03-01 18:22:10 Bob (45) died at [32369,32241,7], killed by a dragon lord.`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeathRecord {
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub victim: String,
+    pub x: u32,
+    pub y: u32,
+    pub z: u8,
+    pub killer: Option<String>,
+}
+
+/// Which deaths to keep. Dates are inclusive `YYYY-MM-DD` bounds; killers are
+/// matched case-insensitively, ignoring a leading "a"/"an".
+#[derive(Debug, Clone, Default)]
+pub struct DeathFilter {
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub killers: Vec<String>,
+}
+
+impl DeathFilter {
+    pub fn matches(&self, death: &DeathRecord) -> bool {
+        if self.since.as_deref().is_some_and(|since| death.date.as_str() < since) {
+            return false;
+        }
+        if self.until.as_deref().is_some_and(|until| death.date.as_str() > until) {
+            return false;
+        }
+        if self.killers.is_empty() {
+            return true;
+        }
+        death.killer.as_deref().is_some_and(|killer| {
+            let killer = normalize_killer(killer);
+            self.killers.iter().any(|k| normalize_killer(k) == killer)
+        })
+    }
+}
+
+fn normalize_killer(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    let name = name
+        .strip_prefix("a ")
+        .or_else(|| name.strip_prefix("an "))
+        .unwrap_or(&name);
+    name.trim().to_string()
+}
+
+/// Checks for a `YYYY-MM-DD` date, which is what the log and the filters use.
+pub fn is_iso_date(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() == 10
+        && b[4] == b'-'
+        && b[7] == b'-'
+        && b.iter()
+            .enumerate()
+            .all(|(i, c)| i == 4 || i == 7 || c.is_ascii_digit())
+}
+
+pub fn parse_death_log<P: AsRef<Path>>(log_path: P, encoding: InputEncoding) -> Result<Vec<DeathRecord>> {
+    let content = read_text(log_path.as_ref(), encoding)
+        .with_context(|| format!("Failed to read death log from {:?}", log_path.as_ref()))?;
+
+    let mut deaths = Vec::new();
+    let mut skipped = 0;
+
+    for line in content.lines().filter(|l| !l.trim().is_empty()) {
+        match parse_death_line(line) {
+            Some(death) => deaths.push(death),
+            None => skipped += 1,
+        }
+    }
+
+    if skipped > 0 {
+        tracing::warn!("Skipped {} death log lines that did not match the expected format", skipped);
+    }
+    tracing::info!("Parsed {} deaths from log", deaths.len());
+    Ok(deaths)
+}
+
+fn parse_death_line(line: &str) -> Option<DeathRecord> {
+    let line = line.trim();
+    let date = line.get(..10).filter(|d| is_iso_date(d))?.to_string();
+
+    let (before, after) = line.split_once(" died at ")?;
+    let victim = before[10..]
+        .trim_start()
+        .split_once(' ')
+        .filter(|(time, _)| time.contains(':'))
+        .map_or(&before[10..], |(_, rest)| rest)
+        .trim()
+        .to_string();
+
+    let open = after.find('[')?;
+    let close = open + after[open..].find(']')?;
+    let mut coords = after[open + 1..close].split(',').map(str::trim);
+    let x = coords.next()?.parse().ok()?;
+    let y = coords.next()?.parse().ok()?;
+    let z = coords.next()?.parse().ok()?;
+
+    let killer = after[close + 1..]
+        .split_once("killed by ")
+        .map(|(_, k)| k.trim().trim_end_matches('.').trim().to_string())
+        .filter(|k| !k.is_empty());
+
+    Some(DeathRecord { date, victim, x, y, z, killer })
+}
+
+/// Collapses deaths on the same tile into one weighted heat point.
+pub fn deaths_to_heat_points(deaths: &[DeathRecord]) -> Vec<HeatPoint> {
+    let mut counts: BTreeMap<(u8, u32, u32), u32> = BTreeMap::new();
+    for death in deaths {
+        *counts.entry((death.z, death.y, death.x)).or_default() += 1;
+    }
+
+    counts
+        .into_iter()
+        .map(|((z, y, x), count)| HeatPoint { x, y, z, weight: count as f64 })
+        .collect()
+}
+
+/// Writes points in the `x,y,z,weight` shape read by [`crate::parse_heatmap_csv`].
+pub fn generate_heatmap_csv(points: &[HeatPoint]) -> String {
+    let mut csv = String::from("x,y,z,weight\n");
+    for p in points {
+        csv.push_str(&format!("{},{},{},{}\n", p.x, p.y, p.z, p.weight));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_death_line() {
+        let death = parse_death_line(
+            "2024-03-01 18:22:10 Sir Bob (45) died at [32369,32241,7], killed by a dragon lord.",
+        )
+        .unwrap();
+        assert_eq!(death.date, "2024-03-01");
+        assert_eq!(death.victim, "Sir Bob (45)");
+        assert_eq!((death.x, death.y, death.z), (32369, 32241, 7));
+        assert_eq!(death.killer.as_deref(), Some("a dragon lord"));
+
+        assert!(parse_death_line("2024-03-01 Bob died at [1,2]").is_none());
+        assert!(parse_death_line("Bob died at [32369,32241,7]").is_none());
+    }
+
+    #[test]
+    fn test_death_filter() {
+        let death = parse_death_line("2024-03-01 Bob died at [1,2,7] killed by A Dragon Lord").unwrap();
+        let filter = |since: &str, killers: &[&str]| DeathFilter {
+            since: Some(since.to_string()),
+            until: None,
+            killers: killers.iter().map(|k| k.to_string()).collect(),
+        };
+
+        assert!(filter("2024-03-01", &["dragon lord"]).matches(&death));
+        assert!(!filter("2024-03-02", &[]).matches(&death));
+        assert!(!filter("2024-01-01", &["dragon"]).matches(&death));
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{parse_content_line, read_text, sector_files_for_floors, sector_to_world, tile_runs, InputEncoding, SectorFile};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// How a tile or sector differs from one map version to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl ChangeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Changed => "changed",
+        }
+    }
+}
+
+/// One tile that differs between two map versions. `before` and `after` are
+/// its object IDs, empty on the side where the tile does not exist.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileChange {
+    pub x: u32,
+    pub y: u32,
+    pub floor: u8,
+    pub kind: ChangeKind,
+    pub before: Vec<u32>,
+    pub after: Vec<u32>,
+}
+
+/// A sector file with at least one changed tile. Sectors found in only one
+/// version are `Added` or `Removed` as a whole.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SectorChange {
+    pub file: String,
+    pub kind: ChangeKind,
+    pub tiles: usize,
+}
+
+/// Everything that differs between two map directories, sorted by file name
+/// and by floor and position.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MapDiff {
+    pub sectors: Vec<SectorChange>,
+    pub tiles: Vec<TileChange>,
+}
+
+/// Tiles of one sector file by local position, each with the text after its
+/// colon (flags, items and their attributes), which is what gets compared,
+/// and its object IDs.
+type SectorTiles = BTreeMap<(u32, u32), (String, Vec<u32>)>;
+
+fn read_sector_tiles(sector: Option<&SectorFile>, encoding: InputEncoding) -> Result<SectorTiles> {
+    let Some(sector) = sector else {
+        return Ok(BTreeMap::new());
+    };
+    let content = read_text(&sector.path, encoding).with_context(|| format!("Failed to read {:?}", sector.path))?;
+
+    Ok(content
+        .lines()
+        .filter_map(|line| {
+            let (coords, rest) = line.split_once(':')?;
+            let (local_x, local_y) = coords.trim().split_once('-')?;
+            let position = (local_x.parse().ok()?, local_y.parse().ok()?);
+            let object_ids = parse_content_line(line).map(|(_, _, ids)| ids).unwrap_or_default();
+            Some((position, (rest.trim().to_string(), object_ids)))
+        })
+        .collect())
+}
+
+/// Compares the sector files of `floors` in `old_dir` and `new_dir` tile by
+/// tile. Files with identical bytes are skipped without being parsed, and
+/// edits that only touch formatting do not count as changes.
+pub fn diff_maps<P: AsRef<Path>, Q: AsRef<Path>>(old_dir: P, new_dir: Q, floors: &[u8], encoding: InputEncoding) -> Result<MapDiff> {
+    let mut files: BTreeMap<String, (Option<SectorFile>, Option<SectorFile>)> = BTreeMap::new();
+    for sector in sector_files_for_floors(old_dir.as_ref(), floors)? {
+        let name = sector.path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        files.entry(name).or_default().0 = Some(sector);
+    }
+    for sector in sector_files_for_floors(new_dir.as_ref(), floors)? {
+        let name = sector.path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        files.entry(name).or_default().1 = Some(sector);
+    }
+
+    let mut diff = MapDiff::default();
+    for (file, (old, new)) in files {
+        if let (Some(old), Some(new)) = (&old, &new)
+            && fs::read(&old.path)? == fs::read(&new.path)?
+        {
+            continue;
+        }
+        let Some(sector) = new.as_ref().or(old.as_ref()) else {
+            continue;
+        };
+
+        let old_tiles = read_sector_tiles(old.as_ref(), encoding)?;
+        let new_tiles = read_sector_tiles(new.as_ref(), encoding)?;
+        let positions: BTreeSet<(u32, u32)> = old_tiles.keys().chain(new_tiles.keys()).copied().collect();
+
+        let first = diff.tiles.len();
+        for (local_x, local_y) in positions {
+            let (kind, before, after) = match (old_tiles.get(&(local_x, local_y)), new_tiles.get(&(local_x, local_y))) {
+                (Some(before), Some(after)) if before.0 == after.0 => continue,
+                (Some(before), Some(after)) => (ChangeKind::Changed, before.1.clone(), after.1.clone()),
+                (Some(before), None) => (ChangeKind::Removed, before.1.clone(), Vec::new()),
+                (None, Some(after)) => (ChangeKind::Added, Vec::new(), after.1.clone()),
+                (None, None) => continue,
+            };
+            let (x, y) = sector_to_world(sector.sector_x, sector.sector_y, local_x, local_y);
+            diff.tiles.push(TileChange { x, y, floor: sector.z, kind, before, after });
+        }
+
+        let tiles = diff.tiles.len() - first;
+        if tiles == 0 {
+            continue;
+        }
+        let kind = match (&old, &new) {
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Removed,
+            _ => ChangeKind::Changed,
+        };
+        diff.sectors.push(SectorChange { file, kind, tiles });
+    }
+
+    diff.tiles.sort_by_key(|t| (t.floor, t.y, t.x));
+
+    tracing::info!("Found {} changed tiles in {} sectors", diff.tiles.len(), diff.sectors.len());
+    Ok(diff)
+}
+
+/// The viewer's diff overlay: changed tiles per floor and kind as
+/// `[y, x_start, x_end]` runs, plus the list of changed sectors.
+pub fn generate_diff_json(diff: &MapDiff) -> Result<String> {
+    let mut tiles_by_change: BTreeMap<(u8, ChangeKind), Vec<(u32, u32)>> = BTreeMap::new();
+    for tile in &diff.tiles {
+        tiles_by_change.entry((tile.floor, tile.kind)).or_default().push((tile.x, tile.y));
+    }
+
+    let mut diff_by_floor: BTreeMap<u8, Vec<serde_json::Value>> = BTreeMap::new();
+    for ((floor, kind), tiles) in tiles_by_change {
+        diff_by_floor.entry(floor).or_default().push(serde_json::json!({
+            "kind": kind,
+            "tile_count": tiles.len(),
+            "runs": tile_runs(&tiles)
+        }));
+    }
+
+    let output = serde_json::json!({
+        "diff_by_floor": diff_by_floor,
+        "sectors": diff.sectors
+    });
+
+    serde_json::to_string(&output).with_context(|| "Failed to serialize map diff to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_maps_reports_tiles_and_sectors() {
+        let dir = std::env::temp_dir().join(format!("demonax-diff-{}", std::process::id()));
+        let (old_dir, new_dir) = (dir.join("old"), dir.join("new"));
+        std::fs::create_dir_all(&old_dir).unwrap();
+        std::fs::create_dir_all(&new_dir).unwrap();
+
+        std::fs::write(old_dir.join("1000-1000-07.sec"), "0-0: Content={100}\n1-0: Content={100, 200}\n2-0: Content={100}\n").unwrap();
+        std::fs::write(new_dir.join("1000-1000-07.sec"), "0-0:  Content={100}\n1-0: Content={100, 201}\n3-0: Content={100}\n").unwrap();
+        std::fs::write(old_dir.join("1000-1001-07.sec"), "0-0: Content={100}\n").unwrap();
+        std::fs::write(new_dir.join("1000-1001-07.sec"), "0-0: Content={100}\n").unwrap();
+        std::fs::write(new_dir.join("1001-1000-07.sec"), "4-5: Content={100}\n").unwrap();
+
+        let diff = diff_maps(&old_dir, &new_dir, &[7], InputEncoding::Auto).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let sectors: Vec<_> = diff.sectors.iter().map(|s| (s.file.as_str(), s.kind, s.tiles)).collect();
+        assert_eq!(
+            sectors,
+            [("1000-1000-07.sec", ChangeKind::Changed, 3), ("1001-1000-07.sec", ChangeKind::Added, 1)]
+        );
+
+        let tiles: Vec<_> = diff.tiles.iter().map(|t| (t.x, t.y, t.kind)).collect();
+        assert_eq!(
+            tiles,
+            [
+                (32001, 32000, ChangeKind::Changed),
+                (32002, 32000, ChangeKind::Removed),
+                (32003, 32000, ChangeKind::Added),
+                (32036, 32005, ChangeKind::Added)
+            ]
+        );
+        assert_eq!((diff.tiles[0].before.as_slice(), diff.tiles[0].after.as_slice()), ([100, 200].as_slice(), [100, 201].as_slice()));
+    }
+}
@@ -0,0 +1,67 @@
+use crate::MapperError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Text encoding of the game data files. Older servers write sector, `.mon`,
+/// `objects.srv` and CSV files in Latin-1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputEncoding {
+    /// UTF-8 when the file is valid UTF-8, Latin-1 otherwise.
+    #[default]
+    Auto,
+    Utf8,
+    Latin1,
+}
+
+impl InputEncoding {
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Self::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+            Self::Auto => match std::str::from_utf8(bytes) {
+                Ok(text) => text.to_owned(),
+                Err(_) => Self::Latin1.decode(bytes),
+            },
+        }
+    }
+}
+
+impl FromStr for InputEncoding {
+    type Err = MapperError;
+
+    fn from_str(s: &str) -> Result<Self, MapperError> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "utf8" | "utf-8" => Ok(Self::Utf8),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Self::Latin1),
+            _ => Err(MapperError::InvalidConfig(format!("Unknown encoding {:?}, expected auto, utf8 or latin1", s))),
+        }
+    }
+}
+
+/// Reads a game data file as text in `encoding`.
+pub fn read_text<P: AsRef<Path>>(path: P, encoding: InputEncoding) -> io::Result<String> {
+    Ok(encoding.decode(&fs::read(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_latin1_and_autodetect() {
+        let latin1 = b"Name = \"K\xf6nig\"";
+        let utf8 = "Name = \"König\"".as_bytes();
+
+        assert_eq!(InputEncoding::Latin1.decode(latin1), "Name = \"König\"");
+        assert_eq!(InputEncoding::Auto.decode(latin1), "Name = \"König\"");
+        assert_eq!(InputEncoding::Auto.decode(utf8), "Name = \"König\"");
+        assert_eq!(InputEncoding::Utf8.decode(latin1), "Name = \"K\u{fffd}nig\"");
+        assert_eq!("ISO-8859-1".parse::<InputEncoding>().unwrap(), InputEncoding::Latin1);
+        assert!("cp1252".parse::<InputEncoding>().is_err());
+    }
+}
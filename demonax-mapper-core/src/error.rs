@@ -0,0 +1,134 @@
+use std::io;
+use std::num::{ParseFloatError, ParseIntError};
+use thiserror::Error;
+
+/// Everything the core parsers and writers can fail with. Match on the
+/// variant to tell failure kinds apart; wrapped I/O and serialization
+/// errors are reachable through [`std::error::Error::source`].
+#[derive(Debug, Error)]
+pub enum MapperError {
+    /// Reading or writing a file or directory failed.
+    #[error("{context}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+    /// An input file or value is malformed.
+    #[error("{0}")]
+    Parse(String),
+    /// JSON or TOML that could not be read or written.
+    #[error("{context}")]
+    Serialization {
+        context: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A sector file name, coordinate or selection that names no sector.
+    #[error("{0}")]
+    InvalidSector(String),
+    /// The sprite source has no sprite for an object.
+    #[error("missing sprite for object {0}")]
+    MissingSprite(u32),
+    /// Settings that are out of range or cannot be combined.
+    #[error("{0}")]
+    InvalidConfig(String),
+    /// A floor cache file this build cannot read.
+    #[error("{0}")]
+    Cache(String),
+}
+
+pub type Result<T, E = MapperError> = std::result::Result<T, E>;
+
+impl From<io::Error> for MapperError {
+    fn from(source: io::Error) -> Self {
+        MapperError::Io { context: "I/O error".to_string(), source }
+    }
+}
+
+impl From<serde_json::Error> for MapperError {
+    fn from(source: serde_json::Error) -> Self {
+        MapperError::Serialization { context: "JSON error".to_string(), source: Box::new(source) }
+    }
+}
+
+impl From<bincode::Error> for MapperError {
+    fn from(source: bincode::Error) -> Self {
+        MapperError::Cache(format!("corrupt floor cache: {}", source))
+    }
+}
+
+/// Attaches a description to a lower-level error, choosing the variant from
+/// the error type, in the style of `anyhow::Context`.
+pub(crate) trait Context<T> {
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T>;
+
+    fn context<C: Into<String>>(self, context: C) -> Result<T>
+    where
+        Self: Sized,
+    {
+        self.with_context(|| context)
+    }
+}
+
+impl<T> Context<T> for std::result::Result<T, io::Error> {
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|source| MapperError::Io { context: context().into(), source })
+    }
+}
+
+impl<T> Context<T> for std::result::Result<T, serde_json::Error> {
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|source| MapperError::Serialization { context: context().into(), source: Box::new(source) })
+    }
+}
+
+impl<T> Context<T> for std::result::Result<T, toml::de::Error> {
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|source| MapperError::Serialization { context: context().into(), source: Box::new(source) })
+    }
+}
+
+impl<T> Context<T> for std::result::Result<T, toml::ser::Error> {
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|source| MapperError::Serialization { context: context().into(), source: Box::new(source) })
+    }
+}
+
+impl<T> Context<T> for std::result::Result<T, ParseIntError> {
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|e| MapperError::Parse(format!("{}: {}", context().into(), e)))
+    }
+}
+
+impl<T> Context<T> for std::result::Result<T, ParseFloatError> {
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|e| MapperError::Parse(format!("{}: {}", context().into(), e)))
+    }
+}
+
+impl<T> Context<T> for Option<T> {
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T> {
+        self.ok_or_else(|| MapperError::Parse(context().into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_picks_variant_by_source() {
+        let io: Result<()> = Err(io::Error::from(io::ErrorKind::NotFound)).context("Failed to read \"a.srv\"");
+        assert!(matches!(io, Err(MapperError::Io { .. })));
+        let io = io.unwrap_err();
+        assert_eq!(io.to_string(), "Failed to read \"a.srv\"");
+        assert!(std::error::Error::source(&io).is_some());
+
+        let number: Result<u32> = "x".parse::<u32>().context("Failed to parse TypeID");
+        assert!(matches!(number, Err(MapperError::Parse(_))));
+
+        let json: Result<u32> = serde_json::from_str::<u32>("{").context("Failed to parse feed");
+        assert!(matches!(json, Err(MapperError::Serialization { .. })));
+    }
+}
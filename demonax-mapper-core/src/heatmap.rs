@@ -0,0 +1,80 @@
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// One weighted position from an external log (kills, deaths, gathering...).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HeatPoint {
+    pub x: u32,
+    pub y: u32,
+    pub z: u8,
+    pub weight: f64,
+}
+
+/// Reads `x,y,z[,weight]` rows (header skipped). A missing weight counts as 1.
+pub fn parse_heatmap_csv<P: AsRef<Path>>(csv_path: P) -> Result<Vec<HeatPoint>> {
+    let content = fs::read_to_string(csv_path.as_ref())
+        .with_context(|| format!("Failed to read heatmap CSV from {:?}", csv_path.as_ref()))?;
+
+    let mut points = Vec::new();
+
+    for (line_num, line) in content.lines().enumerate() {
+        if line_num == 0 || line.trim().is_empty() {
+            continue;
+        }
+
+        match parse_heat_point(line) {
+            Some(point) => points.push(point),
+            None => tracing::warn!("Line {}: Invalid heatmap CSV row: {}", line_num + 1, line),
+        }
+    }
+
+    tracing::info!("Loaded {} heatmap points from CSV", points.len());
+    Ok(points)
+}
+
+fn parse_heat_point(line: &str) -> Option<HeatPoint> {
+    let mut fields = line.split(',').map(str::trim);
+    let x = fields.next()?.parse().ok()?;
+    let y = fields.next()?.parse().ok()?;
+    let z = fields.next()?.parse().ok()?;
+    let weight = match fields.next() {
+        Some(w) if !w.is_empty() => w.parse::<f64>().ok().filter(|w| w.is_finite())?,
+        _ => 1.0,
+    };
+
+    Some(HeatPoint { x, y, z, weight })
+}
+
+/// Summary the viewer reads to decide whether the heatmap toggle is usable.
+pub fn generate_heatmap_json(points: &[HeatPoint], floors: &[u8]) -> Result<String> {
+    let mut points_by_floor: BTreeMap<u8, usize> = BTreeMap::new();
+    for point in points.iter().filter(|p| floors.contains(&p.z)) {
+        *points_by_floor.entry(point.z).or_default() += 1;
+    }
+
+    let output = serde_json::json!({
+        "heatmap_floors": points_by_floor.keys().collect::<Vec<_>>(),
+        "points_by_floor": points_by_floor
+    });
+
+    serde_json::to_string(&output).with_context(|| "Failed to serialize heatmap summary to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_heat_point_defaults_weight() {
+        assert_eq!(
+            parse_heat_point("32000, 32001, 7"),
+            Some(HeatPoint { x: 32000, y: 32001, z: 7, weight: 1.0 })
+        );
+        assert_eq!(parse_heat_point("32000,32001,7,2.5").map(|p| p.weight), Some(2.5));
+        assert_eq!(parse_heat_point("32000,32001,7,lots"), None);
+        assert_eq!(parse_heat_point("32000,32001"), None);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::error::{Context, Result};
+use crate::TileFormat;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// An archived build under `history/<date>/`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryVersion {
+    pub date: String,
+    pub floors: Vec<u8>,
+    #[serde(default)]
+    pub tile_format: TileFormat,
+}
+
+/// `history/index.json`, oldest version first.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistoryIndex {
+    pub versions: Vec<HistoryVersion>,
+}
+
+impl HistoryIndex {
+    pub fn load(output_dir: &Path) -> Result<Self> {
+        let path = output_dir.join("history").join("index.json");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read history index {:?}", path))?;
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse history index {:?}", path))
+    }
+
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        let dir = output_dir.join("history");
+        fs::create_dir_all(&dir)?;
+        let json = serde_json::to_string_pretty(self).with_context(|| "Failed to serialize history index")?;
+        fs::write(dir.join("index.json"), json)?;
+        Ok(())
+    }
+
+    /// Adds or replaces the version for `date`, keeping versions sorted.
+    pub fn record(&mut self, date: &str, floors: &[u8], tile_format: TileFormat) {
+        self.versions.retain(|v| v.date != date);
+        self.versions.push(HistoryVersion {
+            date: date.to_string(),
+            floors: floors.to_vec(),
+            tile_format,
+        });
+        self.versions.sort_by(|a, b| a.date.cmp(&b.date));
+    }
+}
+
+/// Copies the rendered tiles of `floors` into `history/<date>/<floor>/` and
+/// records the version in `history/index.json`. Tiles are copied rather than
+/// hard-linked because later builds overwrite the live tiles in place.
+pub fn archive_build(output_dir: &Path, date: &str, floors: &[u8], tile_format: TileFormat) -> Result<usize> {
+    let version_dir = output_dir.join("history").join(date);
+    let mut files = 0;
+
+    for floor in floors {
+        let src = output_dir.join(floor.to_string());
+        let dst = version_dir.join(floor.to_string());
+        if dst.exists() {
+            fs::remove_dir_all(&dst)
+                .with_context(|| format!("Failed to replace archived floor {:?}", dst))?;
+        }
+        files += copy_dir(&src, &dst)?;
+    }
+
+    let mut index = HistoryIndex::load(output_dir)?;
+    index.record(date, floors, tile_format);
+    index.save(output_dir)?;
+
+    tracing::info!("Archived {} tiles as version {}", files, date);
+    Ok(files)
+}
+
+fn copy_dir(src: &Path, dst: &Path) -> Result<usize> {
+    fs::create_dir_all(dst)?;
+    let mut files = 0;
+
+    for entry in fs::read_dir(src).with_context(|| format!("Failed to read {:?}", src))? {
+        let path = entry?.path();
+        let target = dst.join(path.file_name().unwrap_or_default());
+
+        if path.is_dir() {
+            files += copy_dir(&path, &target)?;
+        } else {
+            fs::copy(&path, &target).with_context(|| format!("Failed to archive {:?}", path))?;
+            files += 1;
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_replaces_and_sorts_versions() {
+        let mut index = HistoryIndex::default();
+        index.record("2024-05-01", &[7], TileFormat::Png);
+        index.record("2024-03-01", &[7, 8], TileFormat::Png);
+        index.record("2024-05-01", &[6, 7], TileFormat::Webp);
+
+        let dates: Vec<&str> = index.versions.iter().map(|v| v.date.as_str()).collect();
+        assert_eq!(dates, vec!["2024-03-01", "2024-05-01"]);
+        assert_eq!(index.versions[1].floors, vec![6, 7]);
+        assert_eq!(index.versions[1].tile_format, TileFormat::Webp);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{read_text, sector_files_for_floors, sector_to_world, tile_runs, InputEncoding};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// The tiles of one house on one floor, from the `HouseID=<id>` attribute of
+/// sector lines. A house spanning several floors gets one entry per floor;
+/// `runs` lists its tiles row by row as `[y, x_start, x_end]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HouseArea {
+    pub id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub floor: u8,
+    pub tile_count: usize,
+    pub center_x: u32,
+    pub center_y: u32,
+    pub runs: Vec<[u32; 3]>,
+}
+
+/// Reads the house id of a sector line such as
+/// `3-4: HouseID=101, ProtectionZone, Content={...}`.
+pub fn parse_house_id(line: &str) -> Option<u32> {
+    let (_, rest) = line.split_once(':')?;
+    let attributes = match rest.find("Content=") {
+        Some(i) => &rest[..i],
+        None => rest,
+    };
+
+    attributes
+        .split(',')
+        .find_map(|attribute| attribute.trim().strip_prefix("HouseID="))
+        .and_then(|id| id.trim().parse().ok())
+}
+
+pub fn parse_houses_from_sectors<P: AsRef<Path>>(
+    map_dir: P,
+    floors: &[u8],
+    names: &HashMap<u32, String>,
+    encoding: InputEncoding,
+) -> Result<Vec<HouseArea>> {
+    let mut tiles_by_house: BTreeMap<(u8, u32), Vec<(u32, u32)>> = BTreeMap::new();
+
+    for sector in sector_files_for_floors(map_dir.as_ref(), floors)? {
+        let content = match read_text(&sector.path, encoding) {
+            Ok(content) => content,
+            Err(e) => {
+                tracing::warn!("Failed to read {:?}: {}", sector.path, e);
+                continue;
+            }
+        };
+
+        for line in content.lines() {
+            let Some(house_id) = parse_house_id(line) else {
+                continue;
+            };
+            let Some((local_x, local_y)) = line
+                .split_once(':')
+                .and_then(|(coords, _)| coords.trim().split_once('-'))
+                .and_then(|(x, y)| Some((x.parse::<u32>().ok()?, y.parse::<u32>().ok()?)))
+            else {
+                continue;
+            };
+
+            let (x, y) = sector_to_world(sector.sector_x, sector.sector_y, local_x, local_y);
+            tiles_by_house.entry((sector.z, house_id)).or_default().push((x, y));
+        }
+    }
+
+    let houses: Vec<HouseArea> = tiles_by_house
+        .into_iter()
+        .map(|((floor, id), mut tiles)| {
+            tiles.sort_unstable_by_key(|&(x, y)| (y, x));
+            tiles.dedup();
+            let (sum_x, sum_y) = tiles
+                .iter()
+                .fold((0u64, 0u64), |(sx, sy), &(x, y)| (sx + x as u64, sy + y as u64));
+            HouseArea {
+                id,
+                name: names.get(&id).cloned(),
+                floor,
+                tile_count: tiles.len(),
+                center_x: (sum_x / tiles.len() as u64) as u32,
+                center_y: (sum_y / tiles.len() as u64) as u32,
+                runs: tile_runs(&tiles),
+            }
+        })
+        .collect();
+
+    tracing::info!("Parsed {} house areas from .sec files", houses.len());
+    Ok(houses)
+}
+
+/// Reads a `house_id,name` CSV with a header line. Names may contain commas.
+pub fn parse_house_names<P: AsRef<Path>>(csv_path: P, encoding: InputEncoding) -> Result<HashMap<u32, String>> {
+    let content = read_text(csv_path.as_ref(), encoding)
+        .with_context(|| format!("Failed to read house names CSV: {:?}", csv_path.as_ref()))?;
+
+    let mut names = HashMap::new();
+    for (line_num, line) in content.lines().enumerate() {
+        if line_num == 0 || line.trim().is_empty() {
+            continue;
+        }
+
+        let Some((id, name)) = line.split_once(',') else {
+            tracing::warn!("Line {}: Invalid house names CSV format", line_num + 1);
+            continue;
+        };
+        let Ok(id) = id.trim().parse::<u32>() else {
+            tracing::warn!("Line {}: Failed to parse house id '{}'", line_num + 1, id);
+            continue;
+        };
+        names.insert(id, name.trim().trim_matches('"').to_string());
+    }
+
+    tracing::info!("Loaded {} house names", names.len());
+    Ok(names)
+}
+
+pub fn generate_houses_json(houses: &[HouseArea]) -> Result<String> {
+    let mut houses_by_floor: BTreeMap<u8, Vec<&HouseArea>> = BTreeMap::new();
+    for house in houses {
+        houses_by_floor.entry(house.floor).or_default().push(house);
+    }
+
+    let output = serde_json::json!({
+        "houses_by_floor": houses_by_floor
+    });
+
+    serde_json::to_string(&output).with_context(|| "Failed to serialize house data to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_house_id() {
+        assert_eq!(parse_house_id("3-4: HouseID=101, ProtectionZone, Content={100}"), Some(101));
+        assert_eq!(parse_house_id("3-4: NoLogout, HouseID=7"), Some(7));
+        assert_eq!(parse_house_id("3-4: ProtectionZone, Content={100}"), None);
+        // Attributes of items inside the tile are not the tile's
+        assert_eq!(parse_house_id("3-4: Content={2843 HouseID=5}"), None);
+    }
+}
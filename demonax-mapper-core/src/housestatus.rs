@@ -0,0 +1,102 @@
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HouseState {
+    Free,
+    Owned,
+    Auctioned,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HouseAuction {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_bid: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ends: Option<String>,
+}
+
+/// One entry of `houses-status.json`, as exported by the game server's web
+/// backend. Everything is optional; an empty object means the house is free.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HouseStatus {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rent_due: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auction: Option<HouseAuction>,
+}
+
+impl HouseStatus {
+    /// A running auction wins over a (previous) owner.
+    pub fn state(&self) -> HouseState {
+        if self.auction.is_some() {
+            HouseState::Auctioned
+        } else if self.owner.is_some() {
+            HouseState::Owned
+        } else {
+            HouseState::Free
+        }
+    }
+}
+
+/// Reads a `{"<house id>": {owner, rent_due, auction}}` map.
+pub fn parse_house_status<P: AsRef<Path>>(path: P) -> Result<BTreeMap<u32, HouseStatus>> {
+    let content = fs::read_to_string(path.as_ref())
+        .with_context(|| format!("Failed to read house status from {:?}", path.as_ref()))?;
+
+    let statuses: BTreeMap<u32, HouseStatus> = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse house status JSON {:?}", path.as_ref()))?;
+
+    tracing::info!("Loaded status for {} houses", statuses.len());
+    Ok(statuses)
+}
+
+#[derive(Serialize)]
+struct HouseStatusOutput<'a> {
+    state: HouseState,
+    #[serde(flatten)]
+    status: &'a HouseStatus,
+}
+
+pub fn generate_house_status_json(statuses: &BTreeMap<u32, HouseStatus>) -> Result<String> {
+    let houses: BTreeMap<u32, HouseStatusOutput> = statuses
+        .iter()
+        .map(|(&id, status)| (id, HouseStatusOutput { state: status.state(), status }))
+        .collect();
+
+    let output = serde_json::json!({
+        "house_status": houses
+    });
+
+    serde_json::to_string(&output).with_context(|| "Failed to serialize house status to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_house_state_from_status_fields() {
+        let statuses: BTreeMap<u32, HouseStatus> = serde_json::from_str(
+            r#"{
+                "1": {},
+                "2": {"owner": "Bob", "rent_due": "2024-04-01"},
+                "3": {"owner": "Bob", "auction": {"current_bid": 50000}}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(statuses[&1].state(), HouseState::Free);
+        assert_eq!(statuses[&2].state(), HouseState::Owned);
+        assert_eq!(statuses[&3].state(), HouseState::Auctioned);
+
+        let json = generate_house_status_json(&statuses).unwrap();
+        assert!(json.contains(r#""2":{"owner":"Bob","rent_due":"2024-04-01","state":"owned"}"#), "{}", json);
+    }
+}
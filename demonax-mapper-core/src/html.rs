@@ -1,17 +1,248 @@
-use anyhow::Result;
+use crate::{PmtilesLayout, RenderMode, TileFormat, WorldBounds, PMTILES_FLOOR_ZOOM_SHIFT};
+use crate::error::{Context, MapperError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 
+/// Build-time switches for optional viewer features.
+#[derive(Debug, Clone, Default)]
+pub struct ViewerOptions {
+    /// Show online players streamed from `players/stream` (serve mode only).
+    pub live_players: bool,
+    /// Re-fetch `spawns.json` and `questchests.json` this often, for
+    /// deployments that regenerate overlay data between tile builds.
+    pub overlay_refresh_minutes: Option<u32>,
+    /// Right-click command template for GMs, e.g. `/goto {x} {y} {z}`.
+    pub admin_command: Option<String>,
+    /// Output-relative URLs of operator stylesheets, linked after the built-in styles.
+    pub extra_stylesheets: Vec<String>,
+    /// Output-relative URLs of operator scripts, loaded after the viewer script.
+    pub extra_scripts: Vec<String>,
+    pub marker_styles: MarkerStyles,
+    /// CSS color shown around the rendered area of each floor; floors
+    /// without one keep the default black.
+    pub floor_backgrounds: BTreeMap<u8, String>,
+    /// CSS color behind floors without a background of their own; black
+    /// when unset.
+    pub void_color: Option<String>,
+    /// CSS color of the water overlay; dodger blue when unset.
+    pub water_color: Option<String>,
+    /// Format of the current build's tiles; archived builds record their own.
+    pub tile_format: TileFormat,
+    /// How the current build drew its tiles, which names the base layer.
+    pub render_mode: RenderMode,
+    /// Offer the `composite/` tiles of all floors stacked as a base layer.
+    pub composite: bool,
+    /// Load Leaflet from `leaflet/` in the output (see [`copy_leaflet`])
+    /// instead of unpkg.
+    pub local_leaflet: bool,
+    /// Read the current build's tiles from PMTiles archives; pmtiles.js
+    /// comes from unpkg, or from `leaflet/` with `local_leaflet`.
+    pub pmtiles: Option<PmtilesLayout>,
+}
+
+/// Overrides for one marker overlay; unset fields keep the viewer default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkerStyle {
+    /// Markers are hidden below this zoom level (default 3).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_zoom: Option<u8>,
+    /// Icon size in pixels for spawns and NPCs, circle radius for quest
+    /// chests, symbol size for transitions, teleports, signs, points of
+    /// interest and raids, line width for the sector grid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u32>,
+    /// Amount text for spawns, outline for NPCs, fill for quest chests,
+    /// transitions, teleports, signs and raids, line color for the sector grid.
+    /// Points of interest use one color per kind unless this is set. A `#hex` value or a CSS color name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+/// Per-overlay marker tuning, so large worlds can show markers further out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarkerStyles {
+    pub spawns: MarkerStyle,
+    pub npcs: MarkerStyle,
+    pub quests: MarkerStyle,
+    pub transitions: MarkerStyle,
+    pub teleports: MarkerStyle,
+    pub signs: MarkerStyle,
+    pub poi: MarkerStyle,
+    pub raids: MarkerStyle,
+    pub grid: MarkerStyle,
+}
+
+impl MarkerStyles {
+    /// Applies one `overlay.key=value` override, e.g. `spawns.min_zoom=2`.
+    pub fn set(&mut self, spec: &str) -> Result<()> {
+        let (target, value) = spec
+            .split_once('=')
+            .with_context(|| format!("Invalid marker style {:?}, expected OVERLAY.KEY=VALUE", spec))?;
+        let (overlay, key) = target
+            .split_once('.')
+            .with_context(|| format!("Invalid marker style {:?}, expected OVERLAY.KEY=VALUE", spec))?;
+
+        let style = match overlay.trim() {
+            "spawns" => &mut self.spawns,
+            "npcs" => &mut self.npcs,
+            "quests" => &mut self.quests,
+            "transitions" => &mut self.transitions,
+            "teleports" => &mut self.teleports,
+            "signs" => &mut self.signs,
+            "poi" => &mut self.poi,
+            "raids" => &mut self.raids,
+            "grid" => &mut self.grid,
+            other => {
+                return Err(MapperError::InvalidConfig(format!(
+                    "Unknown marker overlay {:?} (expected spawns, npcs, quests, transitions, teleports, signs, poi, raids or grid)",
+                    other
+                )));
+            }
+        };
+
+        let value = value.trim();
+        match key.trim() {
+            "min_zoom" => style.min_zoom = Some(value.parse::<u8>().with_context(|| format!("Invalid min_zoom {:?}", value))?),
+            "size" => style.size = Some(value.parse::<u32>().with_context(|| format!("Invalid size {:?}", value))?),
+            "color" => style.color = Some(value.to_string()),
+            other => {
+                return Err(MapperError::InvalidConfig(format!(
+                    "Unknown marker style key {:?} (expected min_zoom, size or color)",
+                    other
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends a description of every out-of-range value to `problems`.
+    pub fn validate(&self, problems: &mut Vec<String>) {
+        let styles = [
+            ("spawns", &self.spawns),
+            ("npcs", &self.npcs),
+            ("quests", &self.quests),
+            ("transitions", &self.transitions),
+            ("teleports", &self.teleports),
+            ("signs", &self.signs),
+            ("poi", &self.poi),
+            ("raids", &self.raids),
+            ("grid", &self.grid),
+        ];
+        for (name, style) in styles {
+            if style.size.is_some_and(|size| size == 0 || size > 128) {
+                problems.push(format!("marker_styles.{}.size must be 1-128", name));
+            }
+            if let Some(color) = &style.color
+                && !is_css_color(color)
+            {
+                problems.push(format!("marker_styles.{}.color {:?} is not a #hex color or color name", name, color));
+            }
+        }
+    }
+}
+
+fn is_css_color(color: &str) -> bool {
+    match color.strip_prefix('#') {
+        Some(hex) => matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => !color.is_empty() && color.chars().all(|c| c.is_ascii_alphabetic()),
+    }
+}
+
+/// Replaces `output/extra/` with a copy of `src_dir` and returns the
+/// output-relative URLs of everything copied, sorted so the link order is stable.
+pub fn copy_extra_assets<P: AsRef<Path>, Q: AsRef<Path>>(src_dir: P, output_path: Q) -> Result<Vec<String>> {
+    let extra_dir = output_path.as_ref().join("extra");
+    if extra_dir.exists() {
+        fs::remove_dir_all(&extra_dir).with_context(|| format!("Failed to clear {:?}", extra_dir))?;
+    }
+
+    let mut urls = Vec::new();
+    copy_assets_dir(src_dir.as_ref(), &extra_dir, "extra", &mut urls)?;
+    urls.sort();
+
+    tracing::info!("Copied {} extra viewer assets", urls.len());
+    Ok(urls)
+}
+
+/// Replaces `output/leaflet/` with a copy of Leaflet's `dist` directory,
+/// including the marker and layer control images its stylesheet refers to.
+pub fn copy_leaflet<P: AsRef<Path>, Q: AsRef<Path>>(leaflet_dir: P, output_path: Q) -> Result<()> {
+    let dst = output_path.as_ref().join("leaflet");
+    if dst.exists() {
+        fs::remove_dir_all(&dst).with_context(|| format!("Failed to clear {:?}", dst))?;
+    }
+
+    let mut urls = Vec::new();
+    copy_assets_dir(leaflet_dir.as_ref(), &dst, "leaflet", &mut urls)?;
+
+    tracing::info!("Copied {} Leaflet files", urls.len());
+    Ok(())
+}
+
+fn copy_assets_dir(src: &Path, dst: &Path, url_prefix: &str, urls: &mut Vec<String>) -> Result<()> {
+    fs::create_dir_all(dst)?;
+
+    for entry in fs::read_dir(src).with_context(|| format!("Failed to read assets from {:?}", src))? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let url = format!("{}/{}", url_prefix, name);
+
+        if path.is_dir() {
+            copy_assets_dir(&path, &dst.join(&name), &url, urls)?;
+        } else {
+            fs::copy(&path, dst.join(&name)).with_context(|| format!("Failed to copy asset {:?}", path))?;
+            urls.push(url);
+        }
+    }
+
+    Ok(())
+}
+
+/// Where the viewer loads Leaflet from unless it is copied into the output.
+const LEAFLET_CDN: &str = "https://unpkg.com/leaflet@1.9.4/dist";
+const PMTILES_CDN: &str = "https://unpkg.com/pmtiles@3.2.1/dist";
+
 pub fn generate_html<P: AsRef<Path>>(
     output_path: P,
     floors: &[u8],
     min_zoom: u8,
     max_zoom: u8,
-    min_tile_x: u32,
-    max_tile_x: u32,
-    min_tile_y: u32,
-    max_tile_y: u32,
+    bounds: &WorldBounds,
+    options: &ViewerOptions,
 ) -> Result<()> {
+    let (min_tile_x, min_tile_y) = bounds.min_tile();
+    let (max_tile_x, max_tile_y) = bounds.max_tile();
+    let floors_json = format!("{:?}", floors);
+    // `<\/` keeps a template containing `</script>` from closing the script tag
+    let admin_command_json = serde_json::to_string(&options.admin_command)?.replace("</", "<\\/");
+    let marker_styles_json = serde_json::to_string(&options.marker_styles)?;
+    let floor_backgrounds_json = serde_json::to_string(&options.floor_backgrounds)?;
+    let void_color = options.void_color.as_deref().unwrap_or("#000000");
+    let void_color_json = serde_json::to_string(void_color)?;
+    let water_color_json = serde_json::to_string(options.water_color.as_deref().unwrap_or("#1E90FF"))?;
+
+    let leaflet_base = if options.local_leaflet { "leaflet" } else { LEAFLET_CDN };
+    let pmtiles_script = match options.pmtiles {
+        Some(_) => format!("    <script src=\"{}/pmtiles.js\"></script>\n", if options.local_leaflet { "leaflet" } else { PMTILES_CDN }),
+        None => String::new(),
+    };
+    let pmtiles_layout_json = serde_json::to_string(&options.pmtiles)?;
+
+    let extra_stylesheets: String = options
+        .extra_stylesheets
+        .iter()
+        .map(|href| format!("    <link rel=\"stylesheet\" href=\"{}\" />\n", escape_attribute(href)))
+        .collect();
+    let extra_scripts: String = options
+        .extra_scripts
+        .iter()
+        .map(|src| format!("    <script src=\"{}\"></script>\n", escape_attribute(src)))
+        .collect();
+
     let html = format!(
         r#"<!DOCTYPE html>
 <html>
@@ -19,9 +250,12 @@ pub fn generate_html<P: AsRef<Path>>(
     <meta charset="utf-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
     <title>Demonax Map</title>
-    <link rel="stylesheet" href="https://unpkg.com/leaflet@1.9.4/dist/leaflet.css" />
-    <script src="https://unpkg.com/leaflet@1.9.4/dist/leaflet.js"></script>
-    <style>
+    <link rel="stylesheet" href="{leaflet_base}/leaflet.css" />
+    <script src="{leaflet_base}/leaflet.js"></script>
+{pmtiles_script}    <style>
+        :root {{
+            --sidebar-width: 130px;
+        }}
         body {{
             margin: 0;
             padding: 0;
@@ -31,8 +265,78 @@ pub fn generate_html<P: AsRef<Path>>(
             position: absolute;
             top: 50px;
             bottom: 0;
+            left: var(--sidebar-width);
+            right: 0;
+            background-color: {void_color};
+        }}
+        .night-tiles {{
+            filter: brightness(0.45) saturate(0.6);
+        }}
+        #floor-sidebar {{
+            position: absolute;
+            top: 50px;
+            bottom: 0;
+            left: 0;
+            width: var(--sidebar-width);
+            overflow-y: auto;
+            background: #222;
+            padding: 4px;
+            box-sizing: border-box;
+            z-index: 1000;
+        }}
+        .floor-thumb {{
+            display: block;
+            width: 100%;
+            margin-bottom: 4px;
+            padding: 3px;
+            background: #333;
+            color: #fff;
+            border: 2px solid transparent;
+            border-radius: 3px;
+            font-family: monospace;
+            font-size: 11px;
+            cursor: pointer;
+        }}
+        .floor-thumb img {{
+            display: block;
+            width: 100%;
+            margin-bottom: 2px;
+        }}
+        .floor-thumb.active {{
+            background: #444;
+            border-color: #ffcc00;
+        }}
+        #spawn-search {{
+            display: none;
+            margin-bottom: 8px;
+        }}
+        #spawn-search.available {{
+            display: block;
+        }}
+        #spawn-search input,
+        #spawn-search button {{
+            display: block;
             width: 100%;
-            background-color: #000000;
+            box-sizing: border-box;
+            margin-bottom: 4px;
+            padding: 4px;
+            font-family: monospace;
+            font-size: 11px;
+            background: #444;
+            color: #fff;
+            border: 1px solid #666;
+            border-radius: 3px;
+        }}
+        #spawn-search button {{
+            cursor: pointer;
+        }}
+        @media (max-width: 600px) {{
+            :root {{
+                --sidebar-width: 72px;
+            }}
+            .floor-thumb img {{
+                display: none;
+            }}
         }}
         #controls {{
             position: absolute;
@@ -66,13 +370,44 @@ pub fn generate_html<P: AsRef<Path>>(
             border: 1px solid #666;
             border-radius: 3px;
         }}
+        #region-button,
+        #measure-button,
+        #copy-link,
+        #marker-control button,
+        #goto-form button,
+        #goto-input {{
+            padding: 5px 10px;
+            font-family: monospace;
+            background: #444;
+            color: white;
+            border: 1px solid #666;
+            border-radius: 3px;
+            cursor: pointer;
+        }}
+        #goto-input {{
+            width: 12em;
+            cursor: text;
+        }}
+        #region-button.active,
+        #measure-button.active {{
+            background: #806600;
+            border-color: #ffcc00;
+        }}
+        .measure-result input {{
+            width: 5em;
+        }}
+        .marker-form input[type="text"] {{
+            width: 12em;
+        }}
+        .region-export a {{
+            display: block;
+            margin-top: 4px;
+        }}
         #coords {{
             margin-left: auto;
             font-size: 14px;
         }}
         .leaflet-marker-icon.spawn-marker {{
-            width: 32px !important;
-            height: 32px !important;
             margin: 0 !important;
             padding: 0 !important;
             display: flex !important;
@@ -83,8 +418,8 @@ pub fn generate_html<P: AsRef<Path>>(
             background: none !important;
         }}
         .spawn-marker img {{
-            max-width: 32px;
-            max-height: 32px;
+            max-width: 100%;
+            max-height: 100%;
             width: auto;
             height: auto;
             image-rendering: pixelated;
@@ -101,27 +436,50 @@ pub fn generate_html<P: AsRef<Path>>(
             pointer-events: none;
         }}
         .leaflet-marker-icon.npc-marker {{
-            width: 32px !important;
-            height: 32px !important;
             display: flex !important;
             align-items: center !important;
             justify-content: center !important;
         }}
         .npc-marker img {{
-            max-width: 32px;
-            max-height: 32px;
+            max-width: 100%;
+            max-height: 100%;
             image-rendering: pixelated;
         }}
-        input[type="checkbox"] {{
-            cursor: pointer;
+        .npc-marker.no-sprite img {{
+            display: none;
+        }}
+        .npc-marker.no-sprite::after {{
+            content: '';
+            width: 8px;
+            height: 8px;
+            border-radius: 50%;
+            background: #ffd24a;
+            border: 1px solid white;
+        }}
+        .sign-tooltip {{
+            max-width: 280px;
+            white-space: pre-line;
+        }}
+        .quest-rewards {{
+            margin: 2px 0 0;
+            padding-left: 16px;
         }}
-        .control-group input[type="checkbox"] {{
-            margin-right: 5px;
+        .leaflet-marker-icon.transition-marker,
+        .leaflet-marker-icon.teleport-marker,
+        .leaflet-marker-icon.sign-marker,
+        .leaflet-marker-icon.poi-marker,
+        .leaflet-marker-icon.raid-marker {{
+            display: flex !important;
+            align-items: center !important;
+            justify-content: center !important;
+            text-shadow: -1px -1px 0 #000, 1px -1px 0 #000, -1px 1px 0 #000, 1px 1px 0 #000;
+            line-height: 1;
+            cursor: pointer;
         }}
         #crosshair {{
             position: absolute;
             top: calc(50% + 25px + 16px);
-            left: calc(50% + 16px);
+            left: calc(50% + var(--sidebar-width) / 2 + 16px);
             transform: translate(-50%, -50%);
             pointer-events: none;
             z-index: 1000;
@@ -154,56 +512,70 @@ pub fn generate_html<P: AsRef<Path>>(
         #copy-toast.show {{
             opacity: 1;
         }}
+        .admin-command {{
+            cursor: pointer;
+            font-family: monospace;
+            color: #0078A8;
+        }}
     </style>
-</head>
+{extra_stylesheets}</head>
 <body>
     <div id="controls">
-        <div class="control-group">
-            <label for="floor-select">Floor:</label>
-            <select id="floor-select">
-{floor_options}
-            </select>
+        <div class="control-group" id="history-control" style="display: none">
+            <label for="history-slider">Version:</label>
+            <input type="range" id="history-slider" min="0" max="0" value="0" step="1" />
+            <span id="history-label">current</span>
         </div>
-        <div class="control-group">
-            <label>
-                <input type="checkbox" id="spawn-toggle" />
-                Show spawns
-            </label>
-        </div>
-        <div class="control-group">
-            <label>
-                <input type="checkbox" id="questchest-toggle" />
-                Show quest locations
-            </label>
-        </div>
-        <div class="control-group">
-            <label>
-                <input type="checkbox" id="npc-toggle" />
-                Show NPCs
-            </label>
-        </div>
-        <div class="control-group">
-            <label>
-                <input type="checkbox" id="crosshair-toggle" />
-                Show crosshair
-            </label>
-        </div>
-        <div class="control-group">
-            <label>
-                <input type="checkbox" id="sector-grid-toggle" />
-                Show sector borders
-            </label>
+        <button id="region-button" title="Drag a rectangle to export it as a PNG or as --region bounds">Export region</button>
+        <button id="measure-button" title="Click two points to measure the distance and walking time between them">Measure</button>
+        <form class="control-group" id="goto-form">
+            <input type="text" id="goto-input" placeholder="X,Y,Z" title="Center the map on a position: X,Y on this floor or X,Y,Z (shortcut: g)" />
+            <button type="submit">Go</button>
+        </form>
+        <button id="copy-link" title="Copy a link to the current position, floor, zoom and overlays">Copy link</button>
+        <div class="control-group" id="marker-control">
+            <button id="markers-export" title="Download your markers as a JSON file">Export markers</button>
+            <button id="markers-import" title="Add the markers of an exported JSON file to yours">Import markers</button>
+            <input type="file" id="markers-file" accept=".json,application/json" style="display: none" />
         </div>
         <div id="coords">
             X: <span id="coord-x">-</span>, Y: <span id="coord-y">-</span>, Z: <span id="coord-z">-</span> | <span id="sector-file">-</span>
         </div>
     </div>
+    <div id="floor-sidebar">
+        <div id="spawn-search">
+            <input type="search" id="spawn-search-input" list="spawn-names" placeholder="Monster" title="Show only spawns of this monster" />
+            <datalist id="spawn-names"></datalist>
+            <button id="spawn-nearest" title="Center the closest matching spawn, looking at other floors if this one has none">Nearest spawn</button>
+        </div>
+{floor_buttons}
+    </div>
     <div id="map"></div>
     <svg id="crosshair" width="40" height="40" viewBox="0 0 40 40">
         <line x1="20" y1="5" x2="20" y2="35" />
         <line x1="5" y1="20" x2="35" y2="20" />
     </svg>
     <div id="copy-toast"></div>
+    <svg width="0" height="0" style="position: absolute">
+        <defs>
+            <pattern id="hatch-ProtectionZone" width="8" height="8" patternUnits="userSpaceOnUse" patternTransform="rotate(45)">
+                <line x1="0" y1="0" x2="0" y2="8" stroke="limegreen" stroke-width="3" />
+            </pattern>
+            <pattern id="hatch-NoLogout" width="8" height="8" patternUnits="userSpaceOnUse" patternTransform="rotate(-45)">
+                <line x1="0" y1="0" x2="0" y2="8" stroke="orangered" stroke-width="3" />
+            </pattern>
+            <pattern id="hatch-Arena" width="8" height="8" patternUnits="userSpaceOnUse">
+                <line x1="0" y1="0" x2="0" y2="8" stroke="gold" stroke-width="3" />
+            </pattern>
+            <pattern id="hatch-Hardcore" width="8" height="8" patternUnits="userSpaceOnUse">
+                <line x1="0" y1="0" x2="8" y2="0" stroke="crimson" stroke-width="3" />
+                <line x1="0" y1="0" x2="0" y2="8" stroke="crimson" stroke-width="3" />
+            </pattern>
+            <pattern id="hatch-other" width="6" height="6" patternUnits="userSpaceOnUse">
+                <circle cx="3" cy="3" r="1.5" fill="mediumorchid" />
+            </pattern>
+        </defs>
+    </svg>
 
     <script>
         const floors = {floors_json};
@@ -214,8 +586,26 @@ pub fn generate_html<P: AsRef<Path>>(
         const minTileY = {min_tile_y};
         const maxTileY = {max_tile_y};
 
+        // The map's lat/lng counts game tiles from the top-left corner of
+        // the rendered area: lat down, lng across. Every conversion between
+        // world coordinates and map positions goes through these two.
+        function worldToLatLng(worldX, worldY) {{
+            return [worldY - minTileY, worldX - minTileX];
+        }}
+
+        // World tile under a map position; may fall outside the map
+        function latLngToWorld(latLng) {{
+            return {{ x: minTileX + Math.floor(latLng.lng), y: minTileY + Math.floor(latLng.lat) }};
+        }}
+
+        const mapBounds = [worldToLatLng(minTileX, minTileY), worldToLatLng(maxTileX + 1, maxTileY + 1)];
+
         let currentFloor = {default_floor};
         let tileLayer = null;
+        // '' for the current build, 'history/<date>/' for an archived one
+        let tilePrefix = '';
+        const buildTileExt = '{tile_ext}';
+        let tileExt = buildTileExt;
 
         const CustomCRS = L.extend({{}}, L.CRS.Simple, {{
             transformation: new L.Transformation(1, 0, 1, 0)
@@ -225,9 +615,197 @@ pub fn generate_html<P: AsRef<Path>>(
             crs: CustomCRS,
             minZoom: minZoom,
             maxZoom: maxZoom,
-            attributionControl: false
+            attributionControl: false,
+            // Keys are handled below, to pan a sector at a time
+            keyboard: false
         }});
 
+        // Base layers hold the floor tiles; the value is the tile directory
+        // prefix inside the output (or archived) directory. Night draws the
+        // day tiles darkened, so it needs no tiles of its own.
+        const baseTileDirs = {base_tile_dirs};
+        const nightFilter = 'brightness(0.45) saturate(0.6)';
+        const baseLayers = {{}};
+        Object.keys(baseTileDirs).forEach(name => baseLayers[name] = L.layerGroup());
+
+        // PMTiles builds pack each tile directory into archives read with
+        // range requests: one per floor, or one for all floors holding floor
+        // f's tile z/x/y at (z + {floor_zoom_shift})/(x + f * 2^(z + {floor_zoom_shift} - 4))/y
+        const pmtilesLayout = {pmtiles_layout_json};
+        const pmtilesArchives = {{}};
+
+        function pmtilesArchive(dir, floor) {{
+            const url = dir + (pmtilesLayout === 'combined' ? 'tiles' : floor) + '.pmtiles';
+            if (!pmtilesArchives[url]) pmtilesArchives[url] = new pmtiles.PMTiles(url);
+            return pmtilesArchives[url];
+        }}
+
+        function getPmtilesTile(dir, floor, z, x, y) {{
+            const archive = pmtilesArchive(dir, floor);
+            return pmtilesLayout === 'combined'
+                ? archive.getZxy(z + {floor_zoom_shift}, x + floor * Math.pow(2, z + {floor_zoom_shift} - 4), y)
+                : archive.getZxy(z, x, y);
+        }}
+
+        // Tiles of one floor from a tile directory ('', 'composite/',
+        // 'heatmap/'). Archived builds under history/ keep plain files.
+        function floorTileLayer(dir, floor, ext, options) {{
+            if (!pmtilesLayout || dir.startsWith('history/')) {{
+                return L.tileLayer(dir + floor + '/{{z}}/{{x}}/{{y}}.' + ext, options);
+            }}
+            if (pmtilesLayout === 'per-floor') {{
+                return pmtiles.leafletRasterLayer(pmtilesArchive(dir, floor), options);
+            }}
+            const CombinedLayer = L.GridLayer.extend({{
+                createTile: function(coords, done) {{
+                    const tile = document.createElement('img');
+                    getPmtilesTile(dir, floor, coords.z, coords.x, coords.y).then(found => {{
+                        if (!found) {{
+                            done(null, tile);
+                            return;
+                        }}
+                        tile.onload = () => {{
+                            URL.revokeObjectURL(tile.src);
+                            done(null, tile);
+                        }};
+                        tile.onerror = error => done(error, tile);
+                        tile.src = URL.createObjectURL(new Blob([found.data], {{ type: 'image/' + ext }}));
+                    }}).catch(error => done(error, tile));
+                    return tile;
+                }}
+            }});
+            return new CombinedLayer(options);
+        }}
+
+        // Built-in marker defaults, overridden by the build's marker styles
+        const markerStyles = {marker_styles_json};
+        const floorBackgrounds = {floor_backgrounds_json};
+        const voidColor = {void_color_json};
+        const waterColor = {water_color_json};
+        const spawnStyle = {{ min_zoom: 3, size: 32, color: '#FFFFFF', ...markerStyles.spawns }};
+        const npcStyle = {{ min_zoom: 3, size: 32, color: null, ...markerStyles.npcs }};
+        const questStyle = {{ min_zoom: 3, size: 10, color: '#FFD700', ...markerStyles.quests }};
+        const transitionStyle = {{ min_zoom: 3, size: 16, color: '#7FFFD4', ...markerStyles.transitions }};
+        const teleportStyle = {{ min_zoom: 3, size: 16, color: '#DA70D6', ...markerStyles.teleports }};
+        const signStyle = {{ min_zoom: 4, size: 14, color: '#F5DEB3', ...markerStyles.signs }};
+        const poiStyle = {{ min_zoom: 2, size: 18, ...markerStyles.poi }};
+        const raidStyle = {{ min_zoom: 2, size: 18, color: '#FF4040', ...markerStyles.raids }};
+        const gridStyle = {{ min_zoom: 3, size: 1, color: '#00FFFF', ...markerStyles.grid }};
+
+        const livePlayers = {live_players};
+        const spawnLayer = L.layerGroup();
+        const spawnAreaLayer = L.layerGroup();
+        const npcLayer = L.layerGroup();
+        const questChestLayer = L.layerGroup();
+        const waterLayer = L.layerGroup();
+        const zoneLayer = L.layerGroup();
+        const houseLayer = L.layerGroup();
+        const transitionLayer = L.layerGroup();
+        const teleportLayer = L.layerGroup();
+        const signLayer = L.layerGroup();
+        const poiLayer = L.layerGroup();
+        const raidLayer = L.layerGroup();
+        const huntingLayer = L.layerGroup();
+        const heatmapLayer = L.layerGroup();
+        const diffLayer = L.layerGroup();
+        const playerLayer = L.layerGroup();
+        const crosshairLayer = L.layerGroup();
+        const sectorGridLayer = L.layerGroup();
+        const userMarkerLayer = L.layerGroup();
+
+        // Keys are the URL toggle names, in the order they appear in the hash
+        const overlays = {{
+            spawns: {{ name: 'Spawns', layer: spawnLayer, update: updateSpawnLayer }},
+            spawn_areas: {{ name: 'Spawn areas', layer: spawnAreaLayer, update: updateSpawnAreaLayer }},
+            npcs: {{ name: 'NPCs', layer: npcLayer, update: updateNpcLayer }},
+            quests: {{ name: 'Quest locations', layer: questChestLayer, update: updateQuestChestLayer }},
+            water: {{ name: 'Water areas', layer: waterLayer, update: updateWaterLayer }},
+            zones: {{ name: 'Zones', layer: zoneLayer, update: updateZoneLayer }},
+            houses: {{ name: 'Houses', layer: houseLayer, update: updateHouseLayer }},
+            transitions: {{ name: 'Stairs and ladders', layer: transitionLayer, update: updateTransitionLayer }},
+            teleports: {{ name: 'Teleports', layer: teleportLayer, update: updateTeleportLayer }},
+            signs: {{ name: 'Signs and books', layer: signLayer, update: updateSignLayer }},
+            poi: {{ name: 'Depots, temples and banks', layer: poiLayer, update: updatePoiLayer }},
+            raids: {{ name: 'Raids', layer: raidLayer, update: updateRaidLayer }},
+            hunting: {{ name: 'Hunting value', layer: huntingLayer, update: updateHuntingLayer }},
+            heatmap: {{ name: 'Heatmap', layer: heatmapLayer, update: updateHeatmapLayer }},
+            diff: {{ name: 'Map changes', layer: diffLayer, update: updateDiffLayer }},
+            ...(livePlayers ? {{ players: {{ name: 'Online players', layer: playerLayer, update: updatePlayerLayer }} }} : {{}}),
+            crosshair: {{ name: 'Crosshair', layer: crosshairLayer, update: updateCrosshair }},
+            grid: {{ name: 'Sector borders', layer: sectorGridLayer, update: updateSectorGridLayer }},
+            markers: {{ name: 'My markers', layer: userMarkerLayer, update: updateUserMarkerLayer }}
+        }};
+
+        const layerControl = L.control.layers(
+            baseLayers,
+            Object.fromEntries(Object.values(overlays).map(overlay => [overlay.name, overlay.layer]))
+        ).addTo(map);
+
+        // Overlays whose data failed to load are taken out of the layer control
+        function setOverlayAvailable(key, available) {{
+            const overlay = overlays[key];
+            if (!overlay || (overlay.unavailable !== true) === available) {{
+                return;
+            }}
+
+            overlay.unavailable = !available;
+            if (available) {{
+                layerControl.addOverlay(overlay.layer, overlay.name);
+            }} else {{
+                layerControl.removeLayer(overlay.layer);
+            }}
+        }}
+
+        // Layer selection is remembered across visits
+        const layerStorageKey = 'demonax-map-layers';
+
+        function loadLayerState() {{
+            try {{
+                return JSON.parse(localStorage.getItem(layerStorageKey)) || {{}};
+            }} catch (err) {{
+                return {{}};
+            }}
+        }}
+
+        function saveLayerState() {{
+            const state = {{
+                base: currentBase,
+                overlays: Object.keys(overlays).filter(key => map.hasLayer(overlays[key].layer))
+            }};
+            try {{
+                localStorage.setItem(layerStorageKey, JSON.stringify(state));
+            }} catch (err) {{
+                console.warn('Could not save layer selection:', err);
+            }}
+        }}
+
+        // The last viewed position is restored when the page opens without a hash
+        const viewStorageKey = 'demonax-map-view';
+
+        function loadSavedView() {{
+            try {{
+                const view = JSON.parse(localStorage.getItem(viewStorageKey));
+                if (!view || !floors.includes(view.z) || view.zoom < minZoom || view.zoom > maxZoom) {{
+                    return null;
+                }}
+                return {{ x: view.x, y: view.y, z: view.z, zoom: view.zoom, toggles: {{}} }};
+            }} catch (err) {{
+                return null;
+            }}
+        }}
+
+        function saveView(x, y, z, zoom) {{
+            try {{
+                localStorage.setItem(viewStorageKey, JSON.stringify({{ x, y, z, zoom }}));
+            }} catch (err) {{
+                console.warn('Could not save map position:', err);
+            }}
+        }}
+
+        const savedLayers = loadLayerState();
+        let currentBase = baseTileDirs[savedLayers.base] !== undefined ? savedLayers.base : Object.keys(baseTileDirs)[0];
+        baseLayers[currentBase].addTo(map);
+
         function toTitleCase(str) {{
             return str.split(' ').map(word =>
                 word.charAt(0).toUpperCase() + word.slice(1).toLowerCase()
@@ -262,117 +840,72 @@ pub fn generate_html<P: AsRef<Path>>(
             return {{ x, y, z, zoom, toggles }};
         }}
 
-        function worldToTile(worldX, worldY) {{
-            const tileX = worldX - minTileX;
-            const tileY = worldY - minTileY;
-            return {{ tileX, tileY }};
-        }}
-
         function updateHash() {{
-            const center = map.getCenter();
             const zoom = map.getZoom();
+            const {{ x: worldX, y: worldY }} = latLngToWorld(map.getCenter());
 
-            const tileX = Math.floor(center.lng);
-            const tileY = Math.floor(center.lat);
-
-            const worldX = minTileX + tileX;
-            const worldY = minTileY + tileY;
-
-            // Collect toggle states
-            const toggleStates = [];
-            const spawnToggle = document.getElementById('spawn-toggle');
-            const npcToggle = document.getElementById('npc-toggle');
-            const questToggle = document.getElementById('questchest-toggle');
-            const crosshairToggle = document.getElementById('crosshair-toggle');
-            const gridToggle = document.getElementById('sector-grid-toggle');
-
-            if (spawnToggle && spawnToggle.checked) toggleStates.push('spawns=1');
-            if (npcToggle && npcToggle.checked) toggleStates.push('npcs=1');
-            if (questToggle && questToggle.checked) toggleStates.push('quests=1');
-            if (crosshairToggle && crosshairToggle.checked) toggleStates.push('crosshair=1');
-            if (gridToggle && gridToggle.checked) toggleStates.push('grid=1');
+            const toggleStates = Object.keys(overlays)
+                .filter(key => map.hasLayer(overlays[key].layer))
+                .map(key => key + '=1');
 
             const queryString = toggleStates.length > 0 ? '?' + toggleStates.join('&') : '';
             const hash = `#${{worldX}},${{worldY}},${{currentFloor}},${{zoom}}${{queryString}}`;
             history.replaceState(null, '', hash);
+            saveView(worldX, worldY, currentFloor, zoom);
         }}
 
+        // Floor picker: one button per floor with its thumbnail (if built)
+        const floorButtons = document.querySelectorAll('.floor-thumb');
+        floorButtons.forEach(button => {{
+            const floor = parseInt(button.dataset.floor);
+            const thumbnail = button.querySelector('img');
+            if (thumbnail) thumbnail.style.backgroundColor = floorBackgrounds[floor] || voidColor;
+            button.addEventListener('click', function() {{
+                loadFloor(floor);
+                updateHash();
+            }});
+        }});
+
         function loadFloor(floor) {{
-            if (tileLayer) {{
-                map.removeLayer(tileLayer);
-            }}
+            Object.values(baseLayers).forEach(group => group.clearLayers());
 
-            tileLayer = L.tileLayer(floor + '/{{z}}/{{x}}/{{y}}.png', {{
+            tileLayer = floorTileLayer(tilePrefix + baseTileDirs[currentBase], floor, tileExt, {{
                 minZoom: minZoom,
                 maxZoom: maxZoom,
                 noWrap: true,
-                bounds: [[0, 0], [{max_tile_y} - {min_tile_y}, {max_tile_x} - {min_tile_x}]]
+                bounds: mapBounds,
+                className: currentBase === 'Night' ? 'night-tiles' : ''
             }});
 
-            tileLayer.addTo(map);
+            tileLayer.addTo(baseLayers[currentBase]);
             currentFloor = floor;
+            document.getElementById('map').style.backgroundColor = floorBackgrounds[floor] || voidColor;
+            floorButtons.forEach(button => {{
+                const active = parseInt(button.dataset.floor) === floor;
+                button.classList.toggle('active', active);
+                if (active) button.scrollIntoView({{ block: 'nearest' }});
+            }});
         }}
 
         const hashParams = parseHash();
+        const initialView = hashParams || loadSavedView();
 
-        if (hashParams) {{
-            currentFloor = hashParams.z;
+        if (initialView) {{
+            currentFloor = initialView.z;
             loadFloor(currentFloor);
 
-            document.getElementById('floor-select').value = currentFloor;
-
-            const {{ tileX, tileY }} = worldToTile(hashParams.x, hashParams.y);
-            map.setView([tileY, tileX], hashParams.zoom);
+            map.setView(worldToLatLng(initialView.x, initialView.y), initialView.zoom);
         }} else {{
-            map.setView([({max_tile_y} - {min_tile_y}) / 2, ({max_tile_x} - {min_tile_x}) / 2], 0);
+            map.setView(worldToLatLng((minTileX + maxTileX + 1) / 2, (minTileY + maxTileY + 1) / 2), 0);
             loadFloor(currentFloor);
         }}
 
-        // Apply saved toggle states from URL
-        if (hashParams && hashParams.toggles) {{
-            const {{ toggles }} = hashParams;
-
-            const spawnToggle = document.getElementById('spawn-toggle');
-            if (spawnToggle && toggles.spawns !== undefined) {{
-                spawnToggle.checked = toggles.spawns;
-            }}
-
-            const npcToggle = document.getElementById('npc-toggle');
-            if (npcToggle && toggles.npcs !== undefined) {{
-                npcToggle.checked = toggles.npcs;
-            }}
-
-            const questToggle = document.getElementById('questchest-toggle');
-            if (questToggle && toggles.quests !== undefined) {{
-                questToggle.checked = toggles.quests;
-            }}
-
-            const crosshairToggle = document.getElementById('crosshair-toggle');
-            const crosshair = document.getElementById('crosshair');
-            if (crosshairToggle && crosshair && toggles.crosshair !== undefined) {{
-                crosshairToggle.checked = toggles.crosshair;
-                if (toggles.crosshair) {{
-                    crosshair.classList.add('visible');
-                }}
-            }}
-
-            const gridToggle = document.getElementById('sector-grid-toggle');
-            if (gridToggle && toggles.grid !== undefined) {{
-                gridToggle.checked = toggles.grid;
-            }}
-        }}
-
         let lastWorldX = 0;
         let lastWorldY = 0;
         let lastSectorFile = '';
 
         map.on('mousemove', function(e) {{
-            const latLng = e.latlng;
-            const tileX = Math.floor(latLng.lng);
-            const tileY = Math.floor(latLng.lat);
-
-            const worldX = minTileX + tileX;
-            const worldY = minTileY + tileY;
+            const {{ x: worldX, y: worldY }} = latLngToWorld(e.latlng);
 
             lastWorldX = worldX;
             lastWorldY = worldY;
@@ -397,30 +930,67 @@ pub fn generate_html<P: AsRef<Path>>(
 
         map.on('zoomend', updateHash);
 
-        document.getElementById('floor-select').addEventListener('change', function(e) {{
-            loadFloor(parseInt(e.target.value));
-            updateHash();
-        }});
-
         window.addEventListener('hashchange', function() {{
             const hashParams = parseHash();
             if (hashParams) {{
                 if (hashParams.z !== currentFloor) {{
                     loadFloor(hashParams.z);
-                    document.getElementById('floor-select').value = hashParams.z;
                 }}
 
-                const {{ tileX, tileY }} = worldToTile(hashParams.x, hashParams.y);
-                map.setView([tileY, tileX], hashParams.zoom);
+                map.setView(worldToLatLng(hashParams.x, hashParams.y), hashParams.zoom);
+            }}
+        }});
+
+        // Time slider over archived builds (--archive)
+        let historyVersions = [];
+
+        fetch('history/index.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('No archived builds');
+                }}
+                return response.json();
+            }})
+            .then(data => {{
+                historyVersions = data.versions || [];
+                if (historyVersions.length === 0) {{
+                    return;
+                }}
+
+                const slider = document.getElementById('history-slider');
+                slider.max = historyVersions.length;
+                slider.value = historyVersions.length;
+                document.getElementById('history-control').style.display = '';
+            }})
+            .catch(() => {{}});
+
+        function selectedHistoryVersion() {{
+            const slider = document.getElementById('history-slider');
+            return historyVersions[parseInt(slider.value)];
+        }}
+
+        function updateHistoryLabel() {{
+            const version = selectedHistoryVersion();
+            const label = document.getElementById('history-label');
+
+            if (!version) {{
+                label.textContent = 'current';
+            }} else if (version.floors.includes(currentFloor)) {{
+                label.textContent = version.date;
+            }} else {{
+                label.textContent = version.date + ' (floor not archived)';
             }}
+        }}
+
+        document.getElementById('history-slider').addEventListener('input', function() {{
+            const version = selectedHistoryVersion();
+            tilePrefix = version ? 'history/' + version.date + '/' : '';
+            tileExt = version ? (version.tile_format || 'png') : buildTileExt;
+            loadFloor(currentFloor);
         }});
 
         // Monster spawn overlay
         let spawnData = null;
-        let spawnMarkers = [];
-
-        // Sector grid overlay
-        let sectorGridLines = [];
 
         fetch('spawns.json')
             .then(response => {{
@@ -431,20 +1001,18 @@ pub fn generate_html<P: AsRef<Path>>(
             }})
             .then(data => {{
                 spawnData = data;
+                updateSpawnSearch();
                 updateSpawnLayer();
+                updateSpawnAreaLayer();
             }})
             .catch(err => {{
                 console.warn('Monster spawns unavailable:', err);
-                const toggle = document.getElementById('spawn-toggle');
-                if (toggle) {{
-                    toggle.disabled = true;
-                    toggle.parentElement.title = 'Monster spawn data not available';
-                }}
+                setOverlayAvailable('spawns', false);
+                setOverlayAvailable('spawn_areas', false);
             }});
 
         // Quest chest overlay
         let questChestData = null;
-        let questChestMarkers = [];
 
         fetch('questchests.json')
             .then(response => {{
@@ -459,192 +1027,1108 @@ pub fn generate_html<P: AsRef<Path>>(
             }})
             .catch(err => {{
                 console.warn('Quest chests unavailable:', err);
-                const toggle = document.getElementById('questchest-toggle');
-                if (toggle) {{
-                    toggle.disabled = true;
-                    toggle.parentElement.title = 'Quest chest data not available';
-                }}
+                setOverlayAvailable('quests', false);
             }});
 
-        // NPC overlay
-        let npcData = null;
-        let npcMarkers = [];
+        // Water region overlay
+        let waterData = null;
 
-        fetch('npcs.json')
+        fetch('water.json')
             .then(response => {{
                 if (!response.ok) {{
-                    throw new Error('NPC data not found');
+                    throw new Error('Water data not found');
                 }}
                 return response.json();
             }})
             .then(data => {{
-                npcData = data;
-                updateNpcLayer();
+                waterData = data;
+                updateWaterLayer();
             }})
             .catch(err => {{
-                console.warn('NPC data unavailable:', err);
-                const toggle = document.getElementById('npc-toggle');
-                if (toggle) {{
-                    toggle.disabled = true;
-                    toggle.parentElement.title = 'NPC data not available';
+                console.warn('Water regions unavailable:', err);
+                setOverlayAvailable('water', false);
+            }});
+
+        // Zone overlay (protection zones, no-logout areas and server-specific flags)
+        let zoneData = null;
+        const zoneColors = {{
+            ProtectionZone: '#32CD32',
+            NoLogout: '#FF4500',
+            Arena: '#FFD700',
+            Hardcore: '#DC143C'
+        }};
+
+        fetch('zones.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('Zone data not found');
                 }}
+                return response.json();
+            }})
+            .then(data => {{
+                zoneData = data;
+                updateZoneLayer();
+            }})
+            .catch(err => {{
+                console.warn('Zones unavailable:', err);
+                setOverlayAvailable('zones', false);
             }});
 
-        function worldToLatLng(worldX, worldY) {{
-            const tileX = worldX - minTileX;
-            const tileY = worldY - minTileY;
-            return [tileY, tileX];
-        }}
+        // Heatmap overlay (pre-rendered tiles from --heatmap-csv)
+        let heatmapData = null;
+        const transparentTile = 'data:image/gif;base64,R0lGODlhAQABAIAAAAAAAP///yH5BAEAAAAALAAAAAABAAEAAAIBRAA7';
 
-        function updateSpawnLayer() {{
-            spawnMarkers.forEach(marker => map.removeLayer(marker));
-            spawnMarkers = [];
+        fetch('heatmap.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('Heatmap data not found');
+                }}
+                return response.json();
+            }})
+            .then(data => {{
+                heatmapData = data;
+                updateHeatmapLayer();
+            }})
+            .catch(err => {{
+                console.warn('Heatmap unavailable:', err);
+                setOverlayAvailable('heatmap', false);
+            }});
 
-            const toggle = document.getElementById('spawn-toggle');
-            const showSpawns = toggle && toggle.checked;
-            const currentZoom = map.getZoom();
+        // Map diff overlay (diff.json written by the diff subcommand)
+        let diffData = null;
+        const diffColors = {{
+            added: '#32CD32',
+            removed: '#DC143C',
+            changed: '#FFA500'
+        }};
 
-            if (!showSpawns || !spawnData || currentZoom < 3) {{
-                return;
-            }}
+        fetch('diff.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('Diff data not found');
+                }}
+                return response.json();
+            }})
+            .then(data => {{
+                diffData = data;
+                updateDiffLayer();
+            }})
+            .catch(err => {{
+                console.warn('Map diff unavailable:', err);
+                setOverlayAvailable('diff', false);
+            }});
 
-            const floorSpawns = spawnData.spawns_by_floor[currentFloor] || [];
-            const bounds = map.getBounds();
+        // Live player overlay (serve mode, enabled with --live-players)
+        let playerData = [];
 
-            const visibleSpawns = floorSpawns.filter(spawn => {{
-                const [lat, lng] = worldToLatLng(spawn.x, spawn.y);
-                return bounds.contains([lat, lng]);
+        if (livePlayers && window.EventSource) {{
+            const playerSource = new EventSource('players/stream');
+            playerSource.addEventListener('players', e => {{
+                playerData = JSON.parse(e.data).players;
+                updatePlayerLayer();
             }});
+            playerSource.onerror = () => console.warn('Player stream disconnected, retrying...');
+        }}
 
-            visibleSpawns.forEach(spawn => {{
-                const [lat, lng] = worldToLatLng(spawn.x, spawn.y);
+        // House ownership status (--houses-status), used to color house shapes
+        let houseStatusData = null;
+        const houseStateColors = {{
+            free: '#32CD32',
+            owned: '#1E90FF',
+            auctioned: '#FFA500'
+        }};
 
-                const icon = L.divIcon({{
+        fetch('houses-status.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('House status not found');
+                }}
+                return response.json();
+            }})
+            .then(data => {{
+                houseStatusData = data.house_status;
+                updateHouseLayer();
+            }})
+            .catch(err => console.warn('House status unavailable:', err));
+
+        function houseStatusColor(houseId) {{
+            const status = houseStatusData && houseStatusData[houseId];
+            return status ? houseStateColors[status.state] : null;
+        }}
+
+        function houseStatusDetails(houseId) {{
+            const status = houseStatusData && houseStatusData[houseId];
+            if (!status) {{
+                return '';
+            }}
+
+            // The status file is supplied by the user, so never trust it as HTML
+            const lines = [`Status: ${{escapeHtml(status.state)}}`];
+            if (status.owner) lines.push(`Owner: ${{escapeHtml(status.owner)}}`);
+            if (status.rent_due) lines.push(`Rent due: ${{escapeHtml(status.rent_due)}}`);
+            if (status.auction) {{
+                if (status.auction.current_bid != null) lines.push(`Current bid: ${{status.auction.current_bid.toLocaleString()}} gp`);
+                if (status.auction.ends) lines.push(`Auction ends: ${{escapeHtml(status.auction.ends)}}`);
+            }}
+            return lines.join('<br/>');
+        }}
+
+        // House areas from the sector files' HouseID attribute
+        let houseData = null;
+
+        fetch('houses.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('House data not found');
+                }}
+                return response.json();
+            }})
+            .then(data => {{
+                houseData = data;
+                updateHouseLayer();
+            }})
+            .catch(err => {{
+                console.warn('Houses unavailable:', err);
+                setOverlayAvailable('houses', false);
+            }});
+
+        // Ladders, stairs, rope spots and holes; clicking one follows it
+        let transitionData = null;
+
+        fetch('transitions.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('Transition data not found');
+                }}
+                return response.json();
+            }})
+            .then(data => {{
+                transitionData = data;
+                updateTransitionLayer();
+            }})
+            .catch(err => {{
+                console.warn('Floor transitions unavailable:', err);
+                setOverlayAvailable('transitions', false);
+            }});
+
+        // Teleporters and portals; clicking one jumps to its destination
+        let teleportData = null;
+
+        fetch('teleports.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('Teleport data not found');
+                }}
+                return response.json();
+            }})
+            .then(data => {{
+                teleportData = data;
+                updateTeleportLayer();
+            }})
+            .catch(err => {{
+                console.warn('Teleports unavailable:', err);
+                setOverlayAvailable('teleports', false);
+            }});
+
+        // Texts of signs, gravestones and books, shown when hovering their marker
+        let signData = null;
+
+        fetch('signs.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('Sign data not found');
+                }}
+                return response.json();
+            }})
+            .then(data => {{
+                signData = data;
+                updateSignLayer();
+            }})
+            .catch(err => {{
+                console.warn('Signs unavailable:', err);
+                setOverlayAvailable('signs', false);
+            }});
+
+        // Depots, temples, mailboxes and banks found on the map
+        let poiData = null;
+
+        fetch('poi.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('Point of interest data not found');
+                }}
+                return response.json();
+            }})
+            .then(data => {{
+                poiData = data;
+                updatePoiLayer();
+            }})
+            .catch(err => {{
+                console.warn('Points of interest unavailable:', err);
+                setOverlayAvailable('poi', false);
+            }});
+
+        // Raid spawns from .raid files
+        let raidData = null;
+
+        fetch('raids.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('Raid data not found');
+                }}
+                return response.json();
+            }})
+            .then(data => {{
+                raidData = data;
+                updateRaidLayer();
+            }})
+            .catch(err => {{
+                console.warn('Raids unavailable:', err);
+                setOverlayAvailable('raids', false);
+            }});
+
+        // Experience per hour of each sector's spawns
+        let huntingData = null;
+
+        fetch('hunting.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('Hunting value data not found');
+                }}
+                return response.json();
+            }})
+            .then(data => {{
+                if (!data.max_exp_per_hour) {{
+                    throw new Error('No monster experience known');
+                }}
+                huntingData = data;
+                updateHuntingLayer();
+            }})
+            .catch(err => {{
+                console.warn('Hunting values unavailable:', err);
+                setOverlayAvailable('hunting', false);
+            }});
+
+        // NPC overlay
+        let npcData = null;
+
+        fetch('npcs.json')
+            .then(response => {{
+                if (!response.ok) {{
+                    throw new Error('NPC data not found');
+                }}
+                return response.json();
+            }})
+            .then(data => {{
+                npcData = data;
+                updateNpcLayer();
+            }})
+            .catch(err => {{
+                console.warn('NPC data unavailable:', err);
+                setOverlayAvailable('npcs', false);
+            }});
+
+        // Markers on screen per overlay, keyed by their data entry. Panning
+        // only adds and removes the markers whose visibility changed, and a
+        // data refresh or floor change replaces them because the entries do.
+        const shownMarkers = new Map();
+
+        function syncMarkers(layer, items, createMarker) {{
+            if (!shownMarkers.has(layer)) {{
+                shownMarkers.set(layer, new Map());
+            }}
+            const shown = shownMarkers.get(layer);
+            const wanted = new Set(items);
+
+            shown.forEach((marker, item) => {{
+                if (!wanted.has(item)) {{
+                    layer.removeLayer(marker);
+                    shown.delete(item);
+                }}
+            }});
+            items.forEach(item => {{
+                if (!shown.has(item)) {{
+                    const marker = createMarker(item);
+                    marker.addTo(layer);
+                    shown.set(item, marker);
+                }}
+            }});
+        }}
+
+        // Markers just off screen are kept so short pans add nothing new
+        function markerBounds() {{
+            return map.getBounds().pad(0.25);
+        }}
+
+        // Circles and plain rectangles are drawn on one canvas instead of as
+        // an SVG element each
+        const overlayCanvas = L.canvas({{ padding: 0.5 }});
+
+        function updateSpawnLayer() {{
+            const showSpawns = map.hasLayer(spawnLayer);
+            const currentZoom = map.getZoom();
+
+            if (!showSpawns || !spawnData || currentZoom < spawnStyle.min_zoom) {{
+                syncMarkers(spawnLayer, [], null);
+                return;
+            }}
+
+            const floorSpawns = spawnData.spawns_by_floor[currentFloor] || [];
+            const bounds = markerBounds();
+
+            const visibleSpawns = floorSpawns.filter(spawn => {{
+                const [lat, lng] = worldToLatLng(spawn.x, spawn.y);
+                return spawnMatches(spawn) && bounds.contains([lat, lng]);
+            }});
+
+            syncMarkers(spawnLayer, visibleSpawns, spawn => {{
+                const [lat, lng] = worldToLatLng(spawn.x, spawn.y);
+
+                const icon = L.divIcon({{
                     className: 'spawn-marker',
                     html: `
                         <img src="monsters/${{spawn.race}}.png" alt="Race ${{spawn.race}}" onerror="this.style.display='none'" />
-                        <div class="spawn-amount">${{spawn.amount}}</div>
+                        <div class="spawn-amount" style="color: ${{spawnStyle.color}}">${{spawn.amount}}</div>
                     `,
-                    iconSize: [32, 32],
-                    iconAnchor: [16, 16],
-                    popupAnchor: [0, -16]
+                    iconSize: [spawnStyle.size, spawnStyle.size],
+                    iconAnchor: [spawnStyle.size / 2, spawnStyle.size / 2],
+                    popupAnchor: [0, -spawnStyle.size / 2]
+                }});
+
+                return L.marker([lat, lng], {{ icon: icon }})
+                    .bindPopup(`
+                        <b>${{spawn.name ? toTitleCase(spawn.name) : 'Race ID: ' + spawn.race}}</b><br/>
+                        Spawn amount: ${{spawn.amount}}<br/>
+                        ${{monsterStatsHtml((spawnData.monsters || {{}})[spawn.race])}}
+                        ${{spawn.loot_value != null ? 'Loot value: ~' + spawn.loot_value.toLocaleString() + ' gp/kill<br/>' : ''}}
+                        Position: ${{spawn.x}}, ${{spawn.y}}
+                    `);
+            }});
+        }}
+
+        // Hit points, experience, summon and convince costs and the likeliest
+        // drops from the race's .mon file, as popup lines
+        function monsterStatsHtml(monster) {{
+            if (!monster) {{
+                return '';
+            }}
+
+            const lines = [];
+            const numbers = [
+                monster.hit_points != null ? monster.hit_points.toLocaleString() + ' HP' : null,
+                monster.experience != null ? monster.experience.toLocaleString() + ' exp' : null
+            ].filter(Boolean);
+            if (numbers.length > 0) {{
+                lines.push(numbers.join(', '));
+            }}
+
+            const creature = [
+                monster.summonable ? `Summon: ${{monster.summon_cost}} mana` : null,
+                monster.convinceable ? `Convince: ${{monster.convince_cost}} mana` : null
+            ].filter(Boolean);
+            if (creature.length > 0) {{
+                lines.push(creature.join(', '));
+            }}
+
+            if (monster.loot && monster.loot.length > 0) {{
+                const drops = monster.loot.map(drop => {{
+                    const name = escapeHtml(drop.name || 'item ' + drop.item_id);
+                    const count = drop.max_count > 1 ? ` (1-${{drop.max_count}})` : '';
+                    return `${{name}}${{count}} ${{(drop.chance / 10).toFixed(1)}}%`;
+                }});
+                lines.push('Loot: ' + drops.join(', '));
+            }}
+
+            return lines.map(line => line + '<br/>').join('');
+        }}
+
+        // The roaming area of each spawn: a radius-r spawn covers the tiles
+        // within r of its center, so the circle gets r + 0.5 tiles
+        function updateSpawnAreaLayer() {{
+            if (!map.hasLayer(spawnAreaLayer) || !spawnData) {{
+                syncMarkers(spawnAreaLayer, [], null);
+                return;
+            }}
+
+            const floorSpawns = spawnData.spawns_by_floor[currentFloor] || [];
+            const bounds = markerBounds();
+
+            const visibleAreas = floorSpawns.filter(spawn => {{
+                const [lat, lng] = worldToLatLng(spawn.x + 0.5, spawn.y + 0.5);
+                return spawnMatches(spawn) && bounds.intersects(L.latLngBounds(
+                    [lat - spawn.radius - 0.5, lng - spawn.radius - 0.5],
+                    [lat + spawn.radius + 0.5, lng + spawn.radius + 0.5]
+                ));
+            }});
+
+            syncMarkers(spawnAreaLayer, visibleAreas, spawn => {{
+                const [lat, lng] = worldToLatLng(spawn.x + 0.5, spawn.y + 0.5);
+                return L.circle([lat, lng], {{
+                    renderer: overlayCanvas,
+                    radius: spawn.radius + 0.5,
+                    color: '#FF5533',
+                    weight: 1,
+                    opacity: 0.6,
+                    fillOpacity: 0.15,
+                    interactive: false
+                }});
+            }});
+        }}
+
+        // Spawn search: the text filters both spawn overlays by monster name
+        // (or race id), and "Nearest spawn" jumps to the closest match
+        let spawnFilter = '';
+
+        function spawnMatches(spawn) {{
+            if (!spawnFilter) return true;
+            const name = spawn.name ? spawn.name.toLowerCase() : '';
+            return name.includes(spawnFilter) || String(spawn.race) === spawnFilter;
+        }}
+
+        function updateSpawnSearch() {{
+            const names = new Set();
+            Object.values(spawnData.spawns_by_floor).forEach(floorSpawns =>
+                floorSpawns.forEach(spawn => {{
+                    if (spawn.name) names.add(toTitleCase(spawn.name));
+                }})
+            );
+
+            const datalist = document.getElementById('spawn-names');
+            datalist.replaceChildren(...Array.from(names).sort().map(name => {{
+                const option = document.createElement('option');
+                option.value = name;
+                return option;
+            }}));
+            document.getElementById('spawn-search').classList.add('available');
+        }}
+
+        // Prefers the current floor, then the closest floor, then distance to
+        // the map center
+        function jumpToNearestSpawn() {{
+            if (!spawnData) return;
+
+            const {{ x: centerX, y: centerY }} = latLngToWorld(map.getCenter());
+
+            let nearest = null;
+            let nearestKey = null;
+            Object.entries(spawnData.spawns_by_floor).forEach(([floorKey, floorSpawns]) => {{
+                const floor = parseInt(floorKey);
+                if (!floors.includes(floor)) return;
+                floorSpawns.filter(spawnMatches).forEach(spawn => {{
+                    const key = [Math.abs(floor - currentFloor), Math.hypot(spawn.x - centerX, spawn.y - centerY)];
+                    if (!nearestKey || key[0] < nearestKey[0] || (key[0] === nearestKey[0] && key[1] < nearestKey[1])) {{
+                        nearest = {{ spawn, floor }};
+                        nearestKey = key;
+                    }}
+                }});
+            }});
+
+            if (!nearest) {{
+                showToast(spawnFilter ? `No spawns match "${{spawnFilter}}"` : 'No spawns on this map');
+                return;
+            }}
+
+            if (nearest.floor !== currentFloor) loadFloor(nearest.floor);
+            if (!map.hasLayer(spawnLayer)) map.addLayer(spawnLayer);
+            map.setView(worldToLatLng(nearest.spawn.x + 0.5, nearest.spawn.y + 0.5), Math.max(map.getZoom(), Math.min(spawnStyle.min_zoom, maxZoom)));
+            updateHash();
+
+            const name = nearest.spawn.name ? toTitleCase(nearest.spawn.name) : 'Race ' + nearest.spawn.race;
+            showToast(`${{name}} at ${{nearest.spawn.x}}, ${{nearest.spawn.y}}, ${{nearest.floor}}`);
+        }}
+
+        const spawnSearchInput = document.getElementById('spawn-search-input');
+        spawnSearchInput.addEventListener('input', function() {{
+            spawnFilter = spawnSearchInput.value.trim().toLowerCase();
+            if (spawnFilter && !map.hasLayer(spawnLayer)) map.addLayer(spawnLayer);
+            updateSpawnLayer();
+            updateSpawnAreaLayer();
+        }});
+        spawnSearchInput.addEventListener('keydown', function(e) {{
+            if (e.key === 'Enter') jumpToNearestSpawn();
+        }});
+        document.getElementById('spawn-nearest').addEventListener('click', jumpToNearestSpawn);
+
+        // Reward items as a list, named after objects.srv where known
+        function questRewardList(rewards) {{
+            if (!rewards || rewards.length === 0) {{
+                return '';
+            }}
+            const items = rewards.map(reward => {{
+                const name = reward.name || `Item ${{reward.id}}`;
+                return `<li>${{reward.amount > 1 ? reward.amount + 'x ' : ''}}${{name}}</li>`;
+            }});
+            return `<br/>Rewards:<ul class="quest-rewards">${{items.join('')}}</ul>`;
+        }}
+
+        function updateQuestChestLayer() {{
+            const showQuestChests = map.hasLayer(questChestLayer);
+            const currentZoom = map.getZoom();
+
+            if (!showQuestChests || !questChestData || currentZoom < questStyle.min_zoom) {{
+                syncMarkers(questChestLayer, [], null);
+                return;
+            }}
+
+            const floorChests = questChestData.questchests_by_floor[currentFloor] || [];
+            const bounds = markerBounds();
+
+            const visibleChests = floorChests.filter(chest => {{
+                const [lat, lng] = worldToLatLng(chest.x, chest.y);
+                return bounds.contains([lat, lng]);
+            }});
+
+            syncMarkers(questChestLayer, visibleChests, chest => {{
+                // Center the marker on the tile by adding 0.5 offset
+                const [lat, lng] = worldToLatLng(chest.x + 0.5, chest.y + 0.5);
+
+                return L.circleMarker([lat, lng], {{
+                    renderer: overlayCanvas,
+                    radius: questStyle.size,
+                    fillColor: questStyle.color,
+                    color: questStyle.color,
+                    weight: 3,
+                    opacity: 0.9,
+                    fillOpacity: 0.7
+                }})
+                .bindPopup(`
+                    <b>${{chest.quest_name ? chest.quest_name : 'Unknown quest'}}</b><br/>
+                    Quest number: ${{chest.quest_number}}
+                    ${{chest.reward_value != null ? '<br/>Reward value: ' + chest.reward_value.toLocaleString() + ' gp' : ''}}
+                    ${{questRewardList(chest.rewards)}}
+                `);
+            }});
+        }}
+
+        function updateWaterLayer() {{
+            waterLayer.clearLayers();
+
+            const showWater = map.hasLayer(waterLayer);
+
+            if (!showWater || !waterData) {{
+                return;
+            }}
+
+            const floorRegions = waterData.water_by_floor[currentFloor] || [];
+            const bounds = map.getBounds();
+
+            floorRegions.forEach((region, index) => {{
+                const regionBounds = L.latLngBounds(
+                    worldToLatLng(region.min_x, region.min_y),
+                    worldToLatLng(region.max_x + 1, region.max_y + 1)
+                );
+                if (!bounds.intersects(regionBounds)) {{
+                    return;
+                }}
+
+                const width = region.max_x - region.min_x + 1;
+                const height = region.max_y - region.min_y + 1;
+                const popup = `
+                    <b>Water region #${{index + 1}}</b><br/>
+                    Area: ${{region.area}} tiles<br/>
+                    Extent: ${{width}} x ${{height}}<br/>
+                    From ${{region.min_x}}, ${{region.min_y}} to ${{region.max_x}}, ${{region.max_y}}
+                `;
+
+                region.runs.forEach(([y, xStart, xEnd]) => {{
+                    const runBounds = L.latLngBounds(worldToLatLng(xStart, y), worldToLatLng(xEnd + 1, y + 1));
+                    if (!bounds.intersects(runBounds)) {{
+                        return;
+                    }}
+
+                    const rect = L.rectangle(
+                        runBounds,
+                        {{
+                            renderer: overlayCanvas,
+                            color: waterColor,
+                            weight: 0,
+                            fillColor: waterColor,
+                            fillOpacity: 0.35
+                        }}
+                    ).bindPopup(popup);
+
+                    rect.addTo(waterLayer);
+                }});
+            }});
+        }}
+
+        function updateZoneLayer() {{
+            zoneLayer.clearLayers();
+
+            const showZones = map.hasLayer(zoneLayer);
+
+            if (!showZones || !zoneData) {{
+                return;
+            }}
+
+            const floorZones = zoneData.zones_by_floor[currentFloor] || {{}};
+            const bounds = map.getBounds();
+
+            Object.values(floorZones).forEach(zone => {{
+                const known = zoneColors[zone.kind] !== undefined;
+                const color = known ? zoneColors[zone.kind] : '#BA55D3';
+                const pattern = known ? `hatch-${{zone.kind}}` : 'hatch-other';
+
+                zone.runs.forEach(([y, xStart, xEnd]) => {{
+                    const runBounds = L.latLngBounds(worldToLatLng(xStart, y), worldToLatLng(xEnd + 1, y + 1));
+                    if (!bounds.intersects(runBounds)) {{
+                        return;
+                    }}
+
+                    const rect = L.rectangle(runBounds, {{
+                        stroke: false,
+                        fillColor: `url(#${{pattern}})`,
+                        fillOpacity: 0.6
+                    }}).bindPopup(`<b style="color: ${{color}}">${{zone.kind}}</b><br/>${{zone.tile_count}} tiles on this floor`);
+
+                    rect.addTo(zoneLayer);
+                }});
+            }});
+        }}
+
+        function updateHouseLayer() {{
+            houseLayer.clearLayers();
+
+            if (!map.hasLayer(houseLayer) || !houseData) {{
+                return;
+            }}
+
+            const floorHouses = houseData.houses_by_floor[currentFloor] || [];
+            const bounds = map.getBounds();
+
+            floorHouses.forEach(house => {{
+                const color = houseStatusColor(house.id) || '#DEB887';
+                const title = house.name ? `${{escapeHtml(house.name)}} (#${{house.id}})` : `House #${{house.id}}`;
+                const details = houseStatusDetails(house.id);
+                const popup = `<b style="color: ${{color}}">${{title}}</b><br/>${{house.tile_count}} tiles on this floor`
+                    + (details ? `<br/>${{details}}` : '');
+
+                house.runs.forEach(([y, xStart, xEnd]) => {{
+                    const runBounds = L.latLngBounds(worldToLatLng(xStart, y), worldToLatLng(xEnd + 1, y + 1));
+                    if (!bounds.intersects(runBounds)) {{
+                        return;
+                    }}
+
+                    L.rectangle(runBounds, {{
+                        stroke: false,
+                        fillColor: color,
+                        fillOpacity: 0.35
+                    }}).bindPopup(popup).addTo(houseLayer);
+                }});
+            }});
+        }}
+
+        function updateHeatmapLayer() {{
+            heatmapLayer.clearLayers();
+
+            const showHeatmap = map.hasLayer(heatmapLayer);
+
+            if (!showHeatmap || !heatmapData || !heatmapData.heatmap_floors.includes(currentFloor)) {{
+                return;
+            }}
+
+            floorTileLayer('heatmap/', currentFloor, 'png', {{
+                minZoom: minZoom,
+                maxZoom: maxZoom,
+                noWrap: true,
+                errorTileUrl: transparentTile,
+                bounds: mapBounds
+            }}).addTo(heatmapLayer);
+        }}
+
+        function updateDiffLayer() {{
+            diffLayer.clearLayers();
+
+            if (!map.hasLayer(diffLayer) || !diffData) {{
+                return;
+            }}
+
+            const floorChanges = diffData.diff_by_floor[currentFloor] || [];
+            const bounds = map.getBounds();
+
+            floorChanges.forEach(change => {{
+                const color = diffColors[change.kind] || '#FFFFFF';
+
+                change.runs.forEach(([y, xStart, xEnd]) => {{
+                    const runBounds = L.latLngBounds(worldToLatLng(xStart, y), worldToLatLng(xEnd + 1, y + 1));
+                    if (!bounds.intersects(runBounds)) {{
+                        return;
+                    }}
+
+                    L.rectangle(runBounds, {{
+                        stroke: false,
+                        fillColor: color,
+                        fillOpacity: 0.5
+                    }}).bindPopup(`<b style="color: ${{color}}">Tiles ${{change.kind}}</b><br/>${{change.tile_count}} tiles on this floor`).addTo(diffLayer);
+                }});
+            }});
+        }}
+
+        function updateCrosshair() {{
+            document.getElementById('crosshair').classList.toggle('visible', map.hasLayer(crosshairLayer));
+        }}
+
+        function updatePlayerLayer() {{
+            playerLayer.clearLayers();
+
+            const showPlayers = map.hasLayer(playerLayer);
+
+            if (!showPlayers) {{
+                return;
+            }}
+
+            const bounds = map.getBounds();
+
+            playerData
+                .filter(player => player.z === currentFloor)
+                .forEach(player => {{
+                    const [lat, lng] = worldToLatLng(player.x + 0.5, player.y + 0.5);
+                    if (!bounds.contains([lat, lng])) {{
+                        return;
+                    }}
+
+                    // Names and vocations come from whoever POSTs to /players, so never trust them as HTML
+                    const name = escapeHtml(player.name);
+                    const details = [player.level ? 'Level ' + player.level : null, player.vocation && escapeHtml(player.vocation)]
+                        .filter(Boolean)
+                        .join(', ');
+                    const marker = L.circleMarker([lat, lng], {{
+                        radius: 6,
+                        fillColor: '#00BFFF',
+                        color: '#FFFFFF',
+                        weight: 2,
+                        opacity: 1,
+                        fillOpacity: 0.9
+                    }}).bindTooltip(details ? `${{name}} (${{details}})` : name);
+
+                    marker.addTo(playerLayer);
+                }});
+        }}
+
+        function updateNpcLayer() {{
+            const showNpcs = map.hasLayer(npcLayer);
+            const currentZoom = map.getZoom();
+
+            if (!showNpcs || !npcData || currentZoom < npcStyle.min_zoom) {{
+                syncMarkers(npcLayer, [], null);
+                return;
+            }}
+
+            const floorNpcs = npcData.npcs_by_floor[currentFloor] || [];
+            const bounds = markerBounds();
+
+            const visibleNpcs = floorNpcs.filter(npc => {{
+                const [lat, lng] = worldToLatLng(npc.x, npc.y);
+                return bounds.contains([lat, lng]);
+            }});
+
+            syncMarkers(npcLayer, visibleNpcs, npc => {{
+                const [lat, lng] = worldToLatLng(npc.x, npc.y);
+
+                const outline = npcStyle.color ? `style="filter: drop-shadow(0 0 1px ${{npcStyle.color}}) drop-shadow(0 0 1px ${{npcStyle.color}})"` : '';
+                const icon = L.divIcon({{
+                    className: 'npc-marker',
+                    html: `<img src="npcs/${{npc.file_name}}.png" alt="${{npc.npc_name}}" ${{outline}} onerror="this.parentNode.classList.add('no-sprite')" />`,
+                    iconSize: [npcStyle.size, npcStyle.size],
+                    iconAnchor: [npcStyle.size / 2, npcStyle.size / 2],
+                    popupAnchor: [0, -npcStyle.size / 2]
+                }});
+
+                return L.marker([lat, lng], {{ icon: icon }})
+                    .bindPopup(`<b>${{npc.npc_name}}</b><br/>Position: ${{npc.x}}, ${{npc.y}}`);
+            }});
+        }}
+
+        const transitionNames = {{
+            ladder: 'Ladder',
+            stairs: 'Stairs',
+            rope_spot: 'Rope spot',
+            hole: 'Hole'
+        }};
+
+        function updateTransitionLayer() {{
+            const showTransitions = map.hasLayer(transitionLayer);
+            const currentZoom = map.getZoom();
+
+            if (!showTransitions || !transitionData || currentZoom < transitionStyle.min_zoom) {{
+                syncMarkers(transitionLayer, [], null);
+                return;
+            }}
+
+            const floorTransitions = transitionData.transitions_by_floor[currentFloor] || [];
+            const bounds = markerBounds();
+
+            const visibleTransitions = floorTransitions.filter(transition => {{
+                const [lat, lng] = worldToLatLng(transition.x, transition.y);
+                return bounds.contains([lat, lng]);
+            }});
+
+            syncMarkers(transitionLayer, visibleTransitions, transition => {{
+                const [lat, lng] = worldToLatLng(transition.x + 0.5, transition.y + 0.5);
+                const up = transition.to_floor < transition.floor;
+                const icon = L.divIcon({{
+                    className: 'transition-marker',
+                    html: `<span style="color: ${{transitionStyle.color}}; font-size: ${{transitionStyle.size}}px">${{up ? '▲' : '▼'}}</span>`,
+                    iconSize: [transitionStyle.size, transitionStyle.size],
+                    iconAnchor: [transitionStyle.size / 2, transitionStyle.size / 2]
+                }});
+                const title = `${{transitionNames[transition.kind] || transition.kind}} ${{up ? 'up' : 'down'}} to floor ${{transition.to_floor}}`;
+
+                return L.marker([lat, lng], {{ icon: icon, title: title }})
+                    .on('click', () => followTransition(transition));
+            }});
+        }}
+
+        // Keeps the zoom and centers the destination floor on the same position
+        function followTransition(transition) {{
+            const built = Array.from(floorButtons).some(button => parseInt(button.dataset.floor) === transition.to_floor);
+            if (!built) {{
+                showToast(`Floor ${{transition.to_floor}} is not part of this map`);
+                return;
+            }}
+
+            loadFloor(transition.to_floor);
+            map.setView(worldToLatLng(transition.x + 0.5, transition.y + 0.5), map.getZoom());
+            updateHash();
+        }}
+
+        function updateTeleportLayer() {{
+            const showTeleports = map.hasLayer(teleportLayer);
+            const currentZoom = map.getZoom();
+
+            if (!showTeleports || !teleportData || currentZoom < teleportStyle.min_zoom) {{
+                syncMarkers(teleportLayer, [], null);
+                return;
+            }}
+
+            const floorTeleports = teleportData.teleports_by_floor[currentFloor] || [];
+            const bounds = markerBounds();
+
+            const visibleTeleports = floorTeleports.filter(teleport => {{
+                const [lat, lng] = worldToLatLng(teleport.x, teleport.y);
+                return bounds.contains([lat, lng]);
+            }});
+
+            syncMarkers(teleportLayer, visibleTeleports, teleport => {{
+                const [lat, lng] = worldToLatLng(teleport.x + 0.5, teleport.y + 0.5);
+                const icon = L.divIcon({{
+                    className: 'teleport-marker',
+                    html: `<span style="color: ${{teleportStyle.color}}; font-size: ${{teleportStyle.size}}px">◎</span>`,
+                    iconSize: [teleportStyle.size, teleportStyle.size],
+                    iconAnchor: [teleportStyle.size / 2, teleportStyle.size / 2]
                 }});
+                const floorNote = teleport.to_floor !== teleport.floor ? `, floor ${{teleport.to_floor}}` : '';
+                const title = `${{teleport.object_name || 'Teleport'}} to ${{teleport.to_x}}, ${{teleport.to_y}}${{floorNote}}`;
+
+                return L.marker([lat, lng], {{ icon: icon, title: title }})
+                    .on('click', () => followTeleport(teleport));
+            }});
+        }}
+
+        // Centers the destination, switching floors when the teleport leads to another one
+        function followTeleport(teleport) {{
+            const built = Array.from(floorButtons).some(button => parseInt(button.dataset.floor) === teleport.to_floor);
+            if (!built) {{
+                showToast(`Floor ${{teleport.to_floor}} is not part of this map`);
+                return;
+            }}
+
+            if (teleport.to_floor !== currentFloor) {{
+                loadFloor(teleport.to_floor);
+            }}
+            map.setView(worldToLatLng(teleport.to_x + 0.5, teleport.to_y + 0.5), map.getZoom());
+            updateHash();
+        }}
+
+        // Sign texts are written by map makers and players, so never trust them as HTML
+        function escapeHtml(text) {{
+            return text.replace(/[&<>"']/g, c => ({{ '&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;', "'": '&#39;' }})[c]);
+        }}
 
-                const marker = L.marker([lat, lng], {{ icon: icon }})
-                    .bindPopup(`
-                        <b>${{spawn.name ? toTitleCase(spawn.name) : 'Race ID: ' + spawn.race}}</b><br/>
-                        Spawn amount: ${{spawn.amount}}<br/>
-                        Position: ${{spawn.x}}, ${{spawn.y}}
-                    `);
+        function updateSignLayer() {{
+            const showSigns = map.hasLayer(signLayer);
+            const currentZoom = map.getZoom();
+
+            if (!showSigns || !signData || currentZoom < signStyle.min_zoom) {{
+                syncMarkers(signLayer, [], null);
+                return;
+            }}
+
+            const floorSigns = signData.signs_by_floor[currentFloor] || [];
+            const bounds = markerBounds();
+
+            const visibleSigns = floorSigns.filter(sign => {{
+                const [lat, lng] = worldToLatLng(sign.x, sign.y);
+                return bounds.contains([lat, lng]);
+            }});
+
+            syncMarkers(signLayer, visibleSigns, sign => {{
+                const [lat, lng] = worldToLatLng(sign.x + 0.5, sign.y + 0.5);
+                const icon = L.divIcon({{
+                    className: 'sign-marker',
+                    html: `<span style="color: ${{signStyle.color}}; font-size: ${{signStyle.size}}px">✎</span>`,
+                    iconSize: [signStyle.size, signStyle.size],
+                    iconAnchor: [signStyle.size / 2, signStyle.size / 2]
+                }});
+                const heading = sign.object_name ? `<b>${{escapeHtml(sign.object_name)}}</b><br/>` : '';
 
-                marker.addTo(map);
-                spawnMarkers.push(marker);
+                return L.marker([lat, lng], {{ icon: icon }})
+                    .bindTooltip(heading + escapeHtml(sign.text), {{ className: 'sign-tooltip', direction: 'top' }});
             }});
         }}
 
-        function updateQuestChestLayer() {{
-            questChestMarkers.forEach(marker => map.removeLayer(marker));
-            questChestMarkers = [];
+        const poiKinds = {{
+            depot: {{ name: 'Depot', symbol: '▣', color: '#D2B48C', unit: 'lockers' }},
+            temple: {{ name: 'Temple', symbol: '✚', color: '#FFFFFF', unit: 'altars' }},
+            mailbox: {{ name: 'Mailbox', symbol: '✉', color: '#87CEFA', unit: 'mailboxes' }},
+            bank: {{ name: 'Bank', symbol: '$', color: '#FFD700', unit: 'counters' }}
+        }};
 
-            const toggle = document.getElementById('questchest-toggle');
-            const showQuestChests = toggle && toggle.checked;
+        function updatePoiLayer() {{
+            const showPoi = map.hasLayer(poiLayer);
             const currentZoom = map.getZoom();
 
-            if (!showQuestChests || !questChestData || currentZoom < 3) {{
+            if (!showPoi || !poiData || currentZoom < poiStyle.min_zoom) {{
+                syncMarkers(poiLayer, [], null);
                 return;
             }}
 
-            const floorChests = questChestData.questchests_by_floor[currentFloor] || [];
-            const bounds = map.getBounds();
+            const floorPoi = poiData.poi_by_floor[currentFloor] || [];
+            const bounds = markerBounds();
 
-            const visibleChests = floorChests.filter(chest => {{
-                const [lat, lng] = worldToLatLng(chest.x, chest.y);
+            const visiblePoi = floorPoi.filter(poi => {{
+                const [lat, lng] = worldToLatLng(poi.x, poi.y);
                 return bounds.contains([lat, lng]);
             }});
 
-            visibleChests.forEach(chest => {{
-                // Center the marker on the tile by adding 0.5 offset
-                const [lat, lng] = worldToLatLng(chest.x + 0.5, chest.y + 0.5);
-
-                const marker = L.circleMarker([lat, lng], {{
-                    radius: 10,
-                    fillColor: '#FFD700',
-                    color: '#FFD700',
-                    weight: 3,
-                    opacity: 0.9,
-                    fillOpacity: 0.7
-                }})
-                .bindPopup(`
-                    <b>${{chest.quest_name ? chest.quest_name : 'Unknown quest'}}</b><br/>
-                    Quest number: ${{chest.quest_number}}
-                `);
+            syncMarkers(poiLayer, visiblePoi, poi => {{
+                const [lat, lng] = worldToLatLng(poi.x + 0.5, poi.y + 0.5);
+                const kind = poiKinds[poi.kind] || {{ name: poi.kind, symbol: '●', color: '#FFFFFF', unit: 'tiles' }};
+                const color = poiStyle.color || kind.color;
+                const icon = L.divIcon({{
+                    className: 'poi-marker',
+                    html: `<span style="color: ${{color}}; font-size: ${{poiStyle.size}}px">${{kind.symbol}}</span>`,
+                    iconSize: [poiStyle.size, poiStyle.size],
+                    iconAnchor: [poiStyle.size / 2, poiStyle.size / 2]
+                }});
+                const title = poi.tiles > 1 ? `${{kind.name}} (${{poi.tiles}} ${{kind.unit}})` : kind.name;
 
-                marker.addTo(map);
-                questChestMarkers.push(marker);
+                return L.marker([lat, lng], {{ icon: icon, title: title }});
             }});
         }}
 
-        function updateNpcLayer() {{
-            npcMarkers.forEach(marker => map.removeLayer(marker));
-            npcMarkers = [];
+        function formatInterval(seconds) {{
+            if (seconds >= 86400) return `${{+(seconds / 86400).toFixed(1)}} days`;
+            if (seconds >= 3600) return `${{+(seconds / 3600).toFixed(1)}} hours`;
+            return `${{Math.round(seconds / 60)}} minutes`;
+        }}
 
-            const toggle = document.getElementById('npc-toggle');
-            const showNpcs = toggle && toggle.checked;
+        // Each raid spawn is its area with a marker at the center; bosses
+        // get a skull and their followers crossed swords
+        function updateRaidLayer() {{
+            const showRaids = map.hasLayer(raidLayer);
             const currentZoom = map.getZoom();
 
-            if (!showNpcs || !npcData || currentZoom < 3) {{
+            if (!showRaids || !raidData || currentZoom < raidStyle.min_zoom) {{
+                syncMarkers(raidLayer, [], null);
                 return;
             }}
 
-            const floorNpcs = npcData.npcs_by_floor[currentFloor] || [];
-            const bounds = map.getBounds();
+            const floorRaids = raidData.raids_by_floor[currentFloor] || [];
+            const bounds = markerBounds();
 
-            const visibleNpcs = floorNpcs.filter(npc => {{
-                const [lat, lng] = worldToLatLng(npc.x, npc.y);
+            const visibleRaids = floorRaids.filter(spawn => {{
+                const [lat, lng] = worldToLatLng(spawn.x, spawn.y);
                 return bounds.contains([lat, lng]);
             }});
 
-            visibleNpcs.forEach(npc => {{
-                const [lat, lng] = worldToLatLng(npc.x, npc.y);
-
+            syncMarkers(raidLayer, visibleRaids, spawn => {{
+                const [lat, lng] = worldToLatLng(spawn.x + 0.5, spawn.y + 0.5);
+                const size = spawn.boss ? Math.round(raidStyle.size * 1.4) : raidStyle.size;
                 const icon = L.divIcon({{
-                    className: 'npc-marker',
-                    html: `<img src="npcs/${{npc.file_name}}.png" alt="${{npc.npc_name}}" onerror="this.style.display='none'" />`,
-                    iconSize: [32, 32],
-                    iconAnchor: [16, 16],
-                    popupAnchor: [0, -16]
+                    className: 'raid-marker',
+                    html: `<span style="color: ${{raidStyle.color}}; font-size: ${{size}}px">${{spawn.boss ? '☠' : '⚔'}}</span>`,
+                    iconSize: [size, size],
+                    iconAnchor: [size / 2, size / 2]
                 }});
+                const monster = spawn.name ? toTitleCase(spawn.name) : 'Race ' + spawn.race;
+                const popup = `
+                    <b>${{escapeHtml(spawn.raid)}}</b><br/>
+                    ${{spawn.boss ? 'Boss: ' : ''}}${{spawn.amount}} x ${{escapeHtml(monster)}}<br/>
+                    ${{spawn.interval ? 'Every ' + formatInterval(spawn.interval) + '<br/>' : ''}}
+                    ${{spawn.message ? '<i>' + escapeHtml(spawn.message) + '</i><br/>' : ''}}
+                    Position: ${{spawn.x}}, ${{spawn.y}}
+                `;
+
+                return L.layerGroup([
+                    L.circle([lat, lng], {{
+                        renderer: overlayCanvas,
+                        radius: spawn.radius + 0.5,
+                        color: raidStyle.color,
+                        weight: 1,
+                        opacity: 0.7,
+                        fillOpacity: 0.1,
+                        dashArray: '4 4',
+                        interactive: false
+                    }}),
+                    L.marker([lat, lng], {{ icon: icon, title: `${{spawn.raid}}: ${{monster}}` }}).bindPopup(popup)
+                ]);
+            }});
+        }}
 
-                const marker = L.marker([lat, lng], {{ icon: icon }})
-                    .bindPopup(`<b>${{npc.npc_name}}</b><br/>Position: ${{npc.x}}, ${{npc.y}}`);
+        // Sectors colored from green to red by the experience per hour their
+        // spawns give. The square root spreads out the many modest sectors
+        // that a few dragon lairs would otherwise push into the greens.
+        function updateHuntingLayer() {{
+            if (!map.hasLayer(huntingLayer) || !huntingData) {{
+                syncMarkers(huntingLayer, [], null);
+                return;
+            }}
+
+            const floorSectors = huntingData.hunting_by_floor[currentFloor] || [];
+            const bounds = markerBounds();
+
+            const visibleSectors = floorSectors.filter(sector => {{
+                const [lat, lng] = worldToLatLng(sector.sector_x * 32, sector.sector_y * 32);
+                return bounds.intersects(L.latLngBounds([lat, lng], [lat + 32, lng + 32]));
+            }});
 
-                marker.addTo(map);
-                npcMarkers.push(marker);
+            syncMarkers(huntingLayer, visibleSectors, sector => {{
+                const [lat, lng] = worldToLatLng(sector.sector_x * 32, sector.sector_y * 32);
+                const value = Math.sqrt(sector.exp_per_hour / huntingData.max_exp_per_hour);
+                const color = `hsl(${{Math.round((1 - value) * 120)}}, 90%, 50%)`;
+                const monster = sector.top_monster ? toTitleCase(sector.top_monster) : 'race ' + sector.top_race;
+
+                return L.rectangle([[lat, lng], [lat + 32, lng + 32]], {{
+                    renderer: overlayCanvas,
+                    color: color,
+                    weight: 0,
+                    fillOpacity: 0.35
+                }}).bindTooltip(
+                    `~${{sector.exp_per_hour.toLocaleString()}} exp/h<br/>` +
+                    `${{sector.monsters_per_hour.toLocaleString()}} monsters/h, mostly ${{escapeHtml(monster)}}`
+                );
             }});
         }}
 
         function updateSectorGridLayer() {{
-            sectorGridLines.forEach(line => map.removeLayer(line));
-            sectorGridLines = [];
+            sectorGridLayer.clearLayers();
 
-            const toggle = document.getElementById('sector-grid-toggle');
-            const showGrid = toggle && toggle.checked;
+            const showGrid = map.hasLayer(sectorGridLayer);
             const currentZoom = map.getZoom();
 
-            if (!showGrid || currentZoom < 3) {{
+            if (!showGrid || currentZoom < gridStyle.min_zoom) {{
                 return;
             }}
 
             const bounds = map.getBounds();
-            const minTileX_view = Math.floor(bounds.getSouthWest().lng);
-            const maxTileX_view = Math.ceil(bounds.getNorthEast().lng);
-            const minTileY_view = Math.floor(bounds.getSouthWest().lat);
-            const maxTileY_view = Math.ceil(bounds.getNorthEast().lat);
+            const viewMin = latLngToWorld(bounds.getSouthWest());
+            const viewMax = latLngToWorld(bounds.getNorthEast());
 
-            const minWorldX_view = minTileX + minTileX_view;
-            const maxWorldX_view = minTileX + maxTileX_view;
-            const minWorldY_view = minTileY + minTileY_view;
-            const maxWorldY_view = minTileY + maxTileY_view;
+            const minWorldX_view = viewMin.x;
+            const maxWorldX_view = viewMax.x + 1;
+            const minWorldY_view = viewMin.y;
+            const maxWorldY_view = viewMax.y + 1;
 
             const minSectorX = Math.floor(minWorldX_view / 32);
             const maxSectorX = Math.ceil(maxWorldX_view / 32);
@@ -669,15 +2153,14 @@ pub fn generate_html<P: AsRef<Path>>(
                 const line = L.polyline(
                     [[latStart, lng], [latEnd, lng]],
                     {{
-                        color: '#00FFFF',
-                        weight: 1,
+                        color: gridStyle.color,
+                        weight: gridStyle.size,
                         opacity: 0.3,
                         interactive: false
                     }}
                 );
 
-                line.addTo(map);
-                sectorGridLines.push(line);
+                line.addTo(sectorGridLayer);
             }}
 
             for (let sectorY = minSectorY; sectorY <= maxSectorY; sectorY++) {{
@@ -693,15 +2176,14 @@ pub fn generate_html<P: AsRef<Path>>(
                 const line = L.polyline(
                     [[lat, lngStart], [lat, lngEnd]],
                     {{
-                        color: '#00FFFF',
-                        weight: 1,
+                        color: gridStyle.color,
+                        weight: gridStyle.size,
                         opacity: 0.3,
                         interactive: false
                     }}
                 );
 
-                line.addTo(map);
-                sectorGridLines.push(line);
+                line.addTo(sectorGridLayer);
             }}
         }}
 
@@ -726,100 +2208,696 @@ pub fn generate_html<P: AsRef<Path>>(
             }}
         }}
 
-        const spawnToggle = document.getElementById('spawn-toggle');
-        if (spawnToggle) {{
-            spawnToggle.addEventListener('change', function() {{
-                updateSpawnLayer();
-                updateHash();
+        map.on('click', function(e) {{
+            if (e.originalEvent.ctrlKey || e.originalEvent.metaKey) {{
+                const coords = `${{lastWorldX}},${{lastWorldY}},${{currentFloor}}`;
+                copyToClipboard(coords, coords);
+                L.DomEvent.stopPropagation(e.originalEvent);
+            }}
+        }});
+
+        map.getContainer().addEventListener('mousedown', function(e) {{
+            if (e.button === 1) {{
+                copyToClipboard(lastSectorFile, lastSectorFile);
+                e.preventDefault();
+            }}
+        }});
+
+        // Jump box: X,Y stays on the current floor, X,Y,Z switches to Z.
+        // Commas, spaces or both separate the numbers, as pasted from chat.
+        document.getElementById('goto-form').addEventListener('submit', function(e) {{
+            e.preventDefault();
+            const parts = document.getElementById('goto-input').value.trim().split(/[\s,]+/).map(p => parseInt(p, 10));
+            const [x, y, z = currentFloor] = parts;
+            if (parts.length < 2 || parts.length > 3 || parts.some(isNaN)) {{
+                showToast('Enter a position as X,Y or X,Y,Z');
+                return;
+            }}
+            if (!floors.includes(z)) {{
+                showToast(`Floor ${{z}} is not part of this map`);
+                return;
+            }}
+
+            if (z !== currentFloor) {{
+                loadFloor(z);
+            }}
+            map.setView(worldToLatLng(x + 0.5, y + 0.5), map.getZoom());
+            updateHash();
+            // Hand the keys back to the map shortcuts
+            document.getElementById('goto-input').blur();
+        }});
+
+        document.getElementById('copy-link').addEventListener('click', function() {{
+            updateHash();
+            copyToClipboard(window.location.href, 'link to ' + window.location.hash.split('?')[0]);
+        }});
+
+        // User markers: right-click to add a labelled marker. They are kept in
+        // this browser's localStorage per floor and move between browsers as
+        // exported JSON files.
+        const userMarkerStorageKey = 'demonax-map-markers';
+        let userMarkers = loadUserMarkers();
+        let lastUserMarkerColor = '#FF4500';
+
+        function loadUserMarkers() {{
+            try {{
+                return JSON.parse(localStorage.getItem(userMarkerStorageKey)) || {{}};
+            }} catch (err) {{
+                return {{}};
+            }}
+        }}
+
+        function saveUserMarkers() {{
+            try {{
+                localStorage.setItem(userMarkerStorageKey, JSON.stringify(userMarkers));
+            }} catch (err) {{
+                console.warn('Could not save markers:', err);
+            }}
+        }}
+
+        function updateUserMarkerLayer() {{
+            userMarkerLayer.clearLayers();
+            if (!map.hasLayer(userMarkerLayer)) {{
+                return;
+            }}
+
+            (userMarkers[currentFloor] || []).forEach(marker => {{
+                const [lat, lng] = worldToLatLng(marker.x + 0.5, marker.y + 0.5);
+                const content = L.DomUtil.create('div');
+                const title = L.DomUtil.create('b', '', content);
+                title.textContent = marker.label || 'Marker';
+                const position = L.DomUtil.create('div', '', content);
+                position.textContent = `${{marker.x}},${{marker.y}},${{currentFloor}}`;
+                const remove = L.DomUtil.create('a', 'admin-command', content);
+                remove.textContent = 'Delete';
+                L.DomEvent.on(remove, 'click', function(ev) {{
+                    L.DomEvent.preventDefault(ev);
+                    userMarkers[currentFloor] = userMarkers[currentFloor].filter(m => m !== marker);
+                    if (userMarkers[currentFloor].length === 0) {{
+                        delete userMarkers[currentFloor];
+                    }}
+                    saveUserMarkers();
+                    map.closePopup();
+                    updateUserMarkerLayer();
+                }});
+
+                const circle = L.circleMarker([lat, lng], {{
+                    radius: 7,
+                    fillColor: marker.color || lastUserMarkerColor,
+                    color: '#FFFFFF',
+                    weight: 2,
+                    opacity: 1,
+                    fillOpacity: 0.9
+                }}).bindPopup(content);
+                if (marker.label) {{
+                    circle.bindTooltip(escapeHtml(marker.label), {{ direction: 'top' }});
+                }}
+                circle.addTo(userMarkerLayer);
             }});
         }}
 
-        const questChestToggle = document.getElementById('questchest-toggle');
-        if (questChestToggle) {{
-            questChestToggle.addEventListener('change', function() {{
-                updateQuestChestLayer();
-                updateHash();
+        function showAddMarkerForm(worldX, worldY, latlng) {{
+            const form = L.DomUtil.create('form', 'marker-form');
+            const label = L.DomUtil.create('input', '', form);
+            label.type = 'text';
+            label.placeholder = 'Label';
+            const color = L.DomUtil.create('input', '', form);
+            color.type = 'color';
+            color.value = lastUserMarkerColor;
+            const add = L.DomUtil.create('button', '', form);
+            add.type = 'submit';
+            add.textContent = 'Add';
+            // Keys typed into the form must not pan or zoom the map
+            L.DomEvent.on(form, 'keydown', L.DomEvent.stopPropagation);
+
+            L.DomEvent.on(form, 'submit', function(ev) {{
+                L.DomEvent.preventDefault(ev);
+                lastUserMarkerColor = color.value;
+                (userMarkers[currentFloor] = userMarkers[currentFloor] || []).push({{
+                    x: worldX,
+                    y: worldY,
+                    label: label.value.trim(),
+                    color: color.value
+                }});
+                saveUserMarkers();
+                map.closePopup();
+                // Showing the overlay redraws it, and keeps it shown next visit
+                if (map.hasLayer(userMarkerLayer)) {{
+                    updateUserMarkerLayer();
+                }} else {{
+                    userMarkerLayer.addTo(map);
+                }}
             }});
+
+            L.popup().setLatLng(latlng).setContent(form).openOn(map);
+            label.focus();
         }}
 
-        const npcToggle = document.getElementById('npc-toggle');
-        if (npcToggle) {{
-            npcToggle.addEventListener('change', function() {{
-                updateNpcLayer();
-                updateHash();
+        document.getElementById('markers-export').addEventListener('click', function() {{
+            const blob = new Blob([JSON.stringify({{ markers_by_floor: userMarkers }}, null, 2)], {{ type: 'application/json' }});
+            const link = document.createElement('a');
+            link.href = URL.createObjectURL(blob);
+            link.download = 'demonax-markers.json';
+            link.click();
+            setTimeout(() => URL.revokeObjectURL(link.href), 1000);
+        }});
+
+        const markersFile = document.getElementById('markers-file');
+        document.getElementById('markers-import').addEventListener('click', () => markersFile.click());
+
+        // Imported markers are added to the ones already here; a marker with
+        // the same position and label as an existing one is skipped
+        markersFile.addEventListener('change', async function() {{
+            const file = markersFile.files[0];
+            markersFile.value = '';
+            if (!file) {{
+                return;
+            }}
+
+            let imported;
+            try {{
+                imported = JSON.parse(await file.text()).markers_by_floor;
+            }} catch (err) {{
+                imported = null;
+            }}
+            if (!imported || typeof imported !== 'object') {{
+                showToast(`${{file.name}} is not a markers file`);
+                return;
+            }}
+
+            let added = 0;
+            Object.entries(imported).forEach(([floor, markers]) => {{
+                if (!Array.isArray(markers)) {{
+                    return;
+                }}
+                const existing = userMarkers[floor] = userMarkers[floor] || [];
+                markers
+                    .filter(m => m && Number.isInteger(m.x) && Number.isInteger(m.y))
+                    .forEach(m => {{
+                        const label = typeof m.label === 'string' ? m.label : '';
+                        if (existing.some(e => e.x === m.x && e.y === m.y && e.label === label)) {{
+                            return;
+                        }}
+                        existing.push({{ x: m.x, y: m.y, label, color: typeof m.color === 'string' ? m.color : lastUserMarkerColor }});
+                        added++;
+                    }});
+                if (existing.length === 0) {{
+                    delete userMarkers[floor];
+                }}
             }});
+
+            saveUserMarkers();
+            showToast(`Imported ${{added}} markers`);
+            if (map.hasLayer(userMarkerLayer)) {{
+                updateUserMarkerLayer();
+            }} else {{
+                userMarkerLayer.addTo(map);
+            }}
+        }});
+
+        // Keyboard shortcuts: PageUp/PageDown change floors, arrow keys pan
+        // one sector, +/- zoom and g opens the jump box. Keys typed into a
+        // text box are left alone.
+        const sectorSize = 32;
+
+        function floorStep(direction) {{
+            // Floor numbers grow downwards, so going up means a lower number
+            const candidates = floors.filter(f => direction < 0 ? f < currentFloor : f > currentFloor);
+            return candidates.length === 0 ? null : (direction < 0 ? Math.max(...candidates) : Math.min(...candidates));
         }}
 
-        const crosshairToggle = document.getElementById('crosshair-toggle');
-        const crosshair = document.getElementById('crosshair');
-        if (crosshairToggle && crosshair) {{
-            crosshairToggle.addEventListener('change', function() {{
-                if (this.checked) {{
-                    crosshair.classList.add('visible');
-                }} else {{
-                    crosshair.classList.remove('visible');
+        document.addEventListener('keydown', function(e) {{
+            if (e.altKey || e.ctrlKey || e.metaKey || e.target.closest('input, textarea, select')) {{
+                return;
+            }}
+
+            const pans = {{ ArrowLeft: [-1, 0], ArrowRight: [1, 0], ArrowUp: [0, -1], ArrowDown: [0, 1] }};
+            if (pans[e.key]) {{
+                const [dx, dy] = pans[e.key];
+                const center = map.getCenter();
+                map.panTo([center.lat + dy * sectorSize, center.lng + dx * sectorSize]);
+            }} else if (e.key === 'PageUp' || e.key === 'PageDown') {{
+                const floor = floorStep(e.key === 'PageUp' ? -1 : 1);
+                if (floor === null) {{
+                    return;
                 }}
+                loadFloor(floor);
                 updateHash();
+            }} else if (e.key === '+' || e.key === '=') {{
+                map.zoomIn();
+            }} else if (e.key === '-' || e.key === '_') {{
+                map.zoomOut();
+            }} else if (e.key === 'g' || e.key === 'G') {{
+                const input = document.getElementById('goto-input');
+                input.focus();
+                input.select();
+            }} else if (e.key === 'Escape') {{
+                map.closePopup();
+                return;
+            }} else {{
+                return;
+            }}
+            e.preventDefault();
+        }});
+
+        // Admin teleport command (--admin)
+        const adminCommand = {admin_command_json};
+
+        map.on('contextmenu', function(e) {{
+            const {{ x: worldX, y: worldY }} = latLngToWorld(e.latlng);
+            const content = L.DomUtil.create('div');
+
+            const addMarker = L.DomUtil.create('a', 'admin-command', content);
+            addMarker.textContent = 'Add marker';
+            L.DomEvent.on(addMarker, 'click', function(ev) {{
+                L.DomEvent.preventDefault(ev);
+                showAddMarkerForm(worldX, worldY, e.latlng);
             }});
+
+            if (adminCommand) {{
+                const command = adminCommand
+                    .split('{{x}}').join(worldX)
+                    .split('{{y}}').join(worldY)
+                    .split('{{z}}').join(currentFloor);
+
+                L.DomUtil.create('br', '', content);
+                const link = L.DomUtil.create('a', 'admin-command', content);
+                link.textContent = 'Copy ' + command;
+                L.DomEvent.on(link, 'click', function(ev) {{
+                    L.DomEvent.preventDefault(ev);
+                    copyToClipboard(command, command);
+                    map.closePopup();
+                }});
+            }}
+
+            L.popup().setLatLng(e.latlng).setContent(content).openOn(map);
+        }});
+
+        // Region export: drag a rectangle, then download it as a PNG or copy
+        // its bounds for `build --region`
+        const regionButton = document.getElementById('region-button');
+        let regionSelecting = false;
+        let regionStart = null;
+        let regionRect = null;
+
+        function setRegionSelecting(on) {{
+            regionSelecting = on;
+            regionButton.classList.toggle('active', on);
+            map.getContainer().style.cursor = on ? 'crosshair' : '';
+            if (on) {{
+                map.dragging.disable();
+            }} else {{
+                map.dragging.enable();
+            }}
         }}
 
-        const sectorGridToggle = document.getElementById('sector-grid-toggle');
-        if (sectorGridToggle) {{
-            sectorGridToggle.addEventListener('change', function() {{
-                updateSectorGridLayer();
-                updateHash();
+        regionButton.addEventListener('click', function() {{
+            if (measuring) {{
+                setMeasuring(false);
+            }}
+            setRegionSelecting(!regionSelecting);
+        }});
+
+        map.on('mousedown', function(e) {{
+            if (!regionSelecting || e.originalEvent.button !== 0) {{
+                return;
+            }}
+            regionStart = e.latlng;
+            if (regionRect) {{
+                map.removeLayer(regionRect);
+            }}
+            regionRect = L.rectangle([regionStart, regionStart], {{
+                color: '#ffcc00',
+                weight: 2,
+                fill: false,
+                interactive: false
+            }}).addTo(map);
+        }});
+
+        map.on('mousemove', function(e) {{
+            if (regionStart && regionRect) {{
+                regionRect.setBounds([regionStart, e.latlng]);
+            }}
+        }});
+
+        map.on('mouseup', function(e) {{
+            if (!regionStart) {{
+                return;
+            }}
+            const region = worldRegion(regionStart, e.latlng);
+            regionStart = null;
+            setRegionSelecting(false);
+            showRegionPopup(region, e.latlng);
+        }});
+
+        // Inclusive world tile rectangle between two corners, clamped to the map
+        function worldRegion(a, b) {{
+            const clamp = (value, min, max) => Math.max(min, Math.min(max, value));
+            const [p, q] = [latLngToWorld(a), latLngToWorld(b)];
+            return {{
+                x1: clamp(Math.min(p.x, q.x), minTileX, maxTileX),
+                y1: clamp(Math.min(p.y, q.y), minTileY, maxTileY),
+                x2: clamp(Math.max(p.x, q.x), minTileX, maxTileX),
+                y2: clamp(Math.max(p.y, q.y), minTileY, maxTileY),
+                z: currentFloor
+            }};
+        }}
+
+        function showRegionPopup(region, latlng) {{
+            const content = L.DomUtil.create('div', 'region-export');
+            const title = L.DomUtil.create('div', '', content);
+            title.textContent = `${{region.x1}},${{region.y1}} - ${{region.x2}},${{region.y2}} (floor ${{region.z}})`;
+
+            const download = L.DomUtil.create('a', 'admin-command', content);
+            download.textContent = 'Download PNG';
+            L.DomEvent.on(download, 'click', function(ev) {{
+                L.DomEvent.preventDefault(ev);
+                downloadRegionPng(region);
             }});
+
+            const regionArg = `--region ${{region.x1}},${{region.y1}},${{region.x2}},${{region.y2}} --floors ${{region.z}}`;
+            const copy = L.DomUtil.create('a', 'admin-command', content);
+            copy.textContent = 'Copy --region';
+            L.DomEvent.on(copy, 'click', function(ev) {{
+                L.DomEvent.preventDefault(ev);
+                copyToClipboard(regionArg, regionArg);
+            }});
+
+            L.popup().setLatLng(latlng).setContent(content).openOn(map);
         }}
 
-        map.on('click', function(e) {{
-            if (e.originalEvent.ctrlKey || e.originalEvent.metaKey) {{
-                const coords = `${{lastWorldX}},${{lastWorldY}},${{currentFloor}}`;
-                copyToClipboard(coords, coords);
-                L.DomEvent.stopPropagation(e.originalEvent);
+        function loadTileImage(url) {{
+            return new Promise((resolve, reject) => {{
+                const img = new Image();
+                img.onload = () => resolve(img);
+                img.onerror = reject;
+                img.src = url;
+            }});
+        }}
+
+        function loadFloorTile(dir, floor, zoom, tx, ty) {{
+            if (!pmtilesLayout || dir.startsWith('history/')) {{
+                return loadTileImage(dir + floor + '/' + zoom + '/' + tx + '/' + ty + '.' + tileExt);
+            }}
+            return getPmtilesTile(dir, floor, zoom, tx, ty).then(found => {{
+                if (!found) throw new Error('no tile');
+                const url = URL.createObjectURL(new Blob([found.data]));
+                return loadTileImage(url).finally(() => URL.revokeObjectURL(url));
+            }});
+        }}
+
+        // Stitches the region from the current floor's tiles at the current zoom
+        async function downloadRegionPng(region) {{
+            const zoom = Math.max(minZoom, Math.min(maxZoom, Math.round(map.getZoom())));
+            const scale = Math.pow(2, zoom);
+            const [regionTop, regionLeft] = worldToLatLng(region.x1, region.y1);
+            const left = regionLeft * scale;
+            const top = regionTop * scale;
+            const width = (region.x2 - region.x1 + 1) * scale;
+            const height = (region.y2 - region.y1 + 1) * scale;
+
+            if (width > 8192 || height > 8192) {{
+                showToast('Region too large at this zoom - zoom out and try again');
+                return;
+            }}
+
+            const canvas = document.createElement('canvas');
+            canvas.width = width;
+            canvas.height = height;
+            const ctx = canvas.getContext('2d');
+            ctx.fillStyle = floorBackgrounds[region.z] || voidColor;
+            ctx.fillRect(0, 0, width, height);
+            if (currentBase === 'Night') ctx.filter = nightFilter;
+
+            const dir = tilePrefix + baseTileDirs[currentBase];
+            const draws = [];
+            for (let ty = Math.floor(top / 256); ty <= Math.floor((top + height - 1) / 256); ty++) {{
+                for (let tx = Math.floor(left / 256); tx <= Math.floor((left + width - 1) / 256); tx++) {{
+                    draws.push(loadFloorTile(dir, region.z, zoom, tx, ty)
+                        .then(img => ctx.drawImage(img, tx * 256 - left, ty * 256 - top))
+                        .catch(() => {{}}));
+                }}
+            }}
+            await Promise.all(draws);
+
+            canvas.toBlob(function(blob) {{
+                const link = document.createElement('a');
+                link.href = URL.createObjectURL(blob);
+                link.download = `region-${{region.x1}}-${{region.y1}}-${{region.x2}}-${{region.y2}}-${{region.z}}.png`;
+                link.click();
+                setTimeout(() => URL.revokeObjectURL(link.href), 1000);
+            }}, 'image/png');
+        }}
+
+        // Distance measurement: click two points on the current floor to see
+        // how far apart they are and roughly how long walking there takes
+        const measureButton = document.getElementById('measure-button');
+        // Ground speed of grass, the most common walking surface. A step takes
+        // 1000 * ground speed / character speed milliseconds.
+        const measureGroundSpeed = 150;
+        let measureSpeed = 220;
+        let measuring = false;
+        let measureStart = null;
+        let measureLine = null;
+
+        function setMeasuring(on) {{
+            measuring = on;
+            measureStart = null;
+            measureButton.classList.toggle('active', on);
+            map.getContainer().style.cursor = on ? 'crosshair' : '';
+        }}
+
+        function clearMeasureLine() {{
+            if (measureLine) {{
+                map.removeLayer(measureLine);
+                measureLine = null;
+            }}
+        }}
+
+        measureButton.addEventListener('click', function() {{
+            if (regionSelecting) {{
+                setRegionSelecting(false);
+            }}
+            setMeasuring(!measuring);
+            if (measuring) {{
+                clearMeasureLine();
             }}
         }});
 
-        map.getContainer().addEventListener('mousedown', function(e) {{
-            if (e.button === 1) {{
-                copyToClipboard(lastSectorFile, lastSectorFile);
-                e.preventDefault();
+        document.addEventListener('keydown', function(e) {{
+            if (e.key === 'Escape' && measuring) {{
+                setMeasuring(false);
+                clearMeasureLine();
             }}
         }});
 
-        map.on('moveend', function() {{
-            updateSpawnLayer();
-            updateQuestChestLayer();
-            updateNpcLayer();
-            updateSectorGridLayer();
+        map.on('click', function(e) {{
+            if (!measuring || e.originalEvent.ctrlKey || e.originalEvent.metaKey) {{
+                return;
+            }}
+            const {{ x, y }} = latLngToWorld(e.latlng);
+            const center = L.latLng(worldToLatLng(x + 0.5, y + 0.5));
+
+            if (!measureStart) {{
+                measureStart = {{ x, y, center }};
+                clearMeasureLine();
+                measureLine = L.polyline([center, center], {{
+                    color: '#ffcc00',
+                    weight: 2,
+                    dashArray: '6 4',
+                    interactive: false
+                }}).addTo(map);
+                return;
+            }}
+
+            measureLine.setLatLngs([measureStart.center, center]);
+            showMeasurePopup(measureStart, {{ x, y }}, center);
+            setMeasuring(false);
         }});
 
-        map.on('zoomend', function() {{
-            updateSpawnLayer();
-            updateQuestChestLayer();
-            updateNpcLayer();
-            updateSectorGridLayer();
+        map.on('mousemove', function(e) {{
+            if (measuring && measureStart && measureLine) {{
+                measureLine.setLatLngs([measureStart.center, e.latlng]);
+            }}
         }});
 
+        function formatDuration(ms) {{
+            const seconds = Math.round(ms / 1000);
+            return seconds < 60 ? `${{seconds}} s` : `${{Math.floor(seconds / 60)}} min ${{seconds % 60}} s`;
+        }}
+
+        // Walking counts straight steps only, as diagonal steps are slower
+        function showMeasurePopup(from, to, latlng) {{
+            const dx = Math.abs(to.x - from.x);
+            const dy = Math.abs(to.y - from.y);
+            const steps = dx + dy;
+
+            const content = L.DomUtil.create('div', 'measure-result');
+            const title = L.DomUtil.create('b', '', content);
+            title.textContent = `${{from.x}},${{from.y}} to ${{to.x}},${{to.y}}`;
+            const distance = L.DomUtil.create('div', '', content);
+            distance.textContent = `${{dx}} x ${{dy}} tiles, ${{Math.hypot(dx, dy).toFixed(1)}} in a straight line`;
+            const walk = L.DomUtil.create('div', '', content);
+            const speedLabel = L.DomUtil.create('label', '', content);
+            speedLabel.textContent = 'Speed ';
+            const speedInput = L.DomUtil.create('input', '', speedLabel);
+            speedInput.type = 'number';
+            speedInput.min = '1';
+            speedInput.value = measureSpeed;
+
+            function updateWalk() {{
+                const speed = parseInt(speedInput.value, 10);
+                if (!(speed > 0)) {{
+                    return;
+                }}
+                measureSpeed = speed;
+                walk.textContent = `Walking: ${{steps}} steps, about ${{formatDuration(steps * 1000 * measureGroundSpeed / speed)}}`;
+            }}
+            speedInput.addEventListener('input', updateWalk);
+            updateWalk();
+
+            L.popup()
+                .setLatLng(latlng)
+                .setContent(content)
+                .on('remove', clearMeasureLine)
+                .openOn(map);
+        }}
+
+        // A zoom fires both zoomend and moveend, and fast pans fire moveend
+        // in bursts; rebuild the overlays once things settle
+        let overlayUpdateTimeout = null;
+
+        function scheduleOverlayUpdate() {{
+            clearTimeout(overlayUpdateTimeout);
+            overlayUpdateTimeout = setTimeout(function() {{
+                updateSpawnLayer();
+                updateSpawnAreaLayer();
+                updateQuestChestLayer();
+                updateWaterLayer();
+                updateZoneLayer();
+                updateHouseLayer();
+                updateTransitionLayer();
+                updateTeleportLayer();
+                updateSignLayer();
+                updatePoiLayer();
+                updateRaidLayer();
+                updateHuntingLayer();
+                updateDiffLayer();
+                updateNpcLayer();
+                updatePlayerLayer();
+                updateSectorGridLayer();
+            }}, 50);
+        }}
+
+        map.on('moveend', scheduleOverlayUpdate);
+        map.on('zoomend', scheduleOverlayUpdate);
+
+        // Periodic overlay refresh (--overlay-refresh)
+        const overlayRefreshMinutes = {overlay_refresh_minutes};
+
+        function refreshOverlay(file, key, apply) {{
+            fetch(file + '?t=' + Date.now())
+                .then(response => {{
+                    if (!response.ok) {{
+                        throw new Error(file + ' not found');
+                    }}
+                    return response.json();
+                }})
+                .then(data => {{
+                    setOverlayAvailable(key, true);
+                    apply(data);
+                }})
+                .catch(err => console.warn('Overlay refresh failed:', err));
+        }}
+
+        if (overlayRefreshMinutes > 0) {{
+            setInterval(() => {{
+                refreshOverlay('spawns.json', 'spawns', data => {{
+                    spawnData = data;
+                    updateSpawnSearch();
+                    setOverlayAvailable('spawn_areas', true);
+                    updateSpawnLayer();
+                    updateSpawnAreaLayer();
+                }});
+                refreshOverlay('questchests.json', 'quests', data => {{
+                    questChestData = data;
+                    updateQuestChestLayer();
+                }});
+            }}, overlayRefreshMinutes * 60 * 1000);
+        }}
+
         const originalLoadFloor = loadFloor;
         loadFloor = function(floor) {{
             originalLoadFloor(floor);
+            updateHistoryLabel();
+            updateHeatmapLayer();
             updateSpawnLayer();
+            updateSpawnAreaLayer();
             updateQuestChestLayer();
+            updateWaterLayer();
+            updateZoneLayer();
+            updateHouseLayer();
+            updateTransitionLayer();
+            updateTeleportLayer();
+            updateSignLayer();
+            updatePoiLayer();
+            updateRaidLayer();
+            updateHuntingLayer();
+            updateDiffLayer();
             updateNpcLayer();
+            updatePlayerLayer();
             updateSectorGridLayer();
+            updateUserMarkerLayer();
         }};
+
+        // Overlays from a shared link win over the ones remembered from the last visit
+        const hashToggles = hashParams ? hashParams.toggles : {{}};
+        const initialOverlays = Object.keys(hashToggles).length > 0
+            ? Object.keys(hashToggles).filter(key => hashToggles[key])
+            : (savedLayers.overlays || []);
+
+        initialOverlays
+            .filter(key => overlays[key])
+            .forEach(key => overlays[key].layer.addTo(map));
+        Object.values(overlays).forEach(overlay => overlay.update());
+
+        map.on('overlayadd overlayremove', function(e) {{
+            const overlay = Object.values(overlays).find(o => o.layer === e.layer);
+            if (overlay) {{
+                overlay.update();
+                updateHash();
+                saveLayerState();
+            }}
+        }});
+
+        map.on('baselayerchange', function(e) {{
+            currentBase = e.name;
+            loadFloor(currentFloor);
+            saveLayerState();
+        }});
     </script>
-</body>
+{extra_scripts}</body>
 </html>"#,
-        floor_options = generate_floor_options(floors),
-        floors_json = format!("{:?}", floors),
+        floor_buttons = generate_floor_buttons(floors),
+        floors_json = floors_json,
         min_zoom = min_zoom,
         max_zoom = max_zoom,
         min_tile_x = min_tile_x,
         max_tile_x = max_tile_x,
         min_tile_y = min_tile_y,
         max_tile_y = max_tile_y,
-        default_floor = floors.first().copied().unwrap_or(7)
+        default_floor = floors.first().copied().unwrap_or(7),
+        live_players = options.live_players,
+        overlay_refresh_minutes = options.overlay_refresh_minutes.unwrap_or(0),
+        admin_command_json = admin_command_json,
+        tile_ext = options.tile_format.extension(),
+        base_tile_dirs = base_tile_dirs(options),
+        leaflet_base = leaflet_base,
+        pmtiles_script = pmtiles_script,
+        pmtiles_layout_json = pmtiles_layout_json,
+        floor_zoom_shift = PMTILES_FLOOR_ZOOM_SHIFT,
+        extra_stylesheets = extra_stylesheets,
+        extra_scripts = extra_scripts
     );
 
     let html_path = output_path.as_ref().join("index.html");
@@ -828,7 +2906,28 @@ pub fn generate_html<P: AsRef<Path>>(
     Ok(())
 }
 
-fn generate_floor_options(floors: &[u8]) -> String {
+/// Base layer name → tile directory prefix, as a JS object literal. The
+/// first entry is the default base layer.
+fn base_tile_dirs(options: &ViewerOptions) -> String {
+    let day = match options.render_mode {
+        RenderMode::Sprites => "Sprites",
+        RenderMode::Color => "Colors",
+    };
+    let mut dirs = vec![(day, ""), ("Night", "")];
+    if options.composite {
+        dirs.push(("All floors", "composite/"));
+    }
+    let entries: Vec<String> = dirs.iter().map(|(name, dir)| format!("'{}': '{}'", name, dir)).collect();
+    format!("{{ {} }}", entries.join(", "))
+}
+
+fn escape_attribute(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;")
+}
+
+/// Floor picker buttons; a thumbnail that was not built removes itself and
+/// leaves the label.
+fn generate_floor_buttons(floors: &[u8]) -> String {
     floors
         .iter()
         .map(|&f| {
@@ -837,7 +2936,9 @@ fn generate_floor_options(floors: &[u8]) -> String {
                 f if f < 7 => format!("Sky {} ({})", 7 - f, f),
                 f => format!("Underground {} ({})", f - 7, f),
             };
-            format!(r#"            <option value="{}">{}</option>"#, f, label)
+            format!(
+                r#"        <button class="floor-thumb" data-floor="{f}" title="{label}"><img src="thumbnails/{f}.png" alt="" loading="lazy" onerror="this.remove()"><span>{label}</span></button>"#
+            )
         })
         .collect::<Vec<_>>()
         .join("\n")
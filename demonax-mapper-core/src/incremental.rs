@@ -0,0 +1,163 @@
+use crate::{parse_sector_coords, RenderMode, TileFormat, WorldBounds};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+pub const RENDER_STATE_FILE: &str = "render-state.json";
+
+/// Modification time, size and content hash of one sector file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectorStamp {
+    pub modified_ns: u64,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+/// Stamps of every sector file on the scanned floors, keyed by file name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectorIndex {
+    pub sectors: BTreeMap<String, SectorStamp>,
+}
+
+impl SectorIndex {
+    /// Stamps the sector files of `floors`. Files whose modification time and
+    /// size match `previous` keep their old hash instead of being re-read.
+    pub fn scan<P: AsRef<Path>>(map_dir: P, floors: &[u8], previous: Option<&SectorIndex>) -> Result<Self> {
+        let map_dir = map_dir.as_ref();
+        let mut sectors = BTreeMap::new();
+
+        for entry in fs::read_dir(map_dir).with_context(|| format!("Failed to read map directory: {:?}", map_dir))? {
+            let entry = entry?;
+            let Some(filename) = entry.file_name().to_str().map(str::to_owned) else {
+                continue;
+            };
+            if !parse_sector_coords(&filename).is_some_and(|(_, _, z)| floors.contains(&z)) {
+                continue;
+            }
+
+            let metadata = entry.metadata()?;
+            let modified_ns = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0);
+            let bytes = metadata.len();
+
+            let sha256 = match previous.and_then(|p| p.sectors.get(&filename)) {
+                Some(stamp) if stamp.modified_ns == modified_ns && stamp.bytes == bytes => stamp.sha256.clone(),
+                _ => {
+                    let digest = Sha256::digest(fs::read(entry.path())?);
+                    digest.iter().map(|b| format!("{:02x}", b)).collect()
+                }
+            };
+            sectors.insert(filename, SectorStamp { modified_ns, bytes, sha256 });
+        }
+
+        Ok(Self { sectors })
+    }
+
+    /// `(x, y, z)` of sectors added, removed or edited since `previous`.
+    /// Touching a file without changing its content does not count.
+    pub fn changed_since(&self, previous: &SectorIndex) -> Vec<(u32, u32, u8)> {
+        let edited = self
+            .sectors
+            .iter()
+            .filter(|(name, stamp)| previous.sectors.get(*name).is_none_or(|old| old.sha256 != stamp.sha256));
+        let removed = previous.sectors.iter().filter(|(name, _)| !self.sectors.contains_key(*name));
+
+        edited
+            .chain(removed)
+            .filter_map(|(name, _)| parse_sector_coords(name))
+            .collect()
+    }
+}
+
+/// What the tiles in an output directory were last rendered from, saved in
+/// the cache so the next build can re-render only what changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderState {
+    pub output: String,
+    pub bounds: WorldBounds,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+    pub floors: Vec<u8>,
+    #[serde(default)]
+    pub render_mode: RenderMode,
+    #[serde(default)]
+    pub tile_format: TileFormat,
+    pub sectors: SectorIndex,
+}
+
+impl RenderState {
+    /// Missing or unreadable state loads as `None`, which means a full render.
+    pub fn load<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let content = fs::read_to_string(path.as_ref()).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(state) => Some(state),
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable render state {:?}: {}", path.as_ref(), e);
+                None
+            }
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path.as_ref(), serde_json::to_string(self)?)
+            .with_context(|| format!("Failed to write {:?}", path.as_ref()))
+    }
+
+    /// Whether `floor` was rendered into the same output with the same
+    /// bounds, zoom levels, render mode and tile format, so its tiles can be
+    /// updated in place.
+    #[allow(clippy::too_many_arguments)]
+    pub fn can_update(
+        &self,
+        output: &str,
+        bounds: &WorldBounds,
+        min_zoom: u8,
+        max_zoom: u8,
+        render_mode: RenderMode,
+        tile_format: TileFormat,
+        floor: u8,
+    ) -> bool {
+        self.output == output
+            && self.render_mode == render_mode
+            && self.tile_format == tile_format
+            && self.bounds == *bounds
+            && self.min_zoom == min_zoom
+            && self.max_zoom == max_zoom
+            && self.floors.contains(&floor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_sectors_by_content() {
+        let dir = std::env::temp_dir().join(format!("demonax-incremental-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1000-1000-07.sec"), "0-0: Content={100}\n").unwrap();
+        fs::write(dir.join("1001-1000-07.sec"), "0-0: Content={101}\n").unwrap();
+        fs::write(dir.join("1000-1000-08.sec"), "0-0: Content={102}\n").unwrap();
+
+        let before = SectorIndex::scan(&dir, &[7], None).unwrap();
+        assert_eq!(before.sectors.len(), 2);
+        assert!(before.changed_since(&before).is_empty());
+
+        fs::write(dir.join("1000-1000-07.sec"), "0-0: Content={200}\n").unwrap();
+        fs::write(dir.join("1001-1000-07.sec"), "0-0: Content={101}\n").unwrap();
+        fs::write(dir.join("1002-1000-07.sec"), "0-0: Content={103}\n").unwrap();
+        let after = SectorIndex::scan(&dir, &[7], Some(&before)).unwrap();
+
+        let mut changed = after.changed_since(&before);
+        changed.sort_unstable();
+        assert_eq!(changed, vec![(1000, 1000, 7), (1002, 1000, 7)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{GameObject, MapperError, ObjectDatabase, ObjectFlags};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::path::Path;
+
+/// Rules every build starts from, after those of `--layer-rules`: quest
+/// chests are drawn although they can be picked up, and planted flowers
+/// (nothing but Unmove, maybe Avoid) lie flat among the ground details.
+pub const DEFAULT_LAYER_RULES: &str = r#"
+[[rule]]
+ids = [2543, 2546, 2550, 2551, 2552, 2555, 2560, 4445, 4830]
+layer = "normal"
+
+[[rule]]
+names = ["*flower*", "*blossom*"]
+flags = ["Unmove"]
+only_flags = ["Unmove", "Avoid"]
+layer = "clip"
+"#;
+
+/// Where an object is drawn within its tile, in drawing order. `Hidden`
+/// objects are not drawn at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpriteLayer {
+    Ground,
+    Clip,
+    Bottom,
+    Normal,
+    Top,
+    Hidden,
+}
+
+impl SpriteLayer {
+    /// The layer objects.srv flags put an object on, for objects no rule
+    /// matches. Takeable items are hidden unless they are containers.
+    pub fn of(object: &GameObject) -> Self {
+        let flags = object.flags;
+        if flags.is_takeable() && !flags.is_container() {
+            Self::Hidden
+        } else if object.is_ground || flags.is_bank() {
+            // Bank marks water and swamp
+            Self::Ground
+        } else if flags.is_clip() {
+            Self::Clip
+        } else if flags.is_top() {
+            Self::Top
+        } else if flags.is_bottom() || flags.is_text() {
+            Self::Bottom
+        } else {
+            Self::Normal
+        }
+    }
+}
+
+/// One `[[rule]]` of a layer rules file. An object matches when it meets
+/// every condition the rule gives; a rule must give at least one.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayerRule {
+    /// Object IDs, any of which matches.
+    #[serde(default)]
+    pub ids: Vec<u32>,
+    /// Name patterns, any of which matches, compared ignoring case; `*`
+    /// stands for any text.
+    #[serde(default)]
+    pub names: Vec<String>,
+    /// objects.srv flags the object must all have.
+    #[serde(default, deserialize_with = "deserialize_flags")]
+    pub flags: ObjectFlags,
+    /// The only flags the object may have; unknown flags never match.
+    #[serde(default, deserialize_with = "deserialize_only_flags")]
+    pub only_flags: Option<ObjectFlags>,
+    pub layer: SpriteLayer,
+}
+
+impl LayerRule {
+    pub fn matches(&self, object: &GameObject) -> bool {
+        let name = object.name.to_lowercase();
+        (self.ids.is_empty() || self.ids.contains(&object.id))
+            && (self.names.is_empty() || self.names.iter().any(|pattern| matches_pattern(&pattern.to_lowercase(), &name)))
+            && object.flags.contains(self.flags)
+            && self
+                .only_flags
+                .is_none_or(|only| only.contains(object.flags) && object.unknown_flags.is_empty())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ids.is_empty() && self.names.is_empty() && self.flags.is_empty() && self.only_flags.is_none()
+    }
+}
+
+fn deserialize_flags<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<ObjectFlags, D::Error> {
+    let names = Vec::<String>::deserialize(deserializer)?;
+    names.iter().try_fold(ObjectFlags::empty(), |flags, name| {
+        ObjectFlags::from_srv_name(name)
+            .map(|flag| flags | flag)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown objects.srv flag {:?}", name)))
+    })
+}
+
+fn deserialize_only_flags<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<ObjectFlags>, D::Error> {
+    deserialize_flags(deserializer).map(Some)
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any run of
+/// characters, including none.
+fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*` at all
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayerRulesFile {
+    #[serde(default)]
+    rule: Vec<LayerRule>,
+}
+
+/// Parses layer rules from TOML, or JSON (`{"rule": [...]}`) when `json` is set.
+pub fn parse_layer_rules(content: &str, json: bool) -> Result<Vec<LayerRule>> {
+    let file: LayerRulesFile = if json {
+        serde_json::from_str(content).map_err(|e| MapperError::InvalidConfig(e.to_string()))?
+    } else {
+        toml::from_str(content).map_err(|e| MapperError::InvalidConfig(e.to_string()))?
+    };
+    if let Some(index) = file.rule.iter().position(LayerRule::is_empty) {
+        return Err(MapperError::InvalidConfig(format!("layer rule {} has no condition", index + 1)));
+    }
+    Ok(file.rule)
+}
+
+/// The rules of `path`, if given, followed by [`DEFAULT_LAYER_RULES`], so
+/// the file's rules win where both match.
+pub fn load_layer_rules(path: Option<&Path>) -> Result<Vec<LayerRule>> {
+    let mut rules = match path {
+        Some(path) => {
+            let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read layer rules {:?}", path))?;
+            let json = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json"));
+            parse_layer_rules(&content, json)
+                .map_err(|e| MapperError::InvalidConfig(format!("Invalid layer rules {:?}: {}", path, e)))?
+        }
+        None => Vec::new(),
+    };
+    rules.extend(parse_layer_rules(DEFAULT_LAYER_RULES, false)?);
+    Ok(rules)
+}
+
+/// Sets the layer of every object the first matching rule applies to.
+/// Returns how many objects got one.
+pub fn apply_layer_rules(objects: &mut ObjectDatabase, rules: &[LayerRule]) -> usize {
+    let mut matched = 0;
+    for object in objects.values_mut() {
+        object.layer = rules.iter().find(|rule| rule.matches(object)).map(|rule| rule.layer);
+        matched += object.layer.is_some() as usize;
+    }
+
+    tracing::info!("Layer rules placed {} objects", matched);
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(id: u32, name: &str, flags: &str) -> GameObject {
+        let (flags, unknown_flags) = ObjectFlags::parse_list(flags);
+        GameObject {
+            id,
+            name: name.to_string(),
+            flags,
+            unknown_flags,
+            waypoints: 0,
+            is_ground: false,
+            is_impassable: false,
+            disguise_target: None,
+            attributes: Default::default(),
+            layer: None,
+        }
+    }
+
+    #[test]
+    fn test_layer_rules_override_defaults() {
+        let rules = parse_layer_rules(
+            "[[rule]]\nnames = [\"dead *\"]\nflags = [\"Take\"]\nlayer = \"clip\"\n\n[[rule]]\nids = [2543]\nlayer = \"hidden\"\n",
+            false,
+        )
+        .unwrap();
+        let mut rules_with_defaults = rules.clone();
+        rules_with_defaults.extend(parse_layer_rules(DEFAULT_LAYER_RULES, false).unwrap());
+
+        let mut objects: ObjectDatabase = [
+            object(2543, "a chest", "Container, Take"),
+            object(2550, "a chest", "Take"),
+            object(3000, "a red flower", "Unmove"),
+            object(3001, "a flowery wall", "Unmove, Hang"),
+            object(3002, "dead human", "Take"),
+            object(3003, "Dead Rat", "Take, Corpse"),
+            object(3004, "a dead tree", "Take"),
+        ]
+        .into_iter()
+        .map(|o| (o.id, o))
+        .collect();
+        assert_eq!(apply_layer_rules(&mut objects, &rules_with_defaults), 5);
+
+        let layer = |id: u32| objects[&id].layer;
+        assert_eq!(
+            [layer(2543), layer(2550), layer(3000), layer(3001), layer(3002), layer(3003), layer(3004)],
+            [
+                Some(SpriteLayer::Hidden),
+                Some(SpriteLayer::Normal),
+                Some(SpriteLayer::Clip),
+                None,
+                Some(SpriteLayer::Clip),
+                Some(SpriteLayer::Clip),
+                None
+            ]
+        );
+        assert_eq!(SpriteLayer::of(&objects[&3004]), SpriteLayer::Hidden);
+
+        assert!(parse_layer_rules("[[rule]]\nflags = [\"Shiny\"]\nlayer = \"top\"\n", false).is_err());
+        assert!(parse_layer_rules("{\"rule\": [{\"layer\": \"top\"}]}", true).is_err());
+    }
+}
@@ -1,15 +1,67 @@
+pub mod error;
+pub mod config;
 pub mod objects;
+pub mod layers;
 pub mod html;
-pub mod sprites;
-pub mod tiles_sprite;
+pub mod sectors;
 pub mod monsters;
+pub mod raids;
 pub mod questchests;
 pub mod npcs;
+pub mod texts;
+pub mod water;
+pub mod transitions;
+pub mod teleports;
+pub mod poi;
+pub mod zones;
+pub mod prices;
+pub mod heatmap;
+pub mod deaths;
+pub mod players;
+pub mod housestatus;
+pub mod houses;
+pub mod history;
+pub mod lint;
+pub mod validate;
+pub mod schema;
+pub mod project;
+pub mod bounds;
+pub mod datapaths;
+pub mod encoding;
+pub mod incremental;
+pub mod diff;
+pub mod cache;
 
+pub use error::MapperError;
+pub use config::*;
 pub use objects::*;
+pub use layers::*;
 pub use html::*;
-pub use sprites::*;
-pub use tiles_sprite::*;
+pub use sectors::*;
 pub use monsters::*;
+pub use raids::*;
 pub use questchests::*;
 pub use npcs::*;
+pub use texts::*;
+pub use water::*;
+pub use transitions::*;
+pub use teleports::*;
+pub use poi::*;
+pub use zones::*;
+pub use prices::*;
+pub use heatmap::*;
+pub use deaths::*;
+pub use players::*;
+pub use housestatus::*;
+pub use houses::*;
+pub use history::*;
+pub use lint::*;
+pub use validate::*;
+pub use schema::*;
+pub use project::*;
+pub use bounds::*;
+pub use datapaths::*;
+pub use encoding::*;
+pub use incremental::*;
+pub use diff::*;
+pub use cache::*;
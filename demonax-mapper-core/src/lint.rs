@@ -0,0 +1,119 @@
+use crate::{parse_sector_item_line, read_text, sector_files_for_floors, sector_to_world, InputEncoding, ObjectDatabase, SectorItem};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Every use of one unknown object ID within one sector file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnknownObjectUse {
+    pub sector: String,
+    pub object_id: u32,
+    pub count: usize,
+    /// World position and 1-based line of the first occurrence.
+    pub first_x: u32,
+    pub first_y: u32,
+    pub z: u8,
+    pub first_line: usize,
+}
+
+/// Flags Content entries (including nested container contents) whose object
+/// ID is missing from `objects`, grouped by sector and ID.
+pub fn find_unknown_object_ids<P: AsRef<Path>>(
+    map_dir: P,
+    floors: &[u8],
+    objects: &ObjectDatabase,
+    encoding: InputEncoding,
+) -> Result<Vec<UnknownObjectUse>> {
+    let mut uses = Vec::new();
+
+    for sector in sector_files_for_floors(map_dir.as_ref(), floors)? {
+        let content = read_text(&sector.path, encoding)
+            .with_context(|| format!("Failed to read {:?}", sector.path))?;
+        let name = sector
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        for (object_id, (count, local_x, local_y, line)) in unknown_ids_in_sector(&content, objects) {
+            let (first_x, first_y) = sector_to_world(sector.sector_x, sector.sector_y, local_x, local_y);
+            uses.push(UnknownObjectUse {
+                sector: name.clone(),
+                object_id,
+                count,
+                first_x,
+                first_y,
+                z: sector.z,
+                first_line: line,
+            });
+        }
+    }
+
+    tracing::info!("Found {} unknown object IDs across sectors", uses.len());
+    Ok(uses)
+}
+
+/// Unknown ID → (count, local x, local y, line) of its first occurrence.
+pub(crate) fn unknown_ids_in_sector(content: &str, objects: &ObjectDatabase) -> BTreeMap<u32, (usize, u32, u32, usize)> {
+    let mut unknown = BTreeMap::new();
+
+    for (line_num, line) in content.lines().enumerate() {
+        if !line.contains("Content=") {
+            continue;
+        }
+        let Some((local_x, local_y, items)) = parse_sector_item_line(line) else {
+            continue;
+        };
+
+        let mut ids = Vec::new();
+        collect_ids(&items, &mut ids);
+        for id in ids.into_iter().filter(|id| !objects.contains_key(id)) {
+            unknown
+                .entry(id)
+                .or_insert((0, local_x, local_y, line_num + 1))
+                .0 += 1;
+        }
+    }
+
+    unknown
+}
+
+fn collect_ids(items: &[SectorItem], ids: &mut Vec<u32>) {
+    for item in items {
+        ids.push(item.id);
+        collect_ids(&item.contents, ids);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GameObject, ObjectFlags};
+
+    #[test]
+    fn test_unknown_ids_in_sector_counts_nested_contents() {
+        let grass = GameObject {
+            id: 100,
+            name: "grass".to_string(),
+            flags: ObjectFlags::BANK,
+            unknown_flags: Vec::new(),
+            waypoints: 0,
+            is_ground: true,
+            is_impassable: false,
+            disguise_target: None,
+            attributes: Default::default(),
+            layer: None,
+        };
+        let objects: ObjectDatabase = [(100, grass)].into();
+
+        let content = "0-0: Content={100, 9999}\n\
+                       # comment\n\
+                       3-4: Content={100, 2000 Content={9999, 100}}";
+        let unknown = unknown_ids_in_sector(content, &objects);
+
+        assert_eq!(unknown.len(), 2);
+        assert_eq!(unknown[&9999], (2, 0, 0, 1));
+        assert_eq!(unknown[&2000], (1, 3, 4, 3));
+    }
+}
@@ -1,6 +1,8 @@
-use anyhow::{Context, Result};
+use crate::prices::parse_inventory;
+use crate::{read_text, InputEncoding, LootEntry, ObjectDatabase};
+use crate::error::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 
@@ -13,10 +15,13 @@ pub struct MonsterSpawn {
     pub radius: u32,
     pub amount: u32,
     pub regen: u32,
+    /// 1-based line in monster.db, for pointing at entries to fix.
+    #[serde(default)]
+    pub line: usize,
 }
 
-pub fn parse_monster_db<P: AsRef<Path>>(path: P) -> Result<Vec<MonsterSpawn>> {
-    let content = fs::read_to_string(path.as_ref())
+pub fn parse_monster_db<P: AsRef<Path>>(path: P, encoding: InputEncoding) -> Result<Vec<MonsterSpawn>> {
+    let content = read_text(path.as_ref(), encoding)
         .with_context(|| format!("Failed to read monster.db from {:?}", path.as_ref()))?;
 
     let mut spawns = Vec::new();
@@ -91,6 +96,7 @@ pub fn parse_monster_db<P: AsRef<Path>>(path: P) -> Result<Vec<MonsterSpawn>> {
             radius,
             amount,
             regen,
+            line: line_num + 1,
         });
     }
 
@@ -98,9 +104,93 @@ pub fn parse_monster_db<P: AsRef<Path>>(path: P) -> Result<Vec<MonsterSpawn>> {
     Ok(spawns)
 }
 
-pub fn parse_monster_names<P: AsRef<Path>>(mon_dir: P) -> Result<HashMap<u32, String>> {
+/// What a race's `.mon` file says about it beyond its look: the numbers
+/// players compare hunting grounds by.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MonsterStats {
+    pub name: String,
+    /// From `HitPoints` in `Skills = {...}`, or a plain `HitPoints = ...` line.
+    pub hit_points: Option<u32>,
+    pub experience: Option<u32>,
+    /// `Flags = {...}` entries, such as `NoConvince` or `SeeInvisible`.
+    pub flags: Vec<String>,
+    /// Mana it takes to summon or convince the monster; 0 when not given.
+    pub summon_cost: u32,
+    pub convince_cost: u32,
+    pub loot: Vec<LootEntry>,
+}
+
+impl MonsterStats {
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f.eq_ignore_ascii_case(flag))
+    }
+
+    pub fn is_summonable(&self) -> bool {
+        self.summon_cost > 0 && !self.has_flag("NoSummon")
+    }
+
+    pub fn is_convinceable(&self) -> bool {
+        self.convince_cost > 0 && !self.has_flag("NoConvince")
+    }
+}
+
+/// The text after `key =` on the line that starts with `key`, running on to
+/// the end of the file so lists spanning several lines can be read. Raid
+/// files share this layout.
+pub(crate) fn mon_value<'a>(content: &'a str, key: &str) -> Option<&'a str> {
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some(rest) = trimmed.strip_prefix(key)
+            && let Some(value) = rest.trim_start().strip_prefix('=')
+        {
+            let start = offset + (line.len() - value.len());
+            return Some(content[start..].trim_start());
+        }
+        offset += line.len();
+    }
+    None
+}
+
+fn mon_number(content: &str, key: &str) -> Option<u32> {
+    mon_value(content, key)?.lines().next()?.trim().parse().ok()
+}
+
+/// Parses one `.mon` file; `None` without a race number or name.
+pub fn parse_mon_stats(content: &str) -> Option<(u32, MonsterStats)> {
+    let race = mon_number(content, "RaceNumber")?;
+    let name = mon_value(content, "Name")?.lines().next()?.trim().trim_matches('"').to_string();
+
+    // Skills = {(HitPoints, 70, 0, 70, 0, 0, 0), ...}: the first value is the current
+    let hit_points = mon_number(content, "HitPoints").or_else(|| {
+        let skill = &content[content.find("(HitPoints")? + "(HitPoints".len()..];
+        skill.trim_start_matches([',', ' ', '\t']).split([',', ')']).next()?.trim().parse().ok()
+    });
+    let flags = mon_value(content, "Flags")
+        .and_then(|value| {
+            let list = value.strip_prefix('{')?;
+            Some(list[..list.find('}')?].split(',').map(str::trim).filter(|f| !f.is_empty()).map(String::from).collect())
+        })
+        .unwrap_or_default();
+
+    Some((
+        race,
+        MonsterStats {
+            name,
+            hit_points,
+            experience: mon_number(content, "Experience"),
+            flags,
+            summon_cost: mon_number(content, "SummonCost").unwrap_or(0),
+            convince_cost: mon_number(content, "ConvinceCost").unwrap_or(0),
+            loot: mon_value(content, "Inventory").map(parse_inventory).unwrap_or_default(),
+        },
+    ))
+}
+
+/// Reads every `.mon` file in `mon_dir`, keyed by race number.
+pub fn parse_monster_stats<P: AsRef<Path>>(mon_dir: P, encoding: InputEncoding) -> Result<HashMap<u32, MonsterStats>> {
     let mon_dir = mon_dir.as_ref();
-    let mut monster_names = HashMap::new();
+    let mut monster_stats = HashMap::new();
 
     let entries = fs::read_dir(mon_dir)
         .with_context(|| format!("Failed to read monster directory: {:?}", mon_dir))?;
@@ -113,39 +203,85 @@ pub fn parse_monster_names<P: AsRef<Path>>(mon_dir: P) -> Result<HashMap<u32, St
             continue;
         }
 
-        let content = fs::read_to_string(&path)
+        let content = read_text(&path, encoding)
             .with_context(|| format!("Failed to read .mon file: {:?}", path))?;
 
-        let mut race_number: Option<u32> = None;
-        let mut name: Option<String> = None;
+        match parse_mon_stats(&content) {
+            Some((race_id, stats)) => {
+                monster_stats.insert(race_id, stats);
+            }
+            None => tracing::warn!("Incomplete monster data in file: {:?}", path),
+        }
+    }
 
-        for line in content.lines() {
-            let line = line.trim();
+    tracing::info!("Loaded {} monsters from .mon files", monster_stats.len());
+    Ok(monster_stats)
+}
 
-            if line.starts_with("RaceNumber") {
-                if let Some(value) = line.split('=').nth(1) {
-                    race_number = value.trim().parse().ok();
-                }
-            } else if line.starts_with("Name") {
-                if let Some(value) = line.split('=').nth(1) {
-                    name = Some(value.trim().trim_matches('"').to_string());
-                }
-            }
+pub fn parse_monster_names<P: AsRef<Path>>(mon_dir: P, encoding: InputEncoding) -> Result<HashMap<u32, String>> {
+    Ok(parse_monster_stats(mon_dir, encoding)?.into_iter().map(|(race, stats)| (race, stats.name)).collect())
+}
 
-            if race_number.is_some() && name.is_some() {
-                break;
-            }
+/// How a race is drawn, from the `Outfit = (lookType, head-body-legs-feet)`
+/// line of its `.mon` file. A lookType of 0 means the monster looks like an
+/// item and the second value is that item's ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MonsterLook {
+    Outfit { look_type: u32, colors: [u8; 4] },
+    Item(u32),
+}
+
+pub fn parse_monster_looks<P: AsRef<Path>>(mon_dir: P, encoding: InputEncoding) -> Result<HashMap<u32, MonsterLook>> {
+    let mon_dir = mon_dir.as_ref();
+    let mut looks = HashMap::new();
+
+    for entry in fs::read_dir(mon_dir)
+        .with_context(|| format!("Failed to read monster directory: {:?}", mon_dir))?
+    {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("mon") {
+            continue;
         }
 
-        if let (Some(race_id), Some(monster_name)) = (race_number, name) {
-            monster_names.insert(race_id, monster_name);
-        } else {
-            tracing::warn!("Incomplete monster data in file: {:?}", path);
+        let content = read_text(&path, encoding)
+            .with_context(|| format!("Failed to read .mon file: {:?}", path))?;
+
+        let value_of = |key: &str| {
+            content
+                .lines()
+                .find_map(|l| l.trim().strip_prefix(key))
+                .map(|v| v.trim().trim_start_matches('=').trim())
+        };
+        let race = value_of("RaceNumber").and_then(|v| v.parse::<u32>().ok());
+
+        match (race, value_of("Outfit").map(parse_outfit)) {
+            (Some(race), Some(Some(look))) => {
+                looks.insert(race, look);
+            }
+            (_, Some(None)) => tracing::warn!("Invalid Outfit in {:?}", path),
+            _ => {}
         }
     }
 
-    tracing::info!("Loaded {} monster names from .mon files", monster_names.len());
-    Ok(monster_names)
+    Ok(looks)
+}
+
+/// Parses `(130, 78-69-58-76)` or, for item-looking monsters, `(0, 3031)`.
+fn parse_outfit(value: &str) -> Option<MonsterLook> {
+    let (look_type, rest) = value.strip_prefix('(')?.strip_suffix(')')?.split_once(',')?;
+    let look_type: u32 = look_type.trim().parse().ok()?;
+    let rest = rest.trim();
+
+    if look_type == 0 {
+        return rest.parse().ok().map(MonsterLook::Item);
+    }
+
+    let mut colors = [0u8; 4];
+    let mut parts = rest.split('-');
+    for color in &mut colors {
+        *color = parts.next()?.trim().parse().ok()?;
+    }
+    parts.next().is_none().then_some(MonsterLook::Outfit { look_type, colors })
 }
 
 #[derive(Serialize)]
@@ -157,14 +293,46 @@ struct SpawnOutput {
     y: u32,
     amount: u32,
     radius: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    loot_value: Option<u64>,
+}
+
+/// How many of a race's likeliest drops the spawn popup lists.
+const LOOT_SUMMARY_LENGTH: usize = 6;
+
+#[derive(Serialize)]
+struct MonsterOutput<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hit_points: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    experience: Option<u32>,
+    summonable: bool,
+    convinceable: bool,
+    summon_cost: u32,
+    convince_cost: u32,
+    flags: &'a [String],
+    loot: Vec<LootOutput>,
+}
+
+#[derive(Serialize)]
+struct LootOutput {
+    item_id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    max_count: u32,
+    chance: u32,
 }
 
 pub fn generate_spawn_json(
     spawns: &[MonsterSpawn],
     floors: &[u8],
     monster_names: &HashMap<u32, String>,
+    loot_values: &HashMap<u32, u64>,
+    monster_stats: &HashMap<u32, MonsterStats>,
+    objects: &ObjectDatabase,
 ) -> Result<String> {
     let mut spawns_by_floor: HashMap<u8, Vec<SpawnOutput>> = HashMap::new();
+    let mut monsters: BTreeMap<u32, MonsterOutput> = BTreeMap::new();
 
     for spawn in spawns {
         if floors.contains(&spawn.z) {
@@ -175,17 +343,23 @@ pub fn generate_spawn_json(
                 y: spawn.y,
                 amount: spawn.amount,
                 radius: spawn.radius,
+                loot_value: loot_values.get(&spawn.race).copied(),
             };
 
             spawns_by_floor
                 .entry(spawn.z)
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(spawn_output);
+
+            if let Some(stats) = monster_stats.get(&spawn.race) {
+                monsters.entry(spawn.race).or_insert_with(|| monster_output(stats, objects));
+            }
         }
     }
 
     let output = serde_json::json!({
-        "spawns_by_floor": spawns_by_floor
+        "spawns_by_floor": spawns_by_floor,
+        "monsters": monsters
     });
 
     let json = serde_json::to_string(&output)
@@ -193,3 +367,442 @@ pub fn generate_spawn_json(
 
     Ok(json)
 }
+
+/// Stats of one race for spawns.json, with its likeliest drops first.
+fn monster_output<'a>(stats: &'a MonsterStats, objects: &ObjectDatabase) -> MonsterOutput<'a> {
+    let mut loot = stats.loot.clone();
+    loot.sort_by_key(|entry| std::cmp::Reverse(entry.chance));
+    loot.truncate(LOOT_SUMMARY_LENGTH);
+
+    MonsterOutput {
+        hit_points: stats.hit_points,
+        experience: stats.experience,
+        summonable: stats.is_summonable(),
+        convinceable: stats.is_convinceable(),
+        summon_cost: stats.summon_cost,
+        convince_cost: stats.convince_cost,
+        flags: &stats.flags,
+        loot: loot
+            .into_iter()
+            .map(|entry| LootOutput {
+                item_id: entry.item_id,
+                name: objects.get(&entry.item_id).map(|o| o.name.clone()).filter(|n| !n.is_empty()),
+                max_count: entry.max_count,
+                chance: entry.chance,
+            })
+            .collect(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpawnIssueKind {
+    /// Same race at the same position.
+    Duplicate,
+    /// Same race with spawn areas that mostly cover each other.
+    Overlap,
+}
+
+impl SpawnIssueKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpawnIssueKind::Duplicate => "duplicate",
+            SpawnIssueKind::Overlap => "overlap",
+        }
+    }
+}
+
+/// A pair of spawn entries that look like a copy-paste mistake.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnIssue {
+    pub kind: SpawnIssueKind,
+    /// Shared area as a fraction of the smaller spawn's area.
+    pub overlap: f64,
+    pub first: MonsterSpawn,
+    pub second: MonsterSpawn,
+}
+
+/// Finds duplicate spawns and same-race spawns whose areas overlap by at
+/// least `min_overlap` (0.0-1.0) of the smaller one.
+pub fn find_spawn_issues(spawns: &[MonsterSpawn], min_overlap: f64) -> Vec<SpawnIssue> {
+    let mut groups: HashMap<(u8, u32), Vec<&MonsterSpawn>> = HashMap::new();
+    for spawn in spawns {
+        groups.entry((spawn.z, spawn.race)).or_default().push(spawn);
+    }
+
+    let mut issues = Vec::new();
+    for group in groups.values() {
+        for (i, a) in group.iter().enumerate() {
+            for b in &group[i + 1..] {
+                let kind = if (a.x, a.y) == (b.x, b.y) {
+                    SpawnIssueKind::Duplicate
+                } else {
+                    SpawnIssueKind::Overlap
+                };
+                let overlap = spawn_overlap(a, b);
+                if kind == SpawnIssueKind::Duplicate || overlap >= min_overlap {
+                    issues.push(SpawnIssue {
+                        kind,
+                        overlap,
+                        first: (*a).clone(),
+                        second: (*b).clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    issues.sort_by_key(|i| (i.first.z, i.first.y, i.first.x, i.first.line, i.second.line));
+    issues
+}
+
+/// One row per issue: kind, overlap and both entries' line, position and
+/// radius, with monster names where known.
+pub fn generate_spawn_issues_csv(issues: &[SpawnIssue], monster_names: &HashMap<u32, String>) -> String {
+    let mut csv = String::from(
+        "kind,overlap,race,name,z,first_line,first_x,first_y,first_radius,second_line,second_x,second_y,second_radius\n",
+    );
+
+    for issue in issues {
+        let (a, b) = (&issue.first, &issue.second);
+        let name = monster_names.get(&a.race).map(String::as_str).unwrap_or("");
+
+        csv.push_str(&format!(
+            "{},{:.2},{},{},{},{},{},{},{},{},{},{},{}\n",
+            issue.kind.as_str(),
+            issue.overlap,
+            a.race,
+            crate::texts::csv_field(name),
+            a.z,
+            a.line,
+            a.x,
+            a.y,
+            a.radius,
+            b.line,
+            b.x,
+            b.y,
+            b.radius
+        ));
+    }
+
+    csv
+}
+
+/// Intersection of the two spawn circles relative to the smaller one. A
+/// radius-r spawn covers the tiles within r of its center, so each circle is
+/// given r + 0.5 to include the center tile itself.
+fn spawn_overlap(a: &MonsterSpawn, b: &MonsterSpawn) -> f64 {
+    use std::f64::consts::PI;
+
+    let r1 = a.radius as f64 + 0.5;
+    let r2 = b.radius as f64 + 0.5;
+    let d = ((a.x as f64 - b.x as f64).powi(2) + (a.y as f64 - b.y as f64).powi(2)).sqrt();
+    let smaller = r1.min(r2);
+
+    if d >= r1 + r2 {
+        return 0.0;
+    }
+    if d <= (r1 - r2).abs() {
+        return 1.0;
+    }
+
+    let part1 = r1 * r1 * ((d * d + r1 * r1 - r2 * r2) / (2.0 * d * r1)).acos();
+    let part2 = r2 * r2 * ((d * d + r2 * r2 - r1 * r1) / (2.0 * d * r2)).acos();
+    let part3 = 0.5 * ((-d + r1 + r2) * (d + r1 - r2) * (d - r1 + r2) * (d + r1 + r2)).sqrt();
+
+    (part1 + part2 - part3) / (PI * smaller * smaller)
+}
+
+/// Monster population of one `cell_size` x `cell_size` square on one floor.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpawnDensityCell {
+    pub z: u8,
+    pub cell_x: u32,
+    pub cell_y: u32,
+    pub spawns: usize,
+    pub monsters: u32,
+    /// Sum of `amount * 3600 / regen`: how many kills the cell can refill per
+    /// hour if hunted continuously.
+    pub monsters_per_hour: f64,
+    /// Race contributing the most monsters per hour.
+    pub top_race: u32,
+}
+
+#[derive(Default)]
+struct CellTotals {
+    spawns: usize,
+    monsters: u32,
+    per_race: HashMap<u32, f64>,
+}
+
+/// Buckets spawns into square cells per floor, sorted by floor then position.
+pub fn spawn_density(spawns: &[MonsterSpawn], cell_size: u32) -> Vec<SpawnDensityCell> {
+    let cell_size = cell_size.max(1);
+    let mut cells: HashMap<(u8, u32, u32), CellTotals> = HashMap::new();
+
+    for spawn in spawns {
+        let key = (spawn.z, spawn.x / cell_size, spawn.y / cell_size);
+        let totals = cells.entry(key).or_default();
+        totals.spawns += 1;
+        totals.monsters += spawn.amount;
+        *totals.per_race.entry(spawn.race).or_default() +=
+            spawn.amount as f64 * 3600.0 / spawn.regen.max(1) as f64;
+    }
+
+    let mut density: Vec<SpawnDensityCell> = cells
+        .into_iter()
+        .map(|((z, cell_x, cell_y), totals)| {
+            let top_race = totals
+                .per_race
+                .iter()
+                .max_by(|a, b| a.1.total_cmp(b.1).then(b.0.cmp(a.0)))
+                .map(|(race, _)| *race)
+                .unwrap_or(0);
+            SpawnDensityCell {
+                z,
+                cell_x,
+                cell_y,
+                spawns: totals.spawns,
+                monsters: totals.monsters,
+                monsters_per_hour: totals.per_race.values().sum(),
+                top_race,
+            }
+        })
+        .collect();
+
+    density.sort_by_key(|c| (c.z, c.cell_y, c.cell_x));
+    density
+}
+
+/// Experience the spawns of one sector can yield per hour.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HuntingValue {
+    pub z: u8,
+    pub sector_x: u32,
+    pub sector_y: u32,
+    /// Sum of `amount * 3600 / regen * experience`: what hunting the sector
+    /// nonstop would give, if every monster were killed as it respawns.
+    pub exp_per_hour: f64,
+    pub monsters_per_hour: f64,
+    /// Race contributing the most experience per hour.
+    pub top_race: u32,
+}
+
+#[derive(Default)]
+struct SectorTotals {
+    monsters_per_hour: f64,
+    exp_per_race: HashMap<u32, f64>,
+}
+
+/// Rates every sector with spawns whose races give experience, sorted by
+/// floor then position. Races missing from `experience` count as monsters
+/// but add no experience.
+pub fn hunting_values(spawns: &[MonsterSpawn], experience: &HashMap<u32, u32>) -> Vec<HuntingValue> {
+    let mut sectors: HashMap<(u8, u32, u32), SectorTotals> = HashMap::new();
+
+    for spawn in spawns {
+        let per_hour = spawn.amount as f64 * 3600.0 / spawn.regen.max(1) as f64;
+        let totals = sectors.entry((spawn.z, spawn.x / 32, spawn.y / 32)).or_default();
+        totals.monsters_per_hour += per_hour;
+        *totals.exp_per_race.entry(spawn.race).or_default() +=
+            per_hour * experience.get(&spawn.race).copied().unwrap_or(0) as f64;
+    }
+
+    let mut values: Vec<HuntingValue> = sectors
+        .into_iter()
+        .filter_map(|((z, sector_x, sector_y), totals)| {
+            let (&top_race, _) = totals
+                .exp_per_race
+                .iter()
+                .filter(|(_, exp)| **exp > 0.0)
+                .max_by(|a, b| a.1.total_cmp(b.1).then(b.0.cmp(a.0)))?;
+            Some(HuntingValue {
+                z,
+                sector_x,
+                sector_y,
+                exp_per_hour: totals.exp_per_race.values().sum(),
+                monsters_per_hour: totals.monsters_per_hour,
+                top_race,
+            })
+        })
+        .collect();
+
+    values.sort_by_key(|v| (v.z, v.sector_y, v.sector_x));
+    values
+}
+
+/// The viewer's hunting value overlay: rated sectors per floor, plus the
+/// best rating on any floor so all floors share one color scale.
+pub fn generate_hunting_json(
+    values: &[HuntingValue],
+    floors: &[u8],
+    monster_names: &HashMap<u32, String>,
+) -> Result<String> {
+    let mut hunting_by_floor: BTreeMap<u8, Vec<serde_json::Value>> = BTreeMap::new();
+    let mut max_exp_per_hour = 0u64;
+
+    for value in values.iter().filter(|v| floors.contains(&v.z)) {
+        let exp_per_hour = value.exp_per_hour.round() as u64;
+        max_exp_per_hour = max_exp_per_hour.max(exp_per_hour);
+        hunting_by_floor.entry(value.z).or_default().push(serde_json::json!({
+            "sector_x": value.sector_x,
+            "sector_y": value.sector_y,
+            "exp_per_hour": exp_per_hour,
+            "monsters_per_hour": (value.monsters_per_hour * 10.0).round() / 10.0,
+            "top_race": value.top_race,
+            "top_monster": monster_names.get(&value.top_race)
+        }));
+    }
+
+    let output = serde_json::json!({
+        "hunting_by_floor": hunting_by_floor,
+        "max_exp_per_hour": max_exp_per_hour
+    });
+
+    serde_json::to_string(&output).with_context(|| "Failed to serialize hunting values to JSON")
+}
+
+pub fn generate_spawn_density_csv(
+    cells: &[SpawnDensityCell],
+    cell_size: u32,
+    monster_names: &HashMap<u32, String>,
+) -> String {
+    let mut csv = String::from("z,x_min,y_min,x_max,y_max,spawns,monsters,monsters_per_hour,top_race,top_monster\n");
+
+    for cell in cells {
+        let x_min = cell.cell_x * cell_size;
+        let y_min = cell.cell_y * cell_size;
+        let name = monster_names.get(&cell.top_race).map(String::as_str).unwrap_or("");
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{:.1},{},{}\n",
+            cell.z,
+            x_min,
+            y_min,
+            x_min + cell_size - 1,
+            y_min + cell_size - 1,
+            cell.spawns,
+            cell.monsters,
+            cell.monsters_per_hour,
+            cell.top_race,
+            crate::texts::csv_field(name)
+        ));
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(race: u32, x: u32, y: u32, radius: u32, line: usize) -> MonsterSpawn {
+        MonsterSpawn {
+            race,
+            x,
+            y,
+            z: 7,
+            radius,
+            amount: 1,
+            regen: 60,
+            line,
+        }
+    }
+
+    #[test]
+    fn test_find_spawn_issues() {
+        let spawns = [
+            spawn(11, 32000, 32000, 3, 1),
+            spawn(11, 32000, 32000, 2, 2), // duplicate of line 1
+            spawn(11, 32001, 32000, 3, 3), // nearly the same area as line 1
+            spawn(11, 32010, 32000, 3, 4), // far enough away
+            spawn(12, 32000, 32000, 3, 5), // different race
+        ];
+
+        let issues = find_spawn_issues(&spawns, 0.8);
+        let pairs: Vec<(SpawnIssueKind, usize, usize)> = issues
+            .iter()
+            .map(|i| (i.kind, i.first.line, i.second.line))
+            .collect();
+
+        assert_eq!(
+            pairs,
+            vec![
+                (SpawnIssueKind::Duplicate, 1, 2),
+                (SpawnIssueKind::Overlap, 1, 3),
+                (SpawnIssueKind::Overlap, 2, 3),
+            ]
+        );
+        assert_eq!(spawn_overlap(&spawns[0], &spawns[3]), 0.0);
+    }
+
+    #[test]
+    fn test_parse_mon_stats() {
+        let content = "RaceNumber    = 2\nName          = \"orc\"\nExperience    = 25\nSummonCost    = 300\nConvinceCost  = 300\n\
+            Flags         = {KickBoxes, NoConvince}\n\
+            Skills        = {(HitPoints, 70, 0, 70, 0, 0, 0),\n                 (GoStrength, 60, 0, 60, 0, 0, 0)}\n\
+            Inventory     = {(3031, 20, 300),\n                 (3307, 1, 80)}\n";
+        let (race, stats) = parse_mon_stats(content).unwrap();
+        assert_eq!(race, 2);
+        assert_eq!(stats.name, "orc");
+        assert_eq!((stats.hit_points, stats.experience), (Some(70), Some(25)));
+        assert_eq!(stats.flags, ["KickBoxes", "NoConvince"]);
+        assert!(stats.is_summonable());
+        assert!(!stats.is_convinceable());
+        assert_eq!(stats.loot.len(), 2);
+        assert_eq!(stats.loot[1], LootEntry { item_id: 3307, max_count: 1, chance: 80 });
+
+        let (_, plain) = parse_mon_stats("RaceNumber = 11\nName = \"hunter\"\nHitPoints = 150\n").unwrap();
+        assert_eq!((plain.hit_points, plain.experience, plain.summon_cost), (Some(150), None, 0));
+        assert!(parse_mon_stats("Name = \"nameless\"\n").is_none());
+    }
+
+    #[test]
+    fn test_parse_outfit() {
+        assert_eq!(
+            parse_outfit("(130, 78-69-58-76)"),
+            Some(MonsterLook::Outfit { look_type: 130, colors: [78, 69, 58, 76] })
+        );
+        assert_eq!(parse_outfit("(0, 3031)"), Some(MonsterLook::Item(3031)));
+        assert_eq!(parse_outfit("(130, 78-69-58)"), None);
+        assert_eq!(parse_outfit("130, 78-69-58-76"), None);
+    }
+
+    #[test]
+    fn test_hunting_values_weight_experience_by_regen() {
+        let mut orcs = spawn(2, 32010, 32010, 3, 1);
+        orcs.amount = 6;
+        orcs.regen = 600;
+        let dragon = spawn(34, 32020, 32030, 3, 2);
+        let rats = spawn(21, 32040, 32000, 3, 3);
+
+        let experience: HashMap<u32, u32> = [(2, 25), (34, 700)].into();
+        let values = hunting_values(&[orcs, dragon, rats], &experience);
+
+        // 6 orcs every 10 minutes and a dragon every minute; the rats' sector
+        // gives no experience and is left out
+        assert_eq!(values.len(), 1);
+        assert_eq!((values[0].sector_x, values[0].sector_y), (1000, 1000));
+        assert_eq!(values[0].exp_per_hour, 36.0 * 25.0 + 60.0 * 700.0);
+        assert_eq!(values[0].monsters_per_hour, 96.0);
+        assert_eq!(values[0].top_race, 34);
+    }
+
+    #[test]
+    fn test_spawn_density_weights_by_regen() {
+        let mut fast = spawn(11, 32050, 32050, 3, 1);
+        fast.amount = 4;
+        fast.regen = 60;
+        let mut slow = spawn(12, 32099, 32000, 3, 2);
+        slow.amount = 10;
+        slow.regen = 3600;
+        let elsewhere = spawn(11, 32100, 32000, 3, 3);
+
+        let cells = spawn_density(&[fast, slow, elsewhere], 100);
+        assert_eq!(cells.len(), 2);
+        assert_eq!((cells[0].cell_x, cells[0].cell_y), (320, 320));
+        assert_eq!(cells[0].spawns, 2);
+        assert_eq!(cells[0].monsters, 14);
+        assert_eq!(cells[0].monsters_per_hour, 250.0);
+        assert_eq!(cells[0].top_race, 11);
+    }
+}
@@ -1,7 +1,7 @@
-use anyhow::{Context, Result};
+use crate::{read_text, InputEncoding};
+use crate::error::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs;
 use std::path::Path;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,8 +14,8 @@ pub struct NpcLocation {
     pub z: u8,
 }
 
-pub fn parse_npc_csv<P: AsRef<Path>>(csv_path: P) -> Result<Vec<NpcLocation>> {
-    let content = fs::read_to_string(csv_path.as_ref())
+pub fn parse_npc_csv<P: AsRef<Path>>(csv_path: P, encoding: InputEncoding) -> Result<Vec<NpcLocation>> {
+    let content = read_text(csv_path.as_ref(), encoding)
         .with_context(|| format!("Failed to read NPC CSV: {:?}", csv_path.as_ref()))?;
 
     let mut npcs = Vec::new();
@@ -117,7 +117,7 @@ pub fn generate_npc_json(npcs: &[NpcLocation], floors: &[u8]) -> Result<String>
 
             npcs_by_floor
                 .entry(npc.z)
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(npc_output);
         }
     }
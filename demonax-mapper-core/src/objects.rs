@@ -1,24 +1,385 @@
-use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs;
+use crate::{read_text, InputEncoding, SpriteLayer};
+use crate::error::{Context, Result};
+use bitflags::bitflags;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 
+bitflags! {
+    /// Flags from the `Flags = {...}` line of objects.srv.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct ObjectFlags: u64 {
+        const BANK = 1 << 0;
+        const CLIP = 1 << 1;
+        const BOTTOM = 1 << 2;
+        const TOP = 1 << 3;
+        const CONTAINER = 1 << 4;
+        const CHEST = 1 << 5;
+        const CUMULATIVE = 1 << 6;
+        const USE_EVENT = 1 << 7;
+        const CHANGE_USE = 1 << 8;
+        const FORCE_USE = 1 << 9;
+        const MULTI_USE = 1 << 10;
+        const DIST_USE = 1 << 11;
+        const MOVEMENT_EVENT = 1 << 12;
+        const COLLISION_EVENT = 1 << 13;
+        const SEPARATION_EVENT = 1 << 14;
+        const KEY = 1 << 15;
+        const KEY_DOOR = 1 << 16;
+        const NAME_DOOR = 1 << 17;
+        const LEVEL_DOOR = 1 << 18;
+        const QUEST_DOOR = 1 << 19;
+        const BED = 1 << 20;
+        const FOOD = 1 << 21;
+        const RUNE = 1 << 22;
+        const INFORMATION = 1 << 23;
+        const TEXT = 1 << 24;
+        const WRITE = 1 << 25;
+        const WRITE_ONCE = 1 << 26;
+        const LIQUID_CONTAINER = 1 << 27;
+        const LIQUID_SOURCE = 1 << 28;
+        const LIQUID_POOL = 1 << 29;
+        const UNPASS = 1 << 30;
+        const UNMOVE = 1 << 31;
+        const UNTHROW = 1 << 32;
+        const UNLAY = 1 << 33;
+        const AVOID = 1 << 34;
+        const MAGIC_FIELD = 1 << 35;
+        const RESTRICT_LEVEL = 1 << 36;
+        const RESTRICT_PROFESSION = 1 << 37;
+        const TAKE = 1 << 38;
+        const HANG = 1 << 39;
+        const HOOK_SOUTH = 1 << 40;
+        const HOOK_EAST = 1 << 41;
+        const ROTATE = 1 << 42;
+        const DESTROY = 1 << 43;
+        const CLOTHES = 1 << 44;
+        const SKILL_BOOST = 1 << 45;
+        const PROTECTION = 1 << 46;
+        const LIGHT = 1 << 47;
+        const ROPE_SPOT = 1 << 48;
+        const CORPSE = 1 << 49;
+        const EXPIRE = 1 << 50;
+        const EXPIRE_STOP = 1 << 51;
+        const WEAR_OUT = 1 << 52;
+        const WEAPON = 1 << 53;
+        const SHIELD = 1 << 54;
+        const BOW = 1 << 55;
+        const THROW = 1 << 56;
+        const WAND = 1 << 57;
+        const AMMO = 1 << 58;
+        const ARMOR = 1 << 59;
+        const HEIGHT = 1 << 60;
+        const DISGUISE = 1 << 61;
+        const SHOW_DETAIL = 1 << 62;
+        const SPECIAL_OBJECT = 1 << 63;
+    }
+}
+
+/// Spelling of each flag as it appears in objects.srv.
+const FLAG_NAMES: &[(&str, ObjectFlags)] = &[
+    ("Bank", ObjectFlags::BANK),
+    ("Clip", ObjectFlags::CLIP),
+    ("Bottom", ObjectFlags::BOTTOM),
+    ("Top", ObjectFlags::TOP),
+    ("Container", ObjectFlags::CONTAINER),
+    ("Chest", ObjectFlags::CHEST),
+    ("Cumulative", ObjectFlags::CUMULATIVE),
+    ("UseEvent", ObjectFlags::USE_EVENT),
+    ("ChangeUse", ObjectFlags::CHANGE_USE),
+    ("ForceUse", ObjectFlags::FORCE_USE),
+    ("MultiUse", ObjectFlags::MULTI_USE),
+    ("DistUse", ObjectFlags::DIST_USE),
+    ("MovementEvent", ObjectFlags::MOVEMENT_EVENT),
+    ("CollisionEvent", ObjectFlags::COLLISION_EVENT),
+    ("SeparationEvent", ObjectFlags::SEPARATION_EVENT),
+    ("Key", ObjectFlags::KEY),
+    ("KeyDoor", ObjectFlags::KEY_DOOR),
+    ("NameDoor", ObjectFlags::NAME_DOOR),
+    ("LevelDoor", ObjectFlags::LEVEL_DOOR),
+    ("QuestDoor", ObjectFlags::QUEST_DOOR),
+    ("Bed", ObjectFlags::BED),
+    ("Food", ObjectFlags::FOOD),
+    ("Rune", ObjectFlags::RUNE),
+    ("Information", ObjectFlags::INFORMATION),
+    ("Text", ObjectFlags::TEXT),
+    ("Write", ObjectFlags::WRITE),
+    ("WriteOnce", ObjectFlags::WRITE_ONCE),
+    ("LiquidContainer", ObjectFlags::LIQUID_CONTAINER),
+    ("LiquidSource", ObjectFlags::LIQUID_SOURCE),
+    ("LiquidPool", ObjectFlags::LIQUID_POOL),
+    ("Unpass", ObjectFlags::UNPASS),
+    ("Unmove", ObjectFlags::UNMOVE),
+    ("Unthrow", ObjectFlags::UNTHROW),
+    ("Unlay", ObjectFlags::UNLAY),
+    ("Avoid", ObjectFlags::AVOID),
+    ("MagicField", ObjectFlags::MAGIC_FIELD),
+    ("RestrictLevel", ObjectFlags::RESTRICT_LEVEL),
+    ("RestrictProfession", ObjectFlags::RESTRICT_PROFESSION),
+    ("Take", ObjectFlags::TAKE),
+    ("Hang", ObjectFlags::HANG),
+    ("HookSouth", ObjectFlags::HOOK_SOUTH),
+    ("HookEast", ObjectFlags::HOOK_EAST),
+    ("Rotate", ObjectFlags::ROTATE),
+    ("Destroy", ObjectFlags::DESTROY),
+    ("Clothes", ObjectFlags::CLOTHES),
+    ("SkillBoost", ObjectFlags::SKILL_BOOST),
+    ("Protection", ObjectFlags::PROTECTION),
+    ("Light", ObjectFlags::LIGHT),
+    ("RopeSpot", ObjectFlags::ROPE_SPOT),
+    ("Corpse", ObjectFlags::CORPSE),
+    ("Expire", ObjectFlags::EXPIRE),
+    ("ExpireStop", ObjectFlags::EXPIRE_STOP),
+    ("WearOut", ObjectFlags::WEAR_OUT),
+    ("Weapon", ObjectFlags::WEAPON),
+    ("Shield", ObjectFlags::SHIELD),
+    ("Bow", ObjectFlags::BOW),
+    ("Throw", ObjectFlags::THROW),
+    ("Wand", ObjectFlags::WAND),
+    ("Ammo", ObjectFlags::AMMO),
+    ("Armor", ObjectFlags::ARMOR),
+    ("Height", ObjectFlags::HEIGHT),
+    ("Disguise", ObjectFlags::DISGUISE),
+    ("ShowDetail", ObjectFlags::SHOW_DETAIL),
+    ("SpecialObject", ObjectFlags::SPECIAL_OBJECT),
+];
+
+impl ObjectFlags {
+    pub fn from_srv_name(name: &str) -> Option<Self> {
+        FLAG_NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, flag)| *flag)
+    }
+
+    /// Parses a comma-separated flag list, returning unrecognised names separately.
+    pub fn parse_list(list: &str) -> (Self, Vec<String>) {
+        let mut flags = Self::empty();
+        let mut unknown = Vec::new();
+
+        for name in list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match Self::from_srv_name(name) {
+                Some(flag) => flags |= flag,
+                None => unknown.push(name.to_string()),
+            }
+        }
+
+        (flags, unknown)
+    }
+
+    /// objects.srv names of the set flags, in declaration order.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        FLAG_NAMES
+            .iter()
+            .filter(|(_, flag)| self.contains(*flag))
+            .map(|(name, _)| *name)
+    }
+
+    pub fn is_takeable(&self) -> bool {
+        self.contains(Self::TAKE)
+    }
+
+    pub fn is_container(&self) -> bool {
+        self.intersects(Self::CONTAINER | Self::CHEST)
+    }
+
+    pub fn is_bank(&self) -> bool {
+        self.contains(Self::BANK)
+    }
+
+    pub fn is_clip(&self) -> bool {
+        self.contains(Self::CLIP)
+    }
+
+    pub fn is_top(&self) -> bool {
+        self.contains(Self::TOP)
+    }
+
+    pub fn is_bottom(&self) -> bool {
+        self.contains(Self::BOTTOM)
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.contains(Self::TEXT)
+    }
+
+    pub fn is_unpassable(&self) -> bool {
+        self.contains(Self::UNPASS)
+    }
+}
+
+// Serialized as the objects.srv names so the JSON cache stays readable and
+// keeps the same shape as the old `Vec<String>` representation.
+impl Serialize for ObjectFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.names())
+    }
+}
+
+impl<'de> Deserialize<'de> for ObjectFlags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        Ok(names
+            .iter()
+            .filter_map(|name| Self::from_srv_name(name))
+            .fold(Self::empty(), |acc, flag| acc | flag))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameObject {
     pub id: u32,
     pub name: String,
-    pub flags: Vec<String>,
+    pub flags: ObjectFlags,
+    /// Flag names not known to [`ObjectFlags`], kept so nothing is lost on round-trip.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unknown_flags: Vec<String>,
     pub waypoints: u32,
     pub is_ground: bool,
     pub is_impassable: bool,
     pub disguise_target: Option<u32>,
+    /// Raw `Attributes = {...}` entries, values unparsed.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+    /// Layer set by [`apply_layer_rules`]; the flags decide when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layer: Option<SpriteLayer>,
+}
+
+/// A world coordinate, as used by teleport destinations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Position {
+    pub x: u32,
+    pub y: u32,
+    pub z: u8,
+}
+
+impl Position {
+    /// Accepts `[x,y,z]`, `{x,y,z}` or bare `x,y,z`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let inner = value.trim().trim_matches(|c| matches!(c, '[' | ']' | '{' | '}' | '(' | ')'));
+        let mut parts = inner.split(',').map(str::trim);
+        let x = parts.next()?.parse().ok()?;
+        let y = parts.next()?.parse().ok()?;
+        let z = parts.next()?.parse().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self { x, y, z })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LiquidType {
+    None,
+    Water,
+    Wine,
+    Beer,
+    Mud,
+    Blood,
+    Slime,
+    Oil,
+    Urine,
+    Milk,
+    ManaFluid,
+    LifeFluid,
+    Lemonade,
+}
+
+impl LiquidType {
+    /// Accepts either the numeric liquid code or its name (case-insensitive).
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().trim_matches('"');
+        if let Ok(code) = value.parse::<u32>() {
+            return Self::from_code(code);
+        }
+
+        let liquid = match value.to_ascii_lowercase().as_str() {
+            "none" => Self::None,
+            "water" => Self::Water,
+            "wine" => Self::Wine,
+            "beer" => Self::Beer,
+            "mud" => Self::Mud,
+            "blood" => Self::Blood,
+            "slime" => Self::Slime,
+            "oil" => Self::Oil,
+            "urine" => Self::Urine,
+            "milk" => Self::Milk,
+            "manafluid" => Self::ManaFluid,
+            "lifefluid" => Self::LifeFluid,
+            "lemonade" => Self::Lemonade,
+            _ => return None,
+        };
+        Some(liquid)
+    }
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        let liquid = match code {
+            0 => Self::None,
+            1 => Self::Water,
+            2 => Self::Wine,
+            3 => Self::Beer,
+            4 => Self::Mud,
+            5 => Self::Blood,
+            6 => Self::Slime,
+            7 => Self::Oil,
+            8 => Self::Urine,
+            9 => Self::Milk,
+            10 => Self::ManaFluid,
+            11 => Self::LifeFluid,
+            12 => Self::Lemonade,
+            _ => return None,
+        };
+        Some(liquid)
+    }
+}
+
+impl GameObject {
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(String::as_str)
+    }
+
+    pub fn attribute_u32(&self, key: &str) -> Option<u32> {
+        self.attribute(key)?.trim().parse().ok()
+    }
+
+    pub fn brightness(&self) -> Option<u32> {
+        self.attribute_u32("Brightness")
+    }
+
+    pub fn elevation(&self) -> Option<u32> {
+        self.attribute_u32("Elevation")
+    }
+
+    pub fn capacity(&self) -> Option<u32> {
+        self.attribute_u32("Capacity")
+    }
+
+    pub fn teleport_destination(&self) -> Option<Position> {
+        self.attribute("TeleportDest")
+            .or_else(|| self.attribute("TeleportDestination"))
+            .and_then(Position::parse)
+    }
+
+    pub fn liquid_type(&self) -> Option<LiquidType> {
+        self.attribute("LiquidType")
+            .or_else(|| self.attribute("SourceLiquidType"))
+            .and_then(LiquidType::parse)
+    }
+
+    /// Ground tiles that count as open water: named like water, or a water
+    /// liquid source.
+    pub fn is_water(&self) -> bool {
+        (self.is_ground || self.flags.is_bank())
+            && (self.name.to_lowercase().contains("water")
+                || self.liquid_type() == Some(LiquidType::Water))
+    }
 }
 
 pub type ObjectDatabase = HashMap<u32, GameObject>;
 
-pub fn parse_objects<P: AsRef<Path>>(path: P) -> Result<ObjectDatabase> {
-    let content = fs::read_to_string(path.as_ref())
+pub fn parse_objects<P: AsRef<Path>>(path: P, encoding: InputEncoding) -> Result<ObjectDatabase> {
+    let content = read_text(path.as_ref(), encoding)
         .with_context(|| format!("Failed to read objects file: {:?}", path.as_ref()))?;
 
     let lines: Vec<&str> = content.lines().collect();
@@ -44,12 +405,29 @@ pub fn parse_objects<P: AsRef<Path>>(path: P) -> Result<ObjectDatabase> {
     Ok(objects)
 }
 
+/// Layers server-specific definitions over `base`: new ids are added and
+/// existing ids are replaced outright. Returns `(added, overridden)`.
+pub fn merge_objects(base: &mut ObjectDatabase, extra: ObjectDatabase) -> (usize, usize) {
+    let mut added = 0;
+    let mut overridden = 0;
+
+    for (id, obj) in extra {
+        if base.insert(id, obj).is_some() {
+            overridden += 1;
+        } else {
+            added += 1;
+        }
+    }
+
+    (added, overridden)
+}
+
 fn parse_object_block(lines: &[&str]) -> Result<GameObject> {
     let mut id = 0;
     let mut name = String::new();
-    let mut flags = Vec::new();
-    let mut waypoints = 0;
-    let mut disguise_target = None;
+    let mut flags = ObjectFlags::empty();
+    let mut unknown_flags = Vec::new();
+    let mut attributes = BTreeMap::new();
 
     for line in lines {
         let line = line.trim();
@@ -60,7 +438,7 @@ fn parse_object_block(lines: &[&str]) -> Result<GameObject> {
         if let Some(value) = line.strip_prefix("TypeID") {
             let value = value.trim().trim_start_matches('=').trim();
             let value = value.split('#').next().unwrap_or(value).trim();
-            id = value.parse().context("Failed to parse TypeID")?;
+            id = value.parse::<u32>().context("Failed to parse TypeID")?;
         } else if let Some(value) = line.strip_prefix("Name") {
             name = value
                 .trim()
@@ -71,19 +449,22 @@ fn parse_object_block(lines: &[&str]) -> Result<GameObject> {
         } else if let Some(value) = line.strip_prefix("Flags") {
             let value = value.trim().trim_start_matches('=').trim();
             let value = value.trim_matches(|c| c == '{' || c == '}');
-            flags = value.split(',').map(|s| s.trim().to_string()).collect();
+            (flags, unknown_flags) = ObjectFlags::parse_list(value);
         } else if let Some(value) = line.strip_prefix("Attributes") {
             let value = value.trim().trim_start_matches('=').trim();
-            if let Some(wp) = extract_waypoints(value) {
-                waypoints = wp;
-            }
-            if let Some(dt) = extract_disguise_target(value) {
-                disguise_target = Some(dt);
-            }
+            attributes.extend(parse_attribute_list(value));
         }
     }
 
-    let has_unpass = flags.iter().any(|f| f == "Unpass");
+    let waypoints = attributes
+        .get("Waypoints")
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(0);
+    let disguise_target = attributes
+        .get("DisguiseTarget")
+        .and_then(|v| v.trim().parse().ok());
+
+    let has_unpass = flags.is_unpassable();
     let is_ground = waypoints > 0 && !has_unpass;
     let is_impassable = has_unpass || waypoints == 0;
 
@@ -91,25 +472,155 @@ fn parse_object_block(lines: &[&str]) -> Result<GameObject> {
         id,
         name,
         flags,
+        unknown_flags,
         waypoints,
         is_ground,
         is_impassable,
         disguise_target,
+        attributes,
+        layer: None,
     })
 }
 
-fn extract_waypoints(attributes: &str) -> Option<u32> {
-    attributes
-        .split(',')
-        .find(|s| s.contains("Waypoints"))
-        .and_then(|s| s.split('=').nth(1))
-        .and_then(|s| s.trim().trim_matches('}').parse().ok())
+/// Splits `{Key=Value,Key=[1,2,3],Key="a, b"}` into key/value pairs, keeping
+/// commas inside brackets or quotes as part of the value.
+fn parse_attribute_list(list: &str) -> Vec<(String, String)> {
+    let list = list.trim();
+    let list = list.strip_prefix('{').unwrap_or(list);
+    let list = list.strip_suffix('}').unwrap_or(list);
+
+    let mut entries = Vec::new();
+    let mut depth = 0i32;
+    let mut in_quotes = false;
+    let mut start = 0;
+
+    let mut push_entry = |entry: &str| {
+        if let Some((key, value)) = entry.split_once('=') {
+            let key = key.trim();
+            if !key.is_empty() {
+                entries.push((key.to_string(), value.trim().to_string()));
+            }
+        }
+    };
+
+    for (i, c) in list.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '[' | '{' | '(' if !in_quotes => depth += 1,
+            ']' | '}' | ')' if !in_quotes => depth -= 1,
+            ',' if !in_quotes && depth == 0 => {
+                push_entry(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    push_entry(&list[start..]);
+
+    entries
 }
 
-fn extract_disguise_target(attributes: &str) -> Option<u32> {
-    attributes
-        .split(',')
-        .find(|s| s.contains("DisguiseTarget"))
-        .and_then(|s| s.split('=').nth(1))
-        .and_then(|s| s.trim().trim_matches('}').parse().ok())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_flag_list_keeps_unknown_names() {
+        let (flags, unknown) = ObjectFlags::parse_list("Bank, Unpass,Sparkle");
+        assert_eq!(flags, ObjectFlags::BANK | ObjectFlags::UNPASS);
+        assert_eq!(unknown, vec!["Sparkle".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_empty_flag_list() {
+        let (flags, unknown) = ObjectFlags::parse_list("");
+        assert!(flags.is_empty());
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn test_flags_serialize_as_names() {
+        let flags = ObjectFlags::TAKE | ObjectFlags::CHEST;
+        let json = serde_json::to_string(&flags).unwrap();
+        assert_eq!(json, r#"["Chest","Take"]"#);
+        let back: ObjectFlags = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, flags);
+    }
+
+    #[test]
+    fn test_parse_object_block() {
+        let lines = [
+            "TypeID      = 2543",
+            "Name        = \"chest\"",
+            "Flags       = {Container,Unmove,Sparkle}",
+            "Attributes  = {Capacity=10,DisguiseTarget=2544}",
+        ];
+        let obj = parse_object_block(&lines).unwrap();
+        assert_eq!(obj.id, 2543);
+        assert!(obj.flags.is_container());
+        assert!(!obj.flags.is_takeable());
+        assert_eq!(obj.unknown_flags, vec!["Sparkle".to_string()]);
+        assert_eq!(obj.disguise_target, Some(2544));
+    }
+
+    #[test]
+    fn test_merge_objects_adds_and_overrides() {
+        let mut base: ObjectDatabase = [
+            (100, parse_object_block(&["TypeID = 100", "Name = \"grass\""]).unwrap()),
+            (101, parse_object_block(&["TypeID = 101", "Name = \"water\""]).unwrap()),
+        ]
+        .into();
+        let extra: ObjectDatabase = [
+            (101, parse_object_block(&["TypeID = 101", "Name = \"frozen water\""]).unwrap()),
+            (9000, parse_object_block(&["TypeID = 9000", "Name = \"pumpkin\""]).unwrap()),
+        ]
+        .into();
+
+        assert_eq!(merge_objects(&mut base, extra), (1, 1));
+        assert_eq!(base[&101].name, "frozen water");
+        assert_eq!(base[&9000].name, "pumpkin");
+        assert_eq!(base.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_attribute_list_respects_brackets_and_quotes() {
+        let entries = parse_attribute_list(r#"{Waypoints=150,TeleportDest=[32000,31000,7],Text="a, b"}"#);
+        assert_eq!(
+            entries,
+            vec![
+                ("Waypoints".to_string(), "150".to_string()),
+                ("TeleportDest".to_string(), "[32000,31000,7]".to_string()),
+                ("Text".to_string(), r#""a, b""#.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_typed_attribute_accessors() {
+        let lines = [
+            "TypeID      = 1387",
+            "Name        = \"magic forcefield\"",
+            "Flags       = {Unmove}",
+            "Attributes  = {Waypoints=0,Brightness=3,Elevation=8,TeleportDest=[32000,31000,7],LiquidType=mud}",
+        ];
+        let obj = parse_object_block(&lines).unwrap();
+        assert_eq!(obj.waypoints, 0);
+        assert_eq!(obj.brightness(), Some(3));
+        assert_eq!(obj.elevation(), Some(8));
+        assert_eq!(obj.capacity(), None);
+        assert_eq!(obj.teleport_destination(), Some(Position { x: 32000, y: 31000, z: 7 }));
+        assert_eq!(obj.liquid_type(), Some(LiquidType::Mud));
+    }
+
+    #[test]
+    fn test_liquid_type_from_code() {
+        assert_eq!(LiquidType::parse("5"), Some(LiquidType::Blood));
+        assert_eq!(LiquidType::parse("42"), None);
+    }
+
+    #[test]
+    fn test_position_parse() {
+        assert_eq!(Position::parse("32000, 31000, 7"), Some(Position { x: 32000, y: 31000, z: 7 }));
+        assert_eq!(Position::parse("[1,2]"), None);
+    }
 }
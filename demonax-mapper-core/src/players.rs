@@ -0,0 +1,127 @@
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// An online player as reported by the game server's status feed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerPosition {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub z: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vocation: Option<String>,
+}
+
+/// Accepts either a bare JSON array of players or `{"players": [...]}`.
+pub fn parse_player_positions(body: &str) -> Result<Vec<PlayerPosition>> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Feed {
+        List(Vec<PlayerPosition>),
+        Wrapped { players: Vec<PlayerPosition> },
+    }
+
+    let feed: Feed = serde_json::from_str(body).with_context(|| "Failed to parse player positions")?;
+    Ok(match feed {
+        Feed::List(players) | Feed::Wrapped { players } => players,
+    })
+}
+
+/// Formats the current player list as a Server-Sent Events `players` event.
+pub fn players_sse_event(players: &[PlayerPosition]) -> Result<String> {
+    let data = serde_json::to_string(&serde_json::json!({ "players": players }))
+        .with_context(|| "Failed to serialize player positions to JSON")?;
+    Ok(format!("event: players\ndata: {}\n\n", data))
+}
+
+#[derive(Default)]
+struct FeedState {
+    version: u64,
+    players: Vec<PlayerPosition>,
+}
+
+/// Latest known player positions, shared between whatever receives updates
+/// (an ingest endpoint or an upstream poller) and the SSE connections that
+/// push them to viewers.
+#[derive(Default)]
+pub struct PlayerFeed {
+    state: Mutex<FeedState>,
+    changed: Condvar,
+}
+
+impl PlayerFeed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&self, players: Vec<PlayerPosition>) {
+        let mut state = self.state.lock().unwrap();
+        state.version += 1;
+        state.players = players;
+        self.changed.notify_all();
+    }
+
+    pub fn snapshot(&self) -> (u64, Vec<PlayerPosition>) {
+        let state = self.state.lock().unwrap();
+        (state.version, state.players.clone())
+    }
+
+    /// Blocks until the feed moves past `seen_version`, or returns `None` after
+    /// `timeout` so callers can send a keep-alive.
+    pub fn wait_for_update(&self, seen_version: u64, timeout: Duration) -> Option<(u64, Vec<PlayerPosition>)> {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self
+            .changed
+            .wait_timeout_while(state, timeout, |s| s.version == seen_version)
+            .unwrap();
+
+        (state.version != seen_version).then(|| (state.version, state.players.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_player_positions_accepts_both_shapes() {
+        let list = parse_player_positions(r#"[{"name":"Bob","x":32000,"y":32001,"z":7}]"#).unwrap();
+        let wrapped = parse_player_positions(
+            r#"{"players":[{"name":"Bob","x":32000,"y":32001,"z":7,"level":20}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(list[0].name, "Bob");
+        assert_eq!(wrapped[0].level, Some(20));
+        assert!(parse_player_positions(r#"{"online":3}"#).is_err());
+    }
+
+    #[test]
+    fn test_feed_wakes_waiters_on_update() {
+        let feed = Arc::new(PlayerFeed::new());
+        assert!(feed.wait_for_update(0, Duration::from_millis(10)).is_none());
+
+        let writer = Arc::clone(&feed);
+        let handle = std::thread::spawn(move || {
+            writer.update(vec![PlayerPosition {
+                name: "Bob".to_string(),
+                x: 1,
+                y: 2,
+                z: 7,
+                level: None,
+                vocation: None,
+            }]);
+        });
+
+        let (version, players) = feed.wait_for_update(0, Duration::from_secs(5)).unwrap();
+        handle.join().unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(players.len(), 1);
+        assert!(players_sse_event(&players).unwrap().starts_with("event: players\ndata: {"));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{GameObject, ObjectDatabase, SpriteMapData};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Tiles of one kind at most this many tiles apart (in x and y) make up one
+/// point of interest, so a depot with a dozen lockers gets a single marker.
+const CLUSTER_DISTANCE: u32 = 4;
+
+/// A city service players look for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoiKind {
+    Depot,
+    Temple,
+    Mailbox,
+    Bank,
+}
+
+impl PoiKind {
+    /// Recognises services by object name, as objects.srv has no flags for
+    /// them. Temples are found by their altars and shrines.
+    pub fn of(object: &GameObject) -> Option<Self> {
+        let name = object.name.to_lowercase();
+        if name.contains("depot") || name.contains("locker") {
+            Some(Self::Depot)
+        } else if name.contains("mailbox") {
+            Some(Self::Mailbox)
+        } else if name.contains("altar") || name.contains("shrine") {
+            Some(Self::Temple)
+        } else if name.contains("bank counter") || name.contains("money counter") {
+            Some(Self::Bank)
+        } else {
+            None
+        }
+    }
+}
+
+/// One service on the map. `(x, y)` is the center of its `tiles` matching
+/// tiles, rounded down.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PointOfInterest {
+    pub kind: PoiKind,
+    pub x: u32,
+    pub y: u32,
+    pub floor: u8,
+    pub tiles: usize,
+}
+
+/// Every point of interest on a floor. Each tile counts once, by its topmost
+/// recognised object, and nearby tiles of the same kind are merged.
+pub fn find_points_of_interest(map_data: &SpriteMapData, objects: &ObjectDatabase) -> Vec<PointOfInterest> {
+    let bounds = map_data.bounds();
+
+    let mut found: Vec<(PoiKind, u32, u32)> = map_data
+        .tiles
+        .iter()
+        .filter_map(|tile| {
+            let kind = tile.object_ids.iter().rev().find_map(|id| PoiKind::of(objects.get(id)?))?;
+            let (x, y) = bounds.map_to_world(tile.x, tile.y);
+            Some((kind, x, y))
+        })
+        .collect();
+    found.sort_by_key(|&(kind, x, y)| (kind, y, x));
+
+    let mut clusters: Vec<(PoiKind, Vec<(u32, u32)>)> = Vec::new();
+    for (kind, x, y) in found {
+        let near = |&(cx, cy): &(u32, u32)| cx.abs_diff(x) <= CLUSTER_DISTANCE && cy.abs_diff(y) <= CLUSTER_DISTANCE;
+        match clusters.iter_mut().find(|(k, tiles)| *k == kind && tiles.iter().any(near)) {
+            Some((_, tiles)) => tiles.push((x, y)),
+            None => clusters.push((kind, vec![(x, y)])),
+        }
+    }
+
+    let mut points: Vec<PointOfInterest> = clusters
+        .into_iter()
+        .map(|(kind, tiles)| {
+            let count = tiles.len() as u64;
+            let x = tiles.iter().map(|&(x, _)| x as u64).sum::<u64>() / count;
+            let y = tiles.iter().map(|&(_, y)| y as u64).sum::<u64>() / count;
+            PointOfInterest { kind, x: x as u32, y: y as u32, floor: map_data.floor, tiles: tiles.len() }
+        })
+        .collect();
+
+    points.sort_by_key(|p| (p.y, p.x));
+    points
+}
+
+pub fn generate_poi_json(points: &[PointOfInterest]) -> Result<String> {
+    let mut poi_by_floor: BTreeMap<u8, Vec<&PointOfInterest>> = BTreeMap::new();
+    for point in points {
+        poi_by_floor.entry(point.floor).or_default().push(point);
+    }
+
+    let output = serde_json::json!({
+        "poi_by_floor": poi_by_floor
+    });
+
+    serde_json::to_string(&output).with_context(|| "Failed to serialize points of interest to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ObjectFlags, TileStack};
+
+    fn object(id: u32, name: &str) -> (u32, GameObject) {
+        let obj = GameObject {
+            id,
+            name: name.to_string(),
+            flags: ObjectFlags::empty(),
+            unknown_flags: Vec::new(),
+            waypoints: 0,
+            is_ground: false,
+            is_impassable: false,
+            disguise_target: None,
+            attributes: Default::default(),
+            layer: None,
+        };
+        (id, obj)
+    }
+
+    #[test]
+    fn test_find_points_of_interest_merges_nearby_tiles() {
+        let objects: ObjectDatabase =
+            [object(100, "grass"), object(200, "a locker"), object(201, "a mailbox"), object(202, "an altar")].into();
+        // Three lockers side by side, a fourth across town, and a mailbox next to them
+        let layout = [
+            (2, 2, vec![100, 200]),
+            (3, 2, vec![100, 200]),
+            (4, 2, vec![100, 200]),
+            (20, 20, vec![200]),
+            (2, 4, vec![201]),
+            (10, 10, vec![202]),
+        ];
+        let map_data = SpriteMapData {
+            floor: 7,
+            tiles: layout.into_iter().map(|(x, y, object_ids)| TileStack { x, y, object_ids }).collect(),
+            min_sector_x: 1000,
+            max_sector_x: 1000,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+
+        let points: Vec<_> = find_points_of_interest(&map_data, &objects)
+            .iter()
+            .map(|p| (p.kind, p.x, p.y, p.tiles))
+            .collect();
+        assert_eq!(
+            points,
+            [
+                (PoiKind::Depot, 32003, 32002, 3),
+                (PoiKind::Mailbox, 32002, 32004, 1),
+                (PoiKind::Temple, 32010, 32010, 1),
+                (PoiKind::Depot, 32020, 32020, 1)
+            ]
+        );
+    }
+}
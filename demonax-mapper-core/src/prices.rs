@@ -0,0 +1,144 @@
+use crate::{read_text, InputEncoding};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Average market price per item type ID, in gold.
+pub type PriceTable = HashMap<u32, u64>;
+
+/// One `(item, max_count, chance)` entry from a `.mon` file's `Inventory`,
+/// where `chance` is out of 1000.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LootEntry {
+    pub item_id: u32,
+    pub max_count: u32,
+    pub chance: u32,
+}
+
+pub fn parse_price_csv<P: AsRef<Path>>(csv_path: P) -> Result<PriceTable> {
+    let content = fs::read_to_string(csv_path.as_ref())
+        .with_context(|| format!("Failed to read price CSV from {:?}", csv_path.as_ref()))?;
+
+    let mut prices = HashMap::new();
+
+    for (line_num, line) in content.lines().enumerate() {
+        if line_num == 0 || line.trim().is_empty() {
+            continue;
+        }
+
+        let parts: Vec<&str> = line.split(',').collect();
+        if parts.len() < 2 {
+            tracing::warn!("Line {}: Invalid price CSV format", line_num + 1);
+            continue;
+        }
+
+        let (Ok(item_id), Ok(price)) = (
+            parts[0].trim().parse::<u32>(),
+            parts[1].trim().parse::<f64>(),
+        ) else {
+            tracing::warn!("Line {}: Failed to parse item id or price", line_num + 1);
+            continue;
+        };
+
+        prices.insert(item_id, price.round().max(0.0) as u64);
+    }
+
+    tracing::info!("Loaded {} item prices from CSV", prices.len());
+    Ok(prices)
+}
+
+/// Reads the `Inventory = {(id, count, chance), ...}` loot table of every
+/// `.mon` file in `mon_dir`, keyed by race number.
+pub fn parse_monster_loot<P: AsRef<Path>>(mon_dir: P, encoding: InputEncoding) -> Result<HashMap<u32, Vec<LootEntry>>> {
+    let mon_dir = mon_dir.as_ref();
+    let mut loot = HashMap::new();
+
+    for entry in fs::read_dir(mon_dir)
+        .with_context(|| format!("Failed to read monster directory: {:?}", mon_dir))?
+    {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("mon") {
+            continue;
+        }
+
+        let content = read_text(&path, encoding)
+            .with_context(|| format!("Failed to read .mon file: {:?}", path))?;
+
+        let race = content
+            .lines()
+            .find_map(|l| l.trim().strip_prefix("RaceNumber"))
+            .and_then(|v| v.trim().trim_start_matches('=').trim().parse::<u32>().ok());
+        let inventory = content.find("Inventory").map(|i| &content[i..]);
+
+        if let (Some(race), Some(inventory)) = (race, inventory) {
+            loot.insert(race, parse_inventory(inventory));
+        }
+    }
+
+    Ok(loot)
+}
+
+pub(crate) fn parse_inventory(text: &str) -> Vec<LootEntry> {
+    let Some(start) = text.find('{') else {
+        return Vec::new();
+    };
+    let end = text[start..].find('}').map(|e| start + e).unwrap_or(text.len());
+
+    text[start + 1..end]
+        .split(')')
+        .filter_map(|tuple| {
+            let tuple = tuple.trim_start_matches([',', ' ', '\t', '\r', '\n', '(']);
+            let mut fields = tuple.split(',').map(|f| f.trim().parse::<u32>());
+            Some(LootEntry {
+                item_id: fields.next()?.ok()?,
+                max_count: fields.next()?.ok()?,
+                chance: fields.next()?.ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Expected gold per kill for each race: every priced drop weighted by its
+/// chance and average stack size. Races with no priced loot are left out.
+pub fn expected_loot_values(
+    loot: &HashMap<u32, Vec<LootEntry>>,
+    prices: &PriceTable,
+) -> HashMap<u32, u64> {
+    loot.iter()
+        .filter_map(|(&race, entries)| {
+            let mut priced = false;
+            let value: f64 = entries
+                .iter()
+                .filter_map(|e| {
+                    let price = *prices.get(&e.item_id)?;
+                    priced = true;
+                    let avg_count = (1 + e.max_count.max(1)) as f64 / 2.0;
+                    Some(price as f64 * avg_count * e.chance.min(1000) as f64 / 1000.0)
+                })
+                .sum();
+            priced.then_some((race, value.round() as u64))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_inventory_and_expected_value() {
+        let entries = parse_inventory("Inventory  = {(3031, 30, 500), (3350, 1, 100),\n    (9999, 1, 1000)}");
+        assert_eq!(
+            entries[0],
+            LootEntry { item_id: 3031, max_count: 30, chance: 500 }
+        );
+        assert_eq!(entries.len(), 3);
+
+        let prices: PriceTable = [(3031, 1), (3350, 400)].into();
+        let values = expected_loot_values(&[(11, entries)].into(), &prices);
+        // 15.5 coins * 50% + 400 gold * 10%
+        assert_eq!(values[&11], 48);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::config::BuildConfig;
+use crate::error::{Context, MapperError, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use toml::Table;
+
+pub const PROJECT_FILE: &str = "demonax-mapper.toml";
+
+/// A project file: top-level keys are shared by every build, and each
+/// `[profiles.<name>]` table overrides them for one build (e.g. "public",
+/// "staff"). Both use the [`BuildConfig`] field names.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Project {
+    #[serde(default)]
+    profiles: BTreeMap<String, Table>,
+    #[serde(flatten)]
+    shared: Table,
+}
+
+impl Project {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read project file {:?}", path))?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse project file {:?}", path))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        toml::from_str(content).context("Failed to parse project file")
+    }
+
+    pub fn profile_names(&self) -> Vec<&str> {
+        self.profiles.keys().map(String::as_str).collect()
+    }
+
+    /// The shared settings with the profile's merged over them. Nested
+    /// tables such as `marker_styles` are merged key by key.
+    pub fn profile(&self, name: &str) -> Result<BuildConfig> {
+        let Some(overrides) = self.profiles.get(name) else {
+            return Err(MapperError::InvalidConfig(format!(
+                "Unknown profile {:?} (available: {})",
+                name,
+                self.profile_names().join(", ")
+            )));
+        };
+
+        let mut merged = self.shared.clone();
+        merge_tables(&mut merged, overrides);
+        merged
+            .try_into()
+            .with_context(|| format!("Invalid settings for profile {:?}", name))
+    }
+}
+
+/// Loads a config file of top-level [`BuildConfig`] keys, then replaces the
+/// keys in `overrides` with their values from `flags`. The CLI passes the
+/// keys of the flags given on the command line, so those win over the file.
+pub fn load_build_config<P: AsRef<Path>>(path: P, flags: &BuildConfig, overrides: &[&str]) -> Result<BuildConfig> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read config file {:?}", path))?;
+    let mut settings: Table =
+        toml::from_str(&content).with_context(|| format!("Failed to parse config file {:?}", path))?;
+
+    let flags = Table::try_from(flags).context("Failed to convert command line flags to config settings")?;
+    let given: Table = flags.into_iter().filter(|(key, _)| overrides.contains(&key.as_str())).collect();
+    merge_tables(&mut settings, &given);
+
+    settings
+        .try_into()
+        .with_context(|| format!("Invalid settings in config file {:?}", path))
+}
+
+/// Loads a config file of top-level [`BuildConfig`] keys as written, for
+/// tools that reuse a build's inputs without flags of their own.
+pub fn read_build_config<P: AsRef<Path>>(path: P) -> Result<BuildConfig> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read config file {:?}", path))?;
+    toml::from_str(&content).with_context(|| format!("Failed to parse config file {:?}", path))
+}
+
+/// What `init-config` writes: every build setting with its default,
+/// commented out except for the inputs a build cannot do without.
+pub const CONFIG_TEMPLATE: &str = r##"# demonax-mapper build settings, loaded with `demonax-mapper build --config FILE`.
+# Flags given on the command line override the values in this file.
+# Relative paths are resolved from the current directory.
+
+# --- Game data (required) ---
+objects_path = "game/dat/objects.srv"
+map_path = "game/map"
+# Sprite PNG directory, zip of PNGs or sprite sheet index
+sprite_path = "sprites"
+# Fail the build instead of drawing placeholders for missing or corrupt sprites
+# strict = false
+# Sprites stored as <id>_<frame>.png: "first", "average" or "animate" (needs tile_format = "webp")
+# sprite_frames = "first"
+# Rules placing objects on drawing layers by ID, flags or name (TOML or JSON)
+# layer_rules = "layer-rules.toml"
+
+# --- What to render ---
+# A single floor, a list ([6, 7, 8]) or a range spec ("0-15", "0,7,9-11")
+floors = "7"
+# min_zoom = 0
+# max_zoom = 5
+# "sprites" or "color"
+# render_mode = "sprites"
+# "png", "webp" or "avif"
+# tile_format = "png"
+# Draw only max_zoom and build lower zoom levels by shrinking it
+# pyramid = false
+# Pack tiles into PMTiles archives: "per-floor" or "combined" (one archive for all floors)
+# pmtiles = "per-floor"
+# Stream tiles into one .zip or .tar.gz instead of the output directory
+# output_archive = "tiles.tar.gz"
+# Also render an "All floors" layer with the floors below darkened, nearest first
+# composite_brightness = [0.6, 0.35]
+# output = "output"
+
+# --- Overlays ---
+# overlays = ["spawns", "quests", "npcs", "water", "zones", "heatmap", "houses", "transitions", "teleports", "signs", "poi", "raids"]
+# monster_db = "game/dat/monster.db"
+# mon_path = "game/mon"
+# monster_sprites = "monster-sprites"
+# npc_csv = "npcs.csv"
+# npc_sprites = "npc-sprites"
+# quest_csv = "quest_overview.csv"
+# Directory of .raid files
+# raids_path = "game/raids"
+# prices_csv = "prices.csv"
+# heatmap_csv = "deaths.csv"
+# heatmap_radius = 8
+
+# --- Marker styles ---
+# [marker_styles]
+# spawns = { min_zoom = 2, size = 24 }
+# quests = { color = "#ff8800" }
+
+# --- Performance ---
+# cache_dir = ".demonax-cache"
+# sprite_cache_mb = 2048
+# threads = 8
+"##;
+
+fn merge_tables(base: &mut Table, overrides: &Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => merge_tables(base, overrides),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_override_shared_settings() {
+        let project = Project::parse(
+            r#"
+            objects_path = "game/dat/objects.srv"
+            map_path = "game/map"
+            sprite_path = "sprites"
+            floors = "0-15"
+            marker_styles = { spawns = { min_zoom = 2 } }
+
+            [profiles.public]
+            output = "public"
+
+            [profiles.staff]
+            output = "staff"
+            floors = [7]
+            live_players = true
+            marker_styles = { spawns = { size = 24 } }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(project.profile_names(), vec!["public", "staff"]);
+
+        let public = project.profile("public").unwrap();
+        assert_eq!(public.floors.len(), 16);
+        assert!(!public.live_players);
+
+        let staff = project.profile("staff").unwrap();
+        assert_eq!(staff.output, Path::new("staff"));
+        assert_eq!(staff.floors, vec![7]);
+        assert!(staff.live_players);
+        assert_eq!(staff.marker_styles.spawns.min_zoom, Some(2));
+        assert_eq!(staff.marker_styles.spawns.size, Some(24));
+
+        assert!(project.profile("minimap").unwrap_err().to_string().contains("public, staff"));
+    }
+
+    #[test]
+    fn test_config_file_with_flag_overrides() {
+        let path = std::env::temp_dir().join(format!("demonax-config-{}.toml", std::process::id()));
+        fs::write(&path, CONFIG_TEMPLATE.replace("# max_zoom = 5", "max_zoom = 3")).unwrap();
+
+        let mut flags: BuildConfig = toml::from_str("objects_path = \"\"\nmap_path = \"\"\nfloors = [8]").unwrap();
+        flags.max_zoom = 4;
+        let config = load_build_config(&path, &flags, &[]).unwrap();
+        assert_eq!((config.floors.clone(), config.max_zoom), (vec![7], 3));
+        assert_eq!(config.map_path, Path::new("game/map"));
+
+        let config = load_build_config(&path, &flags, &["floors", "max_zoom"]).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((config.floors, config.max_zoom), (vec![8], 4));
+        assert_eq!(config.objects_path, Path::new("game/dat/objects.srv"));
+    }
+}
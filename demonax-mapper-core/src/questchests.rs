@@ -1,9 +1,12 @@
-use anyhow::{Context, Result};
+use crate::{read_text, sector_to_world, InputEncoding};
+use crate::error::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
+use crate::{parse_sector_item_line, ObjectDatabase, PriceTable, SectorItem};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QuestChest {
     pub quest_number: u32,
@@ -12,6 +15,17 @@ pub struct QuestChest {
     pub z: u8,
     pub chest_object_id: u32,
     pub quest_name: Option<String>,
+    /// Reward item IDs with their stack amounts, as found in the chest.
+    /// Items inside bags follow the bag they are in.
+    #[serde(default)]
+    pub rewards: Vec<(u32, u32)>,
+}
+
+#[derive(Serialize)]
+struct RewardOutput {
+    id: u32,
+    name: String,
+    amount: u32,
 }
 
 #[derive(Serialize)]
@@ -20,10 +34,14 @@ struct QuestChestOutput {
     x: u32,
     y: u32,
     quest_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reward_value: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rewards: Vec<RewardOutput>,
 }
 
-pub fn parse_quest_csv<P: AsRef<Path>>(csv_path: P) -> Result<HashMap<u32, String>> {
-    let content = fs::read_to_string(csv_path.as_ref())
+pub fn parse_quest_csv<P: AsRef<Path>>(csv_path: P, encoding: InputEncoding) -> Result<HashMap<u32, String>> {
+    let content = read_text(csv_path.as_ref(), encoding)
         .with_context(|| format!("Failed to read quest CSV from {:?}", csv_path.as_ref()))?;
 
     let mut quest_names = HashMap::new();
@@ -64,6 +82,7 @@ pub fn parse_questchests_from_sectors<P: AsRef<Path>>(
     map_dir: P,
     floors: &[u8],
     quest_names: &HashMap<u32, String>,
+    encoding: InputEncoding,
 ) -> Result<Vec<QuestChest>> {
     let map_dir = map_dir.as_ref();
     let mut quest_chests = Vec::new();
@@ -96,8 +115,8 @@ pub fn parse_questchests_from_sectors<P: AsRef<Path>>(
             continue;
         }
 
-        let content = match fs::read(&path) {
-            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        let content = match read_text(&path, encoding) {
+            Ok(content) => content,
             Err(e) => {
                 tracing::warn!("Failed to read {:?}: {}", path, e);
                 continue;
@@ -164,12 +183,12 @@ fn parse_questchest_line(
 
     let local_x: u32 = coords[0]
         .trim()
-        .parse()
+        .parse::<u32>()
         .with_context(|| format!("Failed to parse local X coordinate: {}", coords[0]))?;
 
     let local_y: u32 = coords[1]
         .trim()
-        .parse()
+        .parse::<u32>()
         .with_context(|| format!("Failed to parse local Y coordinate: {}", coords[1]))?;
 
     let content_part = parts[1];
@@ -178,11 +197,23 @@ fn parse_questchest_line(
 
     let chest_object_id = extract_chest_object_id(content_part).unwrap_or(0);
 
-    let world_x = sector_x * 32 + local_x;
-    let world_y = sector_y * 32 + local_y;
+    let (world_x, world_y) = sector_to_world(sector_x, sector_y, local_x, local_y);
 
     let quest_name = quest_names.get(&quest_number).cloned();
 
+    let rewards = parse_sector_item_line(line)
+        .and_then(|(_, _, items)| {
+            items
+                .into_iter()
+                .find(|item| item.attribute("ChestQuestNumber").is_some())
+        })
+        .map(|chest| {
+            let mut rewards = Vec::new();
+            collect_rewards(&chest.contents, &mut rewards);
+            rewards
+        })
+        .unwrap_or_default();
+
     Ok(Some(QuestChest {
         quest_number,
         x: world_x,
@@ -190,9 +221,21 @@ fn parse_questchest_line(
         z,
         chest_object_id,
         quest_name,
+        rewards,
     }))
 }
 
+fn collect_rewards(items: &[SectorItem], out: &mut Vec<(u32, u32)>) {
+    for item in items {
+        let amount = item
+            .attribute("Amount")
+            .and_then(|a| a.parse().ok())
+            .unwrap_or(1);
+        out.push((item.id, amount));
+        collect_rewards(&item.contents, out);
+    }
+}
+
 fn extract_quest_number(content: &str) -> Result<u32> {
     let prefix = "ChestQuestNumber=";
     let start = content
@@ -205,7 +248,7 @@ fn extract_quest_number(content: &str) -> Result<u32> {
     let number_str: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
 
     number_str
-        .parse()
+        .parse::<u32>()
         .with_context(|| format!("Failed to parse quest number: {}", number_str))
 }
 
@@ -219,19 +262,30 @@ fn extract_chest_object_id(content: &str) -> Option<u32> {
     for item in items.iter().take(3) {
         let trimmed = item.trim();
         let id_part = trimmed.split_whitespace().next()?;
-        if let Ok(id) = id_part.parse::<u32>() {
-            if id >= 2543 && id <= 2560 {
-                return Some(id);
-            }
+        if let Ok(id) = id_part.parse::<u32>()
+            && (2543..=2560).contains(&id)
+        {
+            return Some(id);
         }
     }
 
     None
 }
 
+/// Market value of a chest's rewards, or `None` when none of them are priced.
+fn reward_value(rewards: &[(u32, u32)], prices: &PriceTable) -> Option<u64> {
+    let priced: Vec<u64> = rewards
+        .iter()
+        .filter_map(|(id, amount)| prices.get(id).map(|p| p * *amount as u64))
+        .collect();
+    (!priced.is_empty()).then(|| priced.iter().sum())
+}
+
 pub fn generate_questchests_json(
     chests: &[QuestChest],
     floors: &[u8],
+    prices: &PriceTable,
+    objects: &ObjectDatabase,
 ) -> Result<String> {
     let mut chests_by_floor: HashMap<u8, Vec<QuestChestOutput>> = HashMap::new();
 
@@ -242,11 +296,21 @@ pub fn generate_questchests_json(
                 x: chest.x,
                 y: chest.y,
                 quest_name: chest.quest_name.clone(),
+                reward_value: reward_value(&chest.rewards, prices),
+                rewards: chest
+                    .rewards
+                    .iter()
+                    .map(|&(id, amount)| RewardOutput {
+                        id,
+                        name: objects.get(&id).map(|o| o.name.clone()).unwrap_or_default(),
+                        amount,
+                    })
+                    .collect(),
             };
 
             chests_by_floor
                 .entry(chest.z)
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(chest_output);
         }
     }
@@ -260,3 +324,19 @@ pub fn generate_questchests_json(
 
     Ok(json)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quest_chest_rewards_include_bag_contents() {
+        let line = "4-2: Content={405, 2552 ChestQuestNumber=3001 Content={3031 Amount=100, 2853 Content={3357}}}";
+        let names = HashMap::from([(3001, "Dragon Lair".to_string())]);
+        let chest = parse_questchest_line(line, 1000, 1000, 7, &names).unwrap().unwrap();
+
+        assert_eq!((chest.x, chest.y, chest.quest_number), (32004, 32002, 3001));
+        assert_eq!(chest.chest_object_id, 2552);
+        assert_eq!(chest.rewards, [(3031, 100), (2853, 1), (3357, 1)]);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::monsters::mon_value;
+use crate::{read_text, InputEncoding, Position};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// Monsters a raid puts around `(x, y, z)`, within `radius` tiles.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RaidSpawn {
+    pub x: u32,
+    pub y: u32,
+    pub z: u8,
+    pub radius: u32,
+    pub race: u32,
+    pub amount: u32,
+}
+
+/// One `.raid` file. These use the key = value layout of `.mon` files:
+///
+/// ```text
+/// Name     = "Orc Invasion"
+/// Interval = 86400
+/// Message  = "Orcs are marching on the city!"
+/// Boss     = 59
+/// Spawns   = {([32350,32215,7], 5, 2, 10), ([32360,32220,7], 1, 59, 1)}
+/// ```
+///
+/// `Interval` is the time between two raids in seconds; each spawn is
+/// `(position, radius, race, amount)`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Raid {
+    pub name: String,
+    pub interval: Option<u32>,
+    pub message: Option<String>,
+    /// Race of the raid's boss, which the viewer marks apart from its followers.
+    pub boss: Option<u32>,
+    pub spawns: Vec<RaidSpawn>,
+}
+
+fn quoted(value: &str) -> String {
+    value.lines().next().unwrap_or_default().trim().trim_matches('"').to_string()
+}
+
+/// Parses one `.raid` file; `None` without a name.
+pub fn parse_raid(content: &str) -> Option<Raid> {
+    let name = quoted(mon_value(content, "Name")?);
+    let number = |key: &str| -> Option<u32> { mon_value(content, key)?.lines().next()?.trim().parse().ok() };
+
+    let spawns = mon_value(content, "Spawns")
+        .and_then(|value| {
+            let list = value.strip_prefix('{')?;
+            let list = &list[..list.find('}').unwrap_or(list.len())];
+            Some(list.split(')').filter_map(parse_raid_spawn).collect())
+        })
+        .unwrap_or_default();
+
+    Some(Raid {
+        name,
+        interval: number("Interval"),
+        message: mon_value(content, "Message").map(quoted).filter(|m| !m.is_empty()),
+        boss: number("Boss"),
+        spawns,
+    })
+}
+
+/// Parses `([x,y,z], radius, race, amount` (the closing parenthesis is
+/// split off), with any separators left over from the previous entry.
+fn parse_raid_spawn(entry: &str) -> Option<RaidSpawn> {
+    let entry = entry.trim_start_matches([',', ' ', '\t', '\r', '\n', '(']);
+    let (position, rest) = entry.strip_prefix('[')?.split_once(']')?;
+    let position = Position::parse(position)?;
+    let mut fields = rest.split(',').map(str::trim).filter(|f| !f.is_empty()).map(str::parse::<u32>);
+    let spawn = RaidSpawn {
+        x: position.x,
+        y: position.y,
+        z: position.z,
+        radius: fields.next()?.ok()?,
+        race: fields.next()?.ok()?,
+        amount: fields.next()?.ok()?,
+    };
+    fields.next().is_none().then_some(spawn)
+}
+
+/// Reads every `.raid` file in `raid_dir`, sorted by name.
+pub fn parse_raids<P: AsRef<Path>>(raid_dir: P, encoding: InputEncoding) -> Result<Vec<Raid>> {
+    let raid_dir = raid_dir.as_ref();
+    let mut raids = Vec::new();
+
+    for entry in fs::read_dir(raid_dir).with_context(|| format!("Failed to read raid directory: {:?}", raid_dir))? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("raid") {
+            continue;
+        }
+
+        let content = read_text(&path, encoding).with_context(|| format!("Failed to read .raid file: {:?}", path))?;
+        match parse_raid(&content) {
+            Some(raid) if raid.spawns.is_empty() => tracing::warn!("Raid {:?} in {:?} has no spawns", raid.name, path),
+            Some(raid) => raids.push(raid),
+            None => tracing::warn!("Raid without a Name in file: {:?}", path),
+        }
+    }
+
+    raids.sort_by(|a, b| a.name.cmp(&b.name));
+    tracing::info!("Loaded {} raids from {:?}", raids.len(), raid_dir);
+    Ok(raids)
+}
+
+#[derive(Serialize)]
+struct RaidSpawnOutput<'a> {
+    raid: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interval: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+    race: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    boss: bool,
+    x: u32,
+    y: u32,
+    radius: u32,
+    amount: u32,
+}
+
+/// The viewer's raid overlay: every raid spawn on `floors`, each carrying
+/// its raid's name, interval and message.
+pub fn generate_raids_json(raids: &[Raid], floors: &[u8], monster_names: &HashMap<u32, String>) -> Result<String> {
+    let mut raids_by_floor: BTreeMap<u8, Vec<RaidSpawnOutput>> = BTreeMap::new();
+
+    for raid in raids {
+        for spawn in raid.spawns.iter().filter(|s| floors.contains(&s.z)) {
+            raids_by_floor.entry(spawn.z).or_default().push(RaidSpawnOutput {
+                raid: &raid.name,
+                interval: raid.interval,
+                message: raid.message.as_deref(),
+                race: spawn.race,
+                name: monster_names.get(&spawn.race).map(String::as_str),
+                boss: raid.boss == Some(spawn.race),
+                x: spawn.x,
+                y: spawn.y,
+                radius: spawn.radius,
+                amount: spawn.amount,
+            });
+        }
+    }
+
+    let output = serde_json::json!({
+        "raids_by_floor": raids_by_floor
+    });
+
+    serde_json::to_string(&output).with_context(|| "Failed to serialize raids to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_raid() {
+        let raid = parse_raid(
+            "# Orcs from the north\nName     = \"Orc Invasion\"\nInterval = 86400\nBoss     = 59\n\
+             Spawns   = {([32350,32215,7], 5, 2, 10),\n            ([32360,32220,7], 1, 59, 1), ([1,2], 1, 1, 1)}\n",
+        )
+        .unwrap();
+
+        assert_eq!((raid.name.as_str(), raid.interval, raid.boss, raid.message), ("Orc Invasion", Some(86400), Some(59), None));
+        assert_eq!(
+            raid.spawns,
+            [
+                RaidSpawn { x: 32350, y: 32215, z: 7, radius: 5, race: 2, amount: 10 },
+                RaidSpawn { x: 32360, y: 32220, z: 7, radius: 1, race: 59, amount: 1 }
+            ]
+        );
+        assert!(parse_raid("Interval = 60\n").is_none());
+    }
+}
@@ -0,0 +1,325 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl FieldType {
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            FieldType::Number => value.is_number(),
+            FieldType::String => value.is_string(),
+            FieldType::Array => value.is_array(),
+            FieldType::Object => value.is_object(),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            FieldType::Number => "number",
+            FieldType::String => "string",
+            FieldType::Array => "array",
+            FieldType::Object => "object",
+        }
+    }
+}
+
+/// How the entries under an overlay's root key are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryLayout {
+    /// `{"<floor>": [entry, ...]}`
+    FloorList,
+    /// `{"<floor>": {"<name>": entry}}`
+    FloorMap,
+    /// `{"<id>": entry}`
+    Map,
+    /// `[entry, ...]`
+    List,
+}
+
+/// The shape the viewer expects of one overlay JSON file. Only the fields
+/// the viewer reads are required; extra fields are allowed.
+#[derive(Debug, Clone, Copy)]
+pub struct OverlaySchema {
+    pub file: &'static str,
+    pub root: &'static str,
+    pub layout: EntryLayout,
+    pub fields: &'static [(&'static str, FieldType)],
+}
+
+pub const OVERLAY_SCHEMAS: &[OverlaySchema] = &[
+    OverlaySchema {
+        file: "spawns.json",
+        root: "spawns_by_floor",
+        layout: EntryLayout::FloorList,
+        fields: &[("race", FieldType::Number), ("x", FieldType::Number), ("y", FieldType::Number), ("amount", FieldType::Number)],
+    },
+    OverlaySchema {
+        file: "hunting.json",
+        root: "hunting_by_floor",
+        layout: EntryLayout::FloorList,
+        fields: &[("sector_x", FieldType::Number), ("sector_y", FieldType::Number), ("exp_per_hour", FieldType::Number)],
+    },
+    OverlaySchema {
+        file: "questchests.json",
+        root: "questchests_by_floor",
+        layout: EntryLayout::FloorList,
+        fields: &[("x", FieldType::Number), ("y", FieldType::Number), ("quest_number", FieldType::Number)],
+    },
+    OverlaySchema {
+        file: "npcs.json",
+        root: "npcs_by_floor",
+        layout: EntryLayout::FloorList,
+        fields: &[("x", FieldType::Number), ("y", FieldType::Number), ("npc_name", FieldType::String), ("file_name", FieldType::String)],
+    },
+    OverlaySchema {
+        file: "water.json",
+        root: "water_by_floor",
+        layout: EntryLayout::FloorList,
+        fields: &[
+            ("min_x", FieldType::Number),
+            ("min_y", FieldType::Number),
+            ("max_x", FieldType::Number),
+            ("max_y", FieldType::Number),
+            ("area", FieldType::Number),
+            ("runs", FieldType::Array),
+        ],
+    },
+    OverlaySchema {
+        file: "zones.json",
+        root: "zones_by_floor",
+        layout: EntryLayout::FloorMap,
+        fields: &[("kind", FieldType::String), ("tile_count", FieldType::Number), ("runs", FieldType::Array)],
+    },
+    OverlaySchema {
+        file: "transitions.json",
+        root: "transitions_by_floor",
+        layout: EntryLayout::FloorList,
+        fields: &[
+            ("x", FieldType::Number),
+            ("y", FieldType::Number),
+            ("to_floor", FieldType::Number),
+            ("kind", FieldType::String),
+        ],
+    },
+    OverlaySchema {
+        file: "teleports.json",
+        root: "teleports_by_floor",
+        layout: EntryLayout::FloorList,
+        fields: &[
+            ("x", FieldType::Number),
+            ("y", FieldType::Number),
+            ("to_x", FieldType::Number),
+            ("to_y", FieldType::Number),
+            ("to_floor", FieldType::Number),
+        ],
+    },
+    OverlaySchema {
+        file: "signs.json",
+        root: "signs_by_floor",
+        layout: EntryLayout::FloorList,
+        fields: &[("x", FieldType::Number), ("y", FieldType::Number), ("text", FieldType::String)],
+    },
+    OverlaySchema {
+        file: "poi.json",
+        root: "poi_by_floor",
+        layout: EntryLayout::FloorList,
+        fields: &[("kind", FieldType::String), ("x", FieldType::Number), ("y", FieldType::Number), ("tiles", FieldType::Number)],
+    },
+    OverlaySchema {
+        file: "raids.json",
+        root: "raids_by_floor",
+        layout: EntryLayout::FloorList,
+        fields: &[
+            ("raid", FieldType::String),
+            ("race", FieldType::Number),
+            ("x", FieldType::Number),
+            ("y", FieldType::Number),
+            ("radius", FieldType::Number),
+            ("amount", FieldType::Number),
+        ],
+    },
+    OverlaySchema {
+        file: "diff.json",
+        root: "diff_by_floor",
+        layout: EntryLayout::FloorList,
+        fields: &[("kind", FieldType::String), ("tile_count", FieldType::Number), ("runs", FieldType::Array)],
+    },
+    OverlaySchema {
+        file: "heatmap.json",
+        root: "heatmap_floors",
+        layout: EntryLayout::List,
+        fields: &[],
+    },
+    OverlaySchema {
+        file: "houses.json",
+        root: "houses_by_floor",
+        layout: EntryLayout::FloorList,
+        fields: &[
+            ("id", FieldType::Number),
+            ("tile_count", FieldType::Number),
+            ("center_x", FieldType::Number),
+            ("center_y", FieldType::Number),
+            ("runs", FieldType::Array),
+        ],
+    },
+    OverlaySchema {
+        file: "houses-status.json",
+        root: "house_status",
+        layout: EntryLayout::Map,
+        fields: &[("state", FieldType::String)],
+    },
+    OverlaySchema {
+        file: "history/index.json",
+        root: "versions",
+        layout: EntryLayout::List,
+        fields: &[("date", FieldType::String), ("floors", FieldType::Array)],
+    },
+];
+
+/// One way an overlay file deviates from its schema.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SchemaIssue {
+    pub file: String,
+    /// JSON-pointer-like location, e.g. `/spawns_by_floor/7/3/x`.
+    pub path: String,
+    pub problem: String,
+}
+
+/// Checks every overlay present in `output_dir`; missing files are fine since
+/// all overlays are optional.
+pub fn validate_overlays<P: AsRef<Path>>(output_dir: P) -> Vec<SchemaIssue> {
+    let mut issues = Vec::new();
+
+    for schema in OVERLAY_SCHEMAS {
+        let path = output_dir.as_ref().join(schema.file);
+        if !path.exists() {
+            continue;
+        }
+
+        let parsed = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| serde_json::from_str::<Value>(&content).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(value) => issues.extend(validate_overlay(schema, &value)),
+            Err(e) => issues.push(SchemaIssue {
+                file: schema.file.to_string(),
+                path: String::new(),
+                problem: format!("not valid JSON: {}", e),
+            }),
+        }
+    }
+
+    issues
+}
+
+pub fn validate_overlay(schema: &OverlaySchema, value: &Value) -> Vec<SchemaIssue> {
+    let mut issues = Vec::new();
+    let mut report = |path: String, problem: String| {
+        issues.push(SchemaIssue {
+            file: schema.file.to_string(),
+            path,
+            problem,
+        })
+    };
+
+    let root_path = format!("/{}", schema.root);
+    let Some(root) = value.get(schema.root) else {
+        report(root_path, "missing".to_string());
+        return issues;
+    };
+
+    let mut entries: Vec<(String, &Value)> = Vec::new();
+    match schema.layout {
+        EntryLayout::List => match root.as_array() {
+            Some(items) => entries.extend(items.iter().enumerate().map(|(i, v)| (format!("{}/{}", root_path, i), v))),
+            None => report(root_path, "expected array".to_string()),
+        },
+        EntryLayout::Map => match root.as_object() {
+            Some(map) => entries.extend(map.iter().map(|(k, v)| (format!("{}/{}", root_path, k), v))),
+            None => report(root_path, "expected object".to_string()),
+        },
+        EntryLayout::FloorList | EntryLayout::FloorMap => {
+            let Some(floors) = root.as_object() else {
+                report(root_path, "expected object keyed by floor".to_string());
+                return issues;
+            };
+
+            for (floor, items) in floors {
+                let floor_path = format!("{}/{}", root_path, floor);
+                if !floor.parse::<u8>().is_ok_and(|f| f <= 15) {
+                    report(floor_path.clone(), format!("{:?} is not a floor (0-15)", floor));
+                }
+
+                if schema.layout == EntryLayout::FloorList {
+                    match items.as_array() {
+                        Some(items) => entries.extend(items.iter().enumerate().map(|(i, v)| (format!("{}/{}", floor_path, i), v))),
+                        None => report(floor_path, "expected array".to_string()),
+                    }
+                } else {
+                    match items.as_object() {
+                        Some(map) => entries.extend(map.iter().map(|(k, v)| (format!("{}/{}", floor_path, k), v))),
+                        None => report(floor_path, "expected object".to_string()),
+                    }
+                }
+            }
+        }
+    }
+
+    for (path, entry) in entries {
+        if schema.fields.is_empty() {
+            continue;
+        }
+        if !entry.is_object() {
+            report(path, "expected object".to_string());
+            continue;
+        }
+
+        for &(field, field_type) in schema.fields {
+            match entry.get(field) {
+                Some(value) if field_type.matches(value) => {}
+                Some(_) => report(format!("{}/{}", path, field), format!("expected {}", field_type.as_str())),
+                None => report(format!("{}/{}", path, field), "missing".to_string()),
+            }
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_overlay_reports_bad_floors_and_fields() {
+        let spawns = OVERLAY_SCHEMAS.iter().find(|s| s.file == "spawns.json").unwrap();
+        let value: Value = serde_json::from_str(
+            r#"{"spawns_by_floor": {
+                "7": [{"race": 11, "x": 32000, "y": 32000, "amount": 2}, {"race": 11, "x": "32000", "y": 1}],
+                "sky": []
+            }}"#,
+        )
+        .unwrap();
+
+        let issues = validate_overlay(spawns, &value);
+        let paths: Vec<(&str, &str)> = issues.iter().map(|i| (i.path.as_str(), i.problem.as_str())).collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("/spawns_by_floor/sky", "\"sky\" is not a floor (0-15)"),
+                ("/spawns_by_floor/7/1/x", "expected number"),
+                ("/spawns_by_floor/7/1/amount", "missing"),
+            ]
+        );
+
+        assert_eq!(validate_overlay(spawns, &serde_json::json!({})).len(), 1);
+    }
+}
@@ -0,0 +1,521 @@
+use crate::{read_text, sector_to_world, InputEncoding, WorldBounds};
+use crate::error::{Context, MapperError, Result};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileStack {
+    pub x: u32,
+    pub y: u32,
+    pub object_ids: Vec<u32>,
+}
+
+/// Bump when [`SpriteMapData`] gains information older caches lack.
+pub const SPRITE_MAP_VERSION: u32 = 3;
+
+/// A sector file, or one line of it, that could not be parsed and was left
+/// off the map.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParseIssue {
+    pub file: String,
+    /// 1-based; `None` when the whole sector was skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpriteMapData {
+    pub floor: u8,
+    pub tiles: Vec<TileStack>,
+    pub min_sector_x: u32,
+    pub max_sector_x: u32,
+    pub min_sector_y: u32,
+    pub max_sector_y: u32,
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parse_issues: Vec<ParseIssue>,
+}
+
+impl SpriteMapData {
+    pub fn bounds(&self) -> WorldBounds {
+        WorldBounds {
+            min_sector_x: self.min_sector_x,
+            max_sector_x: self.max_sector_x,
+            min_sector_y: self.min_sector_y,
+            max_sector_y: self.max_sector_y,
+        }
+    }
+
+    /// Rough heap + inline size of the parsed floor, used for memory reporting.
+    pub fn estimated_memory_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.tiles.capacity() * std::mem::size_of::<TileStack>()
+            + self
+                .tiles
+                .iter()
+                .map(|t| t.object_ids.capacity() * std::mem::size_of::<u32>())
+                .sum::<usize>()
+    }
+}
+
+/// Parses every sector of `floor`, or only those listed in `sectors`
+/// (`(x, y)` pairs) when it is not empty.
+pub fn parse_sprite_map<P: AsRef<Path>>(
+    map_dir: P,
+    floor: u8,
+    bounds: &WorldBounds,
+    sectors: &[(u32, u32)],
+    encoding: InputEncoding,
+) -> Result<SpriteMapData> {
+    let map_dir = map_dir.as_ref();
+    let _span = tracing::info_span!("parse_sprite_map", floor).entered();
+
+    let sec_files: Vec<PathBuf> = fs::read_dir(map_dir)
+        .with_context(|| format!("Failed to read map directory: {:?}", map_dir))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .map(|n| matches_pattern(n, floor) && in_sector_list(n, sectors))
+                .unwrap_or(false)
+        })
+        .collect();
+
+
+    #[cfg(feature = "parallel")]
+    let sector_iter = sec_files.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let sector_iter = sec_files.iter();
+
+    let results: Vec<(Vec<TileStack>, Vec<ParseIssue>)> = sector_iter
+        .map(|path| {
+            let _span = tracing::debug_span!("parse_sector", file = ?path.file_name()).entered();
+            match parse_sector_file_stacks(path, bounds, encoding) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    tracing::warn!(kind = "sector_parse", id = %sector_file_name(path), "Failed to parse sector {:?}: {}", path.file_name(), e);
+                    let issue = ParseIssue {
+                        file: sector_file_name(path),
+                        line: None,
+                        reason: format!("{:#}", e),
+                    };
+                    (Vec::new(), vec![issue])
+                }
+            }
+        })
+        .collect();
+
+    let mut tiles = Vec::new();
+    let mut parse_issues = Vec::new();
+    for (sector_tiles, issues) in results {
+        tiles.extend(sector_tiles);
+        parse_issues.extend(issues);
+    }
+    parse_issues.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+
+    // Sort tiles for correct Z-ordering when sprites overlap across tiles
+    // Y ascending (back to front), X ascending (left to right)
+    // This ensures sprites farther away (lower Y, lower X) draw first
+    tiles.sort_by_key(|t| (t.y, t.x));
+
+    Ok(SpriteMapData {
+        floor,
+        tiles,
+        min_sector_x: bounds.min_sector_x,
+        max_sector_x: bounds.max_sector_x,
+        min_sector_y: bounds.min_sector_y,
+        max_sector_y: bounds.max_sector_y,
+        version: SPRITE_MAP_VERSION,
+        parse_issues,
+    })
+}
+
+/// `parse-report.json`: every skipped sector or line, with totals up front.
+pub fn generate_parse_report_json(issues: &[ParseIssue]) -> Result<String> {
+    #[derive(Serialize)]
+    struct ParseReport<'a> {
+        sectors_skipped: usize,
+        lines_skipped: usize,
+        issues: &'a [ParseIssue],
+    }
+
+    let sectors_skipped = issues.iter().filter(|i| i.line.is_none()).count();
+    let report = ParseReport {
+        sectors_skipped,
+        lines_skipped: issues.len() - sectors_skipped,
+        issues,
+    };
+
+    serde_json::to_string_pretty(&report).with_context(|| "Failed to serialize parse report to JSON")
+}
+
+fn sector_file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+fn matches_pattern(filename: &str, floor: u8) -> bool {
+    filename.ends_with(&format!("-{:02}.sec", floor))
+}
+
+fn in_sector_list(filename: &str, sectors: &[(u32, u32)]) -> bool {
+    sectors.is_empty()
+        || parse_sector_coords(filename).is_some_and(|(x, y, _)| sectors.contains(&(x, y)))
+}
+
+pub fn parse_sector_coords(filename: &str) -> Option<(u32, u32, u8)> {
+    let name = filename.strip_suffix(".sec")?;
+    let parts: Vec<&str> = name.split('-').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+
+    let x = parts[0].parse().ok()?;
+    let y = parts[1].parse().ok()?;
+    let z = parts[2].parse().ok()?;
+
+    Some((x, y, z))
+}
+
+
+fn parse_sector_file_stacks(
+    path: &Path,
+    bounds: &WorldBounds,
+    encoding: InputEncoding,
+) -> Result<(Vec<TileStack>, Vec<ParseIssue>)> {
+    let filename = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| MapperError::InvalidSector(format!("Invalid filename {:?}", path)))?;
+
+    let (sector_x, sector_y, _) = parse_sector_coords(filename)
+        .ok_or_else(|| MapperError::InvalidSector(format!("Failed to parse sector coordinates from {:?}", filename)))?;
+
+    let content = read_text(path, encoding)?;
+    let mut tiles = Vec::new();
+    let mut issues = Vec::new();
+
+    for (line_num, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || !line.contains("Content=") {
+            continue;
+        }
+
+        let Some((local_x, local_y, obj_ids)) = parse_content_line(line) else {
+            tracing::warn!(kind = "malformed_content", id = filename, "{}:{}: Malformed Content line", filename, line_num + 1);
+            issues.push(ParseIssue {
+                file: filename.to_string(),
+                line: Some(line_num + 1),
+                reason: "malformed Content line".to_string(),
+            });
+            continue;
+        };
+
+        if !obj_ids.is_empty() {
+            let (world_x, world_y) = sector_to_world(sector_x, sector_y, local_x, local_y);
+            let Some((x, y)) = bounds.world_to_map(world_x, world_y) else {
+                continue;
+            };
+
+            tiles.push(TileStack {
+                x,
+                y,
+                object_ids: obj_ids,
+            });
+        }
+    }
+
+    Ok((tiles, issues))
+}
+
+pub(crate) fn parse_content_line(line: &str) -> Option<(u32, u32, Vec<u32>)> {
+    // Split only on the FIRST colon to avoid issues with String attributes containing colons
+    let parts: Vec<&str> = line.splitn(2, ':').collect();
+    if parts.len() < 2 {
+        return None;
+    }
+
+    let coords: Vec<&str> = parts[0].split('-').collect();
+    if coords.len() != 2 {
+        return None;
+    }
+
+    let local_x = coords[0].parse().ok()?;
+    let local_y = coords[1].parse().ok()?;
+
+    let content = parts[1];
+    let start = content.find('{')?;
+    let end = content.find('}')?;
+    let ids_str = &content[start + 1..end];
+
+    let obj_ids: Vec<u32> = ids_str
+        .split(',')
+        .filter_map(|s| {
+            // Extract just the first token (object ID), ignoring attributes like String="..."
+            let trimmed = s.trim();
+            let id_part = trimmed.split_whitespace().next()?;
+            id_part.parse().ok()
+        })
+        .collect();
+
+    Some((local_x, local_y, obj_ids))
+}
+
+/// One object as written in a sector file, e.g.
+/// `2843 String="Hello" Content={3031, 3032}`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SectorItem {
+    pub id: u32,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contents: Vec<SectorItem>,
+}
+
+impl SectorItem {
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(String::as_str)
+    }
+}
+
+/// A `.sec` file on disk together with the sector coordinates from its name.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SectorFile {
+    pub path: PathBuf,
+    pub sector_x: u32,
+    pub sector_y: u32,
+    pub z: u8,
+}
+
+/// Sector files matching any of `floors`, sorted by path.
+pub fn sector_files_for_floors(map_dir: &Path, floors: &[u8]) -> Result<Vec<SectorFile>> {
+    let mut files: Vec<SectorFile> = fs::read_dir(map_dir)
+        .with_context(|| format!("Failed to read map directory: {:?}", map_dir))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let (sector_x, sector_y, z) = parse_sector_coords(path.file_name()?.to_str()?)?;
+            floors.contains(&z).then_some(SectorFile {
+                path,
+                sector_x,
+                sector_y,
+                z,
+            })
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Parses `x-y: ..., Content={...}` into local tile coordinates and the items
+/// on that tile, keeping attributes and nested container contents.
+pub fn parse_sector_item_line(line: &str) -> Option<(u32, u32, Vec<SectorItem>)> {
+    let (coords, rest) = line.split_once(':')?;
+    let (local_x, local_y) = coords.trim().split_once('-')?;
+    let local_x = local_x.parse().ok()?;
+    let local_y = local_y.parse().ok()?;
+
+    let content_start = rest.find("Content=")? + "Content=".len();
+    let block = balanced_block(&rest[content_start..])?;
+
+    Some((local_x, local_y, parse_sector_items(block)))
+}
+
+/// Parses the inside of a `Content={...}` block.
+pub fn parse_sector_items(content: &str) -> Vec<SectorItem> {
+    split_top_level(content)
+        .into_iter()
+        .filter_map(parse_sector_item)
+        .collect()
+}
+
+fn parse_sector_item(entry: &str) -> Option<SectorItem> {
+    let entry = entry.trim();
+    let id_end = entry.find(|c: char| c.is_whitespace()).unwrap_or(entry.len());
+    let id = entry[..id_end].parse().ok()?;
+
+    let mut item = SectorItem {
+        id,
+        ..Default::default()
+    };
+
+    let mut rest = entry[id_end..].trim_start();
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim().to_string();
+        let value = &rest[eq + 1..];
+
+        let consumed = if value.starts_with('"') {
+            let (text, len) = quoted_string(value);
+            item.attributes.insert(key, text);
+            len
+        } else if value.starts_with('{') {
+            let block = balanced_block(value).unwrap_or("");
+            if key == "Content" {
+                item.contents = parse_sector_items(block);
+            } else {
+                item.attributes.insert(key, block.to_string());
+            }
+            block.len() + 2
+        } else {
+            let end = value.find(|c: char| c.is_whitespace()).unwrap_or(value.len());
+            item.attributes.insert(key, value[..end].to_string());
+            end
+        };
+
+        rest = value[consumed.min(value.len())..].trim_start();
+    }
+
+    Some(item)
+}
+
+/// Collapses tiles sorted by `(y, x)` into horizontal runs `[y, x_start, x_end]`
+/// (inclusive), which is how area overlays are shipped to the viewer.
+pub fn tile_runs(sorted_tiles: &[(u32, u32)]) -> Vec<[u32; 3]> {
+    let mut runs: Vec<[u32; 3]> = Vec::new();
+    for &(x, y) in sorted_tiles {
+        match runs.last_mut() {
+            Some(run) if run[0] == y && run[2] + 1 == x => run[2] = x,
+            _ => runs.push([y, x, x]),
+        }
+    }
+    runs
+}
+
+/// Tile flags listed before `Content=` on a sector line, e.g.
+/// `["ProtectionZone", "NoLogout"]` for `3-4: ProtectionZone, NoLogout, Content={...}`.
+pub fn parse_tile_flags(line: &str) -> Vec<&str> {
+    let Some((_, rest)) = line.split_once(':') else {
+        return Vec::new();
+    };
+    let flags_part = match rest.find("Content=") {
+        Some(i) => &rest[..i],
+        None => rest,
+    };
+
+    flags_part
+        .split(',')
+        .map(str::trim)
+        .filter(|f| !f.is_empty() && f.chars().all(|c| c.is_ascii_alphanumeric()))
+        .collect()
+}
+
+/// Returns the text between a leading `{` and its matching `}`.
+fn balanced_block(s: &str) -> Option<&str> {
+    let s = s.strip_prefix('{')?;
+    let mut depth = 1;
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '{' if !in_quotes => depth += 1,
+            '}' if !in_quotes => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&s[..i]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Decodes a leading `"..."` string, returning the text and the number of
+/// bytes consumed including both quotes.
+fn quoted_string(s: &str) -> (String, usize) {
+    let mut text = String::new();
+    let mut escaped = false;
+
+    for (i, c) in s.char_indices().skip(1) {
+        if escaped {
+            text.push(if c == 'n' { '\n' } else { c });
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            return (text, i + 1);
+        } else {
+            text.push(c);
+        }
+    }
+
+    (text, s.len())
+}
+
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '{' if !in_quotes => depth += 1,
+            '}' if !in_quotes => depth -= 1,
+            ',' if !in_quotes && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sector_item_line_with_text_and_nested_content() {
+        let line = r#"5-6: Refresh, Content={100, 1750 Content={1955 String="A \"quoted\", multi\nline book" Editor="Ferumbras"}, 600 String="Welcome, stranger"}"#;
+        let (x, y, items) = parse_sector_item_line(line).unwrap();
+
+        assert_eq!((x, y), (5, 6));
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].id, 100);
+
+        let book = &items[1].contents[0];
+        assert_eq!(book.id, 1955);
+        assert_eq!(book.attribute("String"), Some("A \"quoted\", multi\nline book"));
+        assert_eq!(book.attribute("Editor"), Some("Ferumbras"));
+
+        assert_eq!(items[2].attribute("String"), Some("Welcome, stranger"));
+    }
+
+    #[test]
+    fn test_parse_tile_flags() {
+        assert_eq!(
+            parse_tile_flags("3-4: ProtectionZone, NoLogout, Content={100}"),
+            vec!["ProtectionZone", "NoLogout"]
+        );
+        assert!(parse_tile_flags("3-4: Content={100}").is_empty());
+    }
+
+    #[test]
+    fn test_parse_sector_items_plain_attributes() {
+        let items = parse_sector_items("400 ChestQuestNumber=110 Content={700}, 500 Amount=3");
+        assert_eq!(items[0].attribute("ChestQuestNumber"), Some("110"));
+        assert_eq!(items[0].contents, vec![SectorItem { id: 700, ..Default::default() }]);
+        assert_eq!(items[1].attribute("Amount"), Some("3"));
+    }
+}
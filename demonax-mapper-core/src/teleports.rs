@@ -0,0 +1,139 @@
+use crate::{parse_sector_item_line, read_text, sector_files_for_floors, sector_to_world, InputEncoding, ObjectDatabase, Position, SectorItem};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A teleporter, magic portal or other item at `(x, y, floor)` that moves
+/// whoever steps on it to `(to_x, to_y, to_floor)`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Teleport {
+    pub x: u32,
+    pub y: u32,
+    pub floor: u8,
+    pub to_x: u32,
+    pub to_y: u32,
+    pub to_floor: u8,
+    pub object_id: u32,
+    pub object_name: String,
+}
+
+/// Unpacks an `AbsTeleportDestination` value, which stores a world position
+/// in one integer: 14 bits each for x and y counted from 24576, then 4 bits
+/// for the floor.
+pub fn unpack_position(packed: u32) -> Position {
+    Position {
+        x: ((packed >> 18) & 0x3FFF) + 24576,
+        y: ((packed >> 4) & 0x3FFF) + 24576,
+        z: (packed & 0xF) as u8,
+    }
+}
+
+/// Where `item` sends players: its own `AbsTeleportDestination` (packed) or
+/// `TeleportDest` (`{x,y,z}`) attribute, or else the destination of its
+/// object type.
+fn destination(item: &SectorItem, objects: &ObjectDatabase) -> Option<Position> {
+    if let Some(packed) = item.attribute("AbsTeleportDestination") {
+        return packed.trim().parse().ok().map(unpack_position);
+    }
+    item.attribute("TeleportDest")
+        .or_else(|| item.attribute("TeleportDestination"))
+        .and_then(Position::parse)
+        .or_else(|| objects.get(&item.id)?.teleport_destination())
+}
+
+/// Every teleport lying on a tile of `floors`, sorted by floor and position.
+/// Teleports inside containers are not reachable and are skipped.
+pub fn parse_teleports_from_sectors<P: AsRef<Path>>(
+    map_dir: P,
+    floors: &[u8],
+    objects: &ObjectDatabase,
+    encoding: InputEncoding,
+) -> Result<Vec<Teleport>> {
+    // Without typed teleports only lines naming a destination can hold one
+    let typed_teleports = objects.values().any(|object| object.teleport_destination().is_some());
+    let mut teleports = Vec::new();
+
+    for sector in sector_files_for_floors(map_dir.as_ref(), floors)? {
+        let content = match read_text(&sector.path, encoding) {
+            Ok(content) => content,
+            Err(e) => {
+                tracing::warn!("Failed to read {:?}: {}", sector.path, e);
+                continue;
+            }
+        };
+
+        for line in content.lines() {
+            if !typed_teleports && !line.contains("Teleport") {
+                continue;
+            }
+
+            let Some((local_x, local_y, items)) = parse_sector_item_line(line) else {
+                continue;
+            };
+            let (x, y) = sector_to_world(sector.sector_x, sector.sector_y, local_x, local_y);
+            for item in &items {
+                let Some(to) = destination(item, objects) else {
+                    continue;
+                };
+                teleports.push(Teleport {
+                    x,
+                    y,
+                    floor: sector.z,
+                    to_x: to.x,
+                    to_y: to.y,
+                    to_floor: to.z,
+                    object_id: item.id,
+                    object_name: objects.get(&item.id).map(|o| o.name.clone()).unwrap_or_default(),
+                });
+            }
+        }
+    }
+
+    teleports.sort_by_key(|t| (t.floor, t.y, t.x));
+
+    tracing::info!("Found {} teleports in .sec files", teleports.len());
+    Ok(teleports)
+}
+
+pub fn generate_teleports_json(teleports: &[Teleport]) -> Result<String> {
+    let mut teleports_by_floor: BTreeMap<u8, Vec<&Teleport>> = BTreeMap::new();
+    for teleport in teleports {
+        teleports_by_floor.entry(teleport.floor).or_default().push(teleport);
+    }
+
+    let output = serde_json::json!({
+        "teleports_by_floor": teleports_by_floor
+    });
+
+    serde_json::to_string(&output).with_context(|| "Failed to serialize teleports to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_teleports_from_sectors() {
+        let dir = std::env::temp_dir().join(format!("demonax-teleports-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let packed = ((32100 - 24576) << 18) | ((31900 - 24576) << 4) | 9;
+        std::fs::write(
+            dir.join("1000-1000-07.sec"),
+            format!(
+                "0-0: Content={{100}}\n3-4: Content={{100, 1387 AbsTeleportDestination={}}}\n5-5: Content={{2000 Content={{1387 AbsTeleportDestination=1}}}}\n",
+                packed
+            ),
+        )
+        .unwrap();
+
+        let teleports = parse_teleports_from_sectors(&dir, &[7], &ObjectDatabase::new(), InputEncoding::Auto).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(teleports.len(), 1);
+        let teleport = &teleports[0];
+        assert_eq!((teleport.x, teleport.y, teleport.floor), (32003, 32004, 7));
+        assert_eq!((teleport.to_x, teleport.to_y, teleport.to_floor), (32100, 31900, 9));
+        assert_eq!(unpack_position(packed), Position { x: 32100, y: 31900, z: 9 });
+    }
+}
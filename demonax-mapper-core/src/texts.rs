@@ -0,0 +1,149 @@
+use crate::{parse_sector_item_line, read_text, sector_files_for_floors, sector_to_world, InputEncoding, ObjectDatabase, SectorItem};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A sign, book or other item carrying a `String=` text somewhere on the map.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Readable {
+    pub x: u32,
+    pub y: u32,
+    pub z: u8,
+    pub object_id: u32,
+    pub object_name: String,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub editor: Option<String>,
+    /// Set when the readable sits inside a container (bookcase, chest...).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<u32>,
+}
+
+pub fn parse_readables_from_sectors<P: AsRef<Path>>(
+    map_dir: P,
+    floors: &[u8],
+    objects: &ObjectDatabase,
+    encoding: InputEncoding,
+) -> Result<Vec<Readable>> {
+    let mut readables = Vec::new();
+
+    for sector in sector_files_for_floors(map_dir.as_ref(), floors)? {
+        let content = match read_text(&sector.path, encoding) {
+            Ok(content) => content,
+            Err(e) => {
+                tracing::warn!("Failed to read {:?}: {}", sector.path, e);
+                continue;
+            }
+        };
+
+        for line in content.lines() {
+            if !line.contains("String=") {
+                continue;
+            }
+
+            if let Some((local_x, local_y, items)) = parse_sector_item_line(line) {
+                let (x, y) = sector_to_world(sector.sector_x, sector.sector_y, local_x, local_y);
+                collect_readables(&items, x, y, sector.z, None, objects, &mut readables);
+            }
+        }
+    }
+
+    readables.sort_by_key(|r| (r.z, r.y, r.x));
+
+    tracing::info!("Found {} readables in .sec files", readables.len());
+    Ok(readables)
+}
+
+fn collect_readables(
+    items: &[SectorItem],
+    x: u32,
+    y: u32,
+    z: u8,
+    container_id: Option<u32>,
+    objects: &ObjectDatabase,
+    out: &mut Vec<Readable>,
+) {
+    for item in items {
+        if let Some(text) = item.attribute("String")
+            && !text.trim().is_empty()
+        {
+            out.push(Readable {
+                x,
+                y,
+                z,
+                object_id: item.id,
+                object_name: objects
+                    .get(&item.id)
+                    .map(|o| o.name.clone())
+                    .unwrap_or_default(),
+                text: text.to_string(),
+                editor: item.attribute("Editor").map(str::to_string),
+                container_id,
+            });
+        }
+
+        collect_readables(&item.contents, x, y, z, Some(item.id), objects, out);
+    }
+}
+
+pub fn generate_readables_json(readables: &[Readable]) -> Result<String> {
+    serde_json::to_string_pretty(readables)
+        .with_context(|| "Failed to serialize readables to JSON")
+}
+
+#[derive(Serialize)]
+struct SignOutput<'a> {
+    x: u32,
+    y: u32,
+    object_name: &'a str,
+    text: &'a str,
+}
+
+/// Readables grouped by floor for the viewer's signs overlay. Several texts
+/// on one tile, such as books in a bookcase, each get their own entry.
+pub fn generate_signs_json(readables: &[Readable]) -> Result<String> {
+    let mut signs_by_floor: BTreeMap<u8, Vec<SignOutput>> = BTreeMap::new();
+    for readable in readables {
+        signs_by_floor.entry(readable.z).or_default().push(SignOutput {
+            x: readable.x,
+            y: readable.y,
+            object_name: &readable.object_name,
+            text: &readable.text,
+        });
+    }
+
+    let output = serde_json::json!({
+        "signs_by_floor": signs_by_floor
+    });
+
+    serde_json::to_string(&output).with_context(|| "Failed to serialize signs to JSON")
+}
+
+pub fn generate_readables_csv(readables: &[Readable]) -> String {
+    let mut csv = String::from("x,y,z,object_id,object_name,text,editor,container_id\n");
+
+    for r in readables {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{}\n",
+            r.x,
+            r.y,
+            r.z,
+            r.object_id,
+            csv_field(&r.object_name),
+            csv_field(&r.text),
+            csv_field(r.editor.as_deref().unwrap_or("")),
+            r.container_id.map(|id| id.to_string()).unwrap_or_default()
+        ));
+    }
+
+    csv
+}
+
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{GameObject, ObjectDatabase, ObjectFlags, SpriteMapData, MAX_FLOOR};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Something that moves a player to the floor above or below.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionKind {
+    Ladder,
+    Stairs,
+    RopeSpot,
+    Hole,
+}
+
+impl TransitionKind {
+    /// Recognises transitions by the `RopeSpot` flag or by name, since
+    /// objects.srv has no flag for the others. Holes cover trapdoors,
+    /// sewer grates and pitfalls.
+    pub fn of(object: &GameObject) -> Option<Self> {
+        if object.flags.contains(ObjectFlags::ROPE_SPOT) {
+            return Some(Self::RopeSpot);
+        }
+
+        let name = object.name.to_lowercase();
+        if name.contains("ladder") {
+            Some(Self::Ladder)
+        } else if name.contains("stair") || name.contains("ramp") {
+            Some(Self::Stairs)
+        } else if ["hole", "trapdoor", "trap door", "sewer grate", "pitfall"].iter().any(|n| name.contains(n)) {
+            Some(Self::Hole)
+        } else {
+            None
+        }
+    }
+
+    /// Floor change when used: -1 goes up, as lower floor numbers are higher.
+    pub fn floor_delta(self) -> i8 {
+        match self {
+            Self::Hole => 1,
+            Self::Ladder | Self::Stairs | Self::RopeSpot => -1,
+        }
+    }
+}
+
+/// A floor transition at `(x, y, floor)` leading to the same position on
+/// `to_floor`, which is close enough to the actual arrival tile to orient by.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transition {
+    pub x: u32,
+    pub y: u32,
+    pub floor: u8,
+    pub to_floor: u8,
+    pub kind: TransitionKind,
+    pub object_id: u32,
+}
+
+/// Every transition on a floor, one per tile, taking the topmost transition
+/// object of each tile stack.
+pub fn find_transitions(map_data: &SpriteMapData, objects: &ObjectDatabase) -> Vec<Transition> {
+    let bounds = map_data.bounds();
+
+    let mut transitions: Vec<Transition> = map_data
+        .tiles
+        .iter()
+        .filter_map(|tile| {
+            let (object_id, kind) = tile
+                .object_ids
+                .iter()
+                .rev()
+                .find_map(|id| Some((*id, TransitionKind::of(objects.get(id)?)?)))?;
+            let to_floor = map_data.floor.checked_add_signed(kind.floor_delta()).filter(|&z| z <= MAX_FLOOR)?;
+            let (x, y) = bounds.map_to_world(tile.x, tile.y);
+            Some(Transition { x, y, floor: map_data.floor, to_floor, kind, object_id })
+        })
+        .collect();
+
+    transitions.sort_by_key(|t| (t.y, t.x));
+    transitions
+}
+
+pub fn generate_transitions_json(transitions: &[Transition]) -> Result<String> {
+    let mut transitions_by_floor: BTreeMap<u8, Vec<&Transition>> = BTreeMap::new();
+    for transition in transitions {
+        transitions_by_floor.entry(transition.floor).or_default().push(transition);
+    }
+
+    let output = serde_json::json!({
+        "transitions_by_floor": transitions_by_floor
+    });
+
+    serde_json::to_string(&output).with_context(|| "Failed to serialize floor transitions to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TileStack;
+
+    fn object(id: u32, name: &str, flags: ObjectFlags) -> (u32, GameObject) {
+        let obj = GameObject {
+            id,
+            name: name.to_string(),
+            flags,
+            unknown_flags: Vec::new(),
+            waypoints: 0,
+            is_ground: false,
+            is_impassable: false,
+            disguise_target: None,
+            attributes: Default::default(),
+            layer: None,
+        };
+        (id, obj)
+    }
+
+    #[test]
+    fn test_find_transitions() {
+        let objects: ObjectDatabase = [
+            object(100, "grass", ObjectFlags::BANK),
+            object(200, "ladder", ObjectFlags::USE_EVENT),
+            object(201, "stone stairs", ObjectFlags::empty()),
+            object(202, "", ObjectFlags::ROPE_SPOT),
+            object(203, "sewer grate", ObjectFlags::empty()),
+        ]
+        .into();
+        let layout = [(0, 0, vec![100, 200]), (1, 0, vec![201]), (2, 0, vec![100]), (3, 1, vec![202]), (4, 1, vec![203])];
+        let map_data = SpriteMapData {
+            floor: 0,
+            tiles: layout.into_iter().map(|(x, y, object_ids)| TileStack { x, y, object_ids }).collect(),
+            min_sector_x: 1000,
+            max_sector_x: 1000,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+
+        let transitions = find_transitions(&map_data, &objects);
+        // On floor 0 there is nothing above, so only the grate remains
+        assert_eq!(transitions.len(), 1);
+        assert_eq!((transitions[0].x, transitions[0].y), (32004, 32001));
+        assert_eq!((transitions[0].kind, transitions[0].to_floor), (TransitionKind::Hole, 1));
+
+        let map_data = SpriteMapData { floor: 7, ..map_data };
+        let kinds: Vec<_> = find_transitions(&map_data, &objects).iter().map(|t| (t.kind, t.to_floor)).collect();
+        assert_eq!(
+            kinds,
+            [
+                (TransitionKind::Ladder, 6),
+                (TransitionKind::Stairs, 6),
+                (TransitionKind::RopeSpot, 6),
+                (TransitionKind::Hole, 8)
+            ]
+        );
+    }
+}
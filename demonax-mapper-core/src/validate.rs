@@ -0,0 +1,200 @@
+use crate::lint::unknown_ids_in_sector;
+use crate::sectors::parse_content_line;
+use crate::{read_text, sector_files_for_floors, InputEncoding, MonsterSpawn, ObjectDatabase, QuestChest};
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// How bad a [`Diagnostic`] is. Errors lose map content in a build;
+/// warnings only lose names or labels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// A sector file that could not be read.
+    UnreadableSector,
+    /// A `Content=` line the map parser skips.
+    MalformedContent,
+    /// A sector references an object ID missing from objects.srv.
+    UnknownObject,
+    /// An object in objects.srv carries flags the parser does not know.
+    UnknownFlag,
+    /// A monster.db race with no `.mon` file to name it.
+    UnnamedRace,
+    /// A quest chest whose quest number is missing from the quest CSV.
+    UnnamedQuest,
+}
+
+impl DiagnosticKind {
+    pub fn severity(self) -> Severity {
+        match self {
+            Self::UnreadableSector | Self::MalformedContent | Self::UnknownObject => Severity::Error,
+            Self::UnknownFlag | Self::UnnamedRace | Self::UnnamedQuest => Severity::Warning,
+        }
+    }
+}
+
+/// One problem found by the `validate` checks, pointing at the file and
+/// line to fix where there is one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// 1-based.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(kind: DiagnosticKind, file: Option<String>, line: Option<usize>, message: String) -> Self {
+        Self { severity: kind.severity(), kind, file, line, message }
+    }
+}
+
+/// Malformed `Content=` lines and unknown object IDs (one diagnostic per ID
+/// and sector, at its first use) in every sector file of `floors`.
+pub fn validate_sectors<P: AsRef<Path>>(map_dir: P, floors: &[u8], objects: &ObjectDatabase, encoding: InputEncoding) -> Result<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+
+    for sector in sector_files_for_floors(map_dir.as_ref(), floors)? {
+        let name = sector.path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let content = match read_text(&sector.path, encoding) {
+            Ok(content) => content,
+            Err(e) => {
+                diagnostics.push(Diagnostic::new(DiagnosticKind::UnreadableSector, Some(name), None, e.to_string()));
+                continue;
+            }
+        };
+
+        for (line_num, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || !line.contains("Content=") {
+                continue;
+            }
+            if parse_content_line(line).is_none() {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticKind::MalformedContent,
+                    Some(name.clone()),
+                    Some(line_num + 1),
+                    "malformed Content line".to_string(),
+                ));
+            }
+        }
+
+        for (object_id, (count, _, _, line)) in unknown_ids_in_sector(&content, objects) {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::UnknownObject,
+                Some(name.clone()),
+                Some(line),
+                format!("object {} is not in objects.srv, used {} times", object_id, count),
+            ));
+        }
+    }
+
+    diagnostics.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    Ok(diagnostics)
+}
+
+/// Objects whose flags were kept as unknown, one diagnostic per object.
+pub fn validate_objects(objects: &ObjectDatabase) -> Vec<Diagnostic> {
+    let mut unknown: Vec<_> = objects.values().filter(|o| !o.unknown_flags.is_empty()).collect();
+    unknown.sort_by_key(|o| o.id);
+
+    unknown
+        .into_iter()
+        .map(|object| {
+            Diagnostic::new(
+                DiagnosticKind::UnknownFlag,
+                None,
+                None,
+                format!("object {} has unknown flags: {}", object.id, object.unknown_flags.join(", ")),
+            )
+        })
+        .collect()
+}
+
+/// Races spawned in monster.db that no `.mon` file names, one diagnostic
+/// per race at its first spawn.
+pub fn validate_spawn_races(spawns: &[MonsterSpawn], monster_names: &HashMap<u32, String>, monster_db: &str) -> Vec<Diagnostic> {
+    let mut unnamed: BTreeMap<u32, (usize, &MonsterSpawn)> = BTreeMap::new();
+    for spawn in spawns.iter().filter(|s| !monster_names.contains_key(&s.race)) {
+        unnamed.entry(spawn.race).or_insert((0, spawn)).0 += 1;
+    }
+
+    unnamed
+        .into_iter()
+        .map(|(race, (count, first))| {
+            Diagnostic::new(
+                DiagnosticKind::UnnamedRace,
+                Some(monster_db.to_string()),
+                (first.line > 0).then_some(first.line),
+                format!("race {} has no .mon file ({} spawns)", race, count),
+            )
+        })
+        .collect()
+}
+
+/// Quest numbers of chests the quest CSV does not name, one diagnostic per
+/// number at its first chest.
+pub fn validate_quest_names(chests: &[QuestChest]) -> Vec<Diagnostic> {
+    let mut unnamed: BTreeMap<u32, (usize, &QuestChest)> = BTreeMap::new();
+    for chest in chests.iter().filter(|c| c.quest_name.is_none()) {
+        unnamed.entry(chest.quest_number).or_insert((0, chest)).0 += 1;
+    }
+
+    unnamed
+        .into_iter()
+        .map(|(number, (count, first))| {
+            Diagnostic::new(
+                DiagnosticKind::UnnamedQuest,
+                None,
+                None,
+                format!(
+                    "quest number {} is not in the quest CSV ({} chests, first at {},{},{})",
+                    number, count, first.x, first.y, first.z
+                ),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_sectors_reports_malformed_lines_and_unknown_ids() {
+        let dir = std::env::temp_dir().join(format!("demonax-validate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("1000-1000-07.sec"), "0-0: Content={9999}\n1-x: Content={100}\n").unwrap();
+
+        let diagnostics = validate_sectors(&dir, &[7], &ObjectDatabase::new(), InputEncoding::Auto).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let kinds: Vec<_> = diagnostics.iter().map(|d| (d.kind, d.severity, d.line)).collect();
+        assert_eq!(
+            kinds,
+            [
+                (DiagnosticKind::UnknownObject, Severity::Error, Some(1)),
+                (DiagnosticKind::MalformedContent, Severity::Error, Some(2))
+            ]
+        );
+
+        let spawn = MonsterSpawn { race: 5, x: 0, y: 0, z: 7, radius: 1, amount: 1, regen: 60, line: 3 };
+        let names = HashMap::from([(6, "rat".to_string())]);
+        let races = validate_spawn_races(&[spawn.clone(), spawn], &names, "monster.db");
+        assert_eq!(races.len(), 1);
+        assert_eq!((races[0].severity, races[0].line), (Severity::Warning, Some(3)));
+        assert!(races[0].message.contains("2 spawns"));
+    }
+}
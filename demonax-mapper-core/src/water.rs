@@ -0,0 +1,155 @@
+use crate::{tile_runs, ObjectDatabase, SpriteMapData};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+/// A 4-connected body of water tiles on one floor. Coordinates are world
+/// coordinates; `runs` lists the region row by row as `[y, x_start, x_end]`
+/// (inclusive) so the viewer can draw its exact shape.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaterRegion {
+    pub floor: u8,
+    pub area: usize,
+    pub min_x: u32,
+    pub min_y: u32,
+    pub max_x: u32,
+    pub max_y: u32,
+    pub center_x: u32,
+    pub center_y: u32,
+    pub runs: Vec<[u32; 3]>,
+}
+
+/// Groups the water tiles of a floor into connected regions, largest first.
+pub fn find_water_regions(map_data: &SpriteMapData, objects: &ObjectDatabase) -> Vec<WaterRegion> {
+    let bounds = map_data.bounds();
+
+    let mut water: HashSet<(u32, u32)> = map_data
+        .tiles
+        .iter()
+        .filter(|t| {
+            t.object_ids
+                .iter()
+                .any(|id| objects.get(id).is_some_and(|o| o.is_water()))
+        })
+        .map(|t| bounds.map_to_world(t.x, t.y))
+        .collect();
+
+    let mut regions = Vec::new();
+
+    while let Some(&start) = water.iter().next() {
+        water.remove(&start);
+        let mut queue = VecDeque::from([start]);
+        let mut tiles = Vec::new();
+
+        while let Some((x, y)) = queue.pop_front() {
+            tiles.push((x, y));
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for n in neighbours {
+                if water.remove(&n) {
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        regions.push(build_region(map_data.floor, tiles));
+    }
+
+    regions.sort_by(|a, b| b.area.cmp(&a.area).then((a.min_y, a.min_x).cmp(&(b.min_y, b.min_x))));
+    regions
+}
+
+fn build_region(floor: u8, mut tiles: Vec<(u32, u32)>) -> WaterRegion {
+    tiles.sort_unstable_by_key(|&(x, y)| (y, x));
+    let runs = tile_runs(&tiles);
+
+    let area = tiles.len();
+    let (sum_x, sum_y) = tiles
+        .iter()
+        .fold((0u64, 0u64), |(sx, sy), &(x, y)| (sx + x as u64, sy + y as u64));
+
+    WaterRegion {
+        floor,
+        area,
+        min_x: tiles.iter().map(|t| t.0).min().unwrap_or(0),
+        min_y: tiles.first().map(|t| t.1).unwrap_or(0),
+        max_x: tiles.iter().map(|t| t.0).max().unwrap_or(0),
+        max_y: tiles.last().map(|t| t.1).unwrap_or(0),
+        center_x: (sum_x / area as u64) as u32,
+        center_y: (sum_y / area as u64) as u32,
+        runs,
+    }
+}
+
+pub fn generate_water_json(regions: &[WaterRegion]) -> Result<String> {
+    let mut regions_by_floor: BTreeMap<u8, Vec<&WaterRegion>> = BTreeMap::new();
+    for region in regions {
+        regions_by_floor.entry(region.floor).or_default().push(region);
+    }
+
+    let totals: HashMap<u8, usize> = regions_by_floor
+        .iter()
+        .map(|(floor, regions)| (*floor, regions.iter().map(|r| r.area).sum()))
+        .collect();
+
+    let output = serde_json::json!({
+        "water_by_floor": regions_by_floor,
+        "water_tiles_by_floor": totals
+    });
+
+    serde_json::to_string(&output).with_context(|| "Failed to serialize water regions to JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GameObject, ObjectFlags, TileStack};
+
+    fn object(id: u32, name: &str) -> GameObject {
+        GameObject {
+            id,
+            name: name.to_string(),
+            flags: ObjectFlags::BANK,
+            unknown_flags: Vec::new(),
+            waypoints: 0,
+            is_ground: true,
+            is_impassable: false,
+            disguise_target: None,
+            attributes: Default::default(),
+            layer: None,
+        }
+    }
+
+    #[test]
+    fn test_find_water_regions_splits_unconnected_bodies() {
+        let objects: ObjectDatabase =
+            [(100, object(100, "grass")), (101, object(101, "water"))].into();
+
+        // Two lakes: an L-shape of 3 tiles and a single diagonal-only tile
+        let layout = [(0, 0, 101), (1, 0, 101), (1, 1, 101), (3, 3, 101), (2, 2, 100)];
+        let map_data = SpriteMapData {
+            floor: 7,
+            tiles: layout
+                .iter()
+                .map(|&(x, y, id)| TileStack { x, y, object_ids: vec![id] })
+                .collect(),
+            min_sector_x: 1000,
+            max_sector_x: 1000,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+
+        let regions = find_water_regions(&map_data, &objects);
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].area, 3);
+        assert_eq!((regions[0].min_x, regions[0].min_y), (32000, 32000));
+        assert_eq!(regions[0].runs, vec![[32000, 32000, 32001], [32001, 32001, 32001]]);
+        assert_eq!(regions[1].area, 1);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{parse_tile_flags, read_text, sector_files_for_floors, sector_to_world, tile_runs, InputEncoding};
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Tile flags that mark item respawn rather than an area players care about.
+const NON_ZONE_FLAGS: &[&str] = &["Refresh"];
+
+/// All tiles on one floor carrying the same zone flag (`ProtectionZone`,
+/// `NoLogout`, or whatever a server adds), as `[y, x_start, x_end]` runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneLayer {
+    pub floor: u8,
+    pub kind: String,
+    pub tile_count: usize,
+    pub runs: Vec<[u32; 3]>,
+}
+
+pub fn parse_zones_from_sectors<P: AsRef<Path>>(map_dir: P, floors: &[u8], encoding: InputEncoding) -> Result<Vec<ZoneLayer>> {
+    let mut tiles_by_zone: BTreeMap<(u8, String), Vec<(u32, u32)>> = BTreeMap::new();
+
+    for sector in sector_files_for_floors(map_dir.as_ref(), floors)? {
+        let content = match read_text(&sector.path, encoding) {
+            Ok(content) => content,
+            Err(e) => {
+                tracing::warn!("Failed to read {:?}: {}", sector.path, e);
+                continue;
+            }
+        };
+
+        for line in content.lines() {
+            let flags = parse_tile_flags(line);
+            if flags.iter().all(|f| NON_ZONE_FLAGS.contains(f)) {
+                continue;
+            }
+
+            let Some((local_x, local_y)) = line
+                .split_once(':')
+                .and_then(|(coords, _)| coords.trim().split_once('-'))
+                .and_then(|(x, y)| Some((x.parse::<u32>().ok()?, y.parse::<u32>().ok()?)))
+            else {
+                continue;
+            };
+
+            let (x, y) = sector_to_world(sector.sector_x, sector.sector_y, local_x, local_y);
+
+            for flag in flags.into_iter().filter(|f| !NON_ZONE_FLAGS.contains(f)) {
+                tiles_by_zone
+                    .entry((sector.z, flag.to_string()))
+                    .or_default()
+                    .push((x, y));
+            }
+        }
+    }
+
+    let zones: Vec<ZoneLayer> = tiles_by_zone
+        .into_iter()
+        .map(|((floor, kind), mut tiles)| {
+            tiles.sort_unstable_by_key(|&(x, y)| (y, x));
+            tiles.dedup();
+            ZoneLayer {
+                floor,
+                kind,
+                tile_count: tiles.len(),
+                runs: tile_runs(&tiles),
+            }
+        })
+        .collect();
+
+    tracing::info!("Parsed {} zone layers from .sec files", zones.len());
+    Ok(zones)
+}
+
+pub fn generate_zones_json(zones: &[ZoneLayer]) -> Result<String> {
+    let mut zones_by_floor: BTreeMap<u8, BTreeMap<&str, &ZoneLayer>> = BTreeMap::new();
+    for zone in zones {
+        zones_by_floor
+            .entry(zone.floor)
+            .or_default()
+            .insert(zone.kind.as_str(), zone);
+    }
+
+    let output = serde_json::json!({
+        "zones_by_floor": zones_by_floor
+    });
+
+    serde_json::to_string(&output).with_context(|| "Failed to serialize zone data to JSON")
+}
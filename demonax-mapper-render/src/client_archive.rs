@@ -0,0 +1,300 @@
+use crate::outfit::colorize_outfit;
+use anyhow::{Context, Result};
+use demonax_mapper_core::MonsterLook;
+use image::{Rgba, RgbaImage, imageops};
+use std::fs;
+use std::path::Path;
+
+const SPRITE_SIZE: u32 = 32;
+/// The first item ID in the `.dat`; outfits are numbered from 1.
+const FIRST_ITEM_ID: u32 = 100;
+/// Outfit pattern X is the facing direction: north, east, south, west.
+const FACING_SOUTH: u8 = 2;
+
+/// Little-endian reader over an archive held in memory.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos + len;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .with_context(|| format!("Unexpected end of archive at byte {}", self.pos))?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+/// A client `.spr` file: 32×32 sprites stored as runs of transparent and
+/// RGB pixels. Uses the 7.x layout with a 16-bit sprite count.
+pub struct SpriteArchive {
+    data: Vec<u8>,
+    offsets: Vec<u32>,
+}
+
+impl SpriteArchive {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = fs::read(path.as_ref()).with_context(|| format!("Failed to read sprite archive {:?}", path.as_ref()))?;
+        Self::from_bytes(data).with_context(|| format!("Failed to parse sprite archive {:?}", path.as_ref()))
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        let mut reader = Reader::new(&data, 0);
+        let _signature = reader.u32()?;
+        let count = reader.u16()?;
+        let offsets = (0..count).map(|_| reader.u32()).collect::<Result<Vec<_>>>()?;
+        Ok(Self { data, offsets })
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Decodes sprite `id` (1-based). ID 0 and empty slots are transparent.
+    pub fn sprite(&self, id: u32) -> Result<RgbaImage> {
+        let mut sprite = RgbaImage::new(SPRITE_SIZE, SPRITE_SIZE);
+        let offset = match id.checked_sub(1).and_then(|i| self.offsets.get(i as usize)) {
+            Some(&offset) if offset != 0 => offset as usize,
+            Some(_) => return Ok(sprite),
+            None if id == 0 => return Ok(sprite),
+            None => anyhow::bail!("Sprite {} is out of range (archive has {})", id, self.len()),
+        };
+
+        let mut reader = Reader::new(&self.data, offset);
+        reader.bytes(3)?; // transparent color key
+        let size = reader.u16()? as usize;
+        let end = reader.pos + size;
+
+        let mut pixel = 0u32;
+        while reader.pos < end {
+            pixel += reader.u16()? as u32;
+            let colored = reader.u16()?;
+            for _ in 0..colored {
+                let rgb = reader.bytes(3)?;
+                if pixel >= SPRITE_SIZE * SPRITE_SIZE {
+                    anyhow::bail!("Sprite {} has more pixels than fit in 32×32", id);
+                }
+                sprite.put_pixel(pixel % SPRITE_SIZE, pixel / SPRITE_SIZE, Rgba([rgb[0], rgb[1], rgb[2], 255]));
+                pixel += 1;
+            }
+        }
+
+        Ok(sprite)
+    }
+}
+
+/// How one item or outfit is drawn: a grid of `width`×`height` sprites per
+/// layer, pattern and animation phase.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThingType {
+    pub width: u8,
+    pub height: u8,
+    pub layers: u8,
+    pub pattern_x: u8,
+    pub pattern_y: u8,
+    pub pattern_z: u8,
+    pub phases: u8,
+    pub sprites: Vec<u32>,
+}
+
+impl ThingType {
+    fn sprite_index(&self, w: u8, h: u8, layer: u8, pattern_x: u8) -> usize {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let index = layer as usize + pattern_x as usize * self.layers as usize;
+        (index * height + h as usize) * width + w as usize
+    }
+}
+
+/// A client `.dat` file, in the 7.55-7.72 layout the 7.7 client uses.
+/// Only the drawing information is kept; item flags come from `objects.srv`.
+pub struct DatFile {
+    pub items: Vec<ThingType>,
+    pub outfits: Vec<ThingType>,
+}
+
+impl DatFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let data = fs::read(path.as_ref()).with_context(|| format!("Failed to read {:?}", path.as_ref()))?;
+        Self::from_bytes(&data).with_context(|| format!("Failed to parse {:?}", path.as_ref()))
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data, 0);
+        let _signature = reader.u32()?;
+        let item_count = reader.u16()? as u32;
+        let outfit_count = reader.u16()? as u32;
+        let _effect_count = reader.u16()?;
+        let _missile_count = reader.u16()?;
+
+        let items = (FIRST_ITEM_ID..=item_count)
+            .map(|id| read_thing(&mut reader).with_context(|| format!("Invalid item {}", id)))
+            .collect::<Result<Vec<_>>>()?;
+        let outfits = (1..=outfit_count)
+            .map(|id| read_thing(&mut reader).with_context(|| format!("Invalid outfit {}", id)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { items, outfits })
+    }
+
+    pub fn item(&self, id: u32) -> Option<&ThingType> {
+        self.items.get(id.checked_sub(FIRST_ITEM_ID)? as usize)
+    }
+
+    pub fn outfit(&self, look_type: u32) -> Option<&ThingType> {
+        self.outfits.get(look_type.checked_sub(1)? as usize)
+    }
+}
+
+fn read_thing(reader: &mut Reader) -> Result<ThingType> {
+    loop {
+        match reader.u8()? {
+            0xFF => break,
+            // Ground speed, writable length, elevation, minimap color, lens help
+            0x00 | 0x08 | 0x09 | 0x19 | 0x1C | 0x1D => {
+                reader.u16()?;
+            }
+            // Light (level, color) and displacement (x, y)
+            0x15 | 0x18 => {
+                reader.u16()?;
+                reader.u16()?;
+            }
+            0x01..=0x1E => {}
+            flag => anyhow::bail!("Unknown flag 0x{:02x}", flag),
+        }
+    }
+
+    let width = reader.u8()?;
+    let height = reader.u8()?;
+    if width > 1 || height > 1 {
+        reader.u8()?; // exact draw size
+    }
+
+    let mut thing = ThingType {
+        width,
+        height,
+        layers: reader.u8()?,
+        pattern_x: reader.u8()?,
+        pattern_y: reader.u8()?,
+        pattern_z: reader.u8()?,
+        phases: reader.u8()?,
+        sprites: Vec::new(),
+    };
+
+    let count = [thing.width, thing.height, thing.layers, thing.pattern_x, thing.pattern_y, thing.pattern_z, thing.phases]
+        .iter()
+        .map(|&n| n as usize)
+        .product::<usize>();
+    thing.sprites = (0..count).map(|_| reader.u16().map(u32::from)).collect::<Result<_>>()?;
+    Ok(thing)
+}
+
+/// Draws the first animation frame of one layer and pattern. Multi-tile
+/// things grow up and to the left, so the bottom-right tile is sprite 0.
+pub fn compose_thing(sprites: &SpriteArchive, thing: &ThingType, layer: u8, pattern_x: u8) -> Result<RgbaImage> {
+    let mut image = RgbaImage::new(thing.width as u32 * SPRITE_SIZE, thing.height as u32 * SPRITE_SIZE);
+
+    for h in 0..thing.height {
+        for w in 0..thing.width {
+            let index = thing.sprite_index(w, h, layer, pattern_x);
+            let Some(&id) = thing.sprites.get(index) else {
+                continue;
+            };
+            let sprite = sprites.sprite(id)?;
+            let x = (thing.width - 1 - w) as i64 * SPRITE_SIZE as i64;
+            let y = (thing.height - 1 - h) as i64 * SPRITE_SIZE as i64;
+            imageops::overlay(&mut image, &sprite, x, y);
+        }
+    }
+
+    Ok(image)
+}
+
+/// Renders a monster facing south, tinting outfits that have a template
+/// layer with the monster's outfit colors.
+pub fn render_monster_look(sprites: &SpriteArchive, dat: &DatFile, look: &MonsterLook) -> Result<RgbaImage> {
+    match *look {
+        MonsterLook::Outfit { look_type, colors } => {
+            let outfit = dat.outfit(look_type).with_context(|| format!("Unknown lookType {}", look_type))?;
+            let facing = FACING_SOUTH.min(outfit.pattern_x.saturating_sub(1));
+            let base = compose_thing(sprites, outfit, 0, facing)?;
+            if outfit.layers < 2 {
+                return Ok(base);
+            }
+            let template = compose_thing(sprites, outfit, 1, facing)?;
+            Ok(colorize_outfit(&base, &template, colors))
+        }
+        MonsterLook::Item(id) => {
+            let item = dat.item(id).with_context(|| format!("Unknown item {}", id))?;
+            compose_thing(sprites, item, 0, 0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One sprite: a red pixel at (1, 0) and a template sprite that marks it
+    /// as body.
+    fn archive() -> SpriteArchive {
+        let mut data = vec![0, 0, 0, 0, 2, 0];
+        let first = data.len() as u32 + 8;
+        let sprite = [&[255, 0, 255, 7, 0, 1, 0, 1, 0][..], &[200, 200, 200]].concat();
+        let template = [&[255, 0, 255, 7, 0, 1, 0, 1, 0][..], &[255, 0, 0]].concat();
+        data.extend(first.to_le_bytes());
+        data.extend((first + sprite.len() as u32).to_le_bytes());
+        data.extend(sprite);
+        data.extend(template);
+        SpriteArchive::from_bytes(data).unwrap()
+    }
+
+    #[test]
+    fn test_render_monster_look_from_archives() {
+        let mut dat = vec![0, 0, 0, 0, 100, 0, 1, 0, 0, 0, 0, 0];
+        // Item 100: ground (speed 150), one sprite
+        dat.extend([0x00, 150, 0, 0xFF, 1, 1, 1, 1, 1, 1, 1, 1, 0]);
+        // Outfit 1: two layers, four directions; only south has sprites
+        dat.extend([0xFF, 1, 1, 2, 4, 1, 1, 1]);
+        dat.extend([0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 2, 0, 0, 0, 0, 0]);
+        let dat = DatFile::from_bytes(&dat).unwrap();
+
+        assert_eq!(dat.items.len(), 1);
+        assert_eq!(dat.outfit(1).unwrap().sprites, vec![0, 0, 0, 0, 1, 2, 0, 0]);
+
+        let sprites = archive();
+        let look = MonsterLook::Outfit { look_type: 1, colors: [0, 94, 0, 0] };
+        let icon = render_monster_look(&sprites, &dat, &look).unwrap();
+        assert_eq!(icon.get_pixel(1, 0), &Rgba([200, 0, 0, 255]));
+        assert_eq!(icon.get_pixel(0, 0)[3], 0);
+
+        let item = render_monster_look(&sprites, &dat, &MonsterLook::Item(100)).unwrap();
+        assert_eq!(item.get_pixel(1, 0), &Rgba([200, 200, 200, 255]));
+        assert!(render_monster_look(&sprites, &dat, &MonsterLook::Item(101)).is_err());
+    }
+}
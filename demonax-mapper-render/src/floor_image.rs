@@ -0,0 +1,98 @@
+use crate::progress::{ProgressFn, ProgressTracker};
+use crate::sprites::SpriteCache;
+use crate::tiles_sprite::{all_tiles, draw_sprite_tile};
+use anyhow::Result;
+use demonax_mapper_core::{ObjectDatabase, SpriteMapData};
+use image::{imageops, RgbaImage};
+use rayon::prelude::*;
+use std::sync::Mutex;
+
+/// Largest floor image [`render_floor_image`] will allocate, in pixels
+/// (4 GiB of RGBA). PNG encoders and image viewers give up well before this.
+pub const MAX_FLOOR_IMAGE_PIXELS: u64 = 1 << 30;
+
+const TILE_SIZE: u32 = 256;
+
+/// Renders a whole floor at `zoom` (2^zoom pixels per game tile, 5 for
+/// full-size sprites) into one image covering exactly the map area, by
+/// drawing the same 256px tiles as a build and stitching them together.
+pub fn render_floor_image(
+    map_data: &SpriteMapData,
+    sprite_cache: &SpriteCache,
+    objects: &ObjectDatabase,
+    zoom: u8,
+    progress: Option<&ProgressFn<'_>>,
+) -> Result<RgbaImage> {
+    let scale = 2u32.pow(zoom as u32);
+    let bounds = map_data.bounds();
+    let (width, height) = (bounds.width() * scale, bounds.height() * scale);
+    if width as u64 * height as u64 > MAX_FLOOR_IMAGE_PIXELS {
+        anyhow::bail!(
+            "Floor {} at zoom {} would be {}x{} pixels, too large for one image; use a lower zoom",
+            map_data.floor,
+            zoom,
+            width,
+            height
+        );
+    }
+
+    let tiles = all_tiles(map_data, zoom, zoom);
+    let tracker = ProgressTracker::new(progress, map_data.floor, &tiles);
+    let floor_image = Mutex::new(RgbaImage::new(width, height));
+
+    tiles.par_iter().try_for_each(|&(zoom, tile_x, tile_y)| -> Result<()> {
+        let tile = draw_sprite_tile(map_data, sprite_cache, objects, zoom, tile_x, tile_y)?;
+        // Tiles on the right and bottom edge are clipped to the map
+        imageops::replace(
+            &mut *floor_image.lock().unwrap(),
+            &tile,
+            (tile_x * TILE_SIZE) as i64,
+            (tile_y * TILE_SIZE) as i64,
+        );
+        tracker.tile_done(zoom);
+        Ok(())
+    })?;
+
+    Ok(floor_image.into_inner().unwrap())
+}
+
+/// Shrinks `image` so its longer side is at most `max_size` pixels, keeping
+/// the aspect ratio. Smaller images are returned unchanged.
+pub fn downscale_to_fit(image: RgbaImage, max_size: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    if width.max(height) <= max_size {
+        return image;
+    }
+
+    let factor = max_size as f64 / width.max(height) as f64;
+    let new_width = ((width as f64 * factor).round() as u32).max(1);
+    let new_height = ((height as f64 * factor).round() as u32).max(1);
+    imageops::resize(&image, new_width, new_height, imageops::FilterType::Triangle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_floor_image_covers_map_exactly() {
+        // 64 x 32 game tiles: 512 x 256 pixels at zoom 3, two tiles wide
+        let map_data = SpriteMapData {
+            floor: 7,
+            tiles: Vec::new(),
+            min_sector_x: 1000,
+            max_sector_x: 1001,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+        let sprite_cache = SpriteCache::new(std::env::temp_dir()).unwrap();
+
+        let image = render_floor_image(&map_data, &sprite_cache, &ObjectDatabase::new(), 3, None).unwrap();
+        assert_eq!(image.dimensions(), (512, 256));
+
+        assert_eq!(downscale_to_fit(image.clone(), 1000).dimensions(), (512, 256));
+        assert_eq!(downscale_to_fit(image, 128).dimensions(), (128, 64));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{tile_grid_size, tile_key, write_tile_png, OutputSink, TileMetadata, TileRenderStats};
+use anyhow::Result;
+use demonax_mapper_core::{HeatPoint, SpriteMapData, TileFormat};
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// Heat below this fraction of the floor's peak is left fully transparent.
+const MIN_VISIBLE_HEAT: f32 = 0.02;
+
+/// Per-tile heat for one floor, normalized so the hottest tile is 1.0.
+/// Indexed by map-relative tile coordinates, like [`SpriteMapData::tiles`].
+pub struct HeatGrid {
+    pub width: u32,
+    pub height: u32,
+    values: Vec<f32>,
+}
+
+impl HeatGrid {
+    /// Spreads every point on `map_data.floor` over a `radius`-tile kernel
+    /// that falls off smoothly to zero at the edge.
+    pub fn build(points: &[HeatPoint], map_data: &SpriteMapData, radius: u32) -> Self {
+        let bounds = map_data.bounds();
+        let (origin_x, origin_y) = bounds.min_tile();
+        let (width, height) = (bounds.width(), bounds.height());
+        let mut values = vec![0f32; (width * height) as usize];
+
+        let r = radius as i64;
+        for point in points.iter().filter(|p| p.z == map_data.floor) {
+            let px = point.x as i64 - origin_x as i64;
+            let py = point.y as i64 - origin_y as i64;
+
+            for dy in -r..=r {
+                for dx in -r..=r {
+                    let (x, y) = (px + dx, py + dy);
+                    if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+                        continue;
+                    }
+                    let falloff = if r == 0 {
+                        1.0
+                    } else {
+                        let d2 = (dx * dx + dy * dy) as f64 / (r * r) as f64;
+                        if d2 > 1.0 {
+                            continue;
+                        }
+                        (1.0 - d2).powi(2)
+                    };
+                    values[(y as u32 * width + x as u32) as usize] += (point.weight * falloff) as f32;
+                }
+            }
+        }
+
+        let peak = values.iter().copied().fold(0f32, f32::max);
+        if peak > 0.0 {
+            values.iter_mut().for_each(|v| *v = (*v / peak).max(0.0));
+        }
+
+        Self { width, height, values }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        if x >= self.width || y >= self.height {
+            return 0.0;
+        }
+        self.values[(y * self.width + x) as usize]
+    }
+
+    fn has_heat_in(&self, x0: u32, y0: u32, x1: u32, y1: u32) -> bool {
+        (y0..y1.min(self.height))
+            .any(|y| (x0..x1.min(self.width)).any(|x| self.get(x, y) >= MIN_VISIBLE_HEAT))
+    }
+}
+
+/// Blue → green → yellow → red, more opaque as it gets hotter.
+fn heat_color(heat: f32) -> Rgba<u8> {
+    if heat < MIN_VISIBLE_HEAT {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    const STOPS: [(f32, [u8; 3]); 4] = [
+        (0.0, [0, 0, 255]),
+        (0.35, [0, 255, 0]),
+        (0.65, [255, 255, 0]),
+        (1.0, [255, 0, 0]),
+    ];
+
+    let heat = heat.min(1.0);
+    let i = STOPS.iter().rposition(|(t, _)| *t <= heat).unwrap_or(0).min(STOPS.len() - 2);
+    let (t0, c0) = STOPS[i];
+    let (t1, c1) = STOPS[i + 1];
+    let f = (heat - t0) / (t1 - t0);
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * f).round() as u8;
+    let alpha = (60.0 + 150.0 * heat).round() as u8;
+
+    Rgba([mix(c0[0], c1[0]), mix(c0[1], c1[1]), mix(c0[2], c1[2]), alpha])
+}
+
+/// Renders `heatmap/<floor>/<zoom>/<x>/<y>.png` into `sink` on the same tile
+/// grid as the map. Tiles without any heat are not written; the viewer treats
+/// a missing tile as transparent.
+pub fn generate_heatmap_tiles(
+    points: &[HeatPoint],
+    map_data: &SpriteMapData,
+    sink: &dyn OutputSink,
+    min_zoom: u8,
+    max_zoom: u8,
+    radius: u32,
+) -> Result<TileRenderStats> {
+    const TILE_SIZE: u32 = 256;
+
+    let grid = HeatGrid::build(points, map_data, radius);
+    let mut total = TileRenderStats::default();
+
+    for zoom in min_zoom..=max_zoom {
+        let scale = 2u32.pow(zoom as u32);
+        let (num_tiles_x, num_tiles_y) = tile_grid_size(map_data, zoom);
+
+        let tile_coords: Vec<(u32, u32)> = (0..num_tiles_x)
+            .flat_map(|x| (0..num_tiles_y).map(move |y| (x, y)))
+            .filter(|&(x, y)| {
+                grid.has_heat_in(
+                    x * TILE_SIZE / scale,
+                    y * TILE_SIZE / scale,
+                    (x + 1) * TILE_SIZE / scale,
+                    (y + 1) * TILE_SIZE / scale,
+                )
+            })
+            .collect();
+
+        let bytes = tile_coords
+            .par_iter()
+            .map(|&(tile_x, tile_y)| -> Result<u64> {
+                let image = RgbaImage::from_fn(TILE_SIZE, TILE_SIZE, |px, py| {
+                    heat_color(grid.get(
+                        (tile_x * TILE_SIZE + px) / scale,
+                        (tile_y * TILE_SIZE + py) / scale,
+                    ))
+                });
+
+                let key = format!("heatmap/{}", tile_key(map_data.floor, zoom, tile_x, tile_y, TileFormat::Png));
+                let metadata = TileMetadata::for_tile("heatmap", map_data, zoom, tile_x, tile_y);
+                write_tile_png(sink, &key, &image, &metadata)
+            })
+            .try_reduce(|| 0, |a, b| Ok(a + b))?;
+
+        total += TileRenderStats {
+            tiles: tile_coords.len(),
+            bytes,
+        };
+    }
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_map(floor: u8) -> SpriteMapData {
+        SpriteMapData {
+            floor,
+            tiles: Vec::new(),
+            min_sector_x: 1000,
+            max_sector_x: 1000,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            version: 2,
+            parse_issues: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_heat_grid_peaks_at_hottest_point_and_ignores_other_floors() {
+        let points = [
+            HeatPoint { x: 32010, y: 32010, z: 7, weight: 1.0 },
+            HeatPoint { x: 32020, y: 32010, z: 7, weight: 3.0 },
+            HeatPoint { x: 32005, y: 32005, z: 8, weight: 100.0 },
+        ];
+
+        let grid = HeatGrid::build(&points, &empty_map(7), 4);
+        assert_eq!(grid.get(20, 10), 1.0);
+        assert!((grid.get(10, 10) - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(grid.get(15, 10), 0.0);
+        assert_eq!(grid.get(5, 5), 0.0);
+    }
+
+    #[test]
+    fn test_heat_color_is_transparent_when_cold() {
+        assert_eq!(heat_color(0.0)[3], 0);
+        assert_eq!(heat_color(1.0), Rgba([255, 0, 0, 210]));
+    }
+}
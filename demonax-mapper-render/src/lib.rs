@@ -0,0 +1,43 @@
+pub mod client_archive;
+pub mod floor_image;
+pub mod heatmap;
+pub mod manifest;
+pub mod outfit;
+pub mod output_sink;
+pub mod palette;
+pub mod pmtiles;
+pub mod prefetch;
+pub mod prewarm;
+pub mod progress;
+pub mod pyramid;
+pub mod render_floors;
+pub mod self_test;
+pub mod sprite_sheets;
+pub mod sprite_source;
+pub mod sprites;
+pub mod thumbnails;
+pub mod tile_png;
+pub mod tiles_color;
+pub mod tiles_sprite;
+
+pub use client_archive::*;
+pub use floor_image::*;
+pub use heatmap::*;
+pub use manifest::*;
+pub use outfit::*;
+pub use output_sink::*;
+pub use palette::*;
+pub use pmtiles::*;
+pub use prefetch::*;
+pub use prewarm::*;
+pub use progress::*;
+pub use pyramid::*;
+pub use render_floors::*;
+pub use self_test::*;
+pub use sprite_sheets::*;
+pub use sprite_source::*;
+pub use sprites::*;
+pub use thumbnails::*;
+pub use tile_png::*;
+pub use tiles_color::*;
+pub use tiles_sprite::*;
@@ -0,0 +1,270 @@
+use anyhow::{Context, Result};
+use demonax_mapper_core::TileArchiveFormat;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+pub const TILE_MANIFEST_FILE: &str = "tiles-manifest.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+/// Every tile PNG of a build, keyed by its output-relative path, so a
+/// deployed copy can be checked against what the build produced.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TileManifest {
+    pub generator: String,
+    /// The `--output-archive` the tiles were written to instead of the
+    /// output directory, relative to it when inside it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<PathBuf>,
+    pub tiles: BTreeMap<String, ManifestEntry>,
+}
+
+impl TileManifest {
+    pub fn load(output_dir: &Path) -> Result<Self> {
+        let path = output_dir.join(TILE_MANIFEST_FILE);
+        let content = fs::read_to_string(&path).with_context(|| format!("Failed to read tile manifest {:?}", path))?;
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse tile manifest {:?}", path))
+    }
+
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).with_context(|| "Failed to serialize tile manifest")?;
+        fs::write(output_dir.join(TILE_MANIFEST_FILE), json)?;
+        Ok(())
+    }
+}
+
+/// Hashes every PNG, WebP and AVIF tile under the given output subdirectories
+/// (floor directories, `heatmap/`...) and any of them that is a file, such
+/// as a PMTiles archive. Paths that do not exist are skipped.
+pub fn build_tile_manifest(output_dir: &Path, tile_dirs: &[String]) -> Result<TileManifest> {
+    let mut files = Vec::new();
+    for dir in tile_dirs {
+        let path = output_dir.join(dir);
+        if path.is_dir() {
+            collect_tiles(&path, &mut files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+
+    let tiles = files
+        .par_iter()
+        .map(|path| -> Result<(String, ManifestEntry)> {
+            let data = fs::read(path).with_context(|| format!("Failed to read tile {:?}", path))?;
+            Ok((relative_url(output_dir, path), entry_for(&data)))
+        })
+        .collect::<Result<BTreeMap<_, _>>>()?;
+
+    Ok(TileManifest {
+        generator: format!("demonax-mapper {}", env!("CARGO_PKG_VERSION")),
+        archive: None,
+        tiles,
+    })
+}
+
+fn collect_tiles(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_tiles(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "png" || ext == "webp" || ext == "avif") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn relative_url(output_dir: &Path, path: &Path) -> String {
+    path.strip_prefix(output_dir)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub(crate) fn entry_for(data: &[u8]) -> ManifestEntry {
+    let digest = Sha256::digest(data);
+    ManifestEntry {
+        bytes: data.len() as u64,
+        sha256: digest.iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TileProblem {
+    Missing,
+    SizeMismatch,
+    HashMismatch,
+    Undecodable,
+}
+
+impl TileProblem {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TileProblem::Missing => "missing",
+            TileProblem::SizeMismatch => "size mismatch",
+            TileProblem::HashMismatch => "hash mismatch",
+            TileProblem::Undecodable => "undecodable",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TileIssue {
+    pub tile: String,
+    pub problem: TileProblem,
+}
+
+/// Checks each manifest entry against its tile: it must exist, match the
+/// recorded size and hash, and decode as an image, or start like a PMTiles
+/// archive. Tiles of an archive build are checked inside the archive while
+/// it exists, and on disk when it does not hold them or was unpacked and
+/// removed.
+pub fn verify_tile_manifest(output_dir: &Path, manifest: &TileManifest) -> Result<Vec<TileIssue>> {
+    let mut issues = Vec::new();
+    let mut archived = HashSet::new();
+    if let Some(archive) = manifest.archive.as_ref().map(|archive| output_dir.join(archive))
+        && archive.is_file()
+    {
+        for_each_archived_tile(&archive, |tile, data| {
+            if let Some((tile, expected)) = manifest.tiles.get_key_value(tile) {
+                archived.insert(tile.as_str());
+                issues.extend(check_tile(tile, expected, Some(data)));
+            }
+        })?;
+    }
+
+    issues.par_extend(
+        manifest
+            .tiles
+            .par_iter()
+            .filter(|(tile, _)| !archived.contains(tile.as_str()))
+            .filter_map(|(tile, expected)| check_tile(tile, expected, fs::read(output_dir.join(tile)).ok().as_deref())),
+    );
+
+    issues.sort_by(|a, b| a.tile.cmp(&b.tile));
+    Ok(issues)
+}
+
+fn check_tile(tile: &str, expected: &ManifestEntry, data: Option<&[u8]>) -> Option<TileIssue> {
+    let problem = match data {
+        None => TileProblem::Missing,
+        Some(data) if data.len() as u64 != expected.bytes => TileProblem::SizeMismatch,
+        Some(data) if entry_for(data).sha256 != expected.sha256 => TileProblem::HashMismatch,
+        Some(data) if tile.ends_with(".pmtiles") && !data.starts_with(b"PMTiles") => TileProblem::Undecodable,
+        Some(data) if !tile.ends_with(".pmtiles") && image::load_from_memory(data).is_err() => TileProblem::Undecodable,
+        Some(_) => return None,
+    };
+    Some(TileIssue {
+        tile: tile.to_string(),
+        problem,
+    })
+}
+
+/// Calls `f` with the path and contents of each file in a `.zip` or
+/// `.tar.gz` tile archive, one at a time.
+fn for_each_archived_tile(path: &Path, mut f: impl FnMut(&str, &[u8])) -> Result<()> {
+    let format = TileArchiveFormat::of(path)
+        .with_context(|| format!("Tile archive {:?} must end in .zip, .tar.gz or .tgz", path))?;
+    let file = BufReader::new(File::open(path).with_context(|| format!("Failed to open tile archive {:?}", path))?);
+    let mut data = Vec::new();
+
+    match format {
+        TileArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(file).with_context(|| format!("Failed to read tile archive {:?}", path))?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i)?;
+                data.clear();
+                entry.read_to_end(&mut data)?;
+                f(entry.name(), &data);
+            }
+        }
+        TileArchiveFormat::TarGz => {
+            let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(file));
+            for entry in tar.entries().with_context(|| format!("Failed to read tile archive {:?}", path))? {
+                let mut entry = entry?;
+                let name = entry.path()?.to_string_lossy().into_owned();
+                data.clear();
+                entry.read_to_end(&mut data)?;
+                f(&name, &data);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ArchiveSink, OutputSink};
+
+    #[test]
+    fn test_verify_checks_tiles_inside_archive() {
+        let dir = std::env::temp_dir().join(format!("demonax-manifest-archive-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut png = Vec::new();
+        image::RgbaImage::new(2, 2)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        for name in ["tiles.zip", "tiles.tar.gz"] {
+            let sink = ArchiveSink::create(dir.join(name)).unwrap();
+            sink.write("7/0/0/0.png", &png).unwrap();
+            sink.finish().unwrap();
+
+            let mut manifest = build_tile_manifest(&dir, &["7".to_string()]).unwrap();
+            manifest.tiles.extend(sink.manifest_entries());
+            manifest.archive = Some(PathBuf::from(name));
+            assert!(verify_tile_manifest(&dir, &manifest).unwrap().is_empty(), "{}", name);
+
+            manifest.tiles.insert("7/0/0/1.png".to_string(), entry_for(&png));
+            assert_eq!(
+                verify_tile_manifest(&dir, &manifest).unwrap(),
+                vec![TileIssue { tile: "7/0/0/1.png".to_string(), problem: TileProblem::Missing }]
+            );
+        }
+        fs::remove_dir_all(&dir).ok();
+    }
+
+
+    #[test]
+    fn test_verify_detects_changed_and_missing_tiles() {
+        let dir = std::env::temp_dir().join("demonax-manifest-test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(dir.join("7/0/0")).unwrap();
+        image::RgbaImage::new(2, 2).save(dir.join("7/0/0/0.png")).unwrap();
+        image::RgbaImage::new(2, 2).save(dir.join("7/0/0/1.png")).unwrap();
+        image::RgbaImage::new(2, 2).save(dir.join("7/0/0/2.png")).unwrap();
+
+        let manifest = build_tile_manifest(&dir, &["7".to_string(), "heatmap".to_string()]).unwrap();
+        assert_eq!(manifest.tiles.len(), 3);
+        assert!(verify_tile_manifest(&dir, &manifest).unwrap().is_empty());
+
+        fs::remove_file(dir.join("7/0/0/1.png")).unwrap();
+        let mut corrupt = fs::read(dir.join("7/0/0/2.png")).unwrap();
+        corrupt[20] ^= 0xff;
+        fs::write(dir.join("7/0/0/2.png"), corrupt).unwrap();
+
+        let issues = verify_tile_manifest(&dir, &manifest).unwrap();
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(
+            issues,
+            vec![
+                TileIssue { tile: "7/0/0/1.png".to_string(), problem: TileProblem::Missing },
+                TileIssue { tile: "7/0/0/2.png".to_string(), problem: TileProblem::HashMismatch },
+            ]
+        );
+    }
+}
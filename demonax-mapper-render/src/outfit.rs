@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use image::{Rgba, RgbaImage};
+use std::path::Path;
+
+/// The outfit palette is 19 hues by 7 saturation/intensity rows.
+const HUE_STEPS: u8 = 19;
+const PALETTE_ROWS: u8 = 7;
+
+/// RGB of an outfit color index (0-132), as the client computes it. Out of
+/// range indices fall back to 0 (white).
+pub fn outfit_color(index: u8) -> [u8; 3] {
+    let index = if index >= HUE_STEPS * PALETTE_ROWS { 0 } else { index };
+    let hue_step = index % HUE_STEPS;
+
+    let (hue, saturation, intensity) = if hue_step == 0 {
+        (0.0, 0.0, 1.0 - index as f32 / HUE_STEPS as f32 / PALETTE_ROWS as f32)
+    } else {
+        let (saturation, intensity) = match index / HUE_STEPS {
+            0 => (0.25, 1.0),
+            1 => (0.25, 0.75),
+            2 => (0.5, 0.75),
+            3 => (0.667, 0.75),
+            4 => (1.0, 1.0),
+            5 => (1.0, 0.75),
+            _ => (1.0, 0.5),
+        };
+        (hue_step as f32 / 18.0, saturation, intensity)
+    };
+
+    if intensity == 0.0 {
+        return [0, 0, 0];
+    }
+    if saturation == 0.0 {
+        let grey = (intensity * 255.0) as u8;
+        return [grey, grey, grey];
+    }
+
+    let low = intensity * (1.0 - saturation);
+    let (r, g, b) = if hue < 1.0 / 6.0 {
+        (intensity, low + (intensity - low) * 6.0 * hue, low)
+    } else if hue < 2.0 / 6.0 {
+        (intensity - (intensity - low) * (6.0 * hue - 1.0), intensity, low)
+    } else if hue < 3.0 / 6.0 {
+        (low, intensity, low + (intensity - low) * (6.0 * hue - 2.0))
+    } else if hue < 4.0 / 6.0 {
+        (low, intensity - (intensity - low) * (6.0 * hue - 3.0), intensity)
+    } else if hue < 5.0 / 6.0 {
+        (low + (intensity - low) * (6.0 * hue - 4.0), low, intensity)
+    } else {
+        (intensity, low, intensity - (intensity - low) * (6.0 * hue - 5.0))
+    };
+
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+}
+
+/// Tints a grey outfit sprite with head/body/legs/feet colors. The template
+/// marks each part with a pure color: yellow head, red body, green legs and
+/// blue feet; pixels of any other template color are left as they are.
+pub fn colorize_outfit(base: &RgbaImage, template: &RgbaImage, colors: [u8; 4]) -> RgbaImage {
+    let tints = colors.map(outfit_color);
+    let mut output = base.clone();
+
+    for (x, y, pixel) in output.enumerate_pixels_mut() {
+        if x >= template.width() || y >= template.height() {
+            continue;
+        }
+
+        let part = match template.get_pixel(x, y).0 {
+            [255, 255, 0, _] => 0,
+            [255, 0, 0, _] => 1,
+            [0, 255, 0, _] => 2,
+            [0, 0, 255, _] => 3,
+            _ => continue,
+        };
+
+        let tint = tints[part];
+        let Rgba([r, g, b, a]) = *pixel;
+        *pixel = Rgba([
+            (r as u16 * tint[0] as u16 / 255) as u8,
+            (g as u16 * tint[1] as u16 / 255) as u8,
+            (b as u16 * tint[2] as u16 / 255) as u8,
+            a,
+        ]);
+    }
+
+    output
+}
+
+/// Loads an outfit sprite and its template PNG and colorizes the sprite.
+pub fn load_colorized_outfit(base: &Path, template: &Path, colors: [u8; 4]) -> Result<RgbaImage> {
+    let base = image::open(base).with_context(|| format!("Failed to load outfit sprite {:?}", base))?;
+    let template = image::open(template).with_context(|| format!("Failed to load outfit template {:?}", template))?;
+    Ok(colorize_outfit(&base.to_rgba8(), &template.to_rgba8(), colors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colorize_outfit_tints_template_parts() {
+        assert_eq!(outfit_color(0), [255, 255, 255]);
+        assert_eq!(outfit_color(114), [36, 36, 36]);
+        assert_eq!(outfit_color(94), [255, 0, 0]);
+
+        let base = RgbaImage::from_pixel(3, 1, Rgba([200, 200, 200, 255]));
+        let mut template = RgbaImage::new(3, 1);
+        template.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        template.put_pixel(1, 0, Rgba([0, 0, 255, 255]));
+
+        let colored = colorize_outfit(&base, &template, [0, 94, 0, 114]);
+        assert_eq!(colored.get_pixel(0, 0), &Rgba([200, 0, 0, 255]));
+        assert_eq!(colored.get_pixel(1, 0), &Rgba([28, 28, 28, 255]));
+        assert_eq!(colored.get_pixel(2, 0), &Rgba([200, 200, 200, 255]));
+    }
+}
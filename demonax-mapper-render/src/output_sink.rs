@@ -0,0 +1,242 @@
+use crate::manifest::{entry_for, ManifestEntry};
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use demonax_mapper_core::{TileArchiveFormat, TileFormat};
+use flate2::write::GzEncoder;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where rendered tiles are stored. Renderers only produce encoded bytes and
+/// a key, so archives, databases or object stores can be added as sinks
+/// without touching the rendering code.
+///
+/// Keys are `/`-separated paths relative to the output root, e.g.
+/// `7/3/12/9.png` or `heatmap/7/3/12/9.png`. Tiles are written from many
+/// threads at once.
+pub trait OutputSink: Send + Sync {
+    fn write(&self, key: &str, data: &[u8]) -> Result<()>;
+
+    /// Called once after the last tile, for sinks that buffer or need to
+    /// write an index. The default does nothing.
+    fn finish(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Key of a map tile: `<floor>/<zoom>/<x>/<y>.<png|webp|avif>`.
+pub fn tile_key(floor: u8, zoom: u8, tile_x: u32, tile_y: u32, format: TileFormat) -> String {
+    format!("{}/{}/{}/{}.{}", floor, zoom, tile_x, tile_y, format.extension())
+}
+
+/// Temp files of [`FileSink`] are numbered so two writers never share one.
+static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Writes each key as a file below `root`, creating directories as needed.
+/// Files are written under a temporary name and renamed into place, so a
+/// server reading the output never sees half a tile.
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    root: PathBuf,
+}
+
+impl FileSink {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self { root: root.as_ref().to_path_buf() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl OutputSink for FileSink {
+    fn write(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp, data).with_context(|| format!("Failed to write tile {:?}", temp))?;
+        fs::rename(&temp, &path).with_context(|| {
+            fs::remove_file(&temp).ok();
+            format!("Failed to move tile into place at {:?}", path)
+        })
+    }
+}
+
+enum ArchiveWriter {
+    Zip(zip::ZipWriter<BufWriter<File>>),
+    TarGz(tar::Builder<GzEncoder<BufWriter<File>>>),
+}
+
+/// Streams every key into one `.zip` or `.tar.gz` file, for output on
+/// network filesystems where creating many small files is slow. Tiles are
+/// appended in the order they are drawn; the archive is unusable until
+/// [`OutputSink::finish`] has written its end.
+pub struct ArchiveSink {
+    path: PathBuf,
+    writer: Mutex<Option<ArchiveWriter>>,
+    /// Size and hash of each tile, for the tile manifest.
+    entries: Mutex<BTreeMap<String, ManifestEntry>>,
+    modified: u64,
+}
+
+impl ArchiveSink {
+    /// Creates `path`, a `.zip`, `.tar.gz` or `.tgz` file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let format = TileArchiveFormat::of(&path)
+            .with_context(|| format!("Tile archive {:?} must end in .zip, .tar.gz or .tgz", path))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = BufWriter::new(File::create(&path).with_context(|| format!("Failed to create tile archive {:?}", path))?);
+        let writer = match format {
+            TileArchiveFormat::Zip => ArchiveWriter::Zip(zip::ZipWriter::new(file)),
+            // Tiles are compressed images already, so speed beats ratio
+            TileArchiveFormat::TarGz => ArchiveWriter::TarGz(tar::Builder::new(GzEncoder::new(file, flate2::Compression::fast()))),
+        };
+
+        Ok(Self {
+            path,
+            writer: Mutex::new(Some(writer)),
+            entries: Mutex::new(BTreeMap::new()),
+            modified: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Size and SHA-256 of every tile written, keyed like the tile manifest.
+    pub fn manifest_entries(&self) -> BTreeMap<String, ManifestEntry> {
+        self.entries.lock().expect("archive lock").clone()
+    }
+}
+
+impl OutputSink for ArchiveSink {
+    fn write(&self, key: &str, data: &[u8]) -> Result<()> {
+        let entry = entry_for(data);
+        let mut writer = self.writer.lock().expect("archive lock");
+        match writer.as_mut().context("Tile archive is already finished")? {
+            ArchiveWriter::Zip(zip) => {
+                let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+                zip.start_file(key, options)?;
+                zip.write_all(data)?;
+            }
+            ArchiveWriter::TarGz(tar) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(self.modified);
+                tar.append_data(&mut header, key, data)?;
+            }
+        }
+        drop(writer);
+
+        self.entries.lock().expect("archive lock").insert(key.to_string(), entry);
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<()> {
+        let file = match self.writer.lock().expect("archive lock").take() {
+            Some(ArchiveWriter::Zip(zip)) => zip.finish()?,
+            Some(ArchiveWriter::TarGz(tar)) => tar.into_inner()?.finish()?,
+            None => return Ok(()),
+        };
+        file.into_inner()
+            .map_err(|e| e.into_error())
+            .with_context(|| format!("Failed to write tile archive {:?}", self.path))?;
+        Ok(())
+    }
+}
+
+/// Keeps every tile in memory, for tests that assert on rendered output.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    files: DashMap<String, Vec<u8>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.files.get(key).map(|data| data.clone())
+    }
+
+    /// Every key written so far, sorted.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.files.iter().map(|entry| entry.key().clone()).collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn write(&self, key: &str, data: &[u8]) -> Result<()> {
+        self.files.insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_archive_sink_writes_zip_and_tar_gz() {
+        let dir = std::env::temp_dir().join(format!("demonax-archive-sink-{}", std::process::id()));
+        let (zip_path, tar_path) = (dir.join("tiles.zip"), dir.join("tiles.tar.gz"));
+        for path in [&zip_path, &tar_path] {
+            let sink = ArchiveSink::create(path).unwrap();
+            sink.write("7/0/0/0.png", b"land").unwrap();
+            sink.write("heatmap/7/0/0/0.png", b"heat").unwrap();
+            sink.finish().unwrap();
+            assert_eq!(sink.manifest_entries()["7/0/0/0.png"], entry_for(b"land"));
+        }
+        assert!(ArchiveSink::create(dir.join("tiles.rar")).is_err());
+
+        let mut zip = zip::ZipArchive::new(File::open(&zip_path).unwrap()).unwrap();
+        let mut land = String::new();
+        zip.by_name("7/0/0/0.png").unwrap().read_to_string(&mut land).unwrap();
+        assert_eq!((zip.len(), land.as_str()), (2, "land"));
+
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(File::open(&tar_path).unwrap()));
+        let tar_entries: Vec<(String, String)> = tar
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut data = String::new();
+                entry.read_to_string(&mut data).unwrap();
+                (entry.path().unwrap().to_string_lossy().into_owned(), data)
+            })
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            tar_entries,
+            [("7/0/0/0.png".to_string(), "land".to_string()), ("heatmap/7/0/0/0.png".to_string(), "heat".to_string())]
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use crate::sprites::SpriteCache;
+use anyhow::{Context, Result};
+use demonax_mapper_core::{ObjectDatabase, SpriteMapData};
+use image::RgbaImage;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// One flat color per object ID, for color (minimap-style) rendering.
+/// Sorted so exported files diff cleanly after hand edits.
+pub type ColorMap = BTreeMap<u32, [u8; 3]>;
+
+/// Averages the sprite of every drawable object (following `DisguiseTarget`).
+/// Takeable items and objects without a readable sprite are left out.
+pub fn create_color_map(objects: &ObjectDatabase, sprite_cache: &SpriteCache) -> Result<ColorMap> {
+    let mut colors = ColorMap::new();
+
+    for (&id, obj) in objects {
+        if obj.flags.is_takeable() && !obj.flags.is_container() {
+            continue;
+        }
+
+        let sprite_id = obj.disguise_target.unwrap_or(id);
+        if !sprite_cache.has_sprite(sprite_id) {
+            continue;
+        }
+
+        let sprite = sprite_cache.get_sprite(sprite_id)?;
+        if sprite_cache.is_placeholder(&sprite) {
+            continue;
+        }
+        if let Some(color) = average_color(&sprite) {
+            colors.insert(id, color);
+        }
+    }
+
+    tracing::info!("Computed colors for {} objects", colors.len());
+    Ok(colors)
+}
+
+/// Alpha-weighted mean of the sprite's pixels, `None` if fully transparent.
+pub fn average_color(sprite: &RgbaImage) -> Option<[u8; 3]> {
+    let mut sums = [0u64; 3];
+    let mut weight = 0u64;
+
+    for pixel in sprite.pixels() {
+        let alpha = pixel[3] as u64;
+        for (sum, &channel) in sums.iter_mut().zip(&pixel.0[..3]) {
+            *sum += channel as u64 * alpha;
+        }
+        weight += alpha;
+    }
+
+    (weight > 0).then(|| sums.map(|sum| ((sum + weight / 2) / weight) as u8))
+}
+
+/// Tiles from the map edge that count towards [`floor_background`].
+const BACKGROUND_EDGE: u32 = 8;
+
+/// Average sprite color of an object, following `DisguiseTarget`. `None` for
+/// placeholders and fully transparent sprites.
+pub fn object_sprite_color(objects: &ObjectDatabase, sprite_cache: &SpriteCache, id: u32) -> Option<[u8; 3]> {
+    let sprite_id = objects.get(&id).and_then(|obj| obj.disguise_target).unwrap_or(id);
+    let sprite = sprite_cache.get_sprite(sprite_id).ok()?;
+    if sprite_cache.is_placeholder(&sprite) {
+        return None;
+    }
+    average_color(&sprite)
+}
+
+/// Color of the most common ground (`Bank`) object near the edges of the
+/// floor, so the viewer background blends in past the rendered area. Falls
+/// back to the whole floor when nothing lies near the edges. `color_of` maps
+/// an object ID to its color, e.g. [`object_sprite_color`] or a [`ColorMap`].
+pub fn floor_background(
+    map_data: &SpriteMapData,
+    objects: &ObjectDatabase,
+    color_of: impl Fn(u32) -> Option<[u8; 3]>,
+) -> Option<[u8; 3]> {
+    void_color(&[map_data], objects, color_of)
+}
+
+/// The map's "void": [`floor_background`] taken over all `floors` at once,
+/// shown behind floors that have no background of their own.
+pub fn void_color(
+    floors: &[&SpriteMapData],
+    objects: &ObjectDatabase,
+    color_of: impl Fn(u32) -> Option<[u8; 3]>,
+) -> Option<[u8; 3]> {
+    let is_bank = |id: &u32| objects.get(id).is_some_and(|obj| obj.flags.is_bank());
+    dominant_color(floors, is_bank, true, &color_of).or_else(|| dominant_color(floors, is_bank, false, &color_of))
+}
+
+/// Color of the most common water object on `floors`, for the viewer's
+/// water overlay.
+pub fn water_color(
+    floors: &[&SpriteMapData],
+    objects: &ObjectDatabase,
+    color_of: impl Fn(u32) -> Option<[u8; 3]>,
+) -> Option<[u8; 3]> {
+    let is_water = |id: &u32| objects.get(id).is_some_and(|obj| obj.is_water());
+    dominant_color(floors, is_water, false, &color_of)
+}
+
+/// Color of the object `matches` picks most often, counting the first match
+/// of each tile, on tiles near the map edges only if `edge_only`. Objects
+/// without a color are passed over for the next most common.
+fn dominant_color(
+    floors: &[&SpriteMapData],
+    matches: impl Fn(&u32) -> bool,
+    edge_only: bool,
+    color_of: impl Fn(u32) -> Option<[u8; 3]>,
+) -> Option<[u8; 3]> {
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for map_data in floors {
+        let bounds = map_data.bounds();
+        let near_edge = |x: u32, y: u32| {
+            x < BACKGROUND_EDGE
+                || y < BACKGROUND_EDGE
+                || x + BACKGROUND_EDGE >= bounds.width()
+                || y + BACKGROUND_EDGE >= bounds.height()
+        };
+        for tile in map_data.tiles.iter().filter(|t| !edge_only || near_edge(t.x, t.y)) {
+            if let Some(&id) = tile.object_ids.iter().find(|id| matches(id)) {
+                *counts.entry(id).or_default() += 1;
+            }
+        }
+    }
+
+    let mut found: Vec<(u32, usize)> = counts.into_iter().collect();
+    found.sort_by_key(|&(id, count)| (std::cmp::Reverse(count), id));
+    found.into_iter().find_map(|(id, _)| color_of(id))
+}
+
+pub fn hex_color([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Writes `{"<object id>": "#rrggbb"}`.
+/// Whether a color map path is TOML rather than JSON, by its extension.
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("toml"))
+}
+
+/// Writes `colors` as `<id> = "#rrggbb"` TOML lines when `path` ends in
+/// `.toml` and as a JSON object otherwise, sorted by object ID either way.
+pub fn save_color_map<P: AsRef<Path>>(colors: &ColorMap, path: P) -> Result<()> {
+    let hex: BTreeMap<u32, String> = colors
+        .iter()
+        .map(|(&id, &color)| (id, hex_color(color)))
+        .collect();
+
+    let content = if is_toml(path.as_ref()) {
+        // Written by hand, as TOML tables sort their keys as strings
+        hex.iter().map(|(id, color)| format!("{} = \"{}\"\n", id, color)).collect()
+    } else {
+        serde_json::to_string_pretty(&hex).with_context(|| "Failed to serialize color map")?
+    };
+    fs::write(path.as_ref(), content).with_context(|| format!("Failed to write color map {:?}", path.as_ref()))
+}
+
+/// Reads a color map written by [`save_color_map`], possibly hand edited.
+pub fn load_color_map<P: AsRef<Path>>(path: P) -> Result<ColorMap> {
+    let content = fs::read_to_string(path.as_ref())
+        .with_context(|| format!("Failed to read color map {:?}", path.as_ref()))?;
+    // TOML keys are always strings, so both formats are read with string IDs
+    let hex: BTreeMap<String, String> = if is_toml(path.as_ref()) {
+        toml::from_str(&content).with_context(|| format!("Failed to parse color map {:?}", path.as_ref()))?
+    } else {
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse color map {:?}", path.as_ref()))?
+    };
+
+    hex.into_iter()
+        .map(|(id, value)| {
+            let object_id = id
+                .parse()
+                .with_context(|| format!("Invalid object ID {:?} in {:?}", id, path.as_ref()))?;
+            parse_hex_color(&value)
+                .map(|color| (object_id, color))
+                .with_context(|| format!("Invalid color {:?} for object {} in {:?}", value, id, path.as_ref()))
+        })
+        .collect()
+}
+
+fn parse_hex_color(value: &str) -> Option<[u8; 3]> {
+    let hex = value.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_color_map_round_trips_as_toml_and_json() {
+        let dir = std::env::temp_dir().join(format!("demonax-color-map-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let colors: ColorMap = [(100, [255, 0, 16]), (99, [1, 2, 3])].into_iter().collect();
+
+        for name in ["colors.toml", "colors.json"] {
+            save_color_map(&colors, dir.join(name)).unwrap();
+            assert_eq!(load_color_map(dir.join(name)).unwrap(), colors, "{}", name);
+        }
+        let toml = fs::read_to_string(dir.join("colors.toml")).unwrap();
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(toml, "99 = \"#010203\"\n100 = \"#ff0010\"\n");
+    }
+
+    #[test]
+    fn test_average_color_ignores_transparent_pixels() {
+        let mut sprite = RgbaImage::new(2, 2);
+        sprite.put_pixel(0, 0, Rgba([200, 100, 0, 255]));
+        sprite.put_pixel(1, 0, Rgba([100, 50, 0, 255]));
+        sprite.put_pixel(0, 1, Rgba([255, 255, 255, 0]));
+
+        assert_eq!(average_color(&sprite), Some([150, 75, 0]));
+        assert_eq!(average_color(&RgbaImage::new(2, 2)), None);
+        assert_eq!(parse_hex_color("#4a7F2c"), Some([0x4a, 0x7f, 0x2c]));
+        assert_eq!(parse_hex_color("4a7f2c"), None);
+    }
+
+    #[test]
+    fn test_void_and_water_colors_over_all_floors() {
+        use demonax_mapper_core::{GameObject, ObjectFlags, TileStack};
+
+        let object = |id: u32, name: &str| GameObject {
+            id,
+            name: name.to_string(),
+            flags: ObjectFlags::BANK,
+            unknown_flags: Vec::new(),
+            waypoints: 100,
+            is_ground: true,
+            is_impassable: false,
+            disguise_target: None,
+            attributes: Default::default(),
+            layer: None,
+        };
+        let objects: ObjectDatabase =
+            [(100, object(100, "grass")), (200, object(200, "shallow water")), (201, object(201, "deep water"))].into();
+        let colors: ColorMap = [(100, [0, 200, 0]), (200, [30, 60, 120]), (201, [20, 40, 100])].into();
+        let floor = |floor: u8, tiles: Vec<(u32, u32, u32)>| SpriteMapData {
+            floor,
+            tiles: tiles.into_iter().map(|(x, y, id)| TileStack { x, y, object_ids: vec![id] }).collect(),
+            min_sector_x: 1000,
+            max_sector_x: 1001,
+            min_sector_y: 1000,
+            max_sector_y: 1001,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+        // Sea around the edges of floor 7, an island of grass in the middle
+        // and a pond of shallow water on floor 6
+        let surface = floor(7, vec![(0, 0, 201), (63, 63, 201), (30, 30, 100), (31, 30, 100), (32, 30, 100)]);
+        let upper = floor(6, vec![(30, 30, 200), (31, 30, 200), (32, 30, 200), (33, 30, 200)]);
+
+        let color_of = |id: u32| colors.get(&id).copied();
+        assert_eq!(void_color(&[&surface, &upper], &objects, color_of), Some([20, 40, 100]));
+        assert_eq!(floor_background(&upper, &objects, color_of), Some([30, 60, 120]));
+        assert_eq!(water_color(&[&surface, &upper], &objects, color_of), Some([30, 60, 120]));
+        assert_eq!(water_color(&[&surface], &objects, color_of), Some([20, 40, 100]));
+    }
+}
@@ -0,0 +1,394 @@
+use crate::output_sink::OutputSink;
+use anyhow::{Context, Result};
+use demonax_mapper_core::{PmtilesLayout, PMTILES_FLOOR_ZOOM_SHIFT};
+use memmap2::Mmap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const HEADER_LENGTH: usize = 127;
+/// Clients read the header and root directory with a single 16 KiB request.
+const ROOT_LENGTH: usize = 16384 - HEADER_LENGTH;
+const COMPRESSION_NONE: u8 = 1;
+
+/// One directory entry: `run_length` tiles from `tile_id` on sharing the
+/// bytes at `offset`, or a leaf directory when `run_length` is 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PmtilesEntry {
+    pub tile_id: u64,
+    pub offset: u64,
+    pub length: u32,
+    pub run_length: u32,
+}
+
+/// PMTiles tile ID of `z/x/y`: the tiles of all lower zoom levels, then
+/// the position of `(x, y)` along the Hilbert curve of zoom `z`.
+pub fn pmtiles_tile_id(z: u8, x: u32, y: u32) -> u64 {
+    let base = ((1u64 << (2 * z as u32)) - 1) / 3;
+    let (mut x, mut y) = (x as u64, y as u64);
+    let mut position = 0;
+    let mut s = (1u64 << z) / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u64;
+        let ry = (y & s > 0) as u64;
+        position += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            (x, y) = (y, x);
+        }
+        s /= 2;
+    }
+    base + position
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+/// Encodes a directory column by column: tile ID deltas, run lengths,
+/// lengths, then offsets, where 0 stands for "right after the previous
+/// entry".
+pub fn serialize_directory(entries: &[PmtilesEntry]) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    write_varint(&mut buffer, entries.len() as u64);
+
+    let mut last_id = 0;
+    for entry in entries {
+        write_varint(&mut buffer, entry.tile_id - last_id);
+        last_id = entry.tile_id;
+    }
+    for entry in entries {
+        write_varint(&mut buffer, entry.run_length as u64);
+    }
+    for entry in entries {
+        write_varint(&mut buffer, entry.length as u64);
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        let follows = i > 0 && entries[i - 1].offset + entries[i - 1].length as u64 == entry.offset;
+        write_varint(&mut buffer, if follows { 0 } else { entry.offset + 1 });
+    }
+
+    buffer
+}
+
+/// The root directory and the leaf directories it points to. Entries go
+/// straight into the root while it fits the first request; otherwise they
+/// are split into leaves, twice as large each try, until the root of
+/// leaf pointers fits.
+fn build_directories(entries: &[PmtilesEntry]) -> (Vec<u8>, Vec<u8>) {
+    let root = serialize_directory(entries);
+    if root.len() <= ROOT_LENGTH {
+        return (root, Vec::new());
+    }
+
+    let mut leaf_size = 4096;
+    loop {
+        let mut leaves = Vec::new();
+        let mut pointers = Vec::new();
+        for chunk in entries.chunks(leaf_size) {
+            let leaf = serialize_directory(chunk);
+            pointers.push(PmtilesEntry {
+                tile_id: chunk[0].tile_id,
+                offset: leaves.len() as u64,
+                length: leaf.len() as u32,
+                run_length: 0,
+            });
+            leaves.extend(leaf);
+        }
+        let root = serialize_directory(&pointers);
+        if root.len() <= ROOT_LENGTH {
+            return (root, leaves);
+        }
+        leaf_size *= 2;
+    }
+}
+
+/// What goes into the fixed-size header besides the section offsets.
+struct ArchiveInfo {
+    tile_type: u8,
+    min_zoom: u8,
+    max_zoom: u8,
+    addressed_tiles: u64,
+    tile_entries: u64,
+    tile_contents: u64,
+}
+
+fn header(info: &ArchiveInfo, sections: [(u64, u64); 4]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LENGTH);
+    header.extend(b"PMTiles");
+    header.push(3);
+    for (offset, length) in sections {
+        header.extend(offset.to_le_bytes());
+        header.extend(length.to_le_bytes());
+    }
+    header.extend(info.addressed_tiles.to_le_bytes());
+    header.extend(info.tile_entries.to_le_bytes());
+    header.extend(info.tile_contents.to_le_bytes());
+    // Clustered: tile data is laid out in tile ID order
+    header.push(1);
+    header.extend([COMPRESSION_NONE, COMPRESSION_NONE, info.tile_type, info.min_zoom, info.max_zoom]);
+    // The map is not geographic; claim the whole world
+    for degrees in [-180i32, -85, 180, 85] {
+        header.extend((degrees * 10_000_000).to_le_bytes());
+    }
+    header.push(info.min_zoom);
+    header.extend([0u8; 8]);
+    header
+}
+
+fn tile_type(extension: &str) -> u8 {
+    match extension {
+        "png" => 2,
+        "webp" => 4,
+        "avif" => 5,
+        _ => 0,
+    }
+}
+
+/// Tiles of one archive while the build runs. Their bytes are appended
+/// to a `.part` file next to the archive, each distinct tile once.
+struct ArchiveBuffer {
+    part: BufWriter<File>,
+    part_path: PathBuf,
+    part_length: u64,
+    tile_type: u8,
+    contents: HashMap<[u8; 32], (u64, u32)>,
+    /// `(offset, length)` in the part file by tile ID.
+    tiles: BTreeMap<u64, (u64, u32)>,
+    min_zoom: u8,
+    max_zoom: u8,
+}
+
+/// Collects tiles into PMTiles archives below `root`, written out by
+/// [`OutputSink::finish`]. Keys are split into a tile directory (`""`,
+/// `composite/`, `heatmap/`), a floor and `z/x/y`; each directory gets
+/// `<floor>.pmtiles` or, combined, one `tiles.pmtiles`. Tiles with
+/// identical bytes, such as open sea, are stored once.
+pub struct PmtilesSink {
+    root: PathBuf,
+    layout: PmtilesLayout,
+    archives: Mutex<BTreeMap<String, ArchiveBuffer>>,
+    written: Mutex<Vec<String>>,
+}
+
+impl PmtilesSink {
+    pub fn new<P: AsRef<Path>>(root: P, layout: PmtilesLayout) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            layout,
+            archives: Mutex::new(BTreeMap::new()),
+            written: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Output-relative paths of the archives [`OutputSink::finish`] wrote.
+    pub fn archive_paths(&self) -> Vec<String> {
+        self.written.lock().expect("archive lock").clone()
+    }
+
+    /// The archive a tile key goes into, its extension and its tile ID.
+    fn locate(&self, key: &str) -> Result<(String, String, u8, u64)> {
+        let invalid = || anyhow::anyhow!("Not a tile key: {:?}", key);
+        let mut parts = key.rsplitn(5, '/');
+        let (name, extension) = parts.next().and_then(|file| file.split_once('.')).ok_or_else(invalid)?;
+        let y: u32 = name.parse().map_err(|_| invalid())?;
+        let x: u32 = parts.next().and_then(|x| x.parse().ok()).ok_or_else(invalid)?;
+        let z: u8 = parts.next().and_then(|z| z.parse().ok()).ok_or_else(invalid)?;
+        let floor: u8 = parts.next().and_then(|f| f.parse().ok()).ok_or_else(invalid)?;
+        let dir = parts.next().map(|dir| format!("{}/", dir)).unwrap_or_default();
+
+        Ok(match self.layout {
+            PmtilesLayout::PerFloor => (format!("{}{}.pmtiles", dir, floor), extension.to_string(), z, pmtiles_tile_id(z, x, y)),
+            PmtilesLayout::Combined => {
+                let z = z + PMTILES_FLOOR_ZOOM_SHIFT;
+                let x = x + ((floor as u32) << (z - 4));
+                (format!("{}tiles.pmtiles", dir), extension.to_string(), z, pmtiles_tile_id(z, x, y))
+            }
+        })
+    }
+
+    fn write_archive(&self, name: &str, buffer: ArchiveBuffer) -> Result<()> {
+        let ArchiveBuffer { part, part_path, tile_type, tiles, min_zoom, max_zoom, .. } = buffer;
+        part.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        let part_file = File::open(&part_path)?;
+        // SAFETY: the part file is private to this sink and no longer written
+        let data = unsafe { Mmap::map(&part_file) }.with_context(|| format!("Failed to map {:?}", part_path))?;
+
+        // Lay tile contents out in the order their first tile comes, and
+        // merge consecutive tiles sharing content into runs
+        let mut placed: HashMap<u64, u64> = HashMap::new();
+        let mut order: Vec<(u64, u32)> = Vec::new();
+        let mut data_length = 0;
+        let mut entries: Vec<PmtilesEntry> = Vec::new();
+        for (&tile_id, &(part_offset, length)) in &tiles {
+            let offset = *placed.entry(part_offset).or_insert_with(|| {
+                order.push((part_offset, length));
+                data_length += length as u64;
+                data_length - length as u64
+            });
+            match entries.last_mut() {
+                Some(last) if last.offset == offset && last.tile_id + last.run_length as u64 == tile_id => last.run_length += 1,
+                _ => entries.push(PmtilesEntry { tile_id, offset, length, run_length: 1 }),
+            }
+        }
+
+        let (root, leaves) = build_directories(&entries);
+        let metadata = serde_json::to_vec(&serde_json::json!({
+            "name": name.trim_end_matches(".pmtiles"),
+            "generator": format!("demonax-mapper {}", env!("CARGO_PKG_VERSION")),
+            "layout": self.layout,
+            "floor_zoom_shift": (self.layout == PmtilesLayout::Combined).then_some(PMTILES_FLOOR_ZOOM_SHIFT)
+        }))?;
+
+        let root_offset = HEADER_LENGTH as u64;
+        let metadata_offset = root_offset + root.len() as u64;
+        let leaves_offset = metadata_offset + metadata.len() as u64;
+        let data_offset = leaves_offset + leaves.len() as u64;
+        let info = ArchiveInfo {
+            tile_type,
+            min_zoom,
+            max_zoom,
+            addressed_tiles: tiles.len() as u64,
+            tile_entries: entries.len() as u64,
+            tile_contents: order.len() as u64,
+        };
+        let sections = [
+            (root_offset, root.len() as u64),
+            (metadata_offset, metadata.len() as u64),
+            (leaves_offset, leaves.len() as u64),
+            (data_offset, data_length),
+        ];
+
+        let path = self.root.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = BufWriter::new(File::create(&path).with_context(|| format!("Failed to create {:?}", path))?);
+        file.write_all(&header(&info, sections))?;
+        file.write_all(&root)?;
+        file.write_all(&metadata)?;
+        file.write_all(&leaves)?;
+        for (offset, length) in order {
+            file.write_all(&data[offset as usize..offset as usize + length as usize])?;
+        }
+        file.flush().with_context(|| format!("Failed to write {:?}", path))?;
+
+        drop(data);
+        fs::remove_file(&part_path)?;
+        tracing::info!("Wrote {} tiles ({} distinct) to {:?}", info.addressed_tiles, info.tile_contents, path);
+        Ok(())
+    }
+}
+
+impl OutputSink for PmtilesSink {
+    fn write(&self, key: &str, data: &[u8]) -> Result<()> {
+        let (name, extension, zoom, tile_id) = self.locate(key)?;
+        let digest: [u8; 32] = Sha256::digest(data).into();
+
+        let mut archives = self.archives.lock().expect("archive lock");
+        if !archives.contains_key(&name) {
+            let part_path = self.root.join(format!("{}.part", name));
+            if let Some(parent) = part_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let part = File::create(&part_path).with_context(|| format!("Failed to create {:?}", part_path))?;
+            archives.insert(
+                name.clone(),
+                ArchiveBuffer {
+                    part: BufWriter::new(part),
+                    part_path,
+                    part_length: 0,
+                    tile_type: tile_type(&extension),
+                    contents: HashMap::new(),
+                    tiles: BTreeMap::new(),
+                    min_zoom: zoom,
+                    max_zoom: zoom,
+                },
+            );
+        }
+        let archive = archives.get_mut(&name).expect("archive was just added");
+
+        let location = match archive.contents.get(&digest) {
+            Some(&location) => location,
+            None => {
+                archive.part.write_all(data)?;
+                let location = (archive.part_length, data.len() as u32);
+                archive.part_length += data.len() as u64;
+                archive.contents.insert(digest, location);
+                location
+            }
+        };
+        archive.tiles.insert(tile_id, location);
+        archive.min_zoom = archive.min_zoom.min(zoom);
+        archive.max_zoom = archive.max_zoom.max(zoom);
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<()> {
+        let archives = std::mem::take(&mut *self.archives.lock().expect("archive lock"));
+        for (name, buffer) in archives {
+            self.write_archive(&name, buffer)?;
+            self.written.lock().expect("archive lock").push(name);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_varints(data: &[u8]) -> Vec<u64> {
+        let mut values = Vec::new();
+        let (mut value, mut shift) = (0, 0);
+        for &byte in data {
+            value |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                values.push(value);
+                (value, shift) = (0, 0);
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn test_pmtiles_sink_writes_runs_of_shared_tiles() {
+        assert_eq!(
+            [(0, 0, 0), (1, 0, 0), (1, 0, 1), (1, 1, 1), (1, 1, 0), (2, 0, 0)].map(|(z, x, y)| pmtiles_tile_id(z, x, y)),
+            [0, 1, 2, 3, 4, 5]
+        );
+
+        let dir = std::env::temp_dir().join(format!("demonax-pmtiles-{}", std::process::id()));
+        let sink = PmtilesSink::new(&dir, PmtilesLayout::PerFloor);
+        sink.write("7/0/0/0.png", b"land").unwrap();
+        sink.write("7/1/0/0.png", b"sea").unwrap();
+        sink.write("7/1/0/1.png", b"sea").unwrap();
+        sink.write("composite/7/0/0/0.png", b"land").unwrap();
+        assert!(sink.write("7/0/0.png", b"").is_err());
+        sink.finish().unwrap();
+
+        assert_eq!(sink.archive_paths(), ["7.pmtiles", "composite/7.pmtiles"]);
+        let archive = fs::read(dir.join("7.pmtiles")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let u64_at = |at: usize| u64::from_le_bytes(archive[at..at + 8].try_into().unwrap());
+        assert_eq!((&archive[..7], archive[7], archive[99]), (&b"PMTiles"[..], 3, 2));
+        // 3 tiles in 2 entries, 2 distinct contents
+        assert_eq!((u64_at(72), u64_at(80), u64_at(88)), (3, 2, 2));
+        let (root_offset, root_length, data_offset) = (u64_at(8) as usize, u64_at(16) as usize, u64_at(56) as usize);
+        // Count, tile ID deltas, run lengths, lengths, offsets (0: follows the previous)
+        assert_eq!(read_varints(&archive[root_offset..root_offset + root_length]), [2, 0, 1, 1, 2, 4, 3, 1, 0]);
+        assert_eq!(&archive[data_offset..], b"landsea");
+    }
+}
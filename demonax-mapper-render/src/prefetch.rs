@@ -0,0 +1,284 @@
+use crate::{render_sprite_tile, sprites::SpriteCache, tile_grid_size, tile_key, FileSink};
+use anyhow::Result;
+use demonax_mapper_core::{ObjectDatabase, SpriteMapData, TileFormat};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+/// Neighbours waiting to be pre-rendered, at most. The oldest, from
+/// viewports the user has already left, are dropped first.
+const MAX_PENDING: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    pub floor: u8,
+    pub zoom: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileKey {
+    /// Parses a map tile key such as `7/3/12/9.png` (see [`tile_key`]);
+    /// `None` for anything else, including tiles of another format.
+    pub fn parse(key: &str, format: TileFormat) -> Option<Self> {
+        let stem = key.strip_suffix(format.extension())?.strip_suffix('.')?;
+        let mut parts = stem.split('/');
+        let tile = Self {
+            floor: parts.next()?.parse().ok()?,
+            zoom: parts.next()?.parse().ok()?,
+            x: parts.next()?.parse().ok()?,
+            y: parts.next()?.parse().ok()?,
+        };
+        parts.next().is_none().then_some(tile)
+    }
+}
+
+/// Tiles around `(center_x, center_y)` ring by ring, nearest first, clipped to
+/// a `width` x `height` grid. The center itself is not included.
+pub fn spiral_order(center_x: u32, center_y: u32, radius: u32, width: u32, height: u32) -> Vec<(u32, u32)> {
+    let mut order = Vec::new();
+    let (cx, cy) = (center_x as i64, center_y as i64);
+
+    for ring in 1..=radius as i64 {
+        // Walk the ring clockwise starting at its top-left corner
+        let mut ring_tiles = Vec::with_capacity(8 * ring as usize);
+        for dx in -ring..ring {
+            ring_tiles.push((cx + dx, cy - ring));
+        }
+        for dy in -ring..ring {
+            ring_tiles.push((cx + ring, cy + dy));
+        }
+        for dx in (-ring + 1..=ring).rev() {
+            ring_tiles.push((cx + dx, cy + ring));
+        }
+        for dy in (-ring + 1..=ring).rev() {
+            ring_tiles.push((cx - ring, cy + dy));
+        }
+
+        order.extend(
+            ring_tiles
+                .into_iter()
+                .filter(|&(x, y)| x >= 0 && y >= 0 && x < width as i64 && y < height as i64)
+                .map(|(x, y)| (x as u32, y as u32)),
+        );
+    }
+
+    order
+}
+
+#[derive(Default)]
+struct PrefetchQueue {
+    pending: VecDeque<TileKey>,
+    /// Tiles being rendered right now, by a request or a worker.
+    in_flight: HashSet<TileKey>,
+    shutdown: bool,
+}
+
+struct Shared {
+    queue: Mutex<PrefetchQueue>,
+    /// Wakes workers when neighbours are queued.
+    wake: Condvar,
+    /// Wakes requests waiting for a tile another thread is rendering.
+    rendered: Condvar,
+    floors: HashMap<u8, SpriteMapData>,
+    /// Zoom levels of the build; tiles at other zooms are not rendered.
+    zooms: RangeInclusive<u8>,
+    sprite_cache: SpriteCache,
+    objects: ObjectDatabase,
+    format: TileFormat,
+    sink: FileSink,
+}
+
+impl Shared {
+    fn path(&self, key: TileKey) -> PathBuf {
+        self.sink.root().join(tile_key(key.floor, key.zoom, key.x, key.y, self.format))
+    }
+
+    /// Renders `key` unless it is on disk. A tile another thread is already
+    /// rendering is waited for instead of being drawn twice.
+    fn render_missing(&self, key: TileKey) -> Result<()> {
+        let mut queue = self.queue.lock().unwrap();
+        while queue.in_flight.contains(&key) {
+            queue = self.rendered.wait(queue).unwrap();
+        }
+        if self.path(key).exists() {
+            return Ok(());
+        }
+        queue.in_flight.insert(key);
+        drop(queue);
+
+        let result = render_sprite_tile(
+            &self.floors[&key.floor],
+            &self.sprite_cache,
+            &self.objects,
+            &self.sink,
+            key.zoom,
+            key.x,
+            key.y,
+            self.format,
+        );
+
+        self.queue.lock().unwrap().in_flight.remove(&key);
+        self.rendered.notify_all();
+        result.map(|_| ())
+    }
+}
+
+/// Renders tiles on demand and keeps background workers busy with the
+/// neighbours of the most recently requested tiles, so panning after the
+/// first load hits tiles that already exist on disk. Tiles are written to
+/// the same keys a build would use, through a [`FileSink`] that renames
+/// each one into place, so a tile is never served half written.
+pub struct ViewportPrefetcher {
+    shared: Arc<Shared>,
+    radius: u32,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ViewportPrefetcher {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        floors: Vec<SpriteMapData>,
+        sprite_cache: SpriteCache,
+        objects: ObjectDatabase,
+        output_dir: &Path,
+        format: TileFormat,
+        min_zoom: u8,
+        max_zoom: u8,
+        radius: u32,
+        num_workers: usize,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(PrefetchQueue::default()),
+            wake: Condvar::new(),
+            rendered: Condvar::new(),
+            floors: floors.into_iter().map(|m| (m.floor, m)).collect(),
+            zooms: min_zoom..=max_zoom,
+            sprite_cache,
+            objects,
+            format,
+            sink: FileSink::new(output_dir),
+        });
+
+        let workers = (0..num_workers.max(1))
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                std::thread::spawn(move || prefetch_worker(&shared))
+            })
+            .collect();
+
+        Self { shared, radius, workers }
+    }
+
+    /// Renders `key` now if it is not on disk yet, then queues its
+    /// neighbours ahead of those of earlier requests. `None` when the tile
+    /// lies on no loaded floor, at a zoom the build does not have or
+    /// outside its grid.
+    pub fn request(&self, key: TileKey) -> Result<Option<PathBuf>> {
+        let Some(map_data) = self.shared.floors.get(&key.floor) else {
+            return Ok(None);
+        };
+        if !self.shared.zooms.contains(&key.zoom) {
+            return Ok(None);
+        }
+        let (width, height) = tile_grid_size(map_data, key.zoom);
+        if key.x >= width || key.y >= height {
+            return Ok(None);
+        }
+
+        self.shared.render_missing(key)?;
+
+        let neighbours: Vec<TileKey> = spiral_order(key.x, key.y, self.radius, width, height)
+            .into_iter()
+            .map(|(x, y)| TileKey { x, y, ..key })
+            .filter(|&k| !self.shared.path(k).exists())
+            .collect();
+
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.pending.retain(|k| !neighbours.contains(k));
+        for &neighbour in neighbours.iter().rev() {
+            queue.pending.push_front(neighbour);
+        }
+        queue.pending.truncate(MAX_PENDING);
+        drop(queue);
+        self.shared.wake.notify_all();
+
+        Ok(Some(self.shared.path(key)))
+    }
+
+    pub fn format(&self) -> TileFormat {
+        self.shared.format
+    }
+
+    pub fn pending(&self) -> usize {
+        self.shared.queue.lock().unwrap().pending.len()
+    }
+}
+
+impl Drop for ViewportPrefetcher {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.wake.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn prefetch_worker(shared: &Shared) {
+    loop {
+        let key = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if queue.shutdown {
+                    return;
+                }
+                if let Some(key) = queue.pending.pop_front() {
+                    // Whoever holds it is already drawing it
+                    if !queue.in_flight.contains(&key) {
+                        break key;
+                    }
+                    continue;
+                }
+                queue = shared.wake.wait(queue).unwrap();
+            }
+        };
+
+        if let Err(e) = shared.render_missing(key) {
+            tracing::warn!("Failed to prefetch tile {:?}: {}", key, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spiral_order_nearest_ring_first() {
+        let order = spiral_order(5, 5, 2, 20, 20);
+        assert_eq!(order.len(), 8 + 16);
+        assert!(order[..8]
+            .iter()
+            .all(|&(x, y)| x.abs_diff(5) <= 1 && y.abs_diff(5) <= 1));
+        assert!(!order.contains(&(5, 5)));
+    }
+
+    #[test]
+    fn test_spiral_order_clips_to_grid() {
+        let order = spiral_order(0, 0, 1, 2, 2);
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, vec![(0, 1), (1, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn test_tile_key_parse() {
+        let key = TileKey::parse("7/3/12/9.png", TileFormat::Png);
+        assert_eq!(key, Some(TileKey { floor: 7, zoom: 3, x: 12, y: 9 }));
+        assert_eq!(TileKey::parse("7/3/12/9.webp", TileFormat::Png), None);
+        assert_eq!(TileKey::parse("heatmap/7/3/12/9.png", TileFormat::Png), None);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::manifest::TileManifest;
+use anyhow::{Context, Result};
+use demonax_mapper_core::WorldBounds;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+pub const PREWARM_FILE: &str = "prewarm.txt";
+
+/// Floor most visitors look at first.
+const GROUND_FLOOR: u8 = 7;
+const TILE_SIZE: u32 = 256;
+
+/// A place that draws viewers, such as an NPC (towns are where NPCs
+/// cluster) or a quest chest. Tiles covering more weight are primed first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Landmark {
+    pub x: u32,
+    pub y: u32,
+    pub z: u8,
+    pub weight: f64,
+}
+
+/// `(floor, zoom, x, y)` of a floor tile.
+type TileKey = (u8, u8, u32, u32);
+
+/// A floor tile path from the manifest: `<floor>/<zoom>/<x>/<y>.<ext>`.
+fn parse_tile_path(path: &str) -> Option<TileKey> {
+    let (path, _extension) = path.rsplit_once('.')?;
+    let parts: Vec<&str> = path.split('/').collect();
+    match parts[..] {
+        [floor, zoom, x, y] => Some((floor.parse().ok()?, zoom.parse().ok()?, x.parse().ok()?, y.parse().ok()?)),
+        _ => None,
+    }
+}
+
+/// Orders the floor tiles of a build for CDN priming: lower zoom levels
+/// first, then tiles covering the most landmark weight, then floors closest
+/// to the ground floor. Overlay tiles such as the heatmap are left out.
+pub fn prewarm_order(manifest: &TileManifest, bounds: &WorldBounds, landmarks: &[Landmark]) -> Vec<String> {
+    let mut tiles: Vec<(&String, TileKey)> = manifest
+        .tiles
+        .keys()
+        .filter_map(|path| Some((path, parse_tile_path(path)?)))
+        .collect();
+
+    let zooms: Vec<u8> = {
+        let mut zooms: Vec<u8> = tiles.iter().map(|(_, (_, zoom, _, _))| *zoom).collect();
+        zooms.sort_unstable();
+        zooms.dedup();
+        zooms
+    };
+
+    let mut scores: HashMap<TileKey, f64> = HashMap::new();
+    for landmark in landmarks {
+        let Some((map_x, map_y)) = bounds.world_to_map(landmark.x, landmark.y) else {
+            continue;
+        };
+        for &zoom in &zooms {
+            let scale = 2u32.pow(zoom as u32);
+            let key = (landmark.z, zoom, map_x * scale / TILE_SIZE, map_y * scale / TILE_SIZE);
+            *scores.entry(key).or_default() += landmark.weight;
+        }
+    }
+
+    tiles.sort_by(|(a_path, a), (b_path, b)| {
+        let score = |tile| scores.get(tile).copied().unwrap_or(0.0);
+        a.1.cmp(&b.1)
+            .then(score(b).total_cmp(&score(a)))
+            .then(a.0.abs_diff(GROUND_FLOOR).cmp(&b.0.abs_diff(GROUND_FLOOR)))
+            .then(a_path.cmp(b_path))
+    });
+
+    tiles.into_iter().map(|(path, _)| path.clone()).collect()
+}
+
+/// Writes one output-relative tile URL per line.
+pub fn write_prewarm_list(output_dir: &Path, urls: &[String]) -> Result<()> {
+    let mut content = urls.join("\n");
+    content.push('\n');
+    let path = output_dir.join(PREWARM_FILE);
+    fs::write(&path, content).with_context(|| format!("Failed to write {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::ManifestEntry;
+
+    #[test]
+    fn test_prewarm_orders_by_zoom_then_landmarks() {
+        let entry = ManifestEntry { bytes: 1, sha256: String::new() };
+        let paths = ["7/1/0/0.png", "7/1/1/0.png", "8/0/0/0.png", "7/0/0/0.png", "heatmap/7/0/0/0.png"];
+        let manifest = TileManifest {
+            generator: String::new(),
+            archive: None,
+            tiles: paths.iter().map(|p| (p.to_string(), entry.clone())).collect(),
+        };
+        let bounds = WorldBounds { min_sector_x: 1000, max_sector_x: 1015, min_sector_y: 1000, max_sector_y: 1007 };
+        // At zoom 1 a tile spans 128 world tiles, so this lands in tile x=1
+        let town = Landmark { x: 32000 + 200, y: 32010, z: 7, weight: 1.0 };
+
+        let order = prewarm_order(&manifest, &bounds, &[town]);
+        assert_eq!(order, vec!["7/0/0/0.png", "8/0/0/0.png", "7/1/1/0.png", "7/1/0/0.png"]);
+    }
+}
@@ -0,0 +1,40 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Tiles finished so far out of the total at one zoom level of a floor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderProgress {
+    pub floor: u8,
+    pub zoom: u8,
+    pub rendered: usize,
+    pub total: usize,
+}
+
+/// Called once after every finished tile, from the rendering threads.
+pub type ProgressFn<'a> = dyn Fn(RenderProgress) + Sync + 'a;
+
+/// Counts finished tiles per zoom level and reports them to an optional
+/// [`ProgressFn`].
+pub(crate) struct ProgressTracker<'a> {
+    callback: Option<&'a ProgressFn<'a>>,
+    floor: u8,
+    zooms: BTreeMap<u8, (usize, AtomicUsize)>,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub(crate) fn new(callback: Option<&'a ProgressFn<'a>>, floor: u8, tiles: &[(u8, u32, u32)]) -> Self {
+        let mut zooms: BTreeMap<u8, (usize, AtomicUsize)> = BTreeMap::new();
+        for &(zoom, _, _) in tiles {
+            zooms.entry(zoom).or_insert((0, AtomicUsize::new(0))).0 += 1;
+        }
+        Self { callback, floor, zooms }
+    }
+
+    pub(crate) fn tile_done(&self, zoom: u8) {
+        let (Some(callback), Some((total, rendered))) = (self.callback, self.zooms.get(&zoom)) else {
+            return;
+        };
+        let rendered = rendered.fetch_add(1, Ordering::Relaxed) + 1;
+        callback(RenderProgress { floor: self.floor, zoom, rendered, total: *total });
+    }
+}
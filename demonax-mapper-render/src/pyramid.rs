@@ -0,0 +1,248 @@
+use crate::output_sink::{tile_key, OutputSink};
+use crate::progress::{ProgressFn, ProgressTracker};
+use crate::render_floors::{FloorRenderStats, TileRenderer};
+use crate::tile_png::{write_tile, TileMetadata};
+use crate::tiles_color::{draw_color_tile, ColorGrid};
+use crate::tiles_sprite::{all_tiles, draw_sprite_tile, tile_grid_size, TileRenderStats};
+use anyhow::Result;
+use demonax_mapper_core::{ObjectDatabase, SpriteMapData, TileFormat};
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+const TILE_SIZE: u32 = 256;
+
+/// One floor of a pyramid render, with what its tiles are drawn from.
+struct PyramidFloor<'a> {
+    map_data: &'a SpriteMapData,
+    grid: Option<ColorGrid>,
+    tracker: ProgressTracker<'a>,
+    tiles: usize,
+    /// Pyramids of this floor still being built.
+    remaining: AtomicUsize,
+    bytes: AtomicU64,
+    finished: OnceLock<Duration>,
+}
+
+/// Renders whole floors as a tile pyramid: only `max_zoom` is drawn from
+/// sprites (or colors), and every lower zoom level is made by shrinking the
+/// four tiles below it. This is faster than drawing each level from
+/// scratch, and features keep the same look from one zoom level to the next.
+#[allow(clippy::too_many_arguments)]
+pub fn render_floor_pyramids(
+    floors: &[&SpriteMapData],
+    renderer: TileRenderer<'_>,
+    objects: &ObjectDatabase,
+    sink: &dyn OutputSink,
+    min_zoom: u8,
+    max_zoom: u8,
+    format: TileFormat,
+    progress: Option<&ProgressFn<'_>>,
+) -> Result<Vec<FloorRenderStats>> {
+    let start = Instant::now();
+    let floors: Vec<PyramidFloor> = floors
+        .par_iter()
+        .map(|&map_data| {
+            let tiles = all_tiles(map_data, min_zoom, max_zoom);
+            let (roots_x, roots_y) = tile_grid_size(map_data, min_zoom);
+            PyramidFloor {
+                map_data,
+                grid: match renderer {
+                    TileRenderer::Colors(colors) => Some(ColorGrid::build(map_data, objects, colors)),
+                    TileRenderer::Sprites(_) => None,
+                },
+                tracker: ProgressTracker::new(progress, map_data.floor, &tiles),
+                tiles: tiles.len(),
+                remaining: AtomicUsize::new((roots_x * roots_y) as usize),
+                bytes: AtomicU64::new(0),
+                finished: OnceLock::new(),
+            }
+        })
+        .collect();
+
+    // Each tile of the lowest zoom level is the root of its own pyramid
+    let roots: Vec<(&PyramidFloor, u32, u32)> = floors
+        .iter()
+        .flat_map(|floor| {
+            let (tiles_x, tiles_y) = tile_grid_size(floor.map_data, min_zoom);
+            (0..tiles_x).flat_map(move |x| (0..tiles_y).map(move |y| (floor, x, y)))
+        })
+        .collect();
+
+    roots.par_iter().try_for_each(|&(floor, x, y)| -> Result<()> {
+        build_tile(floor, renderer, objects, sink, max_zoom, min_zoom, x, y, format)?;
+        if floor.remaining.fetch_sub(1, Ordering::Relaxed) == 1 {
+            let _ = floor.finished.set(start.elapsed());
+        }
+        Ok(())
+    })?;
+
+    Ok(floors
+        .iter()
+        .map(|floor| FloorRenderStats {
+            floor: floor.map_data.floor,
+            stats: TileRenderStats { tiles: floor.tiles, bytes: floor.bytes.load(Ordering::Relaxed) },
+            elapsed: floor.finished.get().copied().unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Draws tile `(x, y)` at `zoom` and everything below it down to
+/// `max_zoom`, writing every level on the way. Tiles past the edge
+/// of the grid, which the right and bottom parents reach, are `None`.
+#[allow(clippy::too_many_arguments)]
+fn build_tile(
+    floor: &PyramidFloor,
+    renderer: TileRenderer<'_>,
+    objects: &ObjectDatabase,
+    sink: &dyn OutputSink,
+    max_zoom: u8,
+    zoom: u8,
+    x: u32,
+    y: u32,
+    format: TileFormat,
+) -> Result<Option<RgbaImage>> {
+    let (tiles_x, tiles_y) = tile_grid_size(floor.map_data, zoom);
+    if x >= tiles_x || y >= tiles_y {
+        return Ok(None);
+    }
+
+    let image = if zoom == max_zoom {
+        match renderer {
+            TileRenderer::Sprites(sprite_cache) => draw_sprite_tile(floor.map_data, sprite_cache, objects, zoom, x, y)?,
+            TileRenderer::Colors(_) => {
+                let grid = floor.grid.as_ref().expect("color grids are built for every floor");
+                draw_color_tile(grid, zoom, x, y)
+            }
+        }
+    } else {
+        let children = [(0, 0), (1, 0), (0, 1), (1, 1)]
+            .par_iter()
+            .map(|&(dx, dy)| {
+                build_tile(floor, renderer, objects, sink, max_zoom, zoom + 1, x * 2 + dx, y * 2 + dy, format)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        downsample_children(&children)
+    };
+
+    let key = tile_key(floor.map_data.floor, zoom, x, y, format);
+    let layer = match renderer {
+        TileRenderer::Sprites(_) => "sprites",
+        TileRenderer::Colors(_) => "colors",
+    };
+    let metadata = TileMetadata::for_tile(layer, floor.map_data, zoom, x, y);
+    floor.bytes.fetch_add(write_tile(sink, &key, &image, &metadata, format)?, Ordering::Relaxed);
+    floor.tracker.tile_done(zoom);
+
+    Ok(Some(image))
+}
+
+/// Shrinks the four child tiles (top left, top right, bottom left, bottom
+/// right) into one tile. Each pixel averages a 2x2 square weighted by
+/// alpha, so transparent pixels do not darken the edges of what they border.
+pub(crate) fn downsample_children(children: &[Option<RgbaImage>]) -> RgbaImage {
+    let half = TILE_SIZE / 2;
+    let mut output = RgbaImage::new(TILE_SIZE, TILE_SIZE);
+
+    for (index, child) in children.iter().enumerate() {
+        let Some(child) = child else {
+            continue;
+        };
+        let (offset_x, offset_y) = ((index as u32 % 2) * half, (index as u32 / 2) * half);
+
+        for y in 0..half {
+            for x in 0..half {
+                let mut alpha = 0u32;
+                let mut color = [0u32; 3];
+                for (sx, sy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let Rgba([r, g, b, a]) = *child.get_pixel(x * 2 + sx, y * 2 + sy);
+                    alpha += a as u32;
+                    color[0] += r as u32 * a as u32;
+                    color[1] += g as u32 * a as u32;
+                    color[2] += b as u32 * a as u32;
+                }
+                if alpha == 0 {
+                    continue;
+                }
+                let channel = |sum: u32| ((sum + alpha / 2) / alpha) as u8;
+                let pixel = Rgba([channel(color[0]), channel(color[1]), channel(color[2]), ((alpha + 2) / 4) as u8]);
+                output.put_pixel(offset_x + x, offset_y + y, pixel);
+            }
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::ColorMap;
+    use crate::MemorySink;
+    use demonax_mapper_core::{GameObject, ObjectFlags, TileStack};
+
+    #[test]
+    fn test_downsample_weights_by_alpha() {
+        let mut child = RgbaImage::new(TILE_SIZE, TILE_SIZE);
+        child.put_pixel(0, 0, Rgba([200, 100, 0, 255]));
+        child.put_pixel(0, 1, Rgba([200, 100, 0, 255]));
+        child.put_pixel(2, 0, Rgba([255, 255, 255, 255]));
+        let output = downsample_children(&[None, Some(child), None, None]);
+
+        // Half covered keeps the covered color at half alpha
+        assert_eq!(output.get_pixel(128, 0), &Rgba([200, 100, 0, 128]));
+        assert_eq!(output.get_pixel(129, 0), &Rgba([255, 255, 255, 64]));
+        assert_eq!(output.get_pixel(0, 0)[3], 0);
+    }
+
+    #[test]
+    fn test_pyramid_levels_match_shrunk_max_zoom() {
+        let ground = GameObject {
+            id: 100,
+            name: String::new(),
+            flags: ObjectFlags::BANK,
+            unknown_flags: Vec::new(),
+            waypoints: 150,
+            is_ground: true,
+            is_impassable: false,
+            disguise_target: None,
+            attributes: Default::default(),
+            layer: None,
+        };
+        let objects: ObjectDatabase = [(100, ground)].into();
+        let colors: ColorMap = [(100, [0, 200, 0])].into();
+        // One sector, 32 game tiles square: 2x2 tiles at zoom 4
+        let map_data = SpriteMapData {
+            floor: 7,
+            tiles: vec![TileStack { x: 31, y: 31, object_ids: vec![100] }],
+            min_sector_x: 1000,
+            max_sector_x: 1000,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+
+        let sink = MemorySink::new();
+        let results = render_floor_pyramids(
+            &[&map_data],
+            TileRenderer::Colors(&colors),
+            &objects,
+            &sink,
+            3,
+            4,
+            TileFormat::Png,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(results[0].stats.tiles, 5);
+        assert_eq!(sink.len(), 5);
+        // The single green game tile is 16px at zoom 4 and 8px at zoom 3
+        let tile = image::load_from_memory(&sink.get("7/3/0/0.png").unwrap()).unwrap().to_rgba8();
+        assert_eq!(tile.get_pixel(252, 252), &Rgba([0, 200, 0, 255]));
+        assert_eq!(tile.get_pixel(247, 247)[3], 0);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::palette::ColorMap;
+use crate::progress::{ProgressFn, ProgressTracker};
+use crate::sprites::SpriteCache;
+use crate::tiles_color::{render_color_tile, ColorGrid};
+use crate::tiles_sprite::{all_tiles, render_sprite_tile, TileRenderStats};
+use crate::OutputSink;
+use anyhow::Result;
+use demonax_mapper_core::{ObjectDatabase, SpriteMapData, TileFormat};
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// The tiles to draw of one floor in a [`render_floors`] batch.
+pub struct FloorJob<'a> {
+    pub map_data: &'a SpriteMapData,
+    pub tiles: Vec<(u8, u32, u32)>,
+}
+
+impl<'a> FloorJob<'a> {
+    /// Every tile of the floor from `min_zoom` to `max_zoom`.
+    pub fn all(map_data: &'a SpriteMapData, min_zoom: u8, max_zoom: u8) -> Self {
+        Self { map_data, tiles: all_tiles(map_data, min_zoom, max_zoom) }
+    }
+}
+
+/// What [`render_floors`] draws tiles from.
+#[derive(Clone, Copy)]
+pub enum TileRenderer<'a> {
+    Sprites(&'a SpriteCache),
+    Colors(&'a ColorMap),
+}
+
+/// Outcome of one floor in a [`render_floors`] batch. `elapsed` runs from
+/// the start of the batch until the floor's last tile was written.
+#[derive(Debug, Clone, Copy)]
+pub struct FloorRenderStats {
+    pub floor: u8,
+    pub stats: TileRenderStats,
+    pub elapsed: Duration,
+}
+
+/// Renders the tiles of several floors in one parallel pass, so small
+/// floors and low zoom levels do not leave cores idle while the next floor
+/// waits its turn. `progress` hears about every tile of every floor.
+pub fn render_floors(
+    jobs: &[FloorJob<'_>],
+    renderer: TileRenderer<'_>,
+    objects: &ObjectDatabase,
+    sink: &dyn OutputSink,
+    format: TileFormat,
+    progress: Option<&ProgressFn<'_>>,
+) -> Result<Vec<FloorRenderStats>> {
+    let start = Instant::now();
+    let grids: Vec<Option<ColorGrid>> = jobs
+        .par_iter()
+        .map(|job| match renderer {
+            TileRenderer::Colors(colors) => Some(ColorGrid::build(job.map_data, objects, colors)),
+            TileRenderer::Sprites(_) => None,
+        })
+        .collect();
+
+    let trackers: Vec<ProgressTracker> = jobs
+        .iter()
+        .map(|job| ProgressTracker::new(progress, job.map_data.floor, &job.tiles))
+        .collect();
+    let remaining: Vec<AtomicUsize> = jobs.iter().map(|job| AtomicUsize::new(job.tiles.len())).collect();
+    let bytes: Vec<AtomicU64> = jobs.iter().map(|_| AtomicU64::new(0)).collect();
+    let finished: Vec<OnceLock<Duration>> = jobs.iter().map(|_| OnceLock::new()).collect();
+
+    let work: Vec<(usize, u8, u32, u32)> = jobs
+        .iter()
+        .enumerate()
+        .flat_map(|(index, job)| job.tiles.iter().map(move |&(zoom, x, y)| (index, zoom, x, y)))
+        .collect();
+
+    work.par_iter().try_for_each(|&(index, zoom, x, y)| -> Result<()> {
+        let map_data = jobs[index].map_data;
+        let written = match renderer {
+            TileRenderer::Sprites(sprite_cache) => {
+                render_sprite_tile(map_data, sprite_cache, objects, sink, zoom, x, y, format)?
+            }
+            TileRenderer::Colors(_) => {
+                let grid = grids[index].as_ref().expect("color grids are built for every floor");
+                render_color_tile(map_data, grid, sink, zoom, x, y, format)?
+            }
+        };
+
+        bytes[index].fetch_add(written, Ordering::Relaxed);
+        trackers[index].tile_done(zoom);
+        if remaining[index].fetch_sub(1, Ordering::Relaxed) == 1 {
+            let _ = finished[index].set(start.elapsed());
+        }
+        Ok(())
+    })?;
+
+    Ok(jobs
+        .iter()
+        .enumerate()
+        .map(|(index, job)| FloorRenderStats {
+            floor: job.map_data.floor,
+            stats: TileRenderStats { tiles: job.tiles.len(), bytes: bytes[index].load(Ordering::Relaxed) },
+            elapsed: finished[index].get().copied().unwrap_or_default(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemorySink;
+    use crate::progress::RenderProgress;
+    use std::sync::Mutex;
+
+    fn floor(floor: u8) -> SpriteMapData {
+        SpriteMapData {
+            floor,
+            tiles: Vec::new(),
+            min_sector_x: 1000,
+            max_sector_x: 1000,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            version: 2,
+            parse_issues: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_render_floors_in_one_batch() {
+        let (upper, lower) = (floor(6), floor(7));
+        let jobs = [FloorJob::all(&upper, 0, 3), FloorJob { map_data: &lower, tiles: vec![(3, 0, 0)] }];
+        let colors = ColorMap::new();
+        let sink = MemorySink::new();
+        let seen = Mutex::new(Vec::new());
+        let progress = |p: RenderProgress| seen.lock().unwrap().push((p.floor, p.zoom));
+
+        let results = render_floors(
+            &jobs,
+            TileRenderer::Colors(&colors),
+            &ObjectDatabase::new(),
+            &sink,
+            TileFormat::Png,
+            Some(&progress),
+        )
+        .unwrap();
+
+        // A 32x32 sector is one tile at every zoom up to 3
+        assert_eq!(results.iter().map(|r| (r.floor, r.stats.tiles)).collect::<Vec<_>>(), [(6, 4), (7, 1)]);
+        assert!(sink.get("6/0/0/0.png").is_some() && sink.get("7/3/0/0.png").is_some());
+        assert!(sink.get("7/0/0/0.png").is_none());
+        assert_eq!(seen.lock().unwrap().len(), 5);
+    }
+}
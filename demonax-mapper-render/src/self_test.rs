@@ -0,0 +1,217 @@
+use crate::output_sink::{tile_key, MemorySink};
+use crate::sprites::SpriteCache;
+use crate::tiles_sprite::generate_sprite_tiles;
+use anyhow::{Context, Result};
+use demonax_mapper_core::{parse_objects, parse_sprite_map, InputEncoding, TileFormat, WorldBounds};
+use image::{imageops, RgbaImage};
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+/// Floor of the embedded dataset.
+pub const SELF_TEST_FLOOR: u8 = 7;
+
+/// Zoom levels compared against the golden images: downscaled, half size and
+/// the sprites' native 32px.
+pub const SELF_TEST_ZOOMS: [u8; 3] = [3, 4, 5];
+
+const TILE_SIZE: u32 = 256;
+
+/// A one-sector map exercising every layer rule: ground and water, walls with
+/// a DisguiseTarget, 64px trees reaching past the map edge, translucent
+/// flowers, a chest holding a takeable item and a Top banner.
+const DATASET: &[(&str, &[u8])] = &[
+    ("objects.srv", include_bytes!("../self-test/objects.srv")),
+    ("map/1000-1000-07.sec", include_bytes!("../self-test/map/1000-1000-07.sec")),
+    ("sprites/100.png", include_bytes!("../self-test/sprites/100.png")),
+    ("sprites/101.png", include_bytes!("../self-test/sprites/101.png")),
+    ("sprites/200.png", include_bytes!("../self-test/sprites/200.png")),
+    ("sprites/300.png", include_bytes!("../self-test/sprites/300.png")),
+    ("sprites/350.png", include_bytes!("../self-test/sprites/350.png")),
+    ("sprites/400.png", include_bytes!("../self-test/sprites/400.png")),
+    ("sprites/500.png", include_bytes!("../self-test/sprites/500.png")),
+    ("sprites/700.png", include_bytes!("../self-test/sprites/700.png")),
+];
+
+/// The whole floor at each of [`SELF_TEST_ZOOMS`], stitched into one image.
+const GOLDEN: [(u8, &[u8]); 3] = [
+    (3, include_bytes!("../self-test/golden/zoom-3.png")),
+    (4, include_bytes!("../self-test/golden/zoom-4.png")),
+    (5, include_bytes!("../self-test/golden/zoom-5.png")),
+];
+
+/// A rendered tile that differs from its golden image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileMismatch {
+    pub key: String,
+    /// Pixels where some channel differs by more than the tolerance.
+    pub differing_pixels: usize,
+    /// Largest difference of any channel in the tile.
+    pub max_difference: u8,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SelfTestReport {
+    pub tiles: usize,
+    pub mismatches: Vec<TileMismatch>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Renders the embedded dataset and compares every tile against the golden
+/// images. A pixel only counts as different when one of its channels is off
+/// by more than `tolerance`.
+pub fn run_self_test(tolerance: u8) -> Result<SelfTestReport> {
+    let rendered = render_self_test()?;
+    let mut report = SelfTestReport::default();
+
+    for (zoom, golden) in GOLDEN {
+        let golden = image::load_from_memory(golden)
+            .with_context(|| format!("Failed to decode golden image for zoom {}", zoom))?
+            .to_rgba8();
+        let (num_tiles_x, num_tiles_y) = (golden.width().div_ceil(TILE_SIZE), golden.height().div_ceil(TILE_SIZE));
+
+        for tile_x in 0..num_tiles_x {
+            for tile_y in 0..num_tiles_y {
+                let key = tile_key(SELF_TEST_FLOOR, zoom, tile_x, tile_y, TileFormat::Png);
+                let expected =
+                    imageops::crop_imm(&golden, tile_x * TILE_SIZE, tile_y * TILE_SIZE, TILE_SIZE, TILE_SIZE).to_image();
+                let mismatch = match rendered.get(&key) {
+                    Some(data) => compare_tile(&image::load_from_memory(&data)?.to_rgba8(), &expected, tolerance),
+                    None => Some((expected.width() as usize * expected.height() as usize, u8::MAX)),
+                };
+
+                report.tiles += 1;
+                if let Some((differing_pixels, max_difference)) = mismatch {
+                    report.mismatches.push(TileMismatch { key, differing_pixels, max_difference });
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Writes the current rendering of the dataset as `zoom-<z>.png` golden
+/// images into `dir`, to be checked in after an intended visual change.
+pub fn write_self_test_golden<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    let rendered = render_self_test()?;
+    fs::create_dir_all(dir)?;
+
+    let mut written = Vec::new();
+    for zoom in SELF_TEST_ZOOMS {
+        let mut tiles = Vec::new();
+        let (mut width, mut height) = (0, 0);
+        for key in rendered.keys() {
+            let (Some((tile_x, tile_y)), Some(data)) = (parse_tile_key(&key, zoom), rendered.get(&key)) else {
+                continue;
+            };
+            let tile = image::load_from_memory(&data)?.to_rgba8();
+            width = width.max((tile_x + 1) * TILE_SIZE);
+            height = height.max((tile_y + 1) * TILE_SIZE);
+            tiles.push((tile_x, tile_y, tile));
+        }
+
+        let mut stitched = RgbaImage::new(width, height);
+        for (tile_x, tile_y, tile) in tiles {
+            imageops::replace(&mut stitched, &tile, (tile_x * TILE_SIZE) as i64, (tile_y * TILE_SIZE) as i64);
+        }
+
+        // Checked into the repository, so spend the time on compression
+        let path = dir.join(format!("zoom-{}.png", zoom));
+        let file = fs::File::create(&path).with_context(|| format!("Failed to create {:?}", path))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), stitched.width(), stitched.height());
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(png::Compression::High);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(stitched.as_raw())?;
+        writer.finish()?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// Unpacks the dataset into a scratch directory, since the parsers read from
+/// disk, and renders it into memory.
+fn render_self_test() -> Result<MemorySink> {
+    let dir = std::env::temp_dir().join(format!("demonax-self-test-{}", std::process::id()));
+    let result = unpack_dataset(&dir).and_then(|()| render_dataset(&dir));
+    fs::remove_dir_all(&dir).ok();
+    result
+}
+
+fn unpack_dataset(dir: &Path) -> Result<()> {
+    for (name, data) in DATASET {
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, data).with_context(|| format!("Failed to unpack {:?}", path))?;
+    }
+    Ok(())
+}
+
+fn render_dataset(dir: &Path) -> Result<MemorySink> {
+    let objects = parse_objects(dir.join("objects.srv"), InputEncoding::Auto)?;
+    let map_dir = dir.join("map");
+    let bounds = WorldBounds::scan(&map_dir, &[SELF_TEST_FLOOR], &[])?;
+    let map_data = parse_sprite_map(&map_dir, SELF_TEST_FLOOR, &bounds, &[], InputEncoding::Auto)?;
+    if !map_data.parse_issues.is_empty() {
+        anyhow::bail!("Self-test map failed to parse: {:?}", map_data.parse_issues);
+    }
+
+    let sprite_cache = SpriteCache::new(dir.join("sprites"))?;
+    let sink = MemorySink::new();
+    let (min_zoom, max_zoom) = (SELF_TEST_ZOOMS[0], SELF_TEST_ZOOMS[SELF_TEST_ZOOMS.len() - 1]);
+    generate_sprite_tiles(&map_data, &sprite_cache, &objects, &sink, SELF_TEST_FLOOR, min_zoom, max_zoom, TileFormat::Png, None)?;
+    Ok(sink)
+}
+
+/// `(x, y)` of a `<floor>/<zoom>/<x>/<y>.png` key at `zoom`.
+fn parse_tile_key(key: &str, zoom: u8) -> Option<(u32, u32)> {
+    let mut parts = key.strip_suffix(".png")?.split('/');
+    let (floor, tile_zoom) = (parts.next()?, parts.next()?);
+    if floor != SELF_TEST_FLOOR.to_string() || tile_zoom != zoom.to_string() {
+        return None;
+    }
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+/// `(differing pixels, largest channel difference)` when `actual` is off by
+/// more than `tolerance` anywhere, `None` when it matches.
+fn compare_tile(actual: &RgbaImage, expected: &RgbaImage, tolerance: u8) -> Option<(usize, u8)> {
+    if actual.dimensions() != expected.dimensions() {
+        return Some((expected.width() as usize * expected.height() as usize, u8::MAX));
+    }
+
+    let mut differing_pixels = 0;
+    let mut max_difference = 0;
+    for (a, e) in actual.pixels().zip(expected.pixels()) {
+        let difference = a.0.iter().zip(e.0).map(|(&a, e)| a.abs_diff(e)).max().unwrap_or(0);
+        max_difference = max_difference.max(difference);
+        if difference > tolerance {
+            differing_pixels += 1;
+        }
+    }
+
+    (differing_pixels > 0).then_some((differing_pixels, max_difference))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rendering_matches_golden_images() {
+        let report = run_self_test(0).unwrap();
+        assert_eq!(report.tiles, 1 + 4 + 16);
+        assert!(report.passed(), "tiles differ from golden images: {:?}", report.mismatches);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::sprite_source::SpriteSource;
+use anyhow::{Context, Result};
+use image::RgbaImage;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Where one sprite sits in an atlas, as listed in the sheet index.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SheetRegion {
+    /// Sheet image, relative to the index file.
+    pub sheet: String,
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+/// A decoded sheet, if held, and when it was last cropped from, in ticks of
+/// the sheet clock.
+#[derive(Default)]
+struct SheetSlot {
+    image: Mutex<Option<Arc<RgbaImage>>>,
+    last_used: AtomicU64,
+}
+
+/// Packed sprite atlases described by a JSON index of
+/// `{"<object id>": {"sheet", "x", "y", "w", "h"}}`. Each sheet is decoded
+/// the first time one of its sprites is needed and kept until the sprite
+/// cache releases it to stay within its memory limit, least recently used
+/// sheet first.
+pub struct SpriteSheets {
+    dir: PathBuf,
+    regions: HashMap<u32, SheetRegion>,
+    sheets: HashMap<String, SheetSlot>,
+    /// Decoded bytes of the sheets currently held.
+    bytes: AtomicUsize,
+    clock: AtomicU64,
+}
+
+impl SpriteSheets {
+    pub fn open<P: AsRef<Path>>(index_path: P) -> Result<Self> {
+        let index_path = index_path.as_ref();
+        let content = fs::read_to_string(index_path)
+            .with_context(|| format!("Failed to read sprite sheet index {:?}", index_path))?;
+        let regions: HashMap<u32, SheetRegion> = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse sprite sheet index {:?}", index_path))?;
+
+        let sheets = regions
+            .values()
+            .map(|region| (region.sheet.clone(), SheetSlot::default()))
+            .collect();
+
+        tracing::info!("Indexed {} sprites in sprite sheets", regions.len());
+        Ok(Self {
+            dir: index_path.parent().unwrap_or(Path::new("")).to_path_buf(),
+            regions,
+            sheets,
+            bytes: AtomicUsize::new(0),
+            clock: AtomicU64::new(0),
+        })
+    }
+
+    /// The decoded sheet `name`. Holding its lock while decoding keeps two
+    /// threads from decoding the same sheet at once.
+    fn sheet(&self, name: &str) -> Result<Arc<RgbaImage>> {
+        let slot = &self.sheets[name];
+        slot.last_used.store(self.clock.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+        let mut slot = slot.image.lock().unwrap();
+        if let Some(sheet) = slot.as_ref() {
+            return Ok(Arc::clone(sheet));
+        }
+
+        let sheet = Arc::new(self.decode_sheet(name)?);
+        self.bytes.fetch_add(sheet.as_raw().len(), Ordering::Relaxed);
+        *slot = Some(Arc::clone(&sheet));
+        Ok(sheet)
+    }
+
+    fn decode_sheet(&self, name: &str) -> Result<RgbaImage> {
+        let path = self.dir.join(name);
+        let sheet = image::open(&path)
+            .with_context(|| format!("Failed to decode sprite sheet {:?}", path))?
+            .to_rgba8();
+
+        tracing::debug!("Decoded sprite sheet {:?} ({}x{})", path, sheet.width(), sheet.height());
+        Ok(sheet)
+    }
+}
+
+impl SpriteSource for SpriteSheets {
+    fn contains(&self, object_id: u32) -> bool {
+        self.regions.contains_key(&object_id)
+    }
+
+    fn load(&self, object_id: u32) -> Result<RgbaImage> {
+        let region = self
+            .regions
+            .get(&object_id)
+            .with_context(|| format!("Sprite {} is not in the sprite sheet index", object_id))?;
+        let sheet = self.sheet(&region.sheet)?;
+
+        if region.x + region.w > sheet.width() || region.y + region.h > sheet.height() {
+            anyhow::bail!(
+                "Sprite {} region {}x{}+{}+{} lies outside {:?} ({}x{})",
+                object_id,
+                region.w,
+                region.h,
+                region.x,
+                region.y,
+                region.sheet,
+                sheet.width(),
+                sheet.height()
+            );
+        }
+
+        Ok(image::imageops::crop_imm(sheet.as_ref(), region.x, region.y, region.w, region.h).to_image())
+    }
+
+    fn retained_bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    fn release_oldest(&self) -> bool {
+        let mut held: Vec<(u64, &SheetSlot)> = self
+            .sheets
+            .values()
+            .filter(|slot| slot.image.lock().unwrap().is_some())
+            .map(|slot| (slot.last_used.load(Ordering::Relaxed), slot))
+            .collect();
+        if held.len() < 2 {
+            return false;
+        }
+
+        held.sort_unstable_by_key(|&(last_used, _)| last_used);
+        match held[0].1.image.lock().unwrap().take() {
+            Some(sheet) => {
+                self.bytes.fetch_sub(sheet.as_raw().len(), Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_load_crops_from_sheet() {
+        let dir = std::env::temp_dir().join(format!("demonax-sprite-sheets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut sheet = RgbaImage::new(64, 32);
+        sheet.put_pixel(32, 0, Rgba([10, 20, 30, 255]));
+        sheet.save(dir.join("sheet-0.png")).unwrap();
+        RgbaImage::new(32, 32).save(dir.join("sheet-1.png")).unwrap();
+        fs::write(
+            dir.join("sprites.json"),
+            r#"{"100": {"sheet": "sheet-0.png", "x": 32, "y": 0, "w": 32, "h": 32},
+                "101": {"sheet": "sheet-0.png", "x": 48, "y": 0, "w": 32, "h": 32},
+                "200": {"sheet": "sheet-1.png", "x": 0, "y": 0, "w": 32, "h": 32}}"#,
+        )
+        .unwrap();
+
+        let sheets = SpriteSheets::open(dir.join("sprites.json")).unwrap();
+        let sprite = sheets.load(100).unwrap();
+        let out_of_bounds = sheets.load(101);
+        let only_sheet_kept = !sheets.release_oldest();
+        sheets.load(200).unwrap();
+        let retained = sheets.retained_bytes();
+        let released_oldest = sheets.release_oldest();
+        let released = sheets.retained_bytes();
+        let newest_kept = !sheets.release_oldest();
+        let reloaded = sheets.load(100);
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(sprite.dimensions(), (32, 32));
+        assert_eq!(sprite.get_pixel(0, 0), &Rgba([10, 20, 30, 255]));
+        assert!(out_of_bounds.is_err());
+        assert!(!sheets.contains(102));
+        assert!(only_sheet_kept);
+        assert_eq!(retained, (64 * 32 + 32 * 32) * 4);
+        assert!(released_oldest);
+        assert_eq!(released, 32 * 32 * 4);
+        assert!(newest_kept);
+        assert!(reloaded.is_ok());
+    }
+}
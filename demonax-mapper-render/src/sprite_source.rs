@@ -0,0 +1,382 @@
+use crate::client_archive::{DatFile, SpriteArchive, compose_thing};
+use crate::sprite_sheets::SpriteSheets;
+use anyhow::{Context, Result};
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use zip::ZipArchive;
+
+/// Where [`SpriteCache`](crate::SpriteCache) reads object sprites from.
+/// Implementations only decode; caching, overrides and fallbacks are the
+/// cache's job.
+pub trait SpriteSource: Send + Sync {
+    /// Whether there is a sprite for `object_id`, without decoding it.
+    fn contains(&self, object_id: u32) -> bool;
+
+    fn load(&self, object_id: u32) -> Result<RgbaImage>;
+
+    /// Number of animation frames stored for `object_id`, 0 for still sprites.
+    fn frame_count(&self, _object_id: u32) -> usize {
+        0
+    }
+
+    /// Animation frame `frame` of `object_id`, counted from 0 in frame order.
+    fn load_frame(&self, object_id: u32, frame: usize) -> Result<RgbaImage> {
+        anyhow::bail!("Sprite {} has no animation frame {}", object_id, frame)
+    }
+
+    /// Decoded bytes the source keeps between loads, such as whole sprite
+    /// sheets. The cache counts them against its memory limit.
+    fn retained_bytes(&self) -> usize {
+        0
+    }
+
+    /// Drops the least recently used part of what
+    /// [`retained_bytes`](Self::retained_bytes) counts, decoded again on the
+    /// next load that needs it. The most recently used part is kept, so
+    /// sprites can still be read from it. `false` once nothing more can go.
+    fn release_oldest(&self) -> bool {
+        false
+    }
+}
+
+/// What a sprite PNG holds, going by its file name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpriteFile {
+    /// `<id>.png`
+    Still(u32),
+    /// `<id>_<frame>.png`
+    Frame(u32, u32),
+    /// `<id>_<x>_<y>.png`, one 32x32 part of a multi-tile sprite counted in
+    /// columns and rows from its top left.
+    Part(u32, u32, u32),
+}
+
+impl SpriteFile {
+    fn parse(file_name: &str) -> Option<Self> {
+        let (stem, extension) = file_name.rsplit_once('.')?;
+        if !extension.eq_ignore_ascii_case("png") {
+            return None;
+        }
+        let numbers: Vec<u32> = stem.split('_').map(|n| n.parse().ok()).collect::<Option<_>>()?;
+        match numbers[..] {
+            [id] => Some(Self::Still(id)),
+            [id, frame] => Some(Self::Frame(id, frame)),
+            [id, x, y] => Some(Self::Part(id, x, y)),
+            _ => None,
+        }
+    }
+}
+
+/// Animation frames and multi-tile parts of the sprites in a directory or
+/// archive, where `T` locates a file (a path or an archive entry).
+struct SpriteIndex<T> {
+    /// Frames of each object in frame order.
+    frames: HashMap<u32, Vec<T>>,
+    /// `(x, y, file)` of each part of a split sprite.
+    parts: HashMap<u32, Vec<(u32, u32, T)>>,
+}
+
+impl<T> SpriteIndex<T> {
+    /// Indexes the frame and part files among `files`; stills are skipped.
+    fn new(files: impl IntoIterator<Item = (SpriteFile, T)>) -> Self {
+        let mut frames: Vec<(u32, u32, T)> = Vec::new();
+        let mut parts: HashMap<u32, Vec<(u32, u32, T)>> = HashMap::new();
+        for (file, entry) in files {
+            match file {
+                SpriteFile::Still(_) => {}
+                SpriteFile::Frame(id, frame) => frames.push((id, frame, entry)),
+                SpriteFile::Part(id, x, y) => parts.entry(id).or_default().push((x, y, entry)),
+            }
+        }
+
+        frames.sort_by_key(|&(id, frame, _)| (id, frame));
+        let mut by_id: HashMap<u32, Vec<T>> = HashMap::new();
+        for (id, _, entry) in frames {
+            by_id.entry(id).or_default().push(entry);
+        }
+        Self { frames: by_id, parts }
+    }
+
+    fn frames(&self, object_id: u32) -> &[T] {
+        self.frames.get(&object_id).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    fn has_parts(&self, object_id: u32) -> bool {
+        self.parts.contains_key(&object_id)
+    }
+
+    /// Loads every part of `object_id` with `load` and stitches them into
+    /// the full sprite.
+    fn stitch(&self, object_id: u32, load: impl Fn(&T) -> Result<RgbaImage>) -> Result<RgbaImage> {
+        let parts = self.parts.get(&object_id).map(Vec::as_slice).unwrap_or_default();
+        let images = parts.iter().map(|(_, _, entry)| load(entry)).collect::<Result<Vec<_>>>()?;
+        let (part_width, part_height) = images.first().context("Sprite has no parts")?.dimensions();
+
+        let columns = parts.iter().map(|&(x, _, _)| x).max().unwrap_or(0) + 1;
+        let rows = parts.iter().map(|&(_, y, _)| y).max().unwrap_or(0) + 1;
+        let mut sprite = RgbaImage::new(columns * part_width, rows * part_height);
+        for (&(x, y, _), image) in parts.iter().zip(&images) {
+            image::imageops::replace(&mut sprite, image, (x * part_width) as i64, (y * part_height) as i64);
+        }
+        Ok(sprite)
+    }
+}
+
+/// Opens `path` as the sprite source its form implies: a directory of
+/// `<id>.png` files, a `.zip` of them, or a sprite sheet index.
+pub fn open_sprite_source<P: AsRef<Path>>(path: P) -> Result<Box<dyn SpriteSource>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        anyhow::bail!("Sprite directory does not exist: {:?}", path);
+    }
+
+    if path.is_dir() {
+        Ok(Box::new(SpriteDir::new(path)))
+    } else if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("zip")) {
+        Ok(Box::new(SpriteZip::open(path)?))
+    } else {
+        Ok(Box::new(SpriteSheets::open(path)?))
+    }
+}
+
+/// A directory of `<id>.png` files, one per object, plus `<id>_<frame>.png`
+/// files for animated objects and `<id>_<x>_<y>.png` parts for sprites split
+/// into 32x32 pieces.
+pub struct SpriteDir {
+    dir: PathBuf,
+    /// Frame and part files, read from the directory on first use.
+    index: OnceLock<SpriteIndex<PathBuf>>,
+}
+
+impl SpriteDir {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self { dir: dir.as_ref().to_path_buf(), index: OnceLock::new() }
+    }
+
+    fn path(&self, object_id: u32) -> PathBuf {
+        self.dir.join(format!("{}.png", object_id))
+    }
+
+    fn index(&self) -> &SpriteIndex<PathBuf> {
+        self.index.get_or_init(|| {
+            SpriteIndex::new(fs::read_dir(&self.dir).into_iter().flatten().filter_map(|entry| {
+                let path = entry.ok()?.path();
+                Some((SpriteFile::parse(path.file_name()?.to_str()?)?, path))
+            }))
+        })
+    }
+}
+
+fn open_png(path: &Path) -> Result<RgbaImage> {
+    Ok(image::open(path).with_context(|| format!("Failed to load sprite from {:?}", path))?.to_rgba8())
+}
+
+impl SpriteSource for SpriteDir {
+    fn contains(&self, object_id: u32) -> bool {
+        let index = self.index();
+        self.path(object_id).is_file() || index.has_parts(object_id) || !index.frames(object_id).is_empty()
+    }
+
+    fn load(&self, object_id: u32) -> Result<RgbaImage> {
+        let path = self.path(object_id);
+        let index = self.index();
+        if path.is_file() {
+            open_png(&path)
+        } else if index.has_parts(object_id) {
+            index.stitch(object_id, |part| open_png(part))
+        } else {
+            open_png(index.frames(object_id).first().unwrap_or(&path))
+        }
+    }
+
+    fn frame_count(&self, object_id: u32) -> usize {
+        self.index().frames(object_id).len()
+    }
+
+    fn load_frame(&self, object_id: u32, frame: usize) -> Result<RgbaImage> {
+        let path = self
+            .index()
+            .frames(object_id)
+            .get(frame)
+            .with_context(|| format!("Sprite {} has no animation frame {}", object_id, frame))?;
+        open_png(path)
+    }
+}
+
+/// A zip archive of sprite files named like those of a [`SpriteDir`], in
+/// any folder inside the archive. Entries are read on demand, one at a time.
+pub struct SpriteZip {
+    archive: Mutex<ZipArchive<File>>,
+    entries: HashMap<u32, usize>,
+    index: SpriteIndex<usize>,
+}
+
+impl SpriteZip {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Failed to open sprite archive {:?}", path))?;
+        let archive = ZipArchive::new(file).with_context(|| format!("Failed to read sprite archive {:?}", path))?;
+
+        let files: Vec<(SpriteFile, usize)> = (0..archive.len())
+            .filter_map(|index| {
+                let file = SpriteFile::parse(archive.name_for_index(index)?.rsplit('/').next()?)?;
+                Some((file, index))
+            })
+            .collect();
+        let entries = files
+            .iter()
+            .filter_map(|&(file, index)| match file {
+                SpriteFile::Still(id) => Some((id, index)),
+                _ => None,
+            })
+            .collect::<HashMap<u32, usize>>();
+        let index = SpriteIndex::new(files);
+
+        tracing::info!(
+            "Indexed {} sprites, {} animated and {} split sprites in {:?}",
+            entries.len(),
+            index.frames.len(),
+            index.parts.len(),
+            path
+        );
+        Ok(Self { archive: Mutex::new(archive), entries, index })
+    }
+
+    fn read_entry(&self, index: usize, object_id: u32) -> Result<RgbaImage> {
+        let mut bytes = Vec::new();
+        self.archive
+            .lock()
+            .unwrap()
+            .by_index(index)
+            .with_context(|| format!("Failed to read sprite {} from the sprite archive", object_id))?
+            .read_to_end(&mut bytes)?;
+
+        Ok(image::load_from_memory(&bytes)
+            .with_context(|| format!("Failed to decode sprite {} from the sprite archive", object_id))?
+            .to_rgba8())
+    }
+}
+
+impl SpriteSource for SpriteZip {
+    fn contains(&self, object_id: u32) -> bool {
+        self.entries.contains_key(&object_id)
+            || self.index.has_parts(object_id)
+            || !self.index.frames(object_id).is_empty()
+    }
+
+    fn load(&self, object_id: u32) -> Result<RgbaImage> {
+        if !self.entries.contains_key(&object_id) && self.index.has_parts(object_id) {
+            return self.index.stitch(object_id, |&entry| self.read_entry(entry, object_id));
+        }
+        let index = self
+            .entries
+            .get(&object_id)
+            .or_else(|| self.index.frames(object_id).first())
+            .with_context(|| format!("Sprite {} is not in the sprite archive", object_id))?;
+        self.read_entry(*index, object_id)
+    }
+
+    fn frame_count(&self, object_id: u32) -> usize {
+        self.index.frames(object_id).len()
+    }
+
+    fn load_frame(&self, object_id: u32, frame: usize) -> Result<RgbaImage> {
+        let index = self
+            .index
+            .frames(object_id)
+            .get(frame)
+            .with_context(|| format!("Sprite {} has no animation frame {}", object_id, frame))?;
+        self.read_entry(*index, object_id)
+    }
+}
+
+/// Object sprites drawn straight from the client `.spr`/`.dat` pair, whose
+/// item IDs are the `objects.srv` TypeIDs. Only the first layer, pattern
+/// and animation frame is drawn; multi-tile items come out 64px wide or tall.
+pub struct ClientSprites {
+    sprites: SpriteArchive,
+    dat: DatFile,
+}
+
+impl ClientSprites {
+    pub fn new(sprites: SpriteArchive, dat: DatFile) -> Self {
+        Self { sprites, dat }
+    }
+
+    pub fn open<P: AsRef<Path>, Q: AsRef<Path>>(spr_path: P, dat_path: Q) -> Result<Self> {
+        Ok(Self::new(SpriteArchive::open(spr_path)?, DatFile::open(dat_path)?))
+    }
+}
+
+impl SpriteSource for ClientSprites {
+    fn contains(&self, object_id: u32) -> bool {
+        self.dat
+            .item(object_id)
+            .is_some_and(|item| item.sprites.iter().any(|&sprite| sprite != 0))
+    }
+
+    fn load(&self, object_id: u32) -> Result<RgbaImage> {
+        let item = self
+            .dat
+            .item(object_id)
+            .with_context(|| format!("Item {} is not in the client .dat", object_id))?;
+        compose_thing(&self.sprites, item, 0, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn test_zip_source_finds_sprites_in_folders() {
+        let path = std::env::temp_dir().join(format!("demonax-sprites-{}.zip", std::process::id()));
+        let mut png = std::io::Cursor::new(Vec::new());
+        RgbaImage::from_pixel(32, 32, image::Rgba([7, 8, 9, 255]))
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        writer.start_file("sprites/100.png", SimpleFileOptions::default()).unwrap();
+        writer.write_all(png.get_ref()).unwrap();
+        writer.start_file("sprites/readme.txt", SimpleFileOptions::default()).unwrap();
+        writer.finish().unwrap();
+
+        let source = open_sprite_source(&path).unwrap();
+        assert!(source.contains(100));
+        assert!(!source.contains(101));
+        assert_eq!(source.load(100).unwrap().get_pixel(0, 0)[2], 9);
+        assert!(source.load(101).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_dir_source_stitches_sprite_parts() {
+        let dir = std::env::temp_dir().join(format!("demonax-sprite-parts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (x, y, shade) in [(0, 0, 10), (1, 0, 20), (0, 1, 30), (1, 1, 40)] {
+            RgbaImage::from_pixel(32, 32, image::Rgba([shade, 0, 0, 255]))
+                .save(dir.join(format!("300_{}_{}.png", x, y)))
+                .unwrap();
+        }
+        RgbaImage::new(32, 32).save(dir.join("400_1.png")).unwrap();
+
+        let source = SpriteDir::new(&dir);
+        let sprite = source.load(300);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let sprite = sprite.unwrap();
+        assert_eq!(sprite.dimensions(), (64, 64));
+        assert_eq!([sprite[(0, 0)][0], sprite[(63, 0)][0], sprite[(0, 63)][0], sprite[(63, 63)][0]], [10, 20, 30, 40]);
+        assert!(source.contains(300) && source.contains(400));
+        assert_eq!((source.frame_count(300), source.frame_count(400)), (0, 1));
+        assert_eq!(SpriteFile::parse("300_1_2.PNG"), Some(SpriteFile::Part(300, 1, 2)));
+        assert_eq!(SpriteFile::parse("300_1_2_3.png"), None);
+    }
+}
@@ -0,0 +1,805 @@
+use crate::sprite_source::{SpriteSource, open_sprite_source};
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use demonax_mapper_core::{MapperError, SpriteFrames};
+use image::{Rgba, RgbaImage, imageops};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Snapshot of sprite cache usage, for build reports and capacity planning.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct SpriteCacheStats {
+    pub entries: usize,
+    pub bytes: usize,
+    pub hits: usize,
+    pub misses: usize,
+    pub placeholders: usize,
+    /// Sprites, scaled copies and frames dropped to stay within the memory limit.
+    pub evictions: usize,
+}
+
+impl SpriteCacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
+}
+
+/// Object IDs this far below or above a broken sprite are tried as its
+/// substitute, nearest first.
+const SUBSTITUTE_RANGE: u32 = 3;
+
+/// Width and height of a full-size sprite tile.
+const SPRITE_SIZE: u32 = 32;
+
+/// Eviction frees memory down to this share of the limit, so it runs once
+/// per batch of new sprites rather than for every one.
+const EVICTION_TARGET: f64 = 0.9;
+
+/// What is wrong with a sprite found by [`SpriteCache::validate_sprites`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "problem")]
+pub enum SpriteProblem {
+    Missing,
+    Corrupt { error: String },
+    UnsupportedSize { width: u32, height: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpriteIssue {
+    pub id: u32,
+    #[serde(flatten)]
+    pub problem: SpriteProblem,
+    /// Object ID whose sprite is drawn instead of the placeholder.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub substitute: Option<u32>,
+}
+
+impl std::fmt::Display for SpriteIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.problem {
+            SpriteProblem::Missing => write!(f, "Sprite {}: missing", self.id)?,
+            SpriteProblem::Corrupt { error } => write!(f, "Sprite {}: corrupt ({})", self.id, error)?,
+            SpriteProblem::UnsupportedSize { width, height } => {
+                write!(f, "Sprite {}: unsupported size {}x{}", self.id, width, height)?
+            }
+        }
+        match self.substitute {
+            Some(id) => write!(f, ", drawn as sprite {}", id),
+            None if matches!(self.problem, SpriteProblem::UnsupportedSize { .. }) => Ok(()),
+            None => write!(f, ", drawn as placeholder"),
+        }
+    }
+}
+
+/// `sprite-report.json`: every sprite problem found before rendering.
+pub fn generate_sprite_report_json(issues: &[SpriteIssue]) -> Result<String> {
+    #[derive(Serialize)]
+    struct SpriteReport<'a> {
+        missing: usize,
+        corrupt: usize,
+        unsupported_size: usize,
+        substituted: usize,
+        issues: &'a [SpriteIssue],
+    }
+
+    let count = |f: fn(&SpriteProblem) -> bool| issues.iter().filter(|i| f(&i.problem)).count();
+    let report = SpriteReport {
+        missing: count(|p| matches!(p, SpriteProblem::Missing)),
+        corrupt: count(|p| matches!(p, SpriteProblem::Corrupt { .. })),
+        unsupported_size: count(|p| matches!(p, SpriteProblem::UnsupportedSize { .. })),
+        substituted: issues.iter().filter(|i| i.substitute.is_some()).count(),
+        issues,
+    };
+
+    serde_json::to_string_pretty(&report).with_context(|| "Failed to serialize sprite report to JSON")
+}
+
+fn is_supported_size(width: u32, height: u32) -> bool {
+    (width == 32 || width == 64) && (height == 32 || height == 64)
+}
+
+/// Hand-fixed sprites that replace the ones in the main sprite dump.
+#[derive(Debug, Clone, Default)]
+pub struct SpriteOverrides {
+    files: HashMap<u32, PathBuf>,
+}
+
+impl SpriteOverrides {
+    /// Reads a directory of `<id>.png` files, or a JSON object mapping object
+    /// IDs to PNG paths relative to the mapping file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut files = HashMap::new();
+
+        if path.is_dir() {
+            for entry in fs::read_dir(path).with_context(|| format!("Failed to read sprite overrides {:?}", path))? {
+                let file = entry?.path();
+                if file.extension().is_some_and(|e| e.eq_ignore_ascii_case("png"))
+                    && let Some(id) = file.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse().ok())
+                {
+                    files.insert(id, file);
+                }
+            }
+        } else {
+            let mapping: HashMap<String, PathBuf> = serde_json::from_str(
+                &fs::read_to_string(path).with_context(|| format!("Failed to read sprite overrides {:?}", path))?,
+            )
+            .with_context(|| format!("Failed to parse sprite overrides {:?}", path))?;
+            let base = path.parent().unwrap_or(Path::new(""));
+            for (id, file) in mapping {
+                let id = id
+                    .parse()
+                    .with_context(|| format!("Invalid object ID {:?} in sprite overrides {:?}", id, path))?;
+                files.insert(id, base.join(file));
+            }
+        }
+
+        Ok(Self { files })
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn get(&self, object_id: u32) -> Option<&Path> {
+        self.files.get(&object_id).map(PathBuf::as_path)
+    }
+}
+
+/// A decoded sprite and when it was last drawn, in ticks of the cache clock.
+struct CachedSprite {
+    image: Arc<RgbaImage>,
+    last_used: AtomicU64,
+}
+
+/// Which map of the cache an entry picked for eviction lives in.
+#[derive(Clone, Copy)]
+enum CacheKey {
+    Sprite(u32),
+    Scaled(u32, u32),
+    Frame(u32, usize, u32),
+}
+
+pub struct SpriteCache {
+    sprites: Arc<DashMap<u32, CachedSprite>>,
+    /// Sprites resampled for zoom levels below full size, keyed by object ID
+    /// and size in pixels per game tile.
+    scaled: DashMap<(u32, u32), CachedSprite>,
+    /// Animation frames after the first, keyed by object ID, frame and size
+    /// in pixels per game tile.
+    frames: DashMap<(u32, usize, u32), CachedSprite>,
+    frame_mode: SpriteFrames,
+    source: Box<dyn SpriteSource>,
+    /// Decoded bytes in `sprites`, `scaled` and `frames`, and the most they
+    /// may reach before the least recently used entries are dropped.
+    bytes: AtomicUsize,
+    max_bytes: Option<usize>,
+    clock: AtomicU64,
+    eviction: Mutex<()>,
+    overrides: SpriteOverrides,
+    /// What is drawn for sprites that failed to load: a substitute or the
+    /// placeholder. Kept so each broken sprite is only reported once.
+    fallbacks: DashMap<u32, Arc<RgbaImage>>,
+    substitute_nearest: bool,
+    missing_sprite: Arc<RgbaImage>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    placeholders: AtomicUsize,
+    evictions: AtomicUsize,
+}
+
+impl SpriteCache {
+    /// `sprite_path` is a directory of `<id>.png` files, a `.zip` of them or
+    /// a JSON sprite sheet index (see [`open_sprite_source`]).
+    pub fn new<P: AsRef<Path>>(sprite_path: P) -> Result<Self> {
+        Ok(Self::from_source(open_sprite_source(sprite_path)?))
+    }
+
+    /// Caches the sprites of any source, e.g. [`ClientSprites`](crate::ClientSprites).
+    pub fn from_source(source: Box<dyn SpriteSource>) -> Self {
+        let missing_sprite = Arc::new(Self::create_missing_sprite());
+
+        Self {
+            sprites: Arc::new(DashMap::new()),
+            scaled: DashMap::new(),
+            frames: DashMap::new(),
+            frame_mode: SpriteFrames::First,
+            source,
+            bytes: AtomicUsize::new(0),
+            max_bytes: None,
+            clock: AtomicU64::new(0),
+            eviction: Mutex::new(()),
+            overrides: SpriteOverrides::default(),
+            fallbacks: DashMap::new(),
+            substitute_nearest: false,
+            missing_sprite,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+            placeholders: AtomicUsize::new(0),
+            evictions: AtomicUsize::new(0),
+        }
+    }
+
+    /// Consults `overrides` before `sprite_path` for the objects it lists.
+    pub fn with_overrides(mut self, overrides: SpriteOverrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Draws the nearest object ID with a working sprite, up to
+    /// `SUBSTITUTE_RANGE` below or above, instead of the placeholder for
+    /// missing or corrupt sprites.
+    pub fn with_substitutes(mut self, enabled: bool) -> Self {
+        self.substitute_nearest = enabled;
+        self
+    }
+
+    /// Blends or animates sprites stored as `<id>_<frame>.png` frames
+    /// instead of drawing only the first one.
+    pub fn with_frames(mut self, mode: SpriteFrames) -> Self {
+        self.frame_mode = mode;
+        self
+    }
+
+    /// Keeps decoded sprites within `max_bytes`, dropping the least
+    /// recently drawn ones first. Dropped sprites are decoded again when
+    /// next needed, so a tight limit trades memory for build time.
+    pub fn with_memory_limit(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// A new entry for `image`, marked as just used.
+    fn entry(&self, image: Arc<RgbaImage>) -> CachedSprite {
+        let last_used = AtomicU64::new(self.clock.fetch_add(1, Ordering::Relaxed));
+        CachedSprite { image, last_used }
+    }
+
+    /// Marks `entry` as just used and returns its image.
+    fn touch(&self, entry: &CachedSprite) -> Arc<RgbaImage> {
+        entry.last_used.store(self.clock.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+        Arc::clone(&entry.image)
+    }
+
+    /// Looks up a decoded sprite and marks it as just used.
+    fn cached(&self, object_id: u32) -> Option<Arc<RgbaImage>> {
+        self.sprites.get(&object_id).map(|entry| self.touch(&entry))
+    }
+
+    fn insert(&self, object_id: u32, image: Arc<RgbaImage>) {
+        self.bytes.fetch_add(image.as_raw().len(), Ordering::Relaxed);
+        if let Some(old) = self.sprites.insert(object_id, self.entry(image)) {
+            self.bytes.fetch_sub(old.image.as_raw().len(), Ordering::Relaxed);
+        }
+        self.enforce_limit();
+    }
+
+    fn insert_scaled(&self, key: (u32, u32), image: Arc<RgbaImage>) {
+        self.bytes.fetch_add(image.as_raw().len(), Ordering::Relaxed);
+        if let Some(old) = self.scaled.insert(key, self.entry(image)) {
+            self.bytes.fetch_sub(old.image.as_raw().len(), Ordering::Relaxed);
+        }
+        self.enforce_limit();
+    }
+
+    fn insert_frame(&self, key: (u32, usize, u32), image: Arc<RgbaImage>) {
+        self.bytes.fetch_add(image.as_raw().len(), Ordering::Relaxed);
+        if let Some(old) = self.frames.insert(key, self.entry(image)) {
+            self.bytes.fetch_sub(old.image.as_raw().len(), Ordering::Relaxed);
+        }
+        self.enforce_limit();
+    }
+
+    fn enforce_limit(&self) {
+        if self.max_bytes.is_some_and(|max| self.memory_bytes() > max) {
+            self.evict();
+        }
+    }
+
+    /// Drops what the sprite source retains, least recently used first,
+    /// then the least recently used sprites, scaled copies and frames until
+    /// the cache is back under `EVICTION_TARGET` of the limit. One thread
+    /// evicts at a time; the others carry on, slightly over the limit until
+    /// it is done.
+    fn evict(&self) {
+        let (Some(max_bytes), Ok(_guard)) = (self.max_bytes, self.eviction.try_lock()) else {
+            return;
+        };
+        let target = (max_bytes as f64 * EVICTION_TARGET) as usize;
+        while self.memory_bytes() > target && self.source.release_oldest() {}
+
+        let age = |entry: &CachedSprite| entry.last_used.load(Ordering::Relaxed);
+        let mut by_age: Vec<(u64, CacheKey)> = self
+            .sprites
+            .iter()
+            .map(|entry| (age(entry.value()), CacheKey::Sprite(*entry.key())))
+            .chain(self.scaled.iter().map(|entry| {
+                let (object_id, size) = *entry.key();
+                (age(entry.value()), CacheKey::Scaled(object_id, size))
+            }))
+            .chain(self.frames.iter().map(|entry| {
+                let (object_id, frame, size) = *entry.key();
+                (age(entry.value()), CacheKey::Frame(object_id, frame, size))
+            }))
+            .collect();
+        by_age.sort_unstable_by_key(|&(last_used, _)| last_used);
+
+        for (_, key) in by_age {
+            if self.memory_bytes() <= target {
+                break;
+            }
+            let evicted = match key {
+                CacheKey::Sprite(object_id) => self.sprites.remove(&object_id).map(|(_, entry)| entry),
+                CacheKey::Scaled(object_id, size) => self.scaled.remove(&(object_id, size)).map(|(_, entry)| entry),
+                CacheKey::Frame(object_id, frame, size) => {
+                    self.frames.remove(&(object_id, frame, size)).map(|(_, entry)| entry)
+                }
+            };
+            if let Some(evicted) = evicted {
+                self.bytes.fetch_sub(evicted.image.as_raw().len(), Ordering::Relaxed);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn get_sprite(&self, object_id: u32) -> Result<Arc<RgbaImage>> {
+        if let Some(sprite) = self.cached(object_id) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(sprite);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(fallback) = self.fallbacks.get(&object_id) {
+            let fallback = Arc::clone(&fallback);
+            if self.is_placeholder(&fallback) {
+                self.placeholders.fetch_add(1, Ordering::Relaxed);
+            }
+            return Ok(fallback);
+        }
+
+        match self.load_sprite_from_disk(object_id) {
+            Ok(sprite) => {
+                let sprite_arc = Arc::new(sprite);
+                self.insert(object_id, Arc::clone(&sprite_arc));
+                Ok(sprite_arc)
+            }
+            Err(e) => {
+                let (substitute, sprite) = self.install_fallback(object_id);
+                match substitute {
+                    Some(id) => warn!(kind = "sprite_load", id = object_id, "Failed to load sprite {}: {}. Using sprite {}", object_id, e, id),
+                    None => {
+                        warn!(kind = "sprite_load", id = object_id, "Failed to load sprite {}: {}. Using placeholder", object_id, e);
+                        self.placeholders.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Ok(sprite)
+            }
+        }
+    }
+
+    /// The sprite of `object_id` drawn at `size` pixels per game tile (32 is
+    /// full size). Resampled copies are cached, since every tile of a zoom
+    /// level would otherwise resample the same sprites again.
+    pub fn get_scaled_sprite(&self, object_id: u32, size: u32) -> Result<Arc<RgbaImage>> {
+        if size == SPRITE_SIZE {
+            return self.get_sprite(object_id);
+        }
+
+        if let Some(entry) = self.scaled.get(&(object_id, size)) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(self.touch(&entry));
+        }
+
+        let scaled = Arc::new(scale_sprite(&*self.get_sprite(object_id)?, size));
+        self.insert_scaled((object_id, size), Arc::clone(&scaled));
+        Ok(scaled)
+    }
+
+    /// Animation frames drawn for `object_id`: 1 unless animating and the
+    /// sprite source has frames for it. Overrides are always still images.
+    pub fn frame_count(&self, object_id: u32) -> usize {
+        if self.frame_mode != SpriteFrames::Animate || self.overrides.get(object_id).is_some() {
+            return 1;
+        }
+        self.source.frame_count(object_id).max(1)
+    }
+
+    /// Animation frame `frame` of `object_id` at `size` pixels per game
+    /// tile. Frame 0 is the sprite itself, and frames that fail to load are
+    /// drawn as it too.
+    pub fn get_scaled_frame(&self, object_id: u32, frame: usize, size: u32) -> Result<Arc<RgbaImage>> {
+        if frame == 0 {
+            return self.get_scaled_sprite(object_id, size);
+        }
+
+        if let Some(entry) = self.frames.get(&(object_id, frame, size)) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(self.touch(&entry));
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let image = match self.source.load_frame(object_id, frame) {
+            Ok(image) => Arc::new(scale_sprite(&image, size)),
+            Err(e) => {
+                warn!(kind = "sprite_frame", id = object_id, "Failed to load frame {} of sprite {}: {}", frame, object_id, e);
+                self.get_scaled_sprite(object_id, size)?
+            }
+        };
+        self.insert_frame((object_id, frame, size), Arc::clone(&image));
+        Ok(image)
+    }
+
+    /// Decodes every sprite in `object_ids` up front and reports those that
+    /// are missing, fail to decode or have unsupported dimensions. Broken
+    /// sprites get their substitute or the placeholder here, so rendering
+    /// draws it without warning on every use.
+    pub fn validate_sprites(&self, object_ids: &[u32]) -> Vec<SpriteIssue> {
+        use rayon::prelude::*;
+
+        let mut issues: Vec<SpriteIssue> = object_ids
+            .par_iter()
+            .filter_map(|&id| {
+                if self.sprites.contains_key(&id) || self.fallbacks.contains_key(&id) {
+                    return None;
+                }
+                match self.read_sprite(id) {
+                    Ok(rgba) => {
+                        let (width, height) = rgba.dimensions();
+                        self.insert(id, Arc::new(rgba));
+                        let problem = SpriteProblem::UnsupportedSize { width, height };
+                        (!is_supported_size(width, height)).then_some(SpriteIssue { id, problem, substitute: None })
+                    }
+                    Err(e) => {
+                        let problem = match e.downcast_ref::<MapperError>() {
+                            Some(MapperError::MissingSprite(_)) => SpriteProblem::Missing,
+                            _ => SpriteProblem::Corrupt { error: e.root_cause().to_string() },
+                        };
+                        let (substitute, _) = self.install_fallback(id);
+                        Some(SpriteIssue { id, problem, substitute })
+                    }
+                }
+            })
+            .collect();
+
+        issues.sort_by_key(|issue| issue.id);
+        issues
+    }
+
+    /// Remembers what to draw for a sprite that failed to load.
+    fn install_fallback(&self, object_id: u32) -> (Option<u32>, Arc<RgbaImage>) {
+        let (substitute, sprite) = match self.substitute_nearest.then(|| self.nearest_substitute(object_id)).flatten() {
+            Some((id, sprite)) => (Some(id), sprite),
+            None => (None, Arc::clone(&self.missing_sprite)),
+        };
+        self.fallbacks.insert(object_id, Arc::clone(&sprite));
+        (substitute, sprite)
+    }
+
+    /// Object IDs next to each other are often variants of the same thing
+    /// (rotations, open and closed states), so the closest one that loads
+    /// with a supported size is the least jarring stand-in.
+    fn nearest_substitute(&self, object_id: u32) -> Option<(u32, Arc<RgbaImage>)> {
+        (1..=SUBSTITUTE_RANGE)
+            .flat_map(|distance| [object_id.checked_sub(distance), object_id.checked_add(distance)])
+            .flatten()
+            .find_map(|id| {
+                if let Some(sprite) = self.cached(id) {
+                    return Some((id, sprite));
+                }
+                let rgba = self.read_sprite(id).ok()?;
+                is_supported_size(rgba.width(), rgba.height()).then(|| (id, Arc::new(rgba)))
+            })
+    }
+
+    /// Whether `object_id` is cached or in the sprite source, without falling
+    /// back to the placeholder.
+    pub fn has_sprite(&self, object_id: u32) -> bool {
+        if self.sprites.contains_key(&object_id) {
+            return true;
+        }
+        if let Some(path) = self.overrides.get(object_id) {
+            return path.is_file();
+        }
+        self.source.contains(object_id)
+    }
+
+    /// Whether `get_sprite` fell back to the placeholder for this sprite.
+    pub fn is_placeholder(&self, sprite: &Arc<RgbaImage>) -> bool {
+        Arc::ptr_eq(sprite, &self.missing_sprite)
+    }
+
+    pub fn preload_sprites(&self, object_ids: &[u32]) -> Result<()> {
+        use rayon::prelude::*;
+
+        object_ids.par_iter().try_for_each(|&id| {
+            self.get_sprite(id)?;
+            Ok::<_, anyhow::Error>(())
+        })?;
+
+        Ok(())
+    }
+
+    pub fn cache_size(&self) -> usize {
+        self.sprites.len()
+    }
+
+    /// Decoded and scaled RGBA bytes held by the cache and retained by its
+    /// sprite source (full-size placeholder excluded).
+    pub fn memory_bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed) + self.source.retained_bytes()
+    }
+
+    pub fn stats(&self) -> SpriteCacheStats {
+        SpriteCacheStats {
+            entries: self.cache_size(),
+            bytes: self.memory_bytes(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            placeholders: self.placeholders.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    fn load_sprite_from_disk(&self, object_id: u32) -> Result<RgbaImage> {
+        let rgba = self.read_sprite(object_id)?;
+        let (width, height) = rgba.dimensions();
+
+        if !is_supported_size(width, height) {
+            warn!(
+                kind = "sprite_size",
+                id = object_id,
+                "Sprite {} has unsupported dimensions: {}x{} (supported: 32x32, 64x64, 64x32, 32x64)",
+                object_id,
+                width,
+                height
+            );
+        }
+
+        Ok(rgba)
+    }
+
+    fn read_sprite(&self, object_id: u32) -> Result<RgbaImage> {
+        match self.overrides.get(object_id) {
+            Some(path) => Ok(image::open(path)
+                .with_context(|| format!("Failed to load sprite override from {:?}", path))?
+                .to_rgba8()),
+            None if self.frame_mode == SpriteFrames::Average && self.source.frame_count(object_id) > 1 => {
+                let frames = (0..self.source.frame_count(object_id))
+                    .map(|frame| self.source.load_frame(object_id, frame))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(average_frames(&frames))
+            }
+            None if !self.source.contains(object_id) => Err(MapperError::MissingSprite(object_id).into()),
+            None => self.source.load(object_id),
+        }
+    }
+
+    fn create_missing_sprite() -> RgbaImage {
+        let mut img = RgbaImage::new(32, 32);
+
+        for y in 0..32 {
+            for x in 0..32 {
+                let color = if (x / 8 + y / 8) % 2 == 0 {
+                    Rgba([255, 0, 255, 255])
+                } else {
+                    Rgba([255, 105, 180, 255])
+                };
+                img.put_pixel(x, y, color);
+            }
+        }
+
+        img
+    }
+}
+
+/// Blends animation frames into one image. Each pixel is weighted by alpha,
+/// so frames where it is transparent fade it instead of darkening it.
+/// Frames sized differently from the first are left out.
+fn average_frames(frames: &[RgbaImage]) -> RgbaImage {
+    let (width, height) = frames[0].dimensions();
+    let frames: Vec<&RgbaImage> = frames.iter().filter(|frame| frame.dimensions() == (width, height)).collect();
+    let count = frames.len() as u32;
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let mut alpha = 0u32;
+        let mut color = [0u32; 3];
+        for frame in &frames {
+            let Rgba([r, g, b, a]) = *frame.get_pixel(x, y);
+            alpha += a as u32;
+            color[0] += r as u32 * a as u32;
+            color[1] += g as u32 * a as u32;
+            color[2] += b as u32 * a as u32;
+        }
+        if alpha == 0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let channel = |sum: u32| ((sum + alpha / 2) / alpha) as u8;
+        Rgba([channel(color[0]), channel(color[1]), channel(color[2]), ((alpha + count / 2) / count) as u8])
+    })
+}
+
+fn scale_sprite(sprite: &RgbaImage, target_size: u32) -> RgbaImage {
+    let (width, height) = sprite.dimensions();
+
+    let scale_factor = target_size as f32 / SPRITE_SIZE as f32;
+
+    let new_width = (width as f32 * scale_factor).round() as u32;
+    let new_height = (height as f32 * scale_factor).round() as u32;
+
+    if new_width == width && new_height == height {
+        return (*sprite).clone();
+    }
+
+    imageops::resize(
+        sprite,
+        new_width,
+        new_height,
+        imageops::FilterType::Lanczos3,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_missing_sprite() {
+        let sprite = SpriteCache::create_missing_sprite();
+        assert_eq!(sprite.dimensions(), (32, 32));
+
+        let top_left = sprite.get_pixel(0, 0);
+        assert_eq!(top_left[0], 255);
+    }
+
+    #[test]
+    fn test_stats_count_placeholder_fallbacks() {
+        let cache = SpriteCache::new(std::env::temp_dir()).unwrap();
+        cache.get_sprite(u32::MAX).unwrap();
+        cache.get_sprite(u32::MAX).unwrap();
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 0);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.placeholders, 2);
+        assert_eq!(stats.hit_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_memory_limit_evicts_least_recently_used() {
+        let dir = std::env::temp_dir().join(format!("demonax-lru-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for id in 100..103 {
+            RgbaImage::from_pixel(32, 32, image::Rgba([id as u8, 0, 0, 255])).save(dir.join(format!("{}.png", id))).unwrap();
+        }
+
+        // Room for two 4 KiB sprites
+        let cache = SpriteCache::new(&dir).unwrap().with_memory_limit(2 * 32 * 32 * 4);
+        cache.get_sprite(100).unwrap();
+        cache.get_sprite(101).unwrap();
+        cache.get_sprite(100).unwrap();
+        cache.get_sprite(102).unwrap();
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.evictions), (1, 2));
+        assert!(stats.bytes <= 2 * 32 * 32 * 4);
+        assert!(cache.cached(102).is_some());
+        assert_eq!(cache.get_sprite(101).unwrap().get_pixel(0, 0)[0], 101);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_memory_limit_evicts_scaled_copies() {
+        let dir = std::env::temp_dir().join(format!("demonax-lru-scaled-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        RgbaImage::from_pixel(32, 32, image::Rgba([1, 0, 0, 255])).save(dir.join("100.png")).unwrap();
+
+        // The full-size sprite plus a handful of scaled copies
+        let limit = 32 * 32 * 4 + 2 * 16 * 16 * 4;
+        let cache = SpriteCache::new(&dir).unwrap().with_memory_limit(limit);
+        for size in [16, 17, 18, 19, 20, 21, 22, 23] {
+            cache.get_scaled_sprite(100, size).unwrap();
+        }
+
+        let stats = cache.stats();
+        assert!(stats.bytes <= limit);
+        assert!(stats.evictions > 0);
+        assert!(cache.scaled.contains_key(&(100, 23)));
+        assert!(!cache.scaled.contains_key(&(100, 16)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_scaled_sprites_are_cached() {
+        let dir = std::env::temp_dir().join(format!("demonax-scaled-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        RgbaImage::from_pixel(64, 32, image::Rgba([5, 6, 7, 255])).save(dir.join("100.png")).unwrap();
+
+        let cache = SpriteCache::new(&dir).unwrap();
+        let first = cache.get_scaled_sprite(100, 8).unwrap();
+        assert_eq!(first.dimensions(), (16, 8));
+        assert!(Arc::ptr_eq(&first, &cache.get_scaled_sprite(100, 8).unwrap()));
+        assert!(Arc::ptr_eq(&cache.get_scaled_sprite(100, 32).unwrap(), &cache.get_sprite(100).unwrap()));
+        assert_eq!(cache.memory_bytes(), 64 * 32 * 4 + 16 * 8 * 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_animation_frames_are_blended_or_animated() {
+        let dir = std::env::temp_dir().join(format!("demonax-frames-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        RgbaImage::from_pixel(32, 32, image::Rgba([200, 0, 0, 255])).save(dir.join("100_0.png")).unwrap();
+        RgbaImage::from_pixel(32, 32, image::Rgba([0, 0, 200, 255])).save(dir.join("100_1.png")).unwrap();
+        RgbaImage::from_pixel(32, 32, image::Rgba([0, 0, 0, 0])).save(dir.join("100_2.png")).unwrap();
+
+        let first = SpriteCache::new(&dir).unwrap();
+        assert!(first.has_sprite(100));
+        assert_eq!(first.frame_count(100), 1);
+        assert_eq!(first.get_sprite(100).unwrap().get_pixel(0, 0), &Rgba([200, 0, 0, 255]));
+
+        // The transparent frame fades the blend without darkening it
+        let average = SpriteCache::new(&dir).unwrap().with_frames(SpriteFrames::Average);
+        assert_eq!(average.get_sprite(100).unwrap().get_pixel(0, 0), &Rgba([100, 0, 100, 170]));
+
+        let animate = SpriteCache::new(&dir).unwrap().with_frames(SpriteFrames::Animate);
+        assert_eq!(animate.frame_count(100), 3);
+        assert_eq!(animate.get_scaled_frame(100, 1, 16).unwrap().get_pixel(0, 0), &Rgba([0, 0, 200, 255]));
+        assert_eq!(animate.get_scaled_frame(100, 1, 16).unwrap().dimensions(), (16, 16));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_overrides_replace_sprites() {
+        let dir = std::env::temp_dir().join(format!("demonax-overrides-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sprites")).unwrap();
+        RgbaImage::from_pixel(32, 32, image::Rgba([1, 2, 3, 255])).save(dir.join("sprites/100.png")).unwrap();
+        RgbaImage::from_pixel(32, 32, image::Rgba([9, 9, 9, 255])).save(dir.join("fixed.png")).unwrap();
+        fs::write(dir.join("overrides.json"), r#"{"100": "fixed.png", "101": "fixed.png"}"#).unwrap();
+
+        let overrides = SpriteOverrides::open(dir.join("overrides.json")).unwrap();
+        assert_eq!(overrides.len(), 2);
+        let cache = SpriteCache::new(dir.join("sprites")).unwrap().with_overrides(overrides);
+        assert_eq!(cache.get_sprite(100).unwrap().get_pixel(0, 0)[0], 9);
+        assert!(cache.has_sprite(101));
+        assert!(!cache.has_sprite(102));
+
+        let from_dir = SpriteOverrides::open(dir.join("sprites")).unwrap();
+        assert_eq!(from_dir.get(100), Some(dir.join("sprites/100.png").as_path()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate_reports_and_substitutes_broken_sprites() {
+        let dir = std::env::temp_dir().join(format!("demonax-validate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        RgbaImage::from_pixel(32, 32, image::Rgba([1, 2, 3, 255])).save(dir.join("100.png")).unwrap();
+        RgbaImage::from_pixel(48, 32, image::Rgba([4, 5, 6, 255])).save(dir.join("200.png")).unwrap();
+        fs::write(dir.join("101.png"), &fs::read(dir.join("100.png")).unwrap()[..40]).unwrap();
+
+        let cache = SpriteCache::new(&dir).unwrap().with_substitutes(true);
+        let issues = cache.validate_sprites(&[100, 101, 200, 300]);
+
+        assert_eq!(issues.len(), 3);
+        assert!(matches!(issues[0].problem, SpriteProblem::Corrupt { .. }));
+        assert_eq!(issues[0].substitute, Some(100));
+        assert_eq!(issues[1].problem, SpriteProblem::UnsupportedSize { width: 48, height: 32 });
+        assert_eq!((issues[2].id, &issues[2].problem, issues[2].substitute), (300, &SpriteProblem::Missing, None));
+
+        assert_eq!(cache.get_sprite(101).unwrap().get_pixel(0, 0)[0], 1);
+        assert!(cache.is_placeholder(&cache.get_sprite(300).unwrap()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+use crate::tiles_sprite::tile_grid_size;
+use anyhow::{Context, Result};
+use demonax_mapper_core::{SpriteMapData, TileFormat};
+use image::{imageops, RgbaImage};
+use std::fs;
+use std::path::Path;
+
+pub const THUMBNAIL_DIR: &str = "thumbnails";
+
+/// Longest side of a floor thumbnail in pixels.
+pub const THUMBNAIL_SIZE: u32 = 160;
+
+const TILE_SIZE: u32 = 256;
+
+/// Shrinks the rendered `zoom` tiles of a floor into one small overview image
+/// at `thumbnails/<floor>.png`, for the viewer's floor picker. Each tile is
+/// scaled down on its own, so the full zoom level is never held in memory.
+/// Returns the number of bytes written. AVIF tiles cannot be decoded by this
+/// build, so callers skip thumbnails for them.
+pub fn generate_floor_thumbnail<P: AsRef<Path>>(
+    map_data: &SpriteMapData,
+    output_path: P,
+    zoom: u8,
+    format: TileFormat,
+) -> Result<u64> {
+    let output_path = output_path.as_ref();
+    let bounds = map_data.bounds();
+    let scale = 2u32.pow(zoom as u32);
+    let (map_width, map_height) = (bounds.width() * scale, bounds.height() * scale);
+
+    let factor = THUMBNAIL_SIZE as f64 / map_width.max(map_height) as f64;
+    let to_thumb = |pixels: u32| (pixels as f64 * factor).round() as u32;
+    let mut thumbnail = RgbaImage::new(to_thumb(map_width).max(1), to_thumb(map_height).max(1));
+
+    let zoom_dir = output_path.join(map_data.floor.to_string()).join(zoom.to_string());
+    let (num_tiles_x, num_tiles_y) = tile_grid_size(map_data, zoom);
+    for tile_x in 0..num_tiles_x {
+        for tile_y in 0..num_tiles_y {
+            let tile_path = zoom_dir.join(tile_x.to_string()).join(format!("{}.{}", tile_y, format.extension()));
+            let Ok(tile) = image::open(&tile_path) else {
+                continue;
+            };
+
+            // Tiles on the right and bottom edge extend past the map
+            let (start_x, start_y) = (tile_x * TILE_SIZE, tile_y * TILE_SIZE);
+            let (end_x, end_y) = (((tile_x + 1) * TILE_SIZE).min(map_width), ((tile_y + 1) * TILE_SIZE).min(map_height));
+            let (width, height) = (to_thumb(end_x) - to_thumb(start_x), to_thumb(end_y) - to_thumb(start_y));
+            if width == 0 || height == 0 {
+                continue;
+            }
+
+            let visible = tile.to_rgba8();
+            let visible = imageops::crop_imm(&visible, 0, 0, end_x - start_x, end_y - start_y).to_image();
+            let scaled = imageops::resize(&visible, width, height, imageops::FilterType::Triangle);
+            imageops::overlay(&mut thumbnail, &scaled, to_thumb(start_x) as i64, to_thumb(start_y) as i64);
+        }
+    }
+
+    let thumbnail_dir = output_path.join(THUMBNAIL_DIR);
+    fs::create_dir_all(&thumbnail_dir)?;
+    let path = thumbnail_dir.join(format!("{}.png", map_data.floor));
+    thumbnail
+        .save(&path)
+        .with_context(|| format!("Failed to write thumbnail {:?}", path))?;
+    Ok(fs::metadata(&path)?.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thumbnail_fits_map_aspect() {
+        let dir = std::env::temp_dir().join(format!("demonax-thumbnail-{}", std::process::id()));
+        // 64 x 32 game tiles: two 256px tiles wide at zoom 3, the right one red
+        let map_data = SpriteMapData {
+            floor: 8,
+            tiles: Vec::new(),
+            min_sector_x: 1000,
+            max_sector_x: 1001,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+        for (x, color) in [(0, [0, 0, 255, 255]), (1, [255, 0, 0, 255])] {
+            fs::create_dir_all(dir.join(format!("8/3/{}", x))).unwrap();
+            RgbaImage::from_pixel(256, 256, image::Rgba(color)).save(dir.join(format!("8/3/{}/0.png", x))).unwrap();
+        }
+
+        generate_floor_thumbnail(&map_data, &dir, 3, TileFormat::Png).unwrap();
+        let thumbnail = image::open(dir.join("thumbnails/8.png")).unwrap().to_rgba8();
+        assert_eq!(thumbnail.dimensions(), (THUMBNAIL_SIZE, THUMBNAIL_SIZE / 2));
+        assert_eq!(thumbnail.get_pixel(10, 10).0, [0, 0, 255, 255]);
+        assert_eq!(thumbnail.get_pixel(150, 70).0, [255, 0, 0, 255]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,265 @@
+use crate::output_sink::OutputSink;
+use anyhow::{Context, Result};
+use demonax_mapper_core::{SpriteMapData, TileFormat};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{ExtendedColorType, ImageEncoder, RgbaImage};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+const TILE_SIZE: u32 = 256;
+
+/// AVIF encoder speed (1 slowest to 10 fastest) and quality (1 to 100).
+const AVIF_SPEED: u8 = 8;
+const AVIF_QUALITY: u8 = 90;
+
+/// Provenance written into every tile as PNG tEXt chunks, so a stray tile
+/// (say, one served from a poisoned CDN cache) can be traced to its origin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMetadata {
+    /// Which tile set the tile belongs to, e.g. `sprites` or `heatmap`.
+    pub layer: &'static str,
+    pub floor: u8,
+    pub zoom: u8,
+    pub tile_x: u32,
+    pub tile_y: u32,
+    /// Inclusive world coordinates covered by the tile.
+    pub world_min: (u32, u32),
+    pub world_max: (u32, u32),
+}
+
+impl TileMetadata {
+    pub fn for_tile(layer: &'static str, map_data: &SpriteMapData, zoom: u8, tile_x: u32, tile_y: u32) -> Self {
+        let scale = 2u32.pow(zoom as u32);
+        let bounds = map_data.bounds();
+        let (map_width, map_height) = (bounds.width(), bounds.height());
+        let (origin_x, origin_y) = bounds.min_tile();
+
+        let start_x = tile_x * TILE_SIZE / scale;
+        let start_y = tile_y * TILE_SIZE / scale;
+        let end_x = ((tile_x + 1) * TILE_SIZE / scale).min(map_width).max(start_x + 1);
+        let end_y = ((tile_y + 1) * TILE_SIZE / scale).min(map_height).max(start_y + 1);
+
+        Self {
+            layer,
+            floor: map_data.floor,
+            zoom,
+            tile_x,
+            tile_y,
+            world_min: (origin_x + start_x, origin_y + start_y),
+            world_max: (origin_x + end_x - 1, origin_y + end_y - 1),
+        }
+    }
+
+    pub fn text_chunks(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Software", format!("demonax-mapper {}", env!("CARGO_PKG_VERSION"))),
+            ("Demonax-Layer", self.layer.to_string()),
+            ("Demonax-Floor", self.floor.to_string()),
+            ("Demonax-Zoom", self.zoom.to_string()),
+            ("Demonax-Tile", format!("{},{}", self.tile_x, self.tile_y)),
+            (
+                "Demonax-Bounds",
+                format!(
+                    "{},{}-{},{}",
+                    self.world_min.0, self.world_min.1, self.world_max.0, self.world_max.1
+                ),
+            ),
+        ]
+    }
+}
+
+/// Encodes `image` as a PNG carrying `metadata`.
+pub fn encode_tile_png(image: &RgbaImage, metadata: &TileMetadata) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in metadata.text_chunks() {
+        encoder.add_text_chunk(keyword.to_string(), text)?;
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image.as_raw())?;
+    writer.finish()?;
+
+    Ok(data)
+}
+
+/// Encodes `image` as a tile in `format`. Only PNG tiles carry `metadata`;
+/// WebP tiles are lossless and AVIF tiles lossy.
+pub fn encode_tile(image: &RgbaImage, metadata: &TileMetadata, format: TileFormat) -> Result<Vec<u8>> {
+    let (width, height) = image.dimensions();
+    let mut data = Vec::new();
+    match format {
+        TileFormat::Png => return encode_tile_png(image, metadata),
+        TileFormat::Webp => WebPEncoder::new_lossless(&mut data).write_image(
+            image.as_raw(),
+            width,
+            height,
+            ExtendedColorType::Rgba8,
+        )?,
+        TileFormat::Avif => AvifEncoder::new_with_speed_quality(&mut data, AVIF_SPEED, AVIF_QUALITY).write_image(
+            image.as_raw(),
+            width,
+            height,
+            ExtendedColorType::Rgba8,
+        )?,
+    }
+    Ok(data)
+}
+
+/// Encodes `frames` as a looping animated WebP, each frame shown for
+/// `frame_ms` milliseconds. Frames are encoded losslessly one by one and
+/// wrapped in the extended WebP container, since the encoder only writes
+/// still images.
+pub fn encode_animated_webp(frames: &[RgbaImage], frame_ms: u32) -> Result<Vec<u8>> {
+    fn chunk(data: &mut Vec<u8>, fourcc: &[u8; 4], payload: &[u8]) {
+        data.extend_from_slice(fourcc);
+        data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        data.extend_from_slice(payload);
+        if payload.len() % 2 == 1 {
+            data.push(0);
+        }
+    }
+    fn u24(data: &mut Vec<u8>, value: u32) {
+        data.extend_from_slice(&value.to_le_bytes()[..3]);
+    }
+
+    let (width, height) = frames.first().context("An animated tile needs at least one frame")?.dimensions();
+
+    let mut body = b"WEBP".to_vec();
+    let mut header = vec![0x10 | 0x02, 0, 0, 0]; // alpha and animation flags
+    u24(&mut header, width - 1);
+    u24(&mut header, height - 1);
+    chunk(&mut body, b"VP8X", &header);
+    // Transparent background, loop forever
+    chunk(&mut body, b"ANIM", &[0, 0, 0, 0, 0, 0]);
+
+    for frame in frames {
+        let mut still = Vec::new();
+        WebPEncoder::new_lossless(&mut still).write_image(frame.as_raw(), width, height, ExtendedColorType::Rgba8)?;
+        // A still lossless WebP is `RIFF <size> WEBP VP8L <size> <bitstream>`
+        let bitstream = still.get(12..).filter(|rest| rest.starts_with(b"VP8L")).context("Unexpected WebP encoder output")?;
+
+        let mut payload = Vec::new();
+        u24(&mut payload, 0);
+        u24(&mut payload, 0);
+        u24(&mut payload, width - 1);
+        u24(&mut payload, height - 1);
+        u24(&mut payload, frame_ms);
+        payload.push(0x02); // replace the previous frame instead of blending over it
+        payload.extend_from_slice(bitstream);
+        chunk(&mut body, b"ANMF", &payload);
+    }
+
+    let mut data = b"RIFF".to_vec();
+    data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    data.extend_from_slice(&body);
+    Ok(data)
+}
+
+/// Encodes `image` and stores it in `sink` under the key for `metadata`'s
+/// tile, returning the encoded size.
+pub fn write_tile_png(sink: &dyn OutputSink, key: &str, image: &RgbaImage, metadata: &TileMetadata) -> Result<u64> {
+    write_tile(sink, key, image, metadata, TileFormat::Png)
+}
+
+/// Like [`write_tile_png`], in any [`TileFormat`].
+pub fn write_tile(
+    sink: &dyn OutputSink,
+    key: &str,
+    image: &RgbaImage,
+    metadata: &TileMetadata,
+    format: TileFormat,
+) -> Result<u64> {
+    let data = encode_tile(image, metadata, format)?;
+    sink.write(key, &data)?;
+    Ok(data.len() as u64)
+}
+
+/// The tEXt chunks of a PNG, in file order.
+pub fn read_tile_metadata(path: &Path) -> Result<Vec<(String, String)>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let reader = png::Decoder::new(BufReader::new(file))
+        .read_info()
+        .with_context(|| format!("Failed to read PNG header of {:?}", path))?;
+
+    Ok(reader
+        .info()
+        .uncompressed_latin1_text
+        .iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_metadata_round_trips_through_png() {
+        let map_data = SpriteMapData {
+            floor: 7,
+            min_sector_x: 1000,
+            max_sector_x: 1009,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            tiles: Vec::new(),
+            version: 0,
+            parse_issues: Vec::new(),
+        };
+
+        // 320x32 world tiles at zoom 0: the second tile column is cut at the map edge
+        let metadata = TileMetadata::for_tile("sprites", &map_data, 0, 1, 0);
+        assert_eq!(metadata.world_min, (32256, 32000));
+        assert_eq!(metadata.world_max, (32319, 32031));
+
+        let path = std::env::temp_dir().join(format!("demonax-tile-metadata-{}.png", std::process::id()));
+        fs::write(&path, encode_tile_png(&RgbaImage::new(4, 4), &metadata).unwrap()).unwrap();
+        let chunks = read_tile_metadata(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert!(chunks.contains(&("Demonax-Bounds".to_string(), "32256,32000-32319,32031".to_string())));
+        assert!(chunks.contains(&("Demonax-Floor".to_string(), "7".to_string())));
+    }
+
+    #[test]
+    fn test_webp_tiles_are_lossless() {
+        let image = RgbaImage::from_fn(16, 16, |x, y| image::Rgba([x as u8 * 16, y as u8 * 16, 7, if x < 8 { 255 } else { 0 }]));
+        let metadata = TileMetadata {
+            layer: "sprites",
+            floor: 7,
+            zoom: 0,
+            tile_x: 0,
+            tile_y: 0,
+            world_min: (32000, 32000),
+            world_max: (32015, 32015),
+        };
+
+        let data = encode_tile(&image, &metadata, TileFormat::Webp).unwrap();
+        assert_eq!(image::guess_format(&data).unwrap(), image::ImageFormat::WebP);
+        assert_eq!(image::load_from_memory(&data).unwrap().to_rgba8(), image);
+    }
+
+    #[test]
+    fn test_animated_webp_decodes_every_frame() {
+        use image::AnimationDecoder;
+
+        let frames = [
+            RgbaImage::from_pixel(8, 8, image::Rgba([255, 0, 0, 255])),
+            RgbaImage::from_pixel(8, 8, image::Rgba([0, 0, 255, 128])),
+        ];
+        let data = encode_animated_webp(&frames, 250).unwrap();
+
+        let decoder = image::codecs::webp::WebPDecoder::new(std::io::Cursor::new(data)).unwrap();
+        assert!(decoder.has_animation());
+        let decoded = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[1].delay().numer_denom_ms(), (250, 1));
+        assert_eq!(decoded[0].buffer(), &frames[0]);
+        assert_eq!(decoded[1].buffer(), &frames[1]);
+    }
+}
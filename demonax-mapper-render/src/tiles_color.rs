@@ -0,0 +1,175 @@
+use crate::palette::ColorMap;
+use crate::progress::{ProgressFn, ProgressTracker};
+use crate::tiles_sprite::all_tiles;
+use crate::{select_sprite_layers, tile_key, write_tile, OutputSink, TileMetadata, TileRenderStats};
+use anyhow::Result;
+use demonax_mapper_core::{ObjectDatabase, SpriteMapData, TileFormat};
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+
+const TILE_SIZE: u32 = 256;
+
+/// Flat color of every map tile on one floor, taken from its topmost drawn
+/// object that has one, like the in-game minimap. Indexed by map-relative
+/// tile coordinates.
+pub struct ColorGrid {
+    pub width: u32,
+    pub height: u32,
+    cells: Vec<Option<[u8; 3]>>,
+}
+
+impl ColorGrid {
+    pub fn build(map_data: &SpriteMapData, objects: &ObjectDatabase, colors: &ColorMap) -> Self {
+        let bounds = map_data.bounds();
+        let (width, height) = (bounds.width(), bounds.height());
+        let mut cells = vec![None; (width * height) as usize];
+
+        for tile_stack in &map_data.tiles {
+            if tile_stack.x >= width || tile_stack.y >= height {
+                continue;
+            }
+            let color = select_sprite_layers(&tile_stack.object_ids, objects)
+                .iter()
+                .rev()
+                .find_map(|id| colors.get(id).copied());
+            cells[(tile_stack.y * width + tile_stack.x) as usize] = color;
+        }
+
+        Self { width, height, cells }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<[u8; 3]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.cells[(y * self.width + x) as usize]
+    }
+}
+
+/// Renders every tile of a floor as flat colors instead of sprites, on the
+/// same tile grid and keys as [`generate_sprite_tiles`](crate::generate_sprite_tiles).
+/// Needs only a [`ColorMap`], so it works without any sprite files.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_color_tiles(
+    map_data: &SpriteMapData,
+    objects: &ObjectDatabase,
+    colors: &ColorMap,
+    sink: &dyn OutputSink,
+    min_zoom: u8,
+    max_zoom: u8,
+    format: TileFormat,
+    progress: Option<&ProgressFn<'_>>,
+) -> Result<TileRenderStats> {
+    let tiles = all_tiles(map_data, min_zoom, max_zoom);
+    render_color_tiles(map_data, objects, colors, sink, &tiles, format, progress)
+}
+
+/// Re-renders only the listed `(zoom, x, y)` color tiles, for incremental builds.
+pub fn render_color_tiles(
+    map_data: &SpriteMapData,
+    objects: &ObjectDatabase,
+    colors: &ColorMap,
+    sink: &dyn OutputSink,
+    tiles: &[(u8, u32, u32)],
+    format: TileFormat,
+    progress: Option<&ProgressFn<'_>>,
+) -> Result<TileRenderStats> {
+    let grid = ColorGrid::build(map_data, objects, colors);
+    let tracker = ProgressTracker::new(progress, map_data.floor, tiles);
+
+    let bytes = tiles
+        .par_iter()
+        .map(|&(zoom, tile_x, tile_y)| -> Result<u64> {
+            let bytes = render_color_tile(map_data, &grid, sink, zoom, tile_x, tile_y, format)?;
+            tracker.tile_done(zoom);
+            Ok(bytes)
+        })
+        .try_reduce(|| 0, |a, b| Ok(a + b))?;
+
+    Ok(TileRenderStats { tiles: tiles.len(), bytes })
+}
+
+/// Draws and writes one color tile from a floor's prebuilt [`ColorGrid`].
+pub(crate) fn render_color_tile(
+    map_data: &SpriteMapData,
+    grid: &ColorGrid,
+    sink: &dyn OutputSink,
+    zoom: u8,
+    tile_x: u32,
+    tile_y: u32,
+    format: TileFormat,
+) -> Result<u64> {
+    let image = draw_color_tile(grid, zoom, tile_x, tile_y);
+    let key = tile_key(map_data.floor, zoom, tile_x, tile_y, format);
+    let metadata = TileMetadata::for_tile("colors", map_data, zoom, tile_x, tile_y);
+    write_tile(sink, &key, &image, &metadata, format)
+}
+
+/// Draws one color tile without encoding it.
+pub(crate) fn draw_color_tile(grid: &ColorGrid, zoom: u8, tile_x: u32, tile_y: u32) -> RgbaImage {
+    let scale = 2u32.pow(zoom as u32);
+    RgbaImage::from_fn(TILE_SIZE, TILE_SIZE, |px, py| {
+        match grid.get((tile_x * TILE_SIZE + px) / scale, (tile_y * TILE_SIZE + py) / scale) {
+            Some([r, g, b]) => Rgba([r, g, b, 255]),
+            None => Rgba([0, 0, 0, 0]),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemorySink;
+    use demonax_mapper_core::{GameObject, ObjectFlags, TileStack};
+
+    fn object(id: u32, flags: ObjectFlags, waypoints: u32) -> (u32, GameObject) {
+        let obj = GameObject {
+            id,
+            name: String::new(),
+            flags,
+            unknown_flags: Vec::new(),
+            waypoints,
+            is_ground: waypoints > 0,
+            is_impassable: waypoints == 0,
+            disguise_target: None,
+            attributes: Default::default(),
+            layer: None,
+        };
+        (id, obj)
+    }
+
+    #[test]
+    fn test_color_tiles_use_topmost_colored_object() {
+        let objects: ObjectDatabase = [
+            object(100, ObjectFlags::BANK, 150),
+            object(200, ObjectFlags::BOTTOM, 0),
+            object(300, ObjectFlags::TOP, 0),
+        ]
+        .into();
+        // The Top object has no color, so the wall wins over the ground
+        let colors: ColorMap = [(100, [0, 200, 0]), (200, [90, 90, 90])].into();
+        let map_data = SpriteMapData {
+            floor: 7,
+            tiles: vec![
+                TileStack { x: 0, y: 0, object_ids: vec![100] },
+                TileStack { x: 1, y: 0, object_ids: vec![100, 200, 300] },
+            ],
+            min_sector_x: 1000,
+            max_sector_x: 1000,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+
+        let sink = MemorySink::new();
+        let stats = generate_color_tiles(&map_data, &objects, &colors, &sink, 3, 3, TileFormat::Png, None).unwrap();
+        assert_eq!(stats.tiles, 1);
+
+        // 8 pixels per game tile at zoom 3
+        let tile = image::load_from_memory(&sink.get("7/3/0/0.png").unwrap()).unwrap().to_rgba8();
+        assert_eq!(tile.get_pixel(4, 4).0, [0, 200, 0, 255]);
+        assert_eq!(tile.get_pixel(12, 4).0, [90, 90, 90, 255]);
+        assert_eq!(tile.get_pixel(20, 4).0, [0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,728 @@
+use crate::output_sink::{tile_key, OutputSink};
+use crate::progress::{ProgressFn, ProgressTracker};
+use crate::sprites::SpriteCache;
+use crate::tile_png::{encode_animated_webp, write_tile, TileMetadata};
+use demonax_mapper_core::{ObjectDatabase, Region, SpriteLayer, SpriteMapData, TileFormat, SECTOR_SIZE};
+use anyhow::Result;
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::{debug, trace};
+
+/// Most frames in an animated tile; sprites with more frames are cut short.
+const MAX_TILE_FRAMES: usize = 8;
+
+/// How long each frame of an animated tile is shown, in milliseconds.
+const FRAME_DURATION_MS: u32 = 500;
+
+/// Totals for a batch of rendered tiles.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TileRenderStats {
+    pub tiles: usize,
+    pub bytes: u64,
+}
+
+impl std::ops::AddAssign for TileRenderStats {
+    fn add_assign(&mut self, other: Self) {
+        self.tiles += other.tiles;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Object IDs of a tile stack in drawing order: ground, clip, bottom, normal
+/// and top layers, each keeping the stack's order. Objects no layer rule
+/// placed (see [`demonax_mapper_core::apply_layer_rules`]) go by their flags,
+/// and hidden ones are left out.
+pub fn select_sprite_layers(obj_ids: &[u32], objects: &ObjectDatabase) -> Vec<u32> {
+    let mut layers: Vec<(SpriteLayer, u32)> = obj_ids
+        .iter()
+        .filter_map(|&id| {
+            let obj = objects.get(&id)?;
+            let layer = obj.layer.unwrap_or_else(|| SpriteLayer::of(obj));
+            (layer != SpriteLayer::Hidden).then_some((layer, id))
+        })
+        .collect();
+
+    // Stable, so objects on one layer stay in stack order
+    layers.sort_by_key(|&(layer, _)| layer);
+    layers.into_iter().map(|(_, id)| id).collect()
+}
+
+/// Sprite IDs that rendering this floor will actually draw, after layer
+/// filtering and DisguiseTarget substitution. Sorted and deduplicated.
+pub fn referenced_sprite_ids(map_data: &SpriteMapData, objects: &ObjectDatabase) -> Vec<u32> {
+    let ids: HashSet<u32> = map_data
+        .tiles
+        .par_iter()
+        .fold(HashSet::new, |mut ids, tile_stack| {
+            for obj_id in select_sprite_layers(&tile_stack.object_ids, objects) {
+                let sprite_id = objects
+                    .get(&obj_id)
+                    .and_then(|obj| obj.disguise_target)
+                    .unwrap_or(obj_id);
+                ids.insert(sprite_id);
+            }
+            ids
+        })
+        .reduce(HashSet::new, |mut a, b| {
+            a.extend(b);
+            a
+        });
+
+    let mut ids: Vec<u32> = ids.into_iter().collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// Tiles are stored in `sink` under [`tile_key`], and `progress` hears about
+/// each one as it is written. All zoom levels share one parallel pass, so
+/// the few tiles of the low zoom levels do not leave cores idle.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_sprite_tiles(
+    map_data: &SpriteMapData,
+    sprite_cache: &SpriteCache,
+    objects: &ObjectDatabase,
+    sink: &dyn OutputSink,
+    floor: u8,
+    min_zoom: u8,
+    max_zoom: u8,
+    format: TileFormat,
+    progress: Option<&ProgressFn<'_>>,
+) -> Result<TileRenderStats> {
+    let _span = tracing::info_span!("render_floor", floor).entered();
+    let tiles = all_tiles(map_data, min_zoom, max_zoom);
+    let stats = render_sprite_tiles(map_data, sprite_cache, objects, sink, &tiles, format, progress)?;
+    debug!("Generated {} tiles for floor {}", stats.tiles, floor);
+    Ok(stats)
+}
+
+/// Number of 256px tiles along each axis at `zoom`; none at zooms too deep
+/// for the pixel size of the map to fit a `u32`.
+pub fn tile_grid_size(map_data: &SpriteMapData, zoom: u8) -> (u32, u32) {
+    let bounds = map_data.bounds();
+    let tiles = |length: u32| {
+        2u32.checked_pow(zoom as u32)
+            .and_then(|scale| length.checked_mul(scale))
+            .map_or(0, |pixels| pixels.div_ceil(256))
+    };
+    (tiles(bounds.width()), tiles(bounds.height()))
+}
+
+/// Every `(zoom, x, y)` tile of a floor from `min_zoom` to `max_zoom`.
+pub(crate) fn all_tiles(map_data: &SpriteMapData, min_zoom: u8, max_zoom: u8) -> Vec<(u8, u32, u32)> {
+    (min_zoom..=max_zoom)
+        .flat_map(|zoom| {
+            let (num_tiles_x, num_tiles_y) = tile_grid_size(map_data, zoom);
+            (0..num_tiles_x).flat_map(move |x| (0..num_tiles_y).map(move |y| (zoom, x, y)))
+        })
+        .collect()
+}
+
+/// Output tiles `(zoom, x, y)` that draw part of one of the given `(x, y)`
+/// sectors, across every zoom level. Sprites reach one game tile up and left
+/// of their anchor, so that margin around each sector is included.
+pub fn tiles_covering_sectors(
+    map_data: &SpriteMapData,
+    sectors: &[(u32, u32)],
+    min_zoom: u8,
+    max_zoom: u8,
+) -> Vec<(u8, u32, u32)> {
+    let bounds = map_data.bounds();
+    let mut tiles: HashSet<(u8, u32, u32)> = HashSet::new();
+
+    for &(sector_x, sector_y) in sectors {
+        let Some((map_x, map_y)) = bounds.world_to_map(sector_x * SECTOR_SIZE, sector_y * SECTOR_SIZE) else {
+            continue;
+        };
+        let (start_x, start_y) = (map_x.saturating_sub(1), map_y.saturating_sub(1));
+        let (end_x, end_y) = (map_x + SECTOR_SIZE, map_y + SECTOR_SIZE);
+        insert_tiles_covering(map_data, (start_x, start_y, end_x, end_y), min_zoom, max_zoom, &mut tiles);
+    }
+
+    let mut tiles: Vec<(u8, u32, u32)> = tiles.into_iter().collect();
+    tiles.sort_unstable();
+    tiles
+}
+
+/// Output tiles `(zoom, x, y)` that draw part of a rectangle of world tiles,
+/// across every zoom level, with the same one-tile margin as
+/// [`tiles_covering_sectors`]. Empty when the region lies outside the map.
+pub fn tiles_covering_region(map_data: &SpriteMapData, region: &Region, min_zoom: u8, max_zoom: u8) -> Vec<(u8, u32, u32)> {
+    let Some((min_x, min_y, max_x, max_y)) = region.to_map(&map_data.bounds()) else {
+        return Vec::new();
+    };
+
+    let mut tiles: HashSet<(u8, u32, u32)> = HashSet::new();
+    let rect = (min_x.saturating_sub(1), min_y.saturating_sub(1), max_x + 1, max_y + 1);
+    insert_tiles_covering(map_data, rect, min_zoom, max_zoom, &mut tiles);
+
+    let mut tiles: Vec<(u8, u32, u32)> = tiles.into_iter().collect();
+    tiles.sort_unstable();
+    tiles
+}
+
+/// Adds the output tiles overlapping the map rectangle `(start_x, start_y)`
+/// to `(end_x, end_y)`, end exclusive, at every zoom level.
+fn insert_tiles_covering(
+    map_data: &SpriteMapData,
+    (start_x, start_y, end_x, end_y): (u32, u32, u32, u32),
+    min_zoom: u8,
+    max_zoom: u8,
+    tiles: &mut HashSet<(u8, u32, u32)>,
+) {
+    for zoom in min_zoom..=max_zoom {
+        let scale = 2u32.pow(zoom as u32);
+        let (num_tiles_x, num_tiles_y) = tile_grid_size(map_data, zoom);
+        for x in start_x * scale / 256..=((end_x * scale - 1) / 256).min(num_tiles_x - 1) {
+            for y in start_y * scale / 256..=((end_y * scale - 1) / 256).min(num_tiles_y - 1) {
+                tiles.insert((zoom, x, y));
+            }
+        }
+    }
+}
+
+/// Re-renders only the listed `(zoom, x, y)` tiles of a floor, e.g. those
+/// returned by [`tiles_covering_sectors`] for an incremental build.
+pub fn render_sprite_tiles(
+    map_data: &SpriteMapData,
+    sprite_cache: &SpriteCache,
+    objects: &ObjectDatabase,
+    sink: &dyn OutputSink,
+    tiles: &[(u8, u32, u32)],
+    format: TileFormat,
+    progress: Option<&ProgressFn<'_>>,
+) -> Result<TileRenderStats> {
+    let tracker = ProgressTracker::new(progress, map_data.floor, tiles);
+    let bytes = tiles
+        .par_iter()
+        .map(|&(zoom, x, y)| -> Result<u64> {
+            let bytes = render_sprite_tile(map_data, sprite_cache, objects, sink, zoom, x, y, format)?;
+            tracker.tile_done(zoom);
+            Ok(bytes)
+        })
+        .try_reduce(|| 0, |a, b| Ok(a + b))?;
+
+    Ok(TileRenderStats { tiles: tiles.len(), bytes })
+}
+
+/// Renders a single tile to `<floor>/<zoom>/<x>/<y>.<ext>` in `sink`, for
+/// callers that produce tiles on demand instead of a whole zoom level at once.
+#[allow(clippy::too_many_arguments)]
+pub fn render_sprite_tile(
+    map_data: &SpriteMapData,
+    sprite_cache: &SpriteCache,
+    objects: &ObjectDatabase,
+    sink: &dyn OutputSink,
+    zoom: u8,
+    tile_x: u32,
+    tile_y: u32,
+    format: TileFormat,
+) -> Result<u64> {
+    let bounds = map_data.bounds();
+    let (map_width, map_height) = (bounds.width(), bounds.height());
+
+    render_single_sprite_tile(
+        map_data,
+        sprite_cache,
+        objects,
+        sink,
+        zoom,
+        tile_x,
+        tile_y,
+        map_width,
+        map_height,
+        format,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_single_sprite_tile(
+    map_data: &SpriteMapData,
+    sprite_cache: &SpriteCache,
+    objects: &ObjectDatabase,
+    sink: &dyn OutputSink,
+    zoom: u8,
+    tile_x: u32,
+    tile_y: u32,
+    map_width: u32,
+    map_height: u32,
+    format: TileFormat,
+) -> Result<u64> {
+    let (output, frames) = draw_tile(map_data, sprite_cache, objects, zoom, tile_x, tile_y, map_width, map_height, 0)?;
+
+    let key = tile_key(map_data.floor, zoom, tile_x, tile_y, format);
+    let bytes = if frames > 1 && format == TileFormat::Webp {
+        // Sprites with fewer frames than the tile loop through theirs
+        let mut images = vec![output];
+        for frame in 1..frames.min(MAX_TILE_FRAMES) {
+            images.push(draw_tile(map_data, sprite_cache, objects, zoom, tile_x, tile_y, map_width, map_height, frame)?.0);
+        }
+        let data = encode_animated_webp(&images, FRAME_DURATION_MS)?;
+        sink.write(&key, &data)?;
+        data.len() as u64
+    } else {
+        let metadata = TileMetadata::for_tile("sprites", map_data, zoom, tile_x, tile_y);
+        write_tile(sink, &key, &output, &metadata, format)?
+    };
+
+    trace!("Rendered tile {}/{}", tile_x, tile_y);
+
+    Ok(bytes)
+}
+
+/// Draws one 256px tile of a floor without encoding it, for callers that
+/// compose tiles into something else, such as a whole-floor image.
+/// Animated sprites are drawn as their first frame.
+pub fn draw_sprite_tile(
+    map_data: &SpriteMapData,
+    sprite_cache: &SpriteCache,
+    objects: &ObjectDatabase,
+    zoom: u8,
+    tile_x: u32,
+    tile_y: u32,
+) -> Result<RgbaImage> {
+    let bounds = map_data.bounds();
+    Ok(draw_tile(map_data, sprite_cache, objects, zoom, tile_x, tile_y, bounds.width(), bounds.height(), 0)?.0)
+}
+
+/// Draws one tile of several floors stacked into one image, the floor in
+/// view first, each with the brightness it is drawn at (1.0 for unchanged).
+/// Like the game client's ghosting of lower floors, each floor shows through
+/// wherever the floors above it leave a gap.
+pub fn draw_composite_tile(
+    layers: &[(&SpriteMapData, f32)],
+    sprite_cache: &SpriteCache,
+    objects: &ObjectDatabase,
+    zoom: u8,
+    tile_x: u32,
+    tile_y: u32,
+) -> Result<RgbaImage> {
+    let mut output = RgbaImage::new(256, 256);
+
+    for &(map_data, brightness) in layers.iter().rev() {
+        let mut layer = draw_sprite_tile(map_data, sprite_cache, objects, zoom, tile_x, tile_y)?;
+        if brightness < 1.0 {
+            for pixel in layer.pixels_mut() {
+                for channel in &mut pixel.0[..3] {
+                    *channel = (*channel as f32 * brightness).round() as u8;
+                }
+            }
+        }
+        for (base, top) in output.pixels_mut().zip(layer.pixels()) {
+            *base = alpha_blend(*base, *top);
+        }
+    }
+
+    Ok(output)
+}
+
+/// Renders `composite/<floor>/<zoom>/<x>/<y>.<ext>` into `sink` for each
+/// `(floor, zoom, x, y)` of `tiles`: the floor over those of `floors` up to
+/// `brightness.len()` levels beneath it, drawn as [`draw_composite_tile`]
+/// with `brightness[0]` for the floor right below. Floors missing from
+/// `floors` are skipped, keeping the brightness of their depth.
+pub fn render_composite_tiles(
+    floors: &[&SpriteMapData],
+    brightness: &[f32],
+    sprite_cache: &SpriteCache,
+    objects: &ObjectDatabase,
+    sink: &dyn OutputSink,
+    tiles: &[(u8, u8, u32, u32)],
+    format: TileFormat,
+) -> Result<TileRenderStats> {
+    let bytes = tiles
+        .par_iter()
+        .map(|&(floor, zoom, x, y)| -> Result<u64> {
+            let layers: Vec<(&SpriteMapData, f32)> = std::iter::once(1.0)
+                .chain(brightness.iter().copied())
+                .zip(floor..)
+                .filter_map(|(brightness, z)| Some((*floors.iter().find(|map_data| map_data.floor == z)?, brightness)))
+                .collect();
+            let Some(&(map_data, _)) = layers.first() else {
+                return Ok(0);
+            };
+
+            let image = draw_composite_tile(&layers, sprite_cache, objects, zoom, x, y)?;
+            let key = format!("composite/{}", tile_key(floor, zoom, x, y, format));
+            let metadata = TileMetadata::for_tile("composite", map_data, zoom, x, y);
+            write_tile(sink, &key, &image, &metadata, format)
+        })
+        .try_reduce(|| 0, |a, b| Ok(a + b))?;
+
+    Ok(TileRenderStats { tiles: tiles.len(), bytes })
+}
+
+/// Draws animation frame `frame` of a tile, along with the most frames any
+/// sprite drawn on it has (1 when nothing on it is animated).
+#[allow(clippy::too_many_arguments)]
+fn draw_tile(
+    map_data: &SpriteMapData,
+    sprite_cache: &SpriteCache,
+    objects: &ObjectDatabase,
+    zoom: u8,
+    tile_x: u32,
+    tile_y: u32,
+    map_width: u32,
+    map_height: u32,
+    frame: usize,
+) -> Result<(RgbaImage, usize)> {
+    const TILE_SIZE: u32 = 256;
+    let scale = 2u32.pow(zoom as u32);
+    let _span = tracing::trace_span!("render_tile", tile_x, tile_y, scale).entered();
+
+    let mut output = RgbaImage::from_pixel(
+        TILE_SIZE,
+        TILE_SIZE,
+        Rgba([0, 0, 0, 0]),
+    );
+
+    let tile_start_x = tile_x * TILE_SIZE / scale;
+    let tile_start_y = tile_y * TILE_SIZE / scale;
+    let tile_end_x = ((tile_x + 1) * TILE_SIZE / scale).min(map_width);
+    let tile_end_y = ((tile_y + 1) * TILE_SIZE / scale).min(map_height);
+
+    // Maximum sprite size is 64px, which translates to 64/scale game tiles when scaled
+    let max_sprite_tiles = 64_u32.div_ceil(scale);
+
+    // Only process tiles that could possibly overlap with this output tile
+    // A sprite at position (x,y) can extend up to max_sprite_tiles in each direction
+    let search_start_x = tile_start_x.saturating_sub(max_sprite_tiles);
+    let search_end_x = tile_end_x + max_sprite_tiles;
+    let search_start_y = tile_start_y.saturating_sub(max_sprite_tiles);
+    let search_end_y = tile_end_y + max_sprite_tiles;
+    let mut frames = 1;
+
+    for tile_stack in &map_data.tiles {
+        // Early filter: skip tiles that are definitely out of range
+        if tile_stack.x < search_start_x || tile_stack.x >= search_end_x ||
+           tile_stack.y < search_start_y || tile_stack.y >= search_end_y {
+            continue;
+        }
+
+        // Debug logging for problematic coordinates (scale==4 is zoom 2)
+        if scale == 4 && tile_x == 22 && tile_y == 15 && tile_stack.x >= 1408 && tile_stack.x <= 1415 && tile_stack.y >= 960 && tile_stack.y <= 965 {
+            tracing::debug!("Tile ({}, {}) scale {}: processing tile_stack at ({}, {}) with objects {:?}",
+                tile_x, tile_y, scale, tile_stack.x, tile_stack.y, tile_stack.object_ids);
+        }
+
+        let layers = select_sprite_layers(&tile_stack.object_ids, objects);
+
+        // Debug logging for layer selection
+        if scale == 4 && tile_x == 22 && tile_y == 15 && tile_stack.x >= 1408 && tile_stack.x <= 1415 && tile_stack.y >= 960 && tile_stack.y <= 965 {
+            tracing::debug!("  -> Selected layers: {:?}", layers);
+        }
+
+        for &obj_id in &layers {
+            // Use DisguiseTarget sprite if object has one
+            let sprite_id = objects.get(&obj_id)
+                .and_then(|obj| obj.disguise_target)
+                .unwrap_or(obj_id);
+            let frame_count = sprite_cache.frame_count(sprite_id);
+            let scaled = sprite_cache.get_scaled_frame(sprite_id, frame % frame_count, scale)?;
+            let (sprite_width, sprite_height) = scaled.dimensions();
+
+            let sprite_tiles_wide = sprite_width.div_ceil(scale);
+            let sprite_tiles_high = sprite_height.div_ceil(scale);
+
+            // The tile position is the ANCHOR POINT (bottom-right corner) of the sprite
+            // For a 64x64 sprite (2x2 tiles), we need to offset by -1,-1 to get the top-left
+            let sprite_top_left_x = tile_stack.x as i32 - (sprite_tiles_wide as i32 - 1);
+            let sprite_top_left_y = tile_stack.y as i32 - (sprite_tiles_high as i32 - 1);
+
+            // Calculate sprite bounds (keep as i32 to handle negative coordinates at boundaries)
+            let sprite_end_x = sprite_top_left_x + sprite_tiles_wide as i32;
+            let sprite_end_y = sprite_top_left_y + sprite_tiles_high as i32;
+
+            // Overlap check using i32 to properly handle sprites at sector boundaries
+            // Use <= for top-left checks to include sprites that start exactly at tile boundary
+            if sprite_top_left_x <= tile_end_x as i32 && sprite_end_x > tile_start_x as i32 &&
+               sprite_top_left_y <= tile_end_y as i32 && sprite_end_y > tile_start_y as i32 {
+
+                let px = (sprite_top_left_x - tile_start_x as i32) * scale as i32;
+                let py = (sprite_top_left_y - tile_start_y as i32) * scale as i32;
+
+                overlay_with_alpha(&mut output, &scaled, px, py);
+                frames = frames.max(frame_count);
+            }
+        }
+    }
+
+    Ok((output, frames))
+}
+
+fn overlay_with_alpha(
+    base: &mut RgbaImage,
+    overlay: &RgbaImage,
+    x_offset: i32,
+    y_offset: i32,
+) {
+    let (overlay_width, overlay_height) = overlay.dimensions();
+    let (base_width, base_height) = base.dimensions();
+
+    for y in 0..overlay_height {
+        for x in 0..overlay_width {
+            let base_x = x_offset + x as i32;
+            let base_y = y_offset + y as i32;
+
+            if base_x >= 0 && base_x < base_width as i32 &&
+               base_y >= 0 && base_y < base_height as i32 {
+                let base_pixel = *base.get_pixel(base_x as u32, base_y as u32);
+                let overlay_pixel = *overlay.get_pixel(x, y);
+
+                let blended = alpha_blend(base_pixel, overlay_pixel);
+                base.put_pixel(base_x as u32, base_y as u32, blended);
+            }
+        }
+    }
+}
+
+fn alpha_blend(bottom: Rgba<u8>, top: Rgba<u8>) -> Rgba<u8> {
+    let alpha_top = top[3] as f32 / 255.0;
+
+    if alpha_top == 0.0 {
+        return bottom;
+    }
+
+    if alpha_top == 1.0 {
+        return top;
+    }
+
+    let alpha_bottom = bottom[3] as f32 / 255.0;
+    let alpha_out = alpha_top + alpha_bottom * (1.0 - alpha_top);
+
+    if alpha_out == 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    let blend_channel = |bottom_c: u8, top_c: u8| -> u8 {
+        let bottom_f = bottom_c as f32 / 255.0;
+        let top_f = top_c as f32 / 255.0;
+        let out_f = (top_f * alpha_top + bottom_f * alpha_bottom * (1.0 - alpha_top)) / alpha_out;
+        (out_f * 255.0) as u8
+    };
+
+    Rgba([
+        blend_channel(bottom[0], top[0]),
+        blend_channel(bottom[1], top[1]),
+        blend_channel(bottom[2], top[2]),
+        (alpha_out * 255.0) as u8,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use demonax_mapper_core::{GameObject, ObjectFlags, TileStack};
+    use crate::output_sink::MemorySink;
+    use crate::progress::RenderProgress;
+
+    #[test]
+    fn test_alpha_blend_transparent() {
+        let bottom = Rgba([100, 100, 100, 255]);
+        let top = Rgba([200, 0, 0, 0]);
+        let result = alpha_blend(bottom, top);
+        assert_eq!(result, bottom);
+    }
+
+    #[test]
+    fn test_alpha_blend_opaque() {
+        let bottom = Rgba([100, 100, 100, 255]);
+        let top = Rgba([200, 0, 0, 255]);
+        let result = alpha_blend(bottom, top);
+        assert_eq!(result, top);
+    }
+
+    #[test]
+    fn test_alpha_blend_partial() {
+        let bottom = Rgba([100, 100, 100, 255]);
+        let top = Rgba([200, 0, 0, 128]);
+        let result = alpha_blend(bottom, top);
+
+        assert!(result[0] > 100 && result[0] < 200);
+        assert_eq!(result[3], 255);
+    }
+
+    fn test_object(id: u32, flags: ObjectFlags, waypoints: u32, disguise_target: Option<u32>) -> GameObject {
+        let has_unpass = flags.is_unpassable();
+        GameObject {
+            id,
+            name: String::new(),
+            flags,
+            unknown_flags: Vec::new(),
+            waypoints,
+            is_ground: waypoints > 0 && !has_unpass,
+            is_impassable: has_unpass || waypoints == 0,
+            disguise_target,
+            attributes: Default::default(),
+            layer: None,
+        }
+    }
+
+    #[test]
+    fn test_referenced_sprite_ids_skips_takeables_and_follows_disguise() {
+        let mut objects = ObjectDatabase::new();
+        objects.insert(100, test_object(100, ObjectFlags::BANK, 150, None));
+        objects.insert(200, test_object(200, ObjectFlags::UNMOVE, 0, Some(201)));
+        objects.insert(700, test_object(700, ObjectFlags::TAKE, 0, None));
+
+        let map_data = SpriteMapData {
+            floor: 7,
+            tiles: vec![
+                TileStack { x: 0, y: 0, object_ids: vec![100, 700] },
+                TileStack { x: 1, y: 0, object_ids: vec![100, 200, 4242] },
+            ],
+            min_sector_x: 0,
+            max_sector_x: 0,
+            min_sector_y: 0,
+            max_sector_y: 0,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+
+        assert_eq!(referenced_sprite_ids(&map_data, &objects), vec![100, 201]);
+    }
+
+    #[test]
+    fn test_tiles_covering_sectors_includes_sprite_margin() {
+        let map_data = SpriteMapData {
+            floor: 7,
+            tiles: Vec::new(),
+            min_sector_x: 1000,
+            max_sector_x: 1015,
+            min_sector_y: 1000,
+            max_sector_y: 1007,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+
+        // Map tiles 127..160 x 63..96, the extra row and column for sprites
+        // anchored on the sector's first tiles
+        let tiles = tiles_covering_sectors(&map_data, &[(1004, 1002), (2000, 2000)], 0, 3);
+        assert_eq!(tiles.iter().filter(|(zoom, _, _)| *zoom == 0).count(), 1);
+        assert_eq!(tiles.iter().filter(|(zoom, _, _)| *zoom == 1).count(), 2);
+        assert_eq!(
+            tiles.iter().filter(|(zoom, _, _)| *zoom == 3).copied().collect::<Vec<_>>(),
+            vec![(3, 3, 1), (3, 3, 2), (3, 4, 1), (3, 4, 2)]
+        );
+    }
+
+    #[test]
+    fn test_tile_grid_size_too_deep_is_empty() {
+        let map_data = SpriteMapData {
+            floor: 7,
+            tiles: Vec::new(),
+            min_sector_x: 1000,
+            max_sector_x: 1015,
+            min_sector_y: 1000,
+            max_sector_y: 1007,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+
+        assert_eq!(tile_grid_size(&map_data, 0), (2, 1));
+        assert_eq!(tile_grid_size(&map_data, 40), (0, 0));
+        assert_eq!(tile_grid_size(&map_data, 255), (0, 0));
+    }
+
+    #[test]
+    fn test_tiles_covering_region() {
+        let map_data = SpriteMapData {
+            floor: 7,
+            tiles: Vec::new(),
+            min_sector_x: 1000,
+            max_sector_x: 1015,
+            min_sector_y: 1000,
+            max_sector_y: 1007,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+
+        // Map tiles 128..=135 x 64..=70 plus the sprite margin
+        let region = Region { min_x: 32128, min_y: 32064, max_x: 32135, max_y: 32070 };
+        assert_eq!(tiles_covering_region(&map_data, &region, 3, 3), vec![(3, 3, 1), (3, 3, 2), (3, 4, 1), (3, 4, 2)]);
+        assert_eq!(tiles_covering_region(&map_data, &region, 0, 0), vec![(0, 0, 0)]);
+
+        let outside = Region { min_x: 10, min_y: 10, max_x: 20, max_y: 20 };
+        assert!(tiles_covering_region(&map_data, &outside, 0, 3).is_empty());
+    }
+
+    #[test]
+    fn test_generate_sprite_tiles_into_memory_sink() {
+        // 64 x 32 game tiles: one tile at zoom 2, two at zoom 3
+        let map_data = SpriteMapData {
+            floor: 7,
+            tiles: Vec::new(),
+            min_sector_x: 1000,
+            max_sector_x: 1001,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+        let sprite_cache = SpriteCache::new(std::env::temp_dir()).unwrap();
+        let sink = MemorySink::new();
+        let reports = std::sync::Mutex::new(Vec::new());
+        let progress = |p: RenderProgress| reports.lock().unwrap().push(p);
+
+        let stats =
+            generate_sprite_tiles(&map_data, &sprite_cache, &ObjectDatabase::new(), &sink, 7, 2, 3, TileFormat::Png, Some(&progress))
+                .unwrap();
+        assert_eq!(stats.tiles, 3);
+        assert_eq!(sink.keys(), vec!["7/2/0/0.png", "7/3/0/0.png", "7/3/1/0.png"]);
+
+        let mut reports = reports.into_inner().unwrap();
+        reports.sort_by_key(|p| (p.zoom, p.rendered));
+        let counts: Vec<_> = reports.iter().map(|p| (p.floor, p.zoom, p.rendered, p.total)).collect();
+        assert_eq!(counts, [(7, 2, 1, 1), (7, 3, 1, 2), (7, 3, 2, 2)]);
+
+        let tile = image::load_from_memory(&sink.get("7/3/1/0.png").unwrap()).unwrap().to_rgba8();
+        assert_eq!(tile.dimensions(), (256, 256));
+        assert_eq!(tile.get_pixel(0, 0).0, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_composite_shows_lower_floor_dimmed_through_gaps() {
+        let dir = std::env::temp_dir().join(format!("demonax-composite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        RgbaImage::from_pixel(32, 32, Rgba([200, 100, 50, 255])).save(dir.join("100.png")).unwrap();
+        let sprite_cache = SpriteCache::new(&dir).unwrap();
+        let objects: ObjectDatabase = [(100, test_object(100, ObjectFlags::BANK, 150, None))].into();
+
+        let floor = |floor: u8, tiles: Vec<TileStack>| SpriteMapData {
+            floor,
+            tiles,
+            min_sector_x: 1000,
+            max_sector_x: 1000,
+            min_sector_y: 1000,
+            max_sector_y: 1000,
+            version: 2,
+            parse_issues: Vec::new(),
+        };
+        // Floor 7 covers only the first game tile, floor 8 the first two
+        let upper = floor(7, vec![TileStack { x: 0, y: 0, object_ids: vec![100] }]);
+        let lower = floor(8, (0..2).map(|x| TileStack { x, y: 0, object_ids: vec![100] }).collect());
+
+        let sink = MemorySink::new();
+        let stats = render_composite_tiles(
+            &[&upper, &lower],
+            &[0.5],
+            &sprite_cache,
+            &objects,
+            &sink,
+            &[(7, 3, 0, 0), (8, 3, 0, 0)],
+            TileFormat::Png,
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(stats.tiles, 2);
+        // At zoom 3 each game tile is 8px
+        let tile = image::load_from_memory(&sink.get("composite/7/3/0/0.png").unwrap()).unwrap().to_rgba8();
+        assert_eq!(tile.get_pixel(4, 4).0, [200, 100, 50, 255]);
+        assert_eq!(tile.get_pixel(12, 4).0, [100, 50, 25, 255]);
+        assert_eq!(tile.get_pixel(20, 4)[3], 0);
+        let tile = image::load_from_memory(&sink.get("composite/8/3/0/0.png").unwrap()).unwrap().to_rgba8();
+        assert_eq!(tile.get_pixel(12, 4).0, [200, 100, 50, 255]);
+    }
+}